@@ -0,0 +1,17 @@
+//! Renders the built-in mesh into the six faces of a cubemap before every frame, see `ReflectionProbe`.
+//! Run from the crate root, where the compiled shaders are looked up: `cargo run --example reflection_probe`.
+
+use anyhow::Result;
+use winit::event_loop::{ControlFlow, EventLoop};
+
+use vulcor::App;
+
+fn main() -> Result<()> {
+    let mut app = App::new();
+    app.options.reflection_probe = true;
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.run_app(&mut app)?;
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D linearColor;
+
+layout(location = 0) in vec2 inUv;
+layout(location = 0) out vec4 outColor;
+
+// sRGB transfer function, for targets the hardware does not encode on write such as UNORM swapchains
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+void main() {
+    vec4 color = texture(linearColor, inUv);
+    // Reinhard operator, bringing HDR values into [0, 1] before encoding
+    vec3 mapped = color.rgb / (color.rgb + vec3(1.0));
+    outColor = vec4(encodeSrgb(mapped), color.a);
+}
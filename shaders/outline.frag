@@ -0,0 +1,12 @@
+#version 450
+
+layout(push_constant) uniform Outline {
+    vec4 color;
+    float scale;
+} outline;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = outline.color;
+}
@@ -0,0 +1,19 @@
+#version 450
+
+layout(binding = 0) uniform MVP {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+layout(push_constant) uniform Outline {
+    vec4 color;
+    float scale;
+} outline;
+
+// `Vertex` only provides x and y, z then reads as 0
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = mvp.proj * mvp.view * mvp.model * vec4(inPosition * outline.scale, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec4 outColor;
+
+layout(push_constant) uniform Draw {
+    float pointSize;
+} draw;
+
+void main() {
+    gl_PointSize = draw.pointSize;
+    gl_Position = vec4(inPosition, 0.0, 1.0);
+    outColor = inColor;
+}
\ No newline at end of file
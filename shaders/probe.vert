@@ -0,0 +1,21 @@
+#version 450
+
+layout(binding = 0) uniform MVP {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+layout(push_constant) uniform ProbeFace {
+    mat4 viewProj;
+} face;
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 outColor;
+
+void main() {
+    gl_Position = face.viewProj * mvp.model * vec4(inPosition, 0.0, 1.0);
+    outColor = inColor;
+}
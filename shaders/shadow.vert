@@ -0,0 +1,18 @@
+#version 450
+
+layout(binding = 0) uniform MVP {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} mvp;
+
+layout(push_constant) uniform ShadowCaster {
+    mat4 lightViewProj;
+} caster;
+
+// `Vertex` only provides x and y, z then reads as 0
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = caster.lightViewProj * mvp.model * vec4(inPosition, 1.0);
+}
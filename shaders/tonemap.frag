@@ -0,0 +1,12 @@
+#version 450
+
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput hdrColor;
+
+layout(location = 0) in vec2 inUv;
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 hdr = subpassLoad(hdrColor);
+    // Reinhard operator, the sRGB swapchain applies the gamma curve
+    outColor = vec4(hdr.rgb / (hdr.rgb + vec3(1.0)), hdr.a);
+}
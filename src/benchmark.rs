@@ -0,0 +1,177 @@
+use anyhow::Result;
+use std::{fmt::{self, Display, Formatter}, time::Duration};
+
+use crate::{core::{context::VulkanContext, graphics::Graphics}, query::gpu_timer::GpuTimer};
+
+/// Summary of the frame times of a benchmark run, measured on the CPU or the GPU.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub frames: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration
+}
+
+impl FrameStats {
+    /// `None` when no frame was measured.
+    pub fn new(mut frame_times: Vec<Duration>) -> Option<Self> {
+        if frame_times.is_empty() {
+            return None;
+        }
+        frame_times.sort();
+        let frames = frame_times.len();
+        // Nearest rank percentile
+        let percentile = |p: usize| frame_times[(frames * p).div_ceil(100).max(1) - 1];
+        Some(Self {
+            frames,
+            min: frame_times[0],
+            mean: frame_times.iter().sum::<Duration>() / frames as u32,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: frame_times[frames - 1]
+        })
+    }
+}
+
+impl Display for FrameStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} frames, min: {:.3}ms, mean: {:.3}ms, p50: {:.3}ms, p99: {:.3}ms, max: {:.3}ms",
+            self.frames, ms(self.min), ms(self.mean), ms(self.p50), ms(self.p99), ms(self.max)
+        )
+    }
+}
+
+/// CPU frame times of a benchmark run, and GPU frame times when the graphics queue supports timestamps.
+#[derive(Clone, Copy, Debug)]
+pub struct BenchmarkStats {
+    pub cpu: FrameStats,
+    pub gpu: Option<FrameStats>
+}
+
+impl Display for BenchmarkStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "cpu: {}", self.cpu)?;
+        match &self.gpu {
+            Some(gpu) => write!(f, "gpu: {}", gpu),
+            None => write!(f, "gpu: timestamps unsupported")
+        }
+    }
+}
+
+/// GPU time of every frame of a benchmark, measured by one `GpuTimer` per swapchain image.
+pub struct GpuFrameTimes {
+    timers: Vec<GpuTimer>,
+    /// Whether the frame last recorded with each timer was submitted, its timestamps cannot be read before.
+    submitted: Vec<bool>,
+    times: Vec<Duration>
+}
+
+impl GpuFrameTimes {
+    /// `None` when the graphics queue cannot write timestamps. Room for `frames` times is reserved up front,
+    /// so collecting them does not allocate while rendering.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, images: usize, frames: usize) -> Result<Option<Self>> {
+        let first = GpuTimer::new(context, graphics)?;
+        if !first.supported() {
+            first.cleanup(graphics);
+            return Ok(None);
+        }
+        let mut timers = Self::create_timers(context, graphics, images.saturating_sub(1))
+            .inspect_err(|_| first.cleanup(graphics))?;
+        timers.insert(0, first);
+        Ok(Some(Self { submitted: vec![false; timers.len()], timers, times: Vec::with_capacity(frames) }))
+    }
+
+    /// Recorded around the frame of `image_index`.
+    pub fn timer(&self, image_index: usize) -> &GpuTimer {
+        &self.timers[image_index]
+    }
+
+    /// Marks the frame of `image_index` as submitted, its time is read by the next `collect` for the same image.
+    pub fn submitted(&mut self, image_index: usize) {
+        self.submitted[image_index] = true;
+    }
+
+    /// Reads the time of the frame last submitted for `image_index`, whose fence must have been waited on.
+    pub fn collect(&mut self, graphics: &Graphics, image_index: usize) -> Result<()> {
+        if !self.submitted[image_index] {
+            return Ok(());
+        }
+        self.submitted[image_index] = false;
+        if let Some(ns) = self.timers[image_index].elapsed_ns(graphics)? {
+            self.times.push(Duration::from_nanos(ns));
+        }
+        Ok(())
+    }
+
+    /// Collects every submitted frame, then replaces the timers with one per image of a recreated swapchain.
+    /// The device must be idle.
+    pub fn recreate(&mut self, context: &VulkanContext, graphics: &Graphics, images: usize) -> Result<()> {
+        (0..self.timers.len()).try_for_each(|i| self.collect(graphics, i))?;
+        let timers = Self::create_timers(context, graphics, images)?;
+        self.cleanup(graphics);
+        self.submitted = vec![false; timers.len()];
+        self.timers = timers;
+        Ok(())
+    }
+
+    /// Collects the frames still pending and destroys the timers. The device must be idle.
+    pub fn finish(mut self, graphics: &Graphics) -> Result<Vec<Duration>> {
+        let result = (0..self.timers.len()).try_for_each(|i| self.collect(graphics, i));
+        self.cleanup(graphics);
+        result.map(|_| self.times)
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.timers.iter().for_each(|timer| timer.cleanup(graphics));
+    }
+
+    fn create_timers(context: &VulkanContext, graphics: &Graphics, count: usize) -> Result<Vec<GpuTimer>> {
+        let mut timers = Vec::with_capacity(count + 1);
+        for _ in 0..count {
+            match GpuTimer::new(context, graphics) {
+                Ok(timer) => timers.push(timer),
+                Err(e) => {
+                    timers.iter().for_each(|timer| timer.cleanup(graphics));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(timers)
+    }
+}
+
+/// Time taken to create the same pipelines one `create_graphics_pipelines` call at a time, then in a single call
+/// with the variants deriving from the first pipeline, see `RenderPipeline::create_many`.
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineCreationStats {
+    pub pipelines: usize,
+    pub separate: Duration,
+    pub batched: Duration
+}
+
+impl Display for PipelineCreationStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(f, "{} pipelines, separate: {:.3}ms, batched: {:.3}ms", self.pipelines, ms(self.separate), ms(self.batched))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_stats_use_nearest_rank_percentiles() {
+        let times = (1..=100).rev().map(Duration::from_micros).collect();
+        let stats = FrameStats::new(times).unwrap();
+        assert_eq!((stats.frames, stats.min, stats.max), (100, Duration::from_micros(1), Duration::from_micros(100)));
+        assert_eq!((stats.p50, stats.p99), (Duration::from_micros(50), Duration::from_micros(99)));
+        assert_eq!(stats.mean, Duration::from_nanos(50_500));
+        assert!(FrameStats::new(vec![]).is_none());
+    }
+}
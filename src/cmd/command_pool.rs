@@ -1,27 +1,205 @@
 use core::panic;
 use std::sync::Arc;
 
-use anyhow::{Result};
-use ash::vk::{self, DescriptorSet};
+use anyhow::{anyhow, Result};
+use ash::{vk::{self, DescriptorSet}, Device};
 
-use crate::{core::{graphics::Graphics, logical_device::GraphicsInterface}, pipeline::{render_pipeline::{INDICES, VERTICES}, traits::VulkanPipeline}, resources::buffer::Buffer, swapchain::SwapchainData};
+use crate::{core::{graphics::Graphics, logical_device::GraphicsInterface}, math::geometry::{BoundingSphere, Frustum}, overlay::Overlay, pipeline::{fullscreen_pass::FullscreenPass, particle_system::ParticleSystem, reflection_probe::ReflectionProbe, render_pipeline::{Outline, RenderPipeline}, shadow_pass::ShadowPass, tone_mapping::ToneMapPass, traits::VulkanPipeline}, query::gpu_timer::GpuTimer, resources::{buffer::Buffer, render_target::RenderTarget}, scene::{FrameContext, Scene}, swapchain::SwapchainData};
+
+/// Renders the scene into `targets`, one per swapchain image, then scales each into its swapchain image by drawing the
+/// matching `passes`, which sample it, in the swapchain render pass.
+pub struct PresentBlit<'a> {
+    pub targets: &'a [RenderTarget],
+    pub passes: &'a [FullscreenPass]
+}
+
+impl<'a> PresentBlit<'a> {
+    pub fn new(targets: &'a [RenderTarget], passes: &'a [FullscreenPass]) -> Option<Self> {
+        (!targets.is_empty() && targets.len() == passes.len()).then_some(Self { targets, passes })
+    }
+}
+
+/// Render pass and images the command buffers of the swapchain images render to.
+#[derive(Clone, Copy)]
+pub struct FrameTargets<'a> {
+    pub render_pass: &'a vk::RenderPass,
+    /// One per swapchain image, only drawn into by the `blit` passes when it renders offscreen.
+    pub framebuffers: &'a [vk::Framebuffer],
+    pub swapchain: &'a SwapchainData,
+    pub blit: Option<&'a PresentBlit<'a>>,
+    /// Renders the scene in HDR and tone maps it into the swapchain images instead of `render_pass`, when not blitting.
+    pub tone_map: Option<&'a ToneMapPass>
+}
+
+/// Draw commands of one frame, recorded into the command buffer of the acquired image.
+pub struct FrameScene<'a> {
+    pub pipeline: &'a dyn VulkanPipeline,
+    /// Additional pipelines compatible with `descriptor_set`, for scenes drawing objects differently.
+    pub variants: &'a [RenderPipeline],
+    /// One buffer per binding of the pipeline's vertex input, bound in order from binding 0.
+    pub vertex_buffers: &'a [Buffer],
+    /// Drawn with `mesh.index_count` indices when present, otherwise `mesh.vertex_count` vertices are drawn in order.
+    pub index_buffer: Option<&'a Buffer>,
+    /// Region of the buffers above the mesh is drawn from.
+    pub mesh: MeshRange,
+    pub descriptor_set: DescriptorSet,
+    pub clear_color: [f32; 4],
+    /// What the camera sees, in world space, for scenes culling their objects with `geometry::cull`.
+    pub frustum: Frustum,
+    /// World space bounds of `mesh`, `None` when unknown, e.g. for vertex types without a position the renderer reads.
+    pub bounds: Option<BoundingSphere>,
+    /// Stepped before the frame is rendered, drawn by `MeshScene` over the mesh when present.
+    pub particles: Option<&'a ParticleSystem>,
+    /// `PipelineOptions::inverted_hull` pipeline and its push constants, drawn by `MeshScene` along with the mesh.
+    pub outline: Option<(&'a RenderPipeline, Outline)>,
+    /// Shadow map of the mesh, rendered before the scene pass begins.
+    pub shadows: Option<&'a ShadowPass>,
+    /// Cubemap of the mesh, rendered after the shadows and before the scene pass begins.
+    pub probe: Option<&'a ReflectionProbe>,
+    /// UI recorded after the scene, on top of it, skipped when the scene records secondary command buffers.
+    /// Its `FrameQueries` measure the frame.
+    pub overlay: Option<&'a Overlay>,
+    /// Measures the frame along with the overlay's queries, e.g. for `Vulcor::run_benchmark`.
+    pub timer: Option<&'a GpuTimer>,
+    /// Records the draws, given the resources above.
+    pub scene: &'a dyn Scene
+}
+
+/// Region of shared vertex and index buffers one mesh is drawn from, so that many meshes can be packed into the
+/// same buffers. The default covers nothing, set the counts of the mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshRange {
+    /// Byte offset of the mesh in every vertex buffer, e.g. of its sub-allocation. Meshes packed one after the other
+    /// in separate attribute streams can keep it at 0 and use `vertex_offset` instead, which scales with each stride.
+    pub vertex_buffer_offset: vk::DeviceSize,
+    /// Byte offset of the indices in the index buffer, a multiple of the index size.
+    pub index_buffer_offset: vk::DeviceSize,
+    /// First index read, counted from `index_buffer_offset`.
+    pub first_index: u32,
+    /// Added to every index before fetching vertices, or the first vertex of non-indexed draws.
+    pub vertex_offset: i32,
+    pub index_count: u32,
+    pub vertex_count: u32
+}
+
+impl MeshRange {
+    /// Whole buffers holding a single mesh.
+    pub fn whole(index_count: u32, vertex_count: u32) -> Self {
+        Self { index_count, vertex_count, ..Default::default() }
+    }
+
+    /// Mesh packed right after this one in the same buffers, bound at the same byte offsets and reached through
+    /// `first_index` and `vertex_offset` instead, so consecutive meshes draw without rebinding.
+    pub fn after(&self, index_count: u32, vertex_count: u32) -> Self {
+        Self {
+            first_index: self.first_index + self.index_count,
+            vertex_offset: self.vertex_offset + self.vertex_count as i32,
+            index_count,
+            vertex_count,
+            ..*self
+        }
+    }
+
+    /// Binds `vertex_buffers` from binding 0 and `index_buffer` at the offsets of the range, then draws it.
+    /// Indexed when there is an index buffer. Recorded inside a render pass, after the pipeline.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass with a pipeline bound, and the buffers must hold the range.
+    pub unsafe fn record_draw(&self, device: &Device, command_buffer: vk::CommandBuffer, vertex_buffers: &[Buffer], index_buffer: Option<&Buffer>) {
+        // One binding at a time rather than collecting the handles, so per-frame recording does not allocate
+        for (binding, buffer) in vertex_buffers.iter().enumerate() {
+            device.cmd_bind_vertex_buffers(command_buffer, binding as u32, &[buffer.instance], &[self.vertex_buffer_offset]);
+        }
+        match index_buffer {
+            Some(index_buffer) => {
+                device.cmd_bind_index_buffer(command_buffer, index_buffer.instance, self.index_buffer_offset, vk::IndexType::UINT16);
+                device.cmd_draw_indexed(command_buffer, self.index_count, 1, self.first_index, self.vertex_offset, 0);
+            },
+            None => device.cmd_draw(command_buffer, self.vertex_count, 1, self.vertex_offset.max(0) as u32, 0)
+        }
+    }
+}
+
+/// When the command buffers of the swapchain images are recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Recording {
+    /// Re-record the buffer of the acquired image every frame, so its content can change.
+    #[default]
+    PerFrame,
+    /// Record every buffer once and replay them, for fully static scenes.
+    PreRecorded
+}
 
 pub struct CmdPool {
-    pub instance: vk::CommandPool
+    pub instance: vk::CommandPool,
+    pub queue_family: u32
 }
 
 impl CmdPool {
     pub fn new(device: &GraphicsInterface, queue_family: u32) -> Result<Self> {
+        Self::create(device, queue_family, vk::CommandPoolCreateFlags::empty())
+    }
+
+    /// Pool whose command buffers can be reset and re-recorded individually, see `record_frame`.
+    pub fn resettable(device: &GraphicsInterface, queue_family: u32) -> Result<Self> {
+        Self::create(device, queue_family, vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+    }
+
+    fn create(device: &GraphicsInterface, queue_family: u32, flags: vk::CommandPoolCreateFlags) -> Result<Self> {
         let create_info = vk::CommandPoolCreateInfo::default()
-            .flags(vk::CommandPoolCreateFlags::empty())
+            .flags(flags)
             .queue_family_index(queue_family);
 
         let command_pool = unsafe { device.instance.create_command_pool(&create_info, None)? };
-        Ok(Self { instance: command_pool })
+        Ok(Self { instance: command_pool, queue_family })
     }
 
-    pub unsafe fn create_buffers(&self, device: &GraphicsInterface, render_pass: &vk::RenderPass, pipeline: &dyn VulkanPipeline, framebuffers: &Vec<vk::Framebuffer>, vertex_buffer: &Buffer, index_buffer: &Buffer, swapchain: &SwapchainData, descriptor_sets: &Vec<DescriptorSet>) -> Result<Vec<vk::CommandBuffer>> {
-        let count = framebuffers.len() as u32;
+    /// Skips the following draws when the 32-bit predicate at `offset` in `predicate` is zero.
+    /// The predicate buffer needs the `CONDITIONAL_RENDERING_EXT` usage. Without the extension the draws execute unconditionally.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, and `predicate` must outlive its execution.
+    pub unsafe fn begin_conditional_rendering(&self, device: &GraphicsInterface, command_buffer: vk::CommandBuffer, predicate: &Buffer, offset: vk::DeviceSize) {
+        if let Some(loader) = &device.conditional_rendering {
+            let begin_info = vk::ConditionalRenderingBeginInfoEXT::default()
+                .buffer(predicate.instance)
+                .offset(offset);
+            (loader.fp().cmd_begin_conditional_rendering_ext)(command_buffer, &begin_info);
+        }
+    }
+
+    /// Ends the draws skipped by `begin_conditional_rendering`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, after `begin_conditional_rendering`.
+    pub unsafe fn end_conditional_rendering(&self, device: &GraphicsInterface, command_buffer: vk::CommandBuffer) {
+        if let Some(loader) = &device.conditional_rendering {
+            (loader.fp().cmd_end_conditional_rendering_ext)(command_buffer);
+        }
+    }
+
+    /// Draws up to `max_draw_count` indexed commands from `commands`, the actual count being read from `count_buffer` on the GPU.
+    /// When a compute pass fills either buffer, `Graphics::buffer_memory_barrier` from `COMPUTE_SHADER` to `DRAW_INDIRECT`
+    /// with `INDIRECT_COMMAND_READ` must be recorded for both before the render pass begins.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass, with an indexed pipeline and index buffer bound. Both buffers must outlive its execution.
+    pub unsafe fn draw_indexed_indirect_count(&self, device: &GraphicsInterface, command_buffer: vk::CommandBuffer, commands: &Buffer, count_buffer: &Buffer, count_offset: vk::DeviceSize, max_draw_count: u32) -> Result<()> {
+        let stride = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        match &device.draw_indirect_count_extension {
+            Some(loader) => loader.cmd_draw_indexed_indirect_count(command_buffer, commands.instance, 0, count_buffer.instance, count_offset, max_draw_count, stride),
+            None if device.draw_indirect_count => device.instance.cmd_draw_indexed_indirect_count(command_buffer, commands.instance, 0, count_buffer.instance, count_offset, max_draw_count, stride),
+            None => return Err(anyhow!("Neither the `drawIndirectCount` device feature nor `VK_KHR_draw_indirect_count` is enabled."))
+        }
+        Ok(())
+    }
+
+    /// Allocates and records one command buffer per framebuffer, drawing what `scene` returns for each image index.
+    ///
+    /// # Safety
+    /// Everything `targets` and `scene` reference must stay alive while the buffers may execute.
+    pub unsafe fn create_buffers<'a>(&self, device: &GraphicsInterface, targets: &FrameTargets, scene: impl Fn(usize) -> FrameScene<'a>) -> Result<Vec<vk::CommandBuffer>> {
+        let count = targets.framebuffers.len() as u32;
         let allocate_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(self.instance)
             .level(vk::CommandBufferLevel::PRIMARY)
@@ -29,36 +207,164 @@ impl CmdPool {
 
         let buffers = device.instance.allocate_command_buffers(&allocate_info)?;
         for (i, command_buffer) in buffers.iter().enumerate() {
-            let inheritance = vk::CommandBufferInheritanceInfo::default();
-            let info = vk::CommandBufferBeginInfo::default()
-                .flags(vk::CommandBufferUsageFlags::empty())
-                .inheritance_info(&inheritance);
-            
-            let render_area = vk::Rect2D::default()
-                .offset(vk::Offset2D::default())
-                .extent(swapchain.config.extent);
-            let clear_color_value = vk::ClearValue {
-                color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] }
-            };
-            let clear_values = &[clear_color_value];
-            let begin_info = vk::RenderPassBeginInfo::default()
-                .render_pass(*render_pass)
-                .framebuffer(framebuffers[i])
-                .render_area(render_area)
-                .clear_values(clear_values);
-
-            // Setup commands
-            device.instance.begin_command_buffer(*command_buffer, &info)?;
-            device.instance.cmd_begin_render_pass(*command_buffer, &begin_info, vk::SubpassContents::INLINE);
-            device.instance.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.instance());
-            device.instance.cmd_bind_vertex_buffers(*command_buffer, 0, &[vertex_buffer.instance], &[0]);
-            device.instance.cmd_bind_index_buffer(*command_buffer, index_buffer.instance, 0, vk::IndexType::UINT16);
-            device.instance.cmd_bind_descriptor_sets(*command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout(), 0, &[descriptor_sets[i]], &[]);
-            device.instance.cmd_draw_indexed(*command_buffer, INDICES.len() as u32, 1, 0, 0, 0);
-            device.instance.cmd_end_render_pass(*command_buffer);
-            device.instance.end_command_buffer(*command_buffer)?;
+            self.record_frame(device, *command_buffer, i, targets, &scene(i))
+                .inspect_err(|_| device.instance.free_command_buffers(self.instance, &buffers))?;
         };
 
         Ok(buffers)
     }
-}
\ No newline at end of file
+
+    /// Re-records `existing` in place when there is one per framebuffer, which needs a `resettable` pool.
+    /// Otherwise, typically when the swapchain image count changed, they are freed and new buffers are allocated.
+    ///
+    /// # Safety
+    /// None of `existing` may be pending execution, and everything `targets` and `scene` reference must stay alive while the buffers may execute.
+    pub unsafe fn recreate_buffers<'a>(&self, device: &GraphicsInterface, existing: &[vk::CommandBuffer], targets: &FrameTargets, scene: impl Fn(usize) -> FrameScene<'a>) -> Result<Vec<vk::CommandBuffer>> {
+        if existing.len() != targets.framebuffers.len() {
+            if !existing.is_empty() {
+                device.instance.free_command_buffers(self.instance, existing);
+            }
+            return self.create_buffers(device, targets, scene);
+        }
+        for (i, command_buffer) in existing.iter().enumerate() {
+            self.record_frame(device, *command_buffer, i, targets, &scene(i))?;
+        }
+        Ok(existing.to_vec())
+    }
+
+    /// Records `scene` into the command buffer of `image_index`, discarding what it held.
+    /// A non-initial recording needs a `resettable` pool.
+    ///
+    /// # Safety
+    /// `command_buffer` must come from this pool and not be pending execution. Everything `targets` and `scene` reference must stay alive while it may execute.
+    pub unsafe fn record_frame(&self, device: &GraphicsInterface, command_buffer: vk::CommandBuffer, image_index: usize, targets: &FrameTargets, scene: &FrameScene) -> Result<()> {
+        let FrameTargets { render_pass, framebuffers, swapchain, blit, tone_map } = *targets;
+        let inheritance = vk::CommandBufferInheritanceInfo::default();
+        let info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::empty())
+            .inheritance_info(&inheritance);
+        
+        let swapchain_pass = *render_pass;
+        let tone_map = tone_map.filter(|_| blit.is_none());
+        let (render_pass, framebuffer, extent) = match (blit, tone_map) {
+            (Some(blit), _) => (&blit.targets[image_index].render_pass, blit.targets[image_index].framebuffer, blit.targets[image_index].extent),
+            (None, Some(tone_map)) => (&tone_map.render_pass, tone_map.framebuffer(image_index), swapchain.config.extent),
+            (None, None) => (render_pass, framebuffers[image_index], swapchain.config.extent)
+        };
+        let render_area = vk::Rect2D::default()
+            .offset(vk::Offset2D::default())
+            .extent(extent);
+        let clear_color_value = vk::ClearValue {
+            color: vk::ClearColorValue { float32: scene.clear_color }
+        };
+        let clear_depth_value = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 }
+        };
+        let scene_clear_values = [clear_color_value, clear_depth_value];
+        let tone_map_clear_values = ToneMapPass::clear_values(scene.clear_color);
+        let clear_values: &[vk::ClearValue] = match tone_map {
+            Some(_) => &tone_map_clear_values,
+            None => &scene_clear_values
+        };
+        let begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(*render_pass)
+            .framebuffer(framebuffer)
+            .render_area(render_area)
+            .clear_values(clear_values);
+
+        // Setup commands, beginning implicitly resets buffers of a `resettable` pool
+        device.instance.begin_command_buffer(command_buffer, &info)?;
+        let contents = scene.scene.contents();
+        let queries = scene.overlay.map(|overlay| overlay.queries(image_index));
+        // A query active across secondary command buffers would need `inheritedQueries`
+        let statistics = queries.and_then(|q| q.statistics.as_ref()).filter(|_| contents == vk::SubpassContents::INLINE);
+        if let Some(queries) = queries {
+            queries.timer.reset(&device.instance, command_buffer);
+            queries.timer.begin(&device.instance, command_buffer);
+        }
+        if let Some(timer) = scene.timer {
+            timer.reset(&device.instance, command_buffer);
+            timer.begin(&device.instance, command_buffer);
+        }
+        if let Some(statistics) = statistics {
+            statistics.reset(&device.instance, command_buffer);
+            statistics.begin(&device.instance, command_buffer);
+        }
+        if let Some(shadows) = scene.shadows {
+            shadows.record(&device.instance, command_buffer, scene.descriptor_set, scene.mesh, scene.vertex_buffers, scene.index_buffer);
+        }
+        if let Some(probe) = scene.probe {
+            probe.record(&device.instance, command_buffer, scene);
+        }
+        device.instance.cmd_begin_render_pass(command_buffer, &begin_info, contents);
+        let frame = FrameContext { device, image_index, extent, resources: scene, render_pass: *render_pass, framebuffer, contents };
+        scene.scene.record(command_buffer, &frame);
+        if let Some(overlay) = scene.overlay.filter(|_| contents == vk::SubpassContents::INLINE) {
+            overlay.record(&device.instance, command_buffer, image_index);
+        }
+        if let Some(tone_map) = tone_map {
+            tone_map.record_tone_map(&device.instance, command_buffer);
+        }
+        device.instance.cmd_end_render_pass(command_buffer);
+        if let Some(statistics) = statistics {
+            statistics.end(&device.instance, command_buffer);
+        }
+        if let Some(blit) = blit {
+            Self::record_present_blit(device, command_buffer, &blit.passes[image_index], swapchain_pass, framebuffers[image_index], swapchain.config.extent);
+        }
+        if let Some(queries) = queries {
+            queries.timer.end(&device.instance, command_buffer);
+        }
+        if let Some(timer) = scene.timer {
+            timer.end(&device.instance, command_buffer);
+        }
+        device.instance.end_command_buffer(command_buffer)?;
+        Ok(())
+    }
+
+    /// Draws `pass` into the swapchain image in the swapchain `render_pass`, which clears the letterbox bars to black
+    /// and leaves the image ready to present.
+    unsafe fn record_present_blit(device: &GraphicsInterface, command_buffer: vk::CommandBuffer, pass: &FullscreenPass, render_pass: vk::RenderPass, framebuffer: vk::Framebuffer, extent: vk::Extent2D) {
+        let clear_values = [
+            vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } },
+            vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }
+        ];
+        let begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D::default().extent(extent))
+            .clear_values(&clear_values);
+        device.instance.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE);
+        pass.record_draw(&device.instance, command_buffer);
+        device.instance.cmd_end_render_pass(command_buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_meshes_follow_each_other() {
+        // A cube followed by a quad in one buffer, vertices from byte 256 and 16 bit indices from byte 1024
+        let cube = MeshRange { vertex_buffer_offset: 256, index_buffer_offset: 1024, ..MeshRange::whole(36, 24) };
+        let quad = cube.after(6, 4);
+        assert_eq!(quad, MeshRange {
+            vertex_buffer_offset: 256,
+            index_buffer_offset: 1024,
+            first_index: 36,
+            vertex_offset: 24,
+            index_count: 6,
+            vertex_count: 4
+        });
+        let next = quad.after(3, 3);
+        assert_eq!((next.first_index, next.vertex_offset), (42, 28));
+    }
+
+    #[test]
+    fn whole_range_starts_at_zero() {
+        let range = MeshRange::whole(6, 4);
+        assert_eq!((range.vertex_buffer_offset, range.index_buffer_offset, range.first_index, range.vertex_offset), (0, 0, 0, 0));
+        assert_eq!((range.index_count, range.vertex_count), (6, 4));
+    }
+}
@@ -1,9 +1,28 @@
 use std::{error::Error, ffi::{c_char, CStr}};
-use ash::{ext::debug_utils, khr::surface, vk, Entry, Instance};
+use ash::{ext::{debug_utils, swapchain_colorspace}, khr::{get_physical_device_properties2, portability_enumeration, surface}, vk, Entry, Instance};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use winit::window::Window;
 
-use crate::core::debug;
+use crate::core::debug::{self, ValidationFeatures};
+
+/// When the instance enumerates portability drivers such as MoltenVK, which the loader hides otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Portability {
+    /// Whenever the loader offers `VK_KHR_portability_enumeration`, on any platform,
+    /// e.g. MoltenVK or the Vulkan SDK portability layer outside of Apple platforms.
+    #[default]
+    Detect,
+    /// Only on macOS and iOS, decided at compile time without querying the loader.
+    AppleOnly
+}
+
+impl Portability {
+    fn enabled(self, entry: &Entry) -> bool {
+        match self {
+            Self::Detect => VulkanContext::instance_extension_supported(entry, portability_enumeration::NAME),
+            Self::AppleOnly => cfg!(any(target_os = "macos", target_os = "ios"))
+        }
+    }
+}
 
 pub struct VulkanContext {
     pub entry: Entry,
@@ -13,7 +32,9 @@ pub struct VulkanContext {
 }
 
 impl VulkanContext {
-    pub fn new(named: &CStr, window: &Window) -> Result<Self, Box<dyn Error>> {
+    /// Creates the instance and a surface for `window`, which can be any window providing raw handles,
+    /// e.g. one owned by an embedding application rather than created by `App`.
+    pub fn new<W: HasWindowHandle + HasDisplayHandle + ?Sized>(named: &CStr, window: &W, validation: &ValidationFeatures, portability: Portability) -> Result<Self, Box<dyn Error>> {
         let entry = Entry::linked();
         let app_info = vk::ApplicationInfo::default()
             .application_name(named)
@@ -27,15 +48,22 @@ impl VulkanContext {
             .unwrap()
             .to_vec();
         
-        extension_names.push(debug_utils::NAME.as_ptr());
-        #[cfg(any(target_os = "macos", target_os = "ios"))]
-        {
-            extension_names.push(ash::khr::portability_enumeration::NAME.as_ptr());
+        // Only the validation messenger uses debug utils, release builds then run where the extension is missing
+        if debug::VALIDATION_ENABLED {
+            extension_names.push(debug_utils::NAME.as_ptr());
+        }
+        // Exposes the extended (wide gamut, HDR, display native) color spaces when the loader offers them
+        if Self::instance_extension_supported(&entry, swapchain_colorspace::NAME) {
+            extension_names.push(swapchain_colorspace::NAME.as_ptr());
+        }
+        let portable = portability.enabled(&entry);
+        if portable {
+            extension_names.push(portability_enumeration::NAME.as_ptr());
             // Enabling this extension is a requirement when using `VK_KHR_portability_subset`
-            extension_names.push(ash::khr::get_physical_device_properties2::NAME.as_ptr());
+            extension_names.push(get_physical_device_properties2::NAME.as_ptr());
         }
 
-        let flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
+        let flags = if portable {
             vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
         } else {
             vk::InstanceCreateFlags::default()
@@ -53,25 +81,56 @@ impl VulkanContext {
             .collect();
 
         let mut debug_info = debug::create_debug_info();
+        let enabled_validation = validation.enabled();
+        let mut validation_features = vk::ValidationFeaturesEXT::default()
+            .enabled_validation_features(&enabled_validation);
         if debug::VALIDATION_ENABLED {
             if debug::validation_layers_supported(&entry) {
                 info = info.enabled_layer_names(&layers_names_raw)
                     .push_next(&mut debug_info);
+                if !enabled_validation.is_empty() {
+                    info = info.push_next(&mut validation_features);
+                }
             } else {
                 panic!("Validation layers not supported")
             }
         }
 
         let instance = unsafe { entry.create_instance(&info, None)? };
+        let surface = Self::create_surface(&entry, &instance, window)?;
+        let surface_loader = surface::Instance::new(&entry, &instance);
+        Ok(Self{entry, instance, surface, surface_loader})
+    }
+
+    fn create_surface<W: HasWindowHandle + HasDisplayHandle + ?Sized>(entry: &Entry, instance: &Instance, window: &W) -> Result<vk::SurfaceKHR, Box<dyn Error>> {
         let surface = unsafe { ash_window::create_surface(
-            &entry, 
-            &instance, 
-            display_handle, 
+            entry, 
+            instance, 
+            window.display_handle()?.as_raw(), 
             window.window_handle()?.as_raw(), 
             None
         )? };
-        let surface_loader = surface::Instance::new(&entry, &instance);
-        Ok(Self{entry, instance, surface, surface_loader})
+        Ok(surface)
+    }
+
+    /// New surface for `window`, to replace a lost one with `replace_surface`. The current surface stays valid.
+    pub fn create_replacement_surface<W: HasWindowHandle + HasDisplayHandle + ?Sized>(&self, window: &W) -> Result<vk::SurfaceKHR, Box<dyn Error>> {
+        Self::create_surface(&self.entry, &self.instance, window)
+    }
+
+    /// Destroys the current surface and uses `surface` from now on.
+    ///
+    /// # Safety
+    /// Every swapchain created for the current surface must have been destroyed, and `surface` must belong to this instance.
+    pub unsafe fn replace_surface(&mut self, surface: vk::SurfaceKHR) {
+        self.surface_loader.destroy_surface(self.surface, None);
+        self.surface = surface;
+    }
+
+    pub fn instance_extension_supported(entry: &Entry, name: &CStr) -> bool {
+        let properties = unsafe { entry.enumerate_instance_extension_properties(None).unwrap_or_default() };
+        properties.iter()
+            .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
     }
 
     pub fn cleanup(&self) {
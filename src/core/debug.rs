@@ -1,11 +1,38 @@
 use ash::{Entry, ext::debug_utils, vk};
-use std::{ffi::CStr, os::raw::c_void};
+use std::{ffi::CStr, os::raw::c_void, sync::{Arc, Mutex}};
 
 use crate::core::context::VulkanContext;
 
 pub const VALIDATION_ENABLED: bool = cfg!(debug_assertions);
 pub const VALIDATION_LAYERS: [&'static CStr; 1] = [c"VK_LAYER_KHRONOS_validation"];
 
+/// Heavier validation modes of the Khronos layer, on top of the standard checks. Ignored when validation is disabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidationFeatures {
+    pub best_practices: bool,
+    /// Reports hazards between commands, semaphores and fences that `RenderSync` may miss.
+    pub synchronization: bool,
+    /// Instruments shaders to catch out of bounds descriptor accesses, at a large performance cost.
+    pub gpu_assisted: bool
+}
+
+impl ValidationFeatures {
+    pub fn enabled(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut enabled = vec![];
+        if self.best_practices {
+            enabled.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+        if self.synchronization {
+            enabled.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+        }
+        if self.gpu_assisted {
+            enabled.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            enabled.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+        enabled
+    }
+}
+
 pub fn validation_layers_supported(entry: &Entry) -> bool {
     let mut found: bool = true;
     for required in VALIDATION_LAYERS.iter() {
@@ -27,10 +54,85 @@ pub fn validation_layers_supported(entry: &Entry) -> bool {
     return found
 }
 
-pub fn setup_debug_messenger(context: &VulkanContext) -> Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)> {
+/// Validation message of warning severity or above, as captured into a `ValidationLog`.
+#[derive(Clone, Debug)]
+pub struct ValidationMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub types: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub message: String
+}
+
+/// Warnings and errors reported by the validation layer, collected by the debug callback through its user data
+/// so that tests can assert on them instead of reading the log.
+#[derive(Debug, Default)]
+pub struct ValidationLog {
+    messages: Mutex<Vec<ValidationMessage>>
+}
+
+impl ValidationLog {
+    pub fn messages(&self) -> Vec<ValidationMessage> {
+        self.messages.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn clear(&self) {
+        self.messages.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn push(&self, message: ValidationMessage) {
+        self.messages.lock().unwrap_or_else(|e| e.into_inner()).push(message);
+    }
+}
+
+/// Panics listing every warning and error `log` captured, e.g. at the end of a test rendering a few frames.
+#[cfg(feature = "validation-harness")]
+pub fn assert_no_validation_errors(log: &ValidationLog) {
+    let messages = log.messages();
+    if !messages.is_empty() {
+        let listed = messages.iter()
+            .map(|m| format!("[{:?}] ({:?}) {}", m.severity, m.types, m.message))
+            .collect::<Vec<_>>()
+            .join("\n");
+        panic!("Validation reported {} message(s):\n{}", messages.len(), listed);
+    }
+}
+
+/// What the debug callback reads through its user data.
+struct CallbackState {
+    log: Option<Arc<ValidationLog>>,
+    /// Logs known best practices findings at `info` with a remediation hint, see `best_practices_hint`.
+    best_practices: bool
+}
+
+/// Messenger of the validation layer. The state its callback reads is owned here, so it outlives the messenger.
+pub struct DebugMessenger {
+    pub loader: debug_utils::Instance,
+    pub instance: vk::DebugUtilsMessengerEXT,
+    state: Box<CallbackState>
+}
+
+impl DebugMessenger {
+    /// Captured warnings and errors, only collected with the `validation-harness` feature.
+    pub fn log(&self) -> Option<&Arc<ValidationLog>> {
+        self.state.log.as_ref()
+    }
+
+    /// Destroys the messenger.
+    ///
+    /// # Safety
+    /// Must be called once, before the instance is destroyed.
+    pub unsafe fn cleanup(&self) {
+        self.loader.destroy_debug_utils_messenger(self.instance, None);
+    }
+}
+
+/// `validation` must be the features the instance was created with.
+pub fn setup_debug_messenger(context: &VulkanContext, validation: &ValidationFeatures) -> Option<DebugMessenger> {
     if !VALIDATION_ENABLED { return None; }
 
-    let create_info = create_debug_info();
+    let log = cfg!(feature = "validation-harness").then(|| Arc::new(ValidationLog::default()));
+    let state = Box::new(CallbackState { log, best_practices: validation.best_practices });
+    let create_info = create_debug_info()
+        .user_data(&*state as *const CallbackState as *mut c_void);
     let debug_utils = debug_utils::Instance::new(&context.entry, &context.instance);
     let debug_utils_messenger = unsafe {
         debug_utils
@@ -38,7 +140,26 @@ pub fn setup_debug_messenger(context: &VulkanContext) -> Option<(debug_utils::In
             .unwrap()
     };
 
-    Some((debug_utils, debug_utils_messenger))
+    Some(DebugMessenger { loader: debug_utils, instance: debug_utils_messenger, state })
+}
+
+/// What to change for best practices findings the crate's own patterns are known to trigger, keyed by message ID name.
+fn best_practices_hint(id: &str) -> Option<&'static str> {
+    match id {
+        "BestPractices-vkAllocateMemory-small-allocation" | "BestPractices-vkBindMemory-small-dedicated-allocation" =>
+            Some("sub-allocate small resources from a `MemoryAllocator`, e.g. with `Buffer::new_in`"),
+        "BestPractices-vkCreateCommandPool-command-buffer-reset" =>
+            Some("create the pool without `RESET_COMMAND_BUFFER` and reset the whole pool instead of individual buffers"),
+        "BestPractices-vkBeginCommandBuffer-one-time-submit" =>
+            Some("begin command buffers submitted once with `ONE_TIME_SUBMIT`, as `begin_command_once` does"),
+        "BestPractices-vkCreateFramebuffer-attachment-should-be-transient" =>
+            Some("create attachments never stored with `Image::new_transient`"),
+        "BestPractices-vkCreateSwapchainKHR-suboptimal-swapchain-image-count" =>
+            Some("leave `SwapchainOptions::minimal_image_count` off to request one image above the minimum"),
+        "BestPractices-vkCmdClearAttachments-clear-after-load" =>
+            Some("clear with the render pass `CLEAR` load op rather than `cmd_clear_attachments`"),
+        _ => None
+    }
 }
 
 pub fn create_debug_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
@@ -62,11 +183,25 @@ extern "system" fn debug_callback(
     severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     types: vk::DebugUtilsMessageTypeFlagsEXT,
     data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
-    _: *mut c_void,
+    user_data: *mut c_void,
 ) -> vk::Bool32 {
     let data = unsafe { *data };
     let message = unsafe { CStr::from_ptr(data.p_message) }.to_string_lossy();
-    if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+    // Set by `setup_debug_messenger` to the state of the messenger, null for the instance creation messages
+    let state = unsafe { (user_data as *const CallbackState).as_ref() };
+    if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
+        if let Some(log) = state.and_then(|s| s.log.as_ref()) {
+            log.push(ValidationMessage { severity, types, message: message.to_string() });
+        }
+    }
+    let hint = match (state, data.p_message_id_name.is_null()) {
+        (Some(state), false) if state.best_practices && severity < vk::DebugUtilsMessageSeverityFlagsEXT::ERROR =>
+            best_practices_hint(&unsafe { CStr::from_ptr(data.p_message_id_name) }.to_string_lossy()),
+        _ => None
+    };
+    if let Some(hint) = hint {
+        log::info!("({:?}) {} Hint: {}.", types, message, hint);
+    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
         log::error!("({:?}) {}", types, message);
     } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
         log::warn!("({:?}) {}", types, message);
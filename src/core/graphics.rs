@@ -1,35 +1,183 @@
 use anyhow::{anyhow, Result};
 use ash::vk::{self, SubmitInfo};
 
-use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, logical_device::GraphicsInterface, physical_device::{GraphicsHardware, QueueFamilyIndices}}, pipeline::render_pipeline::{Vertex, VERTICES}, resources::image::Image};
+use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, limits::DeviceLimits, logical_device::{DeviceFeatures, GraphicsInterface}, physical_device::{GraphicsHardware, HardwarePreferences, PhysicalDeviceError, QueueFamilyIndices}}, pipeline::render_pipeline::{Vertex, VERTICES}, resources::{allocator::MemoryUsage, image::Image}};
 
 
+/// Index of the queue presentation happens on within the presentation family. Only one queue per family is created.
+pub const PRESENT_QUEUE_INDEX: u32 = 0;
+
+/// Queue a submission goes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueRole {
+    Graphics,
+    /// The async compute queue when there is one, the graphics queue otherwise.
+    Compute
+}
+
 pub struct Graphics {
     pub physical: GraphicsHardware,
     pub logical: GraphicsInterface,
-    pub queue: vk::Queue
+    pub queue: vk::Queue,
+    /// Queue of `queue_family.compute`, when async compute was requested and the device has a separate compute family.
+    pub compute_queue: Option<vk::Queue>,
+    /// Queue `PRESENT_QUEUE_INDEX` of `queue_family.presentation`, fetched once at device creation.
+    /// The same handle as `queue` when rendering and presentation share a family.
+    pub present_queue: vk::Queue,
+    pub queue_family: QueueFamilyIndices,
+    /// Device memory allocated by the crate's resources, see `MemoryUsage::report`.
+    pub memory_usage: MemoryUsage
 }
 
 impl Graphics {
-    pub fn new(context: &VulkanContext) -> Result<Self> {
-        let physical = GraphicsHardware::new(context)?;
-        let queue_family = QueueFamilyIndices::new(context, &physical.instance)?;
-        let logical = GraphicsInterface::new(context, &physical, &queue_family)?;
+    pub fn new(context: &VulkanContext, preferences: &HardwarePreferences, features: &DeviceFeatures) -> Result<Self> {
+        let physical = GraphicsHardware::new(context, preferences)?;
+        let queue_family = QueueFamilyIndices::new(context, &physical.instance, context.surface, preferences.separate_present_family, preferences.async_compute)?;
+        if queue_family.separate_present() {
+            log::info!("Presenting from queue family {} while rendering on family {}", queue_family.presentation, queue_family.graphics);
+        }
+        match queue_family.compute {
+            Some(compute) => log::info!("Running async compute on queue family {}", compute),
+            None if preferences.async_compute => log::info!("No separate compute queue family, compute runs on the graphics queue"),
+            None => ()
+        }
+        Self::validate_present_queue(context, &physical, queue_family.presentation)?;
+        let logical = GraphicsInterface::new(context, &physical, &queue_family, features)?;
         let graphics_queue = unsafe { logical.instance.get_device_queue(queue_family.graphics, 0) };
+        let compute_queue = queue_family.compute.map(|family| unsafe { logical.instance.get_device_queue(family, 0) });
+        let present_queue = unsafe { logical.instance.get_device_queue(queue_family.presentation, PRESENT_QUEUE_INDEX) };
+        log::info!("Presenting on queue {} of family {}", PRESENT_QUEUE_INDEX, queue_family.presentation);
         
-        Ok(Self { physical: physical, logical: logical, queue: graphics_queue })
+        Ok(Self { physical: physical, logical: logical, queue: graphics_queue, compute_queue, present_queue, queue_family, memory_usage: MemoryUsage::default() })
+    }
+
+    /// Checks that the chosen family can present to the window surface, so that presentation never goes to a queue
+    /// the driver rejects. Support is per family, hence it holds for every queue of it.
+    fn validate_present_queue(context: &VulkanContext, physical: &GraphicsHardware, family: u32) -> Result<()> {
+        let supported = unsafe { context.surface_loader.get_physical_device_surface_support(physical.instance, family, context.surface)? };
+        match supported {
+            true => Ok(()),
+            false => Err(anyhow!(PhysicalDeviceError::NoPresentationSupport))
+        }
+    }
+
+    /// Whether compute work submitted with `QueueRole::Compute` can overlap with rendering.
+    pub fn has_async_compute(&self) -> bool {
+        self.compute_queue.is_some()
     }
 
-    pub unsafe fn copy_buffer(&self, src: &vk::Buffer, dst: &vk::Buffer, size: vk::DeviceSize, cmd_pool: &CmdPool) -> Result<()> {
+    /// Family compute command pools must be created for, matching `QueueRole::Compute`.
+    pub fn compute_family(&self) -> u32 {
+        self.queue_family.compute.unwrap_or(self.queue_family.graphics)
+    }
+
+    pub fn queue(&self, role: QueueRole) -> vk::Queue {
+        match role {
+            QueueRole::Graphics => self.queue,
+            QueueRole::Compute => self.compute_queue.unwrap_or(self.queue)
+        }
+    }
+
+    /// Limits layouts and pipelines are validated against.
+    pub fn limits(&self) -> &DeviceLimits {
+        &self.logical.limits
+    }
+
+    /// Copies `src` into `dst` on the queue of `cmd_pool`. When `dst_pool` belongs to another queue family,
+    /// ownership of `dst` is released on the copying queue and acquired on the destination queue.
+    ///
+    /// # Safety
+    /// `src` and `dst` must be buffers of this device holding at least `size` bytes, and the GPU must not be using `dst`.
+    pub unsafe fn copy_buffer(&self, src: &vk::Buffer, dst: &vk::Buffer, size: vk::DeviceSize, cmd_pool: &CmdPool, dst_pool: &CmdPool) -> Result<()> {
+        let transfer_ownership = cmd_pool.queue_family != dst_pool.queue_family;
         let command_buffer = self.begin_command_once(cmd_pool)?; // Begin
         let regions = vk::BufferCopy::default().size(size);
         self.logical.instance.cmd_copy_buffer(command_buffer, *src, *dst, &[regions]);
+        if transfer_ownership {
+            let release = Self::ownership_barrier(dst, cmd_pool.queue_family, dst_pool.queue_family)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+            self.logical.instance.cmd_pipeline_barrier(
+                command_buffer, 
+                vk::PipelineStageFlags::TRANSFER, 
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE, 
+                vk::DependencyFlags::empty(), 
+                &[] as &[vk::MemoryBarrier], 
+                &[release], 
+                &[] as &[vk::ImageMemoryBarrier]
+            );
+        }
         self.end_command_once(cmd_pool, command_buffer)?;
+
+        if transfer_ownership {
+            let command_buffer = self.begin_command_once(dst_pool)?;
+            let acquire = Self::ownership_barrier(dst, cmd_pool.queue_family, dst_pool.queue_family)
+                .dst_access_mask(vk::AccessFlags::MEMORY_READ);
+            self.logical.instance.cmd_pipeline_barrier(
+                command_buffer, 
+                vk::PipelineStageFlags::TOP_OF_PIPE, 
+                vk::PipelineStageFlags::ALL_COMMANDS, 
+                vk::DependencyFlags::empty(), 
+                &[] as &[vk::MemoryBarrier], 
+                &[acquire], 
+                &[] as &[vk::ImageMemoryBarrier]
+            );
+            self.end_command_once(dst_pool, command_buffer)?;
+        }
         Ok(())
     }
 
-    pub fn queue_submit(&self, submits: &Vec<SubmitInfo>, fence: vk::Fence) -> Result<()> {
-        unsafe { self.logical.instance.queue_submit(self.queue, submits, fence)? };
+    /// Makes writes to `buffer` from `src_stage` visible to `dst_stage`, e.g. a compute shader writing vertices
+    /// (`COMPUTE_SHADER`, `SHADER_WRITE`) read by the next draw (`VERTEX_INPUT`, `VERTEX_ATTRIBUTE_READ`), or indirect
+    /// commands read with `DRAW_INDIRECT` and `INDIRECT_COMMAND_READ`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass.
+    pub unsafe fn buffer_memory_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags
+    ) {
+        let barrier = vk::BufferMemoryBarrier::default()
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access);
+        self.logical.instance.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[barrier],
+            &[] as &[vk::ImageMemoryBarrier]
+        );
+    }
+
+    fn ownership_barrier<'a>(buffer: &vk::Buffer, src_family: u32, dst_family: u32) -> vk::BufferMemoryBarrier<'a> {
+        vk::BufferMemoryBarrier::default()
+            .buffer(*buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .src_queue_family_index(src_family)
+            .dst_queue_family_index(dst_family)
+    }
+
+    pub fn queue_submit(&self, submits: &[SubmitInfo], fence: vk::Fence) -> Result<()> {
+        self.submit(QueueRole::Graphics, submits, fence)
+    }
+
+    /// Submits to the queue of `role`. Work on the async compute queue runs concurrently with rendering, so results
+    /// consumed by a draw must signal a semaphore the graphics submission waits on, and exclusive resources shared
+    /// between both families need an ownership transfer. Without async compute both roles share the graphics queue.
+    pub fn submit(&self, role: QueueRole, submits: &[SubmitInfo], fence: vk::Fence) -> Result<()> {
+        unsafe { self.logical.instance.queue_submit(self.queue(role), submits, fence)? };
         Ok(())
     }
 
@@ -51,9 +199,11 @@ impl Graphics {
         let command_buffers = &[command_buffer];
         let submit_info = vk::SubmitInfo::default()
             .command_buffers(command_buffers);
-        self.queue_submit(&vec![submit_info], vk::Fence::null())?;
+        // One-time commands run on the queue of the family their pool was created for
+        let queue = unsafe { self.logical.instance.get_device_queue(cmd_pool.queue_family, 0) };
         unsafe {
-            self.logical.instance.queue_wait_idle(self.queue)?;
+            self.logical.instance.queue_submit(queue, &[submit_info], vk::Fence::null())?;
+            self.logical.instance.queue_wait_idle(queue)?;
             self.logical.instance.free_command_buffers(cmd_pool.instance, command_buffers);
         }
         Ok(())
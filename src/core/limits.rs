@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+use crate::error::VulcorError;
+
+/// Device limits checked before creating layouts, so exceeding one fails with `VulcorError::ExceedsDeviceLimit`
+/// rather than an opaque driver error.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceLimits {
+    pub max_push_constants_size: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_per_stage_descriptor_uniform_buffers: u32,
+    pub max_per_stage_descriptor_storage_buffers: u32,
+    pub max_per_stage_descriptor_samplers: u32,
+    pub max_per_stage_descriptor_sampled_images: u32,
+    pub max_descriptor_set_uniform_buffers: u32,
+    pub max_descriptor_set_storage_buffers: u32,
+    pub max_descriptor_set_samplers: u32,
+    pub max_descriptor_set_sampled_images: u32,
+    pub max_color_attachments: u32,
+    /// Smallest and largest width `wideLines` allows, `[1.0, 1.0]` in practice without it.
+    pub line_width_range: [f32; 2],
+    /// Step between supported widths, 0 when any width in the range is supported.
+    pub line_width_granularity: f32,
+    /// Smallest and largest `gl_PointSize` honoured, `largePoints` is needed for anything but 1.0.
+    pub point_size_range: [f32; 2],
+    pub point_size_granularity: f32
+}
+
+impl From<&vk::PhysicalDeviceLimits> for DeviceLimits {
+    fn from(limits: &vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            max_push_constants_size: limits.max_push_constants_size,
+            max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+            max_per_stage_descriptor_uniform_buffers: limits.max_per_stage_descriptor_uniform_buffers,
+            max_per_stage_descriptor_storage_buffers: limits.max_per_stage_descriptor_storage_buffers,
+            max_per_stage_descriptor_samplers: limits.max_per_stage_descriptor_samplers,
+            max_per_stage_descriptor_sampled_images: limits.max_per_stage_descriptor_sampled_images,
+            max_descriptor_set_uniform_buffers: limits.max_descriptor_set_uniform_buffers,
+            max_descriptor_set_storage_buffers: limits.max_descriptor_set_storage_buffers,
+            max_descriptor_set_samplers: limits.max_descriptor_set_samplers,
+            max_descriptor_set_sampled_images: limits.max_descriptor_set_sampled_images,
+            max_color_attachments: limits.max_color_attachments,
+            line_width_range: limits.line_width_range,
+            line_width_granularity: limits.line_width_granularity,
+            point_size_range: limits.point_size_range,
+            point_size_granularity: limits.point_size_granularity
+        }
+    }
+}
+
+impl DeviceLimits {
+    /// `ranges` must fit in `maxPushConstantsSize`, measured up to the end of the furthest range.
+    pub fn validate_push_constants(&self, ranges: &[vk::PushConstantRange]) -> Result<()> {
+        let requested = ranges.iter().map(|r| r.offset + r.size).max().unwrap_or(0);
+        Self::check("maxPushConstantsSize", requested, self.max_push_constants_size)
+    }
+
+    pub fn validate_color_attachments(&self, count: u32) -> Result<()> {
+        Self::check("maxColorAttachments", count, self.max_color_attachments)
+    }
+
+    pub fn validate_set_layout_count(&self, count: u32) -> Result<()> {
+        Self::check("maxBoundDescriptorSets", count, self.max_bound_descriptor_sets)
+    }
+
+    /// Counts the descriptors of a set layout, both per shader stage and for the whole set.
+    pub fn validate_bindings(&self, bindings: &[vk::DescriptorSetLayoutBinding]) -> Result<()> {
+        let stages = [
+            vk::ShaderStageFlags::VERTEX,
+            vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            vk::ShaderStageFlags::GEOMETRY,
+            vk::ShaderStageFlags::FRAGMENT,
+            vk::ShaderStageFlags::COMPUTE
+        ];
+        let count = |types: &[vk::DescriptorType], stage: Option<vk::ShaderStageFlags>| bindings.iter()
+            .filter(|b| types.contains(&b.descriptor_type))
+            .filter(|b| stage.is_none_or(|stage| b.stage_flags.contains(stage)))
+            .map(|b| b.descriptor_count)
+            .sum::<u32>();
+        let uniform = [vk::DescriptorType::UNIFORM_BUFFER, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC];
+        let storage = [vk::DescriptorType::STORAGE_BUFFER, vk::DescriptorType::STORAGE_BUFFER_DYNAMIC];
+        let samplers = [vk::DescriptorType::SAMPLER, vk::DescriptorType::COMBINED_IMAGE_SAMPLER];
+        let sampled = [vk::DescriptorType::SAMPLED_IMAGE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::DescriptorType::UNIFORM_TEXEL_BUFFER];
+        for stage in stages.map(Some) {
+            Self::check("maxPerStageDescriptorUniformBuffers", count(&uniform, stage), self.max_per_stage_descriptor_uniform_buffers)?;
+            Self::check("maxPerStageDescriptorStorageBuffers", count(&storage, stage), self.max_per_stage_descriptor_storage_buffers)?;
+            Self::check("maxPerStageDescriptorSamplers", count(&samplers, stage), self.max_per_stage_descriptor_samplers)?;
+            Self::check("maxPerStageDescriptorSampledImages", count(&sampled, stage), self.max_per_stage_descriptor_sampled_images)?;
+        }
+        Self::check("maxDescriptorSetUniformBuffers", count(&uniform, None), self.max_descriptor_set_uniform_buffers)?;
+        Self::check("maxDescriptorSetStorageBuffers", count(&storage, None), self.max_descriptor_set_storage_buffers)?;
+        Self::check("maxDescriptorSetSamplers", count(&samplers, None), self.max_descriptor_set_samplers)?;
+        Self::check("maxDescriptorSetSampledImages", count(&sampled, None), self.max_descriptor_set_sampled_images)
+    }
+
+    /// Clamps `width` to `lineWidthRange`, warning when it falls outside, then rounds it to the nearest
+    /// `lineWidthGranularity` step from the lower bound. The width must be positive and finite.
+    pub fn snap_line_width(&self, width: f32) -> f32 {
+        Self::snap("Line width", width, self.line_width_range, self.line_width_granularity)
+    }
+
+    /// Same as `snap_line_width` for `pointSizeRange` and `pointSizeGranularity`.
+    pub fn snap_point_size(&self, size: f32) -> f32 {
+        Self::snap("Point size", size, self.point_size_range, self.point_size_granularity)
+    }
+
+    fn snap(name: &str, value: f32, [min, max]: [f32; 2], granularity: f32) -> f32 {
+        let clamped = value.clamp(min, max);
+        if clamped != value {
+            log::warn!("{} {} is outside of the supported range [{}, {}], using {}", name, value, min, max, clamped);
+        }
+        if granularity <= 0.0 {
+            return clamped;
+        }
+        let steps = ((clamped - min) / granularity).round();
+        (min + steps * granularity).min(max)
+    }
+
+    fn check(limit: &'static str, requested: u32, max: u32) -> Result<()> {
+        if requested > max {
+            return Err(anyhow!(VulcorError::ExceedsDeviceLimit { limit, requested: requested as u64, max: max as u64 }));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> DeviceLimits {
+        DeviceLimits {
+            max_push_constants_size: 128,
+            max_bound_descriptor_sets: 4,
+            max_per_stage_descriptor_uniform_buffers: 12,
+            max_per_stage_descriptor_storage_buffers: 4,
+            max_per_stage_descriptor_samplers: 16,
+            max_per_stage_descriptor_sampled_images: 16,
+            max_descriptor_set_uniform_buffers: 72,
+            max_descriptor_set_storage_buffers: 24,
+            max_descriptor_set_samplers: 96,
+            max_descriptor_set_sampled_images: 96,
+            max_color_attachments: 4,
+            line_width_range: [1.0, 8.0],
+            line_width_granularity: 0.5,
+            point_size_range: [1.0, 64.0],
+            point_size_granularity: 0.125
+        }
+    }
+
+    #[test]
+    fn line_width_is_clamped_to_range() {
+        let limits = limits();
+        assert_eq!(limits.snap_line_width(0.25), 1.0);
+        assert_eq!(limits.snap_line_width(20.0), 8.0);
+        assert_eq!(limits.snap_line_width(8.0), 8.0);
+    }
+
+    #[test]
+    fn line_width_is_rounded_to_granularity() {
+        let limits = limits();
+        assert_eq!(limits.snap_line_width(2.2), 2.0);
+        assert_eq!(limits.snap_line_width(2.3), 2.5);
+
+        let any_width = DeviceLimits { line_width_granularity: 0.0, ..limits };
+        assert_eq!(any_width.snap_line_width(2.3), 2.3);
+    }
+
+    #[test]
+    fn point_size_is_clamped_to_range() {
+        let limits = limits();
+        assert_eq!(limits.snap_point_size(0.5), 1.0);
+        assert_eq!(limits.snap_point_size(100.0), 64.0);
+    }
+
+    #[test]
+    fn point_size_is_rounded_to_granularity() {
+        let limits = limits();
+        assert_eq!(limits.snap_point_size(3.3), 3.25);
+
+        // Rounding up never leaves the range
+        let uneven = DeviceLimits { point_size_range: [1.0, 2.3], point_size_granularity: 0.5, ..limits };
+        assert_eq!(uneven.snap_point_size(2.3), 2.3);
+    }
+
+    fn exceeded_limit(result: Result<()>) -> Option<(&'static str, u64, u64)> {
+        match result.err()?.downcast_ref::<VulcorError>()? {
+            VulcorError::ExceedsDeviceLimit { limit, requested, max } => Some((*limit, *requested, *max)),
+            _ => None
+        }
+    }
+
+    fn binding(index: u32, descriptor_type: vk::DescriptorType, count: u32, stages: vk::ShaderStageFlags) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(index)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(count)
+            .stage_flags(stages)
+    }
+
+    #[test]
+    fn push_constants_are_measured_to_furthest_range() {
+        let limits = limits();
+        let range = |offset, size| vk::PushConstantRange::default().offset(offset).size(size);
+        assert!(limits.validate_push_constants(&[range(0, 64), range(64, 64)]).is_ok());
+        assert_eq!(exceeded_limit(limits.validate_push_constants(&[range(0, 16), range(120, 16)])), Some(("maxPushConstantsSize", 136, 128)));
+    }
+
+    #[test]
+    fn counts_are_checked_against_limits() {
+        let limits = limits();
+        assert!(limits.validate_set_layout_count(4).is_ok());
+        assert_eq!(exceeded_limit(limits.validate_set_layout_count(5)), Some(("maxBoundDescriptorSets", 5, 4)));
+        assert_eq!(exceeded_limit(limits.validate_color_attachments(8)), Some(("maxColorAttachments", 8, 4)));
+    }
+
+    #[test]
+    fn bindings_are_counted_per_stage() {
+        let limits = limits();
+        let fits = [
+            binding(0, vk::DescriptorType::STORAGE_BUFFER, 4, vk::ShaderStageFlags::VERTEX),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER, 4, vk::ShaderStageFlags::FRAGMENT)
+        ];
+        assert!(limits.validate_bindings(&fits).is_ok());
+
+        let exceeds = [
+            binding(0, vk::DescriptorType::STORAGE_BUFFER, 4, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1, vk::ShaderStageFlags::FRAGMENT)
+        ];
+        assert_eq!(exceeded_limit(limits.validate_bindings(&exceeds)), Some(("maxPerStageDescriptorStorageBuffers", 5, 4)));
+    }
+
+    #[test]
+    fn bindings_are_counted_per_set() {
+        let limits = DeviceLimits { max_descriptor_set_storage_buffers: 6, ..limits() };
+        let bindings = [
+            binding(0, vk::DescriptorType::STORAGE_BUFFER, 4, vk::ShaderStageFlags::VERTEX),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER, 4, vk::ShaderStageFlags::FRAGMENT)
+        ];
+        assert_eq!(exceeded_limit(limits.validate_bindings(&bindings)), Some(("maxDescriptorSetStorageBuffers", 8, 6)));
+    }
+}
@@ -1,29 +1,240 @@
-use ash::{vk, Device};
-use anyhow::{Result};
+use ash::{ext::{conditional_rendering, robustness2}, khr::{draw_indirect_count, image_format_list, maintenance1, maintenance2, portability_subset, swapchain_mutable_format}, vk, Device};
+use anyhow::{anyhow, Result};
 
-use crate::{core::{context::VulkanContext, physical_device::GraphicsHardware}, QueueFamilyIndices};
+use crate::core::{context::VulkanContext, limits::DeviceLimits, physical_device::{GraphicsHardware, QueueFamilyIndices}};
+
+/// Optional features and extensions to request when creating the logical device.
+/// Each is only enabled when the hardware supports it, so these can only narrow what gets enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceFeatures {
+    pub tessellation_shader: bool,
+    pub geometry_shader: bool,
+    pub depth_bias_clamp: bool,
+    pub depth_bounds: bool,
+    pub depth_clamp: bool,
+    pub multi_draw_indirect: bool,
+    pub pipeline_statistics_query: bool,
+    pub sampler_anisotropy: bool,
+    pub logic_op: bool,
+    pub independent_blend: bool,
+    pub wide_lines: bool,
+    pub large_points: bool,
+    pub sample_rate_shading: bool,
+    pub draw_indirect_count: bool,
+    pub null_descriptor: bool,
+    pub conditional_rendering: bool,
+    /// `VK_KHR_swapchain_mutable_format`, letting swapchain images be viewed in other compatible formats.
+    pub swapchain_mutable_format: bool,
+    /// Scheduling priority of the queue created for each role, between 0 and 1.
+    pub queue_priorities: QueuePriorities
+}
+
+/// Relative priority of the queues, a hint some drivers use to favour graphics over background work.
+/// A family serving several roles gets the highest priority among them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueuePriorities {
+    pub graphics: f32,
+    pub presentation: f32,
+    pub transfer: f32,
+    /// Only used when an async compute queue was acquired.
+    pub compute: f32
+}
+
+impl Default for QueuePriorities {
+    fn default() -> Self {
+        Self { graphics: 1.0, presentation: 1.0, transfer: 1.0, compute: 1.0 }
+    }
+}
+
+impl QueuePriorities {
+    pub fn validate(&self) -> Result<()> {
+        for (role, priority) in [("graphics", self.graphics), ("presentation", self.presentation), ("transfer", self.transfer), ("compute", self.compute)] {
+            if !(0.0..=1.0).contains(&priority) {
+                return Err(anyhow!("The {} queue priority {} is outside of [0, 1].", role, priority));
+            }
+        }
+        Ok(())
+    }
+
+    /// Priority of the queue of `family`, the highest among the roles it serves.
+    fn of(&self, family: u32, queue_family: &QueueFamilyIndices) -> f32 {
+        [(queue_family.graphics, self.graphics), (queue_family.presentation, self.presentation), (queue_family.transfer, self.transfer)]
+            .into_iter()
+            .chain(queue_family.compute.map(|compute| (compute, self.compute)))
+            .filter(|(index, _)| *index == family)
+            .map(|(_, priority)| priority)
+            .fold(0.0, f32::max)
+    }
+}
+
+impl Default for DeviceFeatures {
+    fn default() -> Self {
+        Self {
+            tessellation_shader: true,
+            geometry_shader: true,
+            depth_bias_clamp: true,
+            depth_bounds: true,
+            depth_clamp: true,
+            multi_draw_indirect: true,
+            pipeline_statistics_query: true,
+            sampler_anisotropy: true,
+            logic_op: true,
+            independent_blend: true,
+            wide_lines: true,
+            large_points: true,
+            sample_rate_shading: true,
+            draw_indirect_count: true,
+            null_descriptor: true,
+            conditional_rendering: true,
+            swapchain_mutable_format: true,
+            queue_priorities: QueuePriorities::default()
+        }
+    }
+}
 
 pub struct GraphicsInterface {
-    pub instance: Device
+    pub instance: Device,
+    pub features: vk::PhysicalDeviceFeatures,
+    /// Loaded only when `VK_EXT_conditional_rendering` is available on the device.
+    pub conditional_rendering: Option<conditional_rendering::Device>,
+    /// Whether the draw count can be read from a buffer, through the Vulkan 1.2 `drawIndirectCount` feature or the extension.
+    pub draw_indirect_count: bool,
+    /// Loaded only when `VK_KHR_draw_indirect_count` stands in for the missing core feature.
+    pub draw_indirect_count_extension: Option<draw_indirect_count::Device>,
+    /// Whether `nullDescriptor` from `VK_EXT_robustness2` was enabled, letting unbound descriptors read as zero.
+    pub null_descriptor: bool,
+    /// `maxSamplerAnisotropy` of the device, `None` when `samplerAnisotropy` was not enabled.
+    pub max_sampler_anisotropy: Option<f32>,
+    /// Whether viewports may have a negative height to flip y, core since Vulkan 1.1 and from `VK_KHR_maintenance1` before.
+    pub negative_viewport_height: bool,
+    /// Whether `VK_KHR_swapchain_mutable_format` was enabled, see `SwapchainOptions::view_formats`.
+    pub swapchain_mutable_format: bool,
+    pub limits: DeviceLimits
 }
 
 impl GraphicsInterface {
-    pub fn new(context: &VulkanContext, physical_device: &GraphicsHardware, queue_family: &QueueFamilyIndices) -> Result<GraphicsInterface> {
-        let queue_priority = &[1.0];
-        let queue_create_infos = queue_family.unique_values().iter().map(|family_index|
+    pub fn new(context: &VulkanContext, physical_device: &GraphicsHardware, queue_family: &QueueFamilyIndices, requested: &DeviceFeatures) -> Result<GraphicsInterface> {
+        requested.queue_priorities.validate()?;
+        let families = queue_family.unique_values().into_iter().collect::<Vec<_>>();
+        let priorities = families.iter()
+            .map(|family| [requested.queue_priorities.of(*family, queue_family)])
+            .collect::<Vec<_>>();
+        let queue_create_infos = families.iter().zip(priorities.iter()).map(|(family_index, priority)|
             vk::DeviceQueueCreateInfo::default()
                 .queue_family_index(*family_index)
-                .queue_priorities(queue_priority)
+                .queue_priorities(priority)
         ).collect::<Vec<_>>();
 
-        let features = vk::PhysicalDeviceFeatures::default();
-        let extensions = GraphicsHardware::required_extensions().into_iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
-        let device_create_info: vk::DeviceCreateInfo<'_> = vk::DeviceCreateInfo::default()
+        let features = Self::enabled_features(physical_device, requested);
+        let supported_vulkan12 = Self::supported_vulkan12_features(context, physical_device);
+        let core_draw_indirect_count = requested.draw_indirect_count && supported_vulkan12.draw_indirect_count == vk::TRUE;
+        let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::default()
+            .draw_indirect_count(core_draw_indirect_count);
+        // Devices below 1.2 may still expose the commands through the extension
+        let draw_indirect_count_extension = requested.draw_indirect_count && !core_draw_indirect_count
+            && GraphicsHardware::extension_supported(context, &physical_device.instance, draw_indirect_count::NAME);
+        let null_descriptor = requested.null_descriptor && Self::supports_null_descriptor(context, physical_device);
+        let mut robustness2_features = vk::PhysicalDeviceRobustness2FeaturesEXT::default()
+            .null_descriptor(true);
+        let conditional_rendering_supported = requested.conditional_rendering && GraphicsHardware::extension_supported(context, &physical_device.instance, conditional_rendering::NAME);
+        let mut extensions = GraphicsHardware::required_extensions().into_iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+        if conditional_rendering_supported {
+            extensions.push(conditional_rendering::NAME.as_ptr());
+        }
+        // Portability drivers enumerated outside of Apple platforms must have their subset enabled as well
+        if !cfg!(any(target_os = "macos", target_os = "ios")) && GraphicsHardware::extension_supported(context, &physical_device.instance, portability_subset::NAME) {
+            extensions.push(portability_subset::NAME.as_ptr());
+        }
+        if null_descriptor {
+            extensions.push(robustness2::NAME.as_ptr());
+        }
+        if draw_indirect_count_extension {
+            extensions.push(draw_indirect_count::NAME.as_ptr());
+        }
+        // The instance asks for 1.3, so the device version is what gets negotiated
+        let core_maintenance1 = physical_device.properties().api_version >= vk::API_VERSION_1_1;
+        let maintenance1_extension = !core_maintenance1 && GraphicsHardware::extension_supported(context, &physical_device.instance, maintenance1::NAME);
+        if maintenance1_extension {
+            extensions.push(maintenance1::NAME.as_ptr());
+        }
+        let negative_viewport_height = core_maintenance1 || maintenance1_extension;
+        let swapchain_mutable_format = requested.swapchain_mutable_format && Self::supports_swapchain_mutable_format(context, physical_device);
+        if swapchain_mutable_format {
+            extensions.push(swapchain_mutable_format::NAME.as_ptr());
+            // Its dependencies only became core in Vulkan 1.2
+            if !Self::supports_vulkan12(physical_device) {
+                extensions.push(image_format_list::NAME.as_ptr());
+                extensions.push(maintenance2::NAME.as_ptr());
+            }
+        }
+        let mut device_create_info: vk::DeviceCreateInfo<'_> = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
             .enabled_features(&features)
             .enabled_extension_names(&extensions);
+        if Self::supports_vulkan12(physical_device) {
+            device_create_info = device_create_info.push_next(&mut vulkan12_features);
+        }
+        if null_descriptor {
+            device_create_info = device_create_info.push_next(&mut robustness2_features);
+        }
 
         let device = unsafe { context.instance.create_device(physical_device.instance, &device_create_info, None)? };
-        Ok(Self { instance: device })
+        let conditional_rendering = conditional_rendering_supported.then(|| conditional_rendering::Device::new(&context.instance, &device));
+        let draw_indirect_count = core_draw_indirect_count || draw_indirect_count_extension;
+        let draw_indirect_count_extension = draw_indirect_count_extension.then(|| draw_indirect_count::Device::new(&context.instance, &device));
+        let properties = physical_device.properties();
+        let max_sampler_anisotropy = (features.sampler_anisotropy == vk::TRUE).then_some(properties.limits.max_sampler_anisotropy);
+        let limits = DeviceLimits::from(&properties.limits);
+        Ok(Self { instance: device, features, conditional_rendering, draw_indirect_count, draw_indirect_count_extension, null_descriptor, max_sampler_anisotropy, negative_viewport_height, swapchain_mutable_format, limits })
+    }
+
+    /// Optional features are only requested when the hardware reports them, so pipelines can check what was actually enabled.
+    fn enabled_features(physical_device: &GraphicsHardware, requested: &DeviceFeatures) -> vk::PhysicalDeviceFeatures {
+        let supported = physical_device.features();
+        vk::PhysicalDeviceFeatures::default()
+            .tessellation_shader(requested.tessellation_shader && supported.tessellation_shader == vk::TRUE)
+            .geometry_shader(requested.geometry_shader && supported.geometry_shader == vk::TRUE)
+            .depth_bias_clamp(requested.depth_bias_clamp && supported.depth_bias_clamp == vk::TRUE)
+            .depth_bounds(requested.depth_bounds && supported.depth_bounds == vk::TRUE)
+            .depth_clamp(requested.depth_clamp && supported.depth_clamp == vk::TRUE)
+            .multi_draw_indirect(requested.multi_draw_indirect && supported.multi_draw_indirect == vk::TRUE)
+            .pipeline_statistics_query(requested.pipeline_statistics_query && supported.pipeline_statistics_query == vk::TRUE)
+            .sampler_anisotropy(requested.sampler_anisotropy && supported.sampler_anisotropy == vk::TRUE)
+            .logic_op(requested.logic_op && supported.logic_op == vk::TRUE)
+            .independent_blend(requested.independent_blend && supported.independent_blend == vk::TRUE)
+            .wide_lines(requested.wide_lines && supported.wide_lines == vk::TRUE)
+            .large_points(requested.large_points && supported.large_points == vk::TRUE)
+            .sample_rate_shading(requested.sample_rate_shading && supported.sample_rate_shading == vk::TRUE)
+    }
+
+    fn supports_vulkan12(physical_device: &GraphicsHardware) -> bool {
+        physical_device.properties().api_version >= vk::API_VERSION_1_2
+    }
+
+    /// Before Vulkan 1.2 the extension also needs `VK_KHR_image_format_list` and `VK_KHR_maintenance2`.
+    fn supports_swapchain_mutable_format(context: &VulkanContext, physical_device: &GraphicsHardware) -> bool {
+        let supported = |name: &std::ffi::CStr| GraphicsHardware::extension_supported(context, &physical_device.instance, name);
+        supported(swapchain_mutable_format::NAME)
+            && (Self::supports_vulkan12(physical_device) || (supported(image_format_list::NAME) && supported(maintenance2::NAME)))
+    }
+
+    fn supports_null_descriptor(context: &VulkanContext, physical_device: &GraphicsHardware) -> bool {
+        if !GraphicsHardware::extension_supported(context, &physical_device.instance, robustness2::NAME) {
+            return false;
+        }
+        let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut robustness2);
+        unsafe { context.instance.get_physical_device_features2(physical_device.instance, &mut features2) };
+        robustness2.null_descriptor == vk::TRUE
+    }
+
+    /// Vulkan 1.2 features can only be chained when the device itself supports 1.2.
+    fn supported_vulkan12_features(context: &VulkanContext, physical_device: &GraphicsHardware) -> vk::PhysicalDeviceVulkan12Features<'static> {
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+        if Self::supports_vulkan12(physical_device) {
+            let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan12);
+            unsafe { context.instance.get_physical_device_features2(physical_device.instance, &mut features2) };
+        }
+        vulkan12
     }
 }
\ No newline at end of file
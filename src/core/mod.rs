@@ -2,4 +2,5 @@ pub mod context;
 pub mod debug;
 pub mod physical_device;
 pub mod logical_device;
-pub mod graphics;
\ No newline at end of file
+pub mod graphics;
+pub mod limits;
\ No newline at end of file
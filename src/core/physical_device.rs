@@ -1,77 +1,172 @@
 use anyhow::{anyhow, Result};
-use std::{collections::{BTreeMap, HashSet}, ffi::CStr, fmt::{self, Display, Formatter}};
+use std::{cmp::Ordering, collections::HashSet, ffi::CStr, fmt::{self, Display, Formatter}};
 use ash::vk;
 
 use crate::{core::context::VulkanContext, swapchain::SwapchainSupport};
 
+/// Image dimensions above this do not rank a device any higher, which keeps the limit below the type preference.
+const MAX_IMAGE_DIMENSION_SCORE: u32 = 1 << 16;
+const GEOMETRY_SHADER_SCORE: i32 = 2000;
+/// Outweighs geometry shaders and the largest image dimension together, so `HardwarePreferences::prefer_integrated` decides.
+const PREFERRED_TYPE_SCORE: i32 = 2 * MAX_IMAGE_DIMENSION_SCORE as i32 + GEOMETRY_SHADER_SCORE;
 
 pub struct QueueFamilyIndices {
     pub graphics: u32,
     pub presentation: u32,
-    pub transfer: u32
+    pub transfer: u32,
+    /// Family other than the graphics one running compute work concurrently with rendering,
+    /// `None` when it was not requested or the device has none, compute then runs on the graphics queue.
+    pub compute: Option<u32>
 }
 
 impl QueueFamilyIndices {
-    pub fn new(context: &VulkanContext, physical_device: &vk::PhysicalDevice) -> Result<Self> {
+    /// Families of `physical_device` for rendering to `surface`. Present support is specific to a surface, so other
+    /// surfaces have to be checked again, e.g. with `get_physical_device_surface_support` on `presentation`.
+    /// With `separate_present`, a family able to present but distinct from the graphics one is picked when the device has one.
+    /// With `async_compute`, a compute family distinct from the graphics one is looked for, favouring a dedicated one without graphics.
+    pub fn new(context: &VulkanContext, physical_device: &vk::PhysicalDevice, surface: vk::SurfaceKHR, separate_present: bool, async_compute: bool) -> Result<Self> {
         let properties = unsafe { context.instance.get_physical_device_queue_family_properties(*physical_device) };
+        let flags = properties.iter().map(|p| p.queue_flags).collect::<Vec<_>>();
+        let present_support = (0..properties.len() as u32)
+            .map(|index| unsafe { context.surface_loader.get_physical_device_surface_support(*physical_device, index, surface) })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::select(&flags, &present_support, separate_present, async_compute)
+    }
 
-        // TODO Unify both graphics and presentation queues
-        let graphics = properties
-            .iter()
-            .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
-            .map(|i| i as u32);
-
-        let transfer = properties
-            .iter()
-            .position(|p| p.queue_flags.contains(vk::QueueFlags::TRANSFER))
-            .map_or( graphics, |i| Some(i as u32));
-
-        let mut presentation = None;
-        for (index, _) in properties.iter().enumerate() {
-            let supported = unsafe { context.surface_loader.get_physical_device_surface_support(*physical_device, index as u32, context.surface)? };
-            if supported {
-                presentation = Some(index as u32);
-                break;
-            }
-        }
+    /// Picks the families from their capabilities alone, `present_support[i]` telling whether family `i` can present.
+    /// A graphics family that can also present is preferred, so rendering and presentation share a queue
+    /// unless `separate_present` asks otherwise.
+    fn select(flags: &[vk::QueueFlags], present_support: &[bool], separate_present: bool, async_compute: bool) -> Result<Self> {
+        let families = || (0..flags.len() as u32).zip(flags.iter().copied());
+        let presents = |index: u32| present_support.get(index as usize).copied().unwrap_or(false);
+        let graphics_families = families()
+            .filter(|(_, f)| f.contains(vk::QueueFlags::GRAPHICS))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let graphics = graphics_families.iter()
+            .find(|i| presents(**i))
+            .or(graphics_families.first())
+            .copied();
+
+        let transfer = families()
+            .find(|(_, f)| f.contains(vk::QueueFlags::TRANSFER))
+            .map_or(graphics, |(i, _)| Some(i));
+
+        let compute_families = families()
+            .filter(|(i, f)| f.contains(vk::QueueFlags::COMPUTE) && Some(*i) != graphics)
+            .collect::<Vec<_>>();
+        let compute = match async_compute {
+            true => compute_families.iter()
+                .find(|(_, f)| !f.contains(vk::QueueFlags::GRAPHICS))
+                .or(compute_families.first())
+                .map(|(i, _)| *i),
+            false => None
+        };
+
+        let presenting = families().map(|(i, _)| i).filter(|i| presents(*i)).collect::<Vec<_>>();
+        let presentation = match separate_present {
+            true => presenting.iter().find(|i| Some(**i) != graphics).or(presenting.first()).copied(),
+            false => graphics.filter(|g| presents(*g)).or(presenting.first().copied())
+        };
         
-        if let (Some(graphics), Some(presentation), Some(transfer)) = (graphics, presentation, transfer) {
-            Ok(Self { graphics, presentation, transfer })
-        } else {
-            Err(anyhow!(PhysicalDeviceError::NoSuitableQueueFamily))
+        match (graphics, presentation, transfer) {
+            (Some(graphics), Some(presentation), Some(transfer)) => Ok(Self { graphics, presentation, transfer, compute }),
+            (_, None, _) => Err(anyhow!(PhysicalDeviceError::NoPresentationSupport)),
+            _ => Err(anyhow!(PhysicalDeviceError::NoSuitableQueueFamily))
         }
     }
 
+    /// Whether presentation happens on another family than rendering. Swapchain images are then created with
+    /// `CONCURRENT` sharing between both families, so they need no ownership transfer but may be slower to access.
+    pub fn separate_present(&self) -> bool {
+        self.graphics != self.presentation
+    }
+
     pub fn unique_values(&self) -> HashSet<u32> {
-        return HashSet::from([self.graphics, self.presentation, self.transfer]);
+        let mut values = HashSet::from([self.graphics, self.presentation, self.transfer]);
+        values.extend(self.compute);
+        return values;
     }
 }
 
 #[derive(Debug)]
 pub enum PhysicalDeviceError {
-    NoSuitableDevice,
-    NoSuitableQueueFamily
+    /// The loader enumerated no device at all, usually missing or broken drivers rather than unmet requirements.
+    NoVulkanDevices,
+    /// Devices were found but each missed a requirement, listed as device name and reason.
+    NoSuitableDevice { rejected: Vec<(String, String)> },
+    NoSuitableQueueFamily,
+    /// No queue family of the device can present to the window surface.
+    NoPresentationSupport
 }
 
 impl Display for PhysicalDeviceError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Self::NoSuitableDevice => write!(f, "No suitable physical devices found."),
+            Self::NoVulkanDevices => write!(f, "No Vulkan device found, make sure a GPU driver with Vulkan support is installed."),
+            Self::NoSuitableDevice { rejected } => {
+                write!(f, "No physical device meets the requirements:")?;
+                for (name, reason) in rejected {
+                    write!(f, " [{}] {}.", name, reason)?;
+                }
+                Ok(())
+            },
             Self::NoSuitableQueueFamily => write!(f, "No suitable queue family found on the device."),
+            Self::NoPresentationSupport => write!(f, "No queue family can present to the window surface."),
         }
     }
 }
 
 impl std::error::Error for PhysicalDeviceError {}
 
+/// Preferences that influence which physical device gets selected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HardwarePreferences {
+    /// Favour integrated GPUs over discrete ones to save power, e.g. on laptops running on battery.
+    pub prefer_integrated: bool,
+    /// Present from a queue family other than the graphics one when the device allows it. Most devices present
+    /// from their graphics family, so this exercises the cross-family submission path under validation.
+    pub separate_present_family: bool,
+    /// Accept `CPU` devices such as lavapipe or SwiftShader when they meet the minimum requirements, e.g. on CI
+    /// or headless machines. Hardware devices are still preferred when both are available.
+    pub allow_software: bool,
+    /// Acquire a queue from a compute family other than the graphics one, so compute work submitted there
+    /// overlaps with rendering. Ignored on devices without such a family, see `Graphics::has_async_compute`.
+    pub async_compute: bool
+}
+
+/// Selected physical device along with its properties, queried once as they never change for a given device.
 pub struct GraphicsHardware {
-    pub instance: vk::PhysicalDevice
+    pub instance: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
+    features: vk::PhysicalDeviceFeatures,
+    memory_properties: vk::PhysicalDeviceMemoryProperties
 }
 
 impl GraphicsHardware {
-    pub fn new(context: &VulkanContext) -> Result<Self> {
-        let physical_device = Self::select_physical_device(&context)?;
-        Ok(Self { instance: physical_device })
+    pub fn new(context: &VulkanContext, preferences: &HardwarePreferences) -> Result<Self> {
+        let physical_device = Self::select_physical_device(&context, preferences)?;
+        let (properties, features, memory_properties) = unsafe {(
+            context.instance.get_physical_device_properties(physical_device),
+            context.instance.get_physical_device_features(physical_device),
+            context.instance.get_physical_device_memory_properties(physical_device)
+        )};
+        Ok(Self { instance: physical_device, properties, features, memory_properties })
+    }
+
+    /// Name, type, API version and limits of the device.
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    /// Every feature the device supports, not only those enabled on the logical device.
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// Memory types and heaps, e.g. to pick a memory type index for an allocation.
+    pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
     }
 
     pub fn required_extensions() -> Vec<&'static CStr> {
@@ -83,55 +178,231 @@ impl GraphicsHardware {
         return extensions
     }
 
-    fn select_physical_device(context: &VulkanContext) -> Result<vk::PhysicalDevice> {
+    /// Picks the best ranked suitable device, see `Candidate::rank` for how equal scores are settled.
+    fn select_physical_device(context: &VulkanContext, preferences: &HardwarePreferences) -> Result<vk::PhysicalDevice> {
         let devices = unsafe { context.instance.enumerate_physical_devices()? };
-        let mut candidates: BTreeMap<i32, vk::PhysicalDevice> = BTreeMap::new();
+        if devices.is_empty() {
+            return Err(anyhow!(PhysicalDeviceError::NoVulkanDevices));
+        }
+        let mut candidates = vec![];
+        let mut rejected = vec![];
 
         for physical_device in devices {
             let swapchain_support = SwapchainSupport::new(context, &physical_device)?;
-            let score = Self::device_suitability_score(context, &physical_device, &swapchain_support);
             let properties = unsafe { context.instance.get_physical_device_properties(physical_device) };
-            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
-            println!("Physical device [{}] => {}", name.to_string_lossy(), score.to_string());
-            candidates.insert(score, physical_device);
+            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy().into_owned();
+            match Self::device_suitability_score(context, &physical_device, &swapchain_support, preferences) {
+                Ok(score) => {
+                    println!("Physical device [{}] => {}", name, score);
+                    candidates.push(Candidate {
+                        device: physical_device,
+                        score,
+                        name,
+                        vendor_id: properties.vendor_id,
+                        device_id: properties.device_id
+                    });
+                },
+                Err(reason) => {
+                    println!("Physical device [{}] => unsuitable: {}", name, reason);
+                    rejected.push((name, reason));
+                }
+            }
         }
 
-        if let Some((&score, &physical_device)) = candidates.first_key_value() {
-            if score > 0 {
-                Ok(physical_device)
-            } else {
-                Err(anyhow!(PhysicalDeviceError::NoSuitableDevice))
-            }
-        } else {
-            Err(anyhow!(PhysicalDeviceError::NoSuitableDevice))
+        let Some(physical_device) = candidates.iter().min_by(|a, b| Candidate::rank(a, b)).map(|c| c.device) else {
+            return Err(anyhow!(PhysicalDeviceError::NoSuitableDevice { rejected }));
+        };
+        let properties = unsafe { context.instance.get_physical_device_properties(physical_device) };
+        if Self::is_software(&properties) {
+            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+            log::warn!("Selected software device [{}], rendering will be slow", name.to_string_lossy());
         }
+        Ok(physical_device)
     }
 
-        /// Assigns an increasing score based on the available features, or 0 when geometry shaders are not supported.
-    fn device_suitability_score(context: &VulkanContext, physical_device: &vk::PhysicalDevice, swapchain: &SwapchainSupport) -> i32 {
-        let queue_family = QueueFamilyIndices::new(context, physical_device);
-        if queue_family.is_err() { return 0; }
-        if !Self::device_supports_extensions(&context, physical_device) { return 0; }
-    
-        if swapchain.formats.is_empty() || swapchain.present_modes.is_empty() { return 0; }
-    
+    /// Assigns an increasing score to devices meeting every requirement, see `hardware_score`.
+    /// Devices missing a requirement are rejected with the reason, so that it can be reported when none is left.
+    fn device_suitability_score(context: &VulkanContext, physical_device: &vk::PhysicalDevice, swapchain: &SwapchainSupport, preferences: &HardwarePreferences) -> std::result::Result<i32, String> {
+        QueueFamilyIndices::new(context, physical_device, context.surface, preferences.separate_present_family, preferences.async_compute)
+            .map_err(|e| e.to_string().trim_end_matches('.').to_owned())?;
+        let missing = Self::missing_extensions(context, physical_device);
+        if !missing.is_empty() {
+            let names = missing.iter().map(|name| name.to_string_lossy()).collect::<Vec<_>>();
+            return Err(format!("Missing the {} extension(s)", names.join(", ")));
+        }
+
+        if swapchain.formats.is_empty() { return Err("No surface format".to_owned()); }
+        if swapchain.present_modes.is_empty() { return Err("No present mode".to_owned()); }
+
         let properties = unsafe { context.instance.get_physical_device_properties(*physical_device) };
+        if Self::is_software(&properties) {
+            // Only the minimum requirements above apply, any hardware device ranks higher
+            return if preferences.allow_software { Ok(1) } else { Err("Software device, see `HardwarePreferences::allow_software`".to_owned()) };
+        }
         let features = unsafe { context.instance.get_physical_device_features(*physical_device) };
+        Ok(Self::hardware_score(&properties, &features, preferences))
+    }
+
+    /// Ranks hardware devices by their type first, the preferred one scoring above any other combination of terms,
+    /// then favours devices that support geometry shaders and finally those allowing larger 2D images.
+    fn hardware_score(properties: &vk::PhysicalDeviceProperties, features: &vk::PhysicalDeviceFeatures, preferences: &HardwarePreferences) -> i32 {
         let mut score: i32 = 0;
-        if features.geometry_shader == vk::FALSE { score += 2000; }
-        if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU { score += 1000; }
+        let preferred_type = if preferences.prefer_integrated { vk::PhysicalDeviceType::INTEGRATED_GPU } else { vk::PhysicalDeviceType::DISCRETE_GPU };
+        if properties.device_type == preferred_type { score += PREFERRED_TYPE_SCORE; }
+        if features.geometry_shader == vk::TRUE { score += GEOMETRY_SHADER_SCORE; }
+        score + properties.limits.max_image_dimension2_d.min(MAX_IMAGE_DIMENSION_SCORE) as i32
+    }
+
+    /// Rasterizes on the CPU, e.g. lavapipe or SwiftShader.
+    fn is_software(properties: &vk::PhysicalDeviceProperties) -> bool {
+        properties.device_type == vk::PhysicalDeviceType::CPU
+    }
 
-        score += properties.limits.max_image_dimension2_d as i32;
-        return score;
+    pub fn extension_supported(context: &VulkanContext, physical_device: &vk::PhysicalDevice, name: &CStr) -> bool {
+        let properties = unsafe { context.instance.enumerate_device_extension_properties(*physical_device).unwrap_or_default() };
+        properties.iter()
+            .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
     }
 
-    fn device_supports_extensions(context: &VulkanContext, physical_device: &vk::PhysicalDevice) -> bool {
-        let required: HashSet<&CStr> = Self::required_extensions().iter().map(|x| *x).collect::<HashSet<_>>();
-        let properties = unsafe { context.instance.enumerate_device_extension_properties(*physical_device).unwrap() };
+    /// Required extensions the device does not support, in the order of `required_extensions`.
+    fn missing_extensions(context: &VulkanContext, physical_device: &vk::PhysicalDevice) -> Vec<&'static CStr> {
+        let properties = unsafe { context.instance.enumerate_device_extension_properties(*physical_device).unwrap_or_default() };
         let available = properties.iter()
             .map(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) })
             .collect::<HashSet<_>>();
 
-        return required.intersection(&available).collect::<HashSet<_>>().len() == required.len();
+        Self::required_extensions().into_iter().filter(|name| !available.contains(name)).collect()
+    }
+}
+
+/// Suitable device scored by `PhysicalDevice::device_suitability_score`.
+struct Candidate {
+    device: vk::PhysicalDevice,
+    score: i32,
+    name: String,
+    vendor_id: u32,
+    device_id: u32
+}
+
+impl Candidate {
+    /// Orders the preferred candidate first: the highest score, then on a tie the smallest name, vendor ID and device ID.
+    /// Identical devices, e.g. two of the same GPU, keep the enumeration order, so the first one enumerated wins.
+    fn rank(a: &Candidate, b: &Candidate) -> Ordering {
+        b.score.cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then(a.vendor_id.cmp(&b.vendor_id))
+            .then(a.device_id.cmp(&b.device_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPHICS: vk::QueueFlags = vk::QueueFlags::from_raw(vk::QueueFlags::GRAPHICS.as_raw() | vk::QueueFlags::COMPUTE.as_raw() | vk::QueueFlags::TRANSFER.as_raw());
+    const COMPUTE: vk::QueueFlags = vk::QueueFlags::from_raw(vk::QueueFlags::COMPUTE.as_raw() | vk::QueueFlags::TRANSFER.as_raw());
+
+    #[test]
+    fn prefers_graphics_family_that_presents() {
+        let families = QueueFamilyIndices::select(&[GRAPHICS, GRAPHICS], &[false, true], false, false).unwrap();
+        assert_eq!(families.graphics, 1);
+        assert_eq!(families.presentation, 1);
+        assert_eq!(families.compute, None);
+    }
+
+    #[test]
+    fn separate_present_picks_another_family() {
+        let families = QueueFamilyIndices::select(&[GRAPHICS, COMPUTE], &[true, true], true, false).unwrap();
+        assert_eq!(families.graphics, 0);
+        assert_eq!(families.presentation, 1);
+
+        // Falls back to the graphics family when it is the only one presenting
+        let families = QueueFamilyIndices::select(&[GRAPHICS, COMPUTE], &[true, false], true, false).unwrap();
+        assert_eq!(families.presentation, 0);
+    }
+
+    #[test]
+    fn async_compute_prefers_dedicated_family() {
+        let families = QueueFamilyIndices::select(&[GRAPHICS, GRAPHICS, COMPUTE], &[true, false, false], false, true).unwrap();
+        assert_eq!(families.graphics, 0);
+        assert_eq!(families.compute, Some(2));
+
+        // A second graphics family is used when the device has no compute-only one
+        let families = QueueFamilyIndices::select(&[GRAPHICS, GRAPHICS], &[true, false], false, true).unwrap();
+        assert_eq!(families.compute, Some(1));
+
+        let families = QueueFamilyIndices::select(&[GRAPHICS], &[true], false, true).unwrap();
+        assert_eq!(families.compute, None);
+    }
+
+    #[test]
+    fn missing_graphics_is_rejected() {
+        let error = QueueFamilyIndices::select(&[COMPUTE], &[true], false, false).err().unwrap();
+        assert!(matches!(error.downcast_ref(), Some(PhysicalDeviceError::NoSuitableQueueFamily)));
+
+        let error = QueueFamilyIndices::select(&[GRAPHICS], &[false], false, false).err().unwrap();
+        assert!(matches!(error.downcast_ref(), Some(PhysicalDeviceError::NoPresentationSupport)));
     }
-}
\ No newline at end of file
+
+    fn candidate(raw: u64, score: i32, name: &str) -> Candidate {
+        use ash::vk::Handle;
+        Candidate { device: vk::PhysicalDevice::from_raw(raw), score, name: name.to_string(), vendor_id: 0x10de, device_id: 0x2684 }
+    }
+
+    fn properties(device_type: vk::PhysicalDeviceType, max_image_dimension2_d: u32) -> vk::PhysicalDeviceProperties {
+        let mut properties = vk::PhysicalDeviceProperties { device_type, ..Default::default() };
+        properties.limits.max_image_dimension2_d = max_image_dimension2_d;
+        properties
+    }
+
+    #[test]
+    fn preferred_type_outweighs_limits() {
+        // Common laptop pairing, the discrete GPU allowing larger images and both supporting geometry shaders
+        let features = vk::PhysicalDeviceFeatures { geometry_shader: vk::TRUE, ..Default::default() };
+        let integrated = properties(vk::PhysicalDeviceType::INTEGRATED_GPU, 16384);
+        let discrete = properties(vk::PhysicalDeviceType::DISCRETE_GPU, 32768);
+        let prefer_integrated = HardwarePreferences { prefer_integrated: true, ..Default::default() };
+        assert!(GraphicsHardware::hardware_score(&integrated, &features, &prefer_integrated) > GraphicsHardware::hardware_score(&discrete, &features, &prefer_integrated));
+
+        let default = HardwarePreferences::default();
+        assert!(GraphicsHardware::hardware_score(&discrete, &features, &default) > GraphicsHardware::hardware_score(&integrated, &features, &default));
+
+        // Even without geometry shaders and with the largest image dimension, the other type ranks lower
+        let without_geometry = vk::PhysicalDeviceFeatures::default();
+        let huge = properties(vk::PhysicalDeviceType::DISCRETE_GPU, u32::MAX);
+        assert!(GraphicsHardware::hardware_score(&integrated, &without_geometry, &prefer_integrated) > GraphicsHardware::hardware_score(&huge, &features, &prefer_integrated));
+    }
+
+    #[test]
+    fn geometry_shaders_win() {
+        // Scored from the features, listed first so that the enumeration order cannot pick it by accident
+        let preferences = HardwarePreferences::default();
+        let discrete = properties(vk::PhysicalDeviceType::DISCRETE_GPU, 16384);
+        let without = vk::PhysicalDeviceFeatures::default();
+        let with = vk::PhysicalDeviceFeatures { geometry_shader: vk::TRUE, ..Default::default() };
+        let candidates = [
+            candidate(1, GraphicsHardware::hardware_score(&discrete, &without, &preferences), "Without geometry shaders"),
+            candidate(2, GraphicsHardware::hardware_score(&discrete, &with, &preferences), "With geometry shaders")
+        ];
+        let picked = candidates.iter().min_by(|a, b| Candidate::rank(a, b)).unwrap();
+        assert_eq!(picked.device, candidates[1].device);
+    }
+
+    #[test]
+    fn identical_devices_keep_enumeration_order() {
+        let candidates = [candidate(1, 100, "GPU"), candidate(2, 100, "GPU")];
+        let picked = candidates.iter().min_by(|a, b| Candidate::rank(a, b)).unwrap();
+        assert_eq!(picked.device, candidates[0].device);
+    }
+
+    #[test]
+    fn tied_scores_pick_smallest_name() {
+        let candidates = [candidate(1, 100, "GPU B"), candidate(2, 100, "GPU A"), candidate(3, 50, "GPU 0")];
+        let picked = candidates.iter().min_by(|a, b| Candidate::rank(a, b)).unwrap();
+        assert_eq!(picked.name, "GPU A");
+
+        // The order of enumeration does not matter
+        let picked = candidates.iter().rev().min_by(|a, b| Candidate::rank(a, b)).unwrap();
+        assert_eq!(picked.name, "GPU A");
+    }
+}
@@ -3,66 +3,176 @@ use std::any::Any;
 
 use ash::vk::{self, DescriptorSet};
 
-use crate::{core::graphics::Graphics, resources::buffer::Buffer};
+use crate::{core::graphics::Graphics, descriptor::globals::GLOBALS_BINDING};
 
+/// Binding of the optional combined image sampler read by textured pipelines.
+pub const TEXTURE_BINDING: u32 = 2;
+
+/// Pools sets are allocated from, growing by another pool of the same size whenever the last one is full.
 pub struct DescriptorPool {
-    pub instance: vk::DescriptorPool,
+    /// Every pool created so far with the number of sets it has room for.
+    pools: Vec<(vk::DescriptorPool, u32)>,
+    /// Index of the pool allocated from, the earlier ones are full.
+    current: usize,
+    sets_per_pool: u32,
+    bindings_per_set: u32,
     pub sets: Vec<vk::DescriptorSet>,
     pub layout: vk::DescriptorSetLayout
 }
 
 impl DescriptorPool {
-    pub fn new(size: u32, graphics: &Graphics, uniform_buffers: &Vec<Buffer>) -> Result<Self> {
+    /// `uniforms` holds the MVP descriptor of each set, e.g. `Buffer::descriptor_buffer_info` or `UniformRing::descriptor_infos`.
+    /// `globals` adds the `Globals` binding to the layout, one descriptor per set as well.
+    /// Pipelines that do not need it should pass `None` rather than leave it unused.
+    pub fn new(size: u32, graphics: &Graphics, uniforms: &[vk::DescriptorBufferInfo], globals: Option<&[vk::DescriptorBufferInfo]>) -> Result<Self> {
+        let layout = Self::create_descriptor_set_layout(&graphics, globals.is_some(), false)?;
+        Self::with_layout(size, graphics, uniforms, globals, layout)
+    }
+
+    /// Allocates sets from an existing layout, so pipelines created with it stay valid.
+    /// `cleanup` leaves the layout alive for that reason.
+    pub fn with_layout(size: u32, graphics: &Graphics, uniforms: &[vk::DescriptorBufferInfo], globals: Option<&[vk::DescriptorBufferInfo]>, layout: vk::DescriptorSetLayout) -> Result<Self> {
+        let bindings_per_set = if globals.is_some() { 2 } else { 1 };
+        let pool = Self::create_pool(graphics, size, bindings_per_set)?;
+        let layouts = vec![layout; size as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        let sets = unsafe { graphics.logical.instance.allocate_descriptor_sets(&allocate_info) }
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_descriptor_pool(pool, None) })?;
+        let mut bindings = vec![(0, uniforms)];
+        if let Some(globals) = globals {
+            bindings.push((GLOBALS_BINDING, globals));
+        }
+        Self::configure_descriptor_sets(&sets, &bindings, graphics);
+        Ok(Self { pools: vec![(pool, size)], current: 0, sets_per_pool: size.max(1), bindings_per_set, sets: sets, layout })
+    }
+
+    /// Allocates `count` more sets of `layout`, e.g. for objects added to a scene, without writing any descriptor.
+    /// When the current pool is exhausted another one of the same size is created and allocated from.
+    pub fn allocate(&mut self, graphics: &Graphics, count: u32) -> Result<Vec<vk::DescriptorSet>> {
+        let layouts = vec![self.layout; count as usize];
+        let allocate = |pool| {
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+            unsafe { graphics.logical.instance.allocate_descriptor_sets(&allocate_info) }
+        };
+        // Pools recycled by `reset` are filled again before growing
+        while let Some((pool, _)) = self.pools.get(self.current) {
+            match allocate(*pool) {
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => self.current += 1,
+                result => return Ok(result?)
+            }
+        }
+        // Requests larger than a pool get a pool of their own
+        let size = self.sets_per_pool.max(count);
+        let pool = Self::create_pool(graphics, size, self.bindings_per_set)?;
+        self.pools.push((pool, size));
+        log::debug!("Descriptor pool grown to {} pools, {} sets", self.pools.len(), self.capacity());
+        Ok(allocate(pool)?)
+    }
+
+    /// Sets the pools have room for, allocated or not.
+    pub fn capacity(&self) -> u32 {
+        self.pools.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Frees every set of every pool at once, `sets` included, so that allocations start over in the first pool.
+    /// The pools are kept for reuse. None of the sets may be used by a pending command buffer.
+    pub fn reset(&mut self, graphics: &Graphics) -> Result<()> {
+        for (pool, _) in &self.pools {
+            unsafe { graphics.logical.instance.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())? };
+        }
+        self.current = 0;
+        self.sets.clear();
+        Ok(())
+    }
+
+    fn create_pool(graphics: &Graphics, size: u32, bindings_per_set: u32) -> Result<vk::DescriptorPool> {
         let pool_size = vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(size * bindings_per_set);
+        // Room for `TEXTURE_BINDING` whether or not the layout declares it
+        let texture_pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .descriptor_count(size);
-        let pool_sizes = &[pool_size];
+        let pool_sizes = &[pool_size, texture_pool_size];
         let create_info = vk::DescriptorPoolCreateInfo::default()
             .pool_sizes(pool_sizes)
             .max_sets(size);
-
         let pool = unsafe { graphics.logical.instance.create_descriptor_pool(&create_info, None)? };
-        let layout = Self::create_descriptor_set_layout(&graphics)?;
-        let layouts = vec![layout; size as usize];
-        let allocate_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(pool)
-            .set_layouts(&layouts);
-        let sets = unsafe { graphics.logical.instance.allocate_descriptor_sets(&allocate_info)? };
-        Self::configure_descriptor_sets(&sets, uniform_buffers, graphics);
-        Ok(Self { instance: pool, sets: sets, layout })
+        Ok(pool)
+    }
+
+    /// Points `TEXTURE_BINDING` of every set at `view`, which must be in `SHADER_READ_ONLY_OPTIMAL` when sampled.
+    /// Only valid when the layout was created with `texture`.
+    pub fn write_texture(&self, graphics: &Graphics, view: vk::ImageView, sampler: vk::Sampler) {
+        let image_info = &[vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)];
+        let writes = self.sets.iter()
+            .map(|set| vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(TEXTURE_BINDING)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(image_info))
+            .collect::<Vec<_>>();
+        unsafe { graphics.logical.instance.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]) };
     }
 
     pub fn cleanup(&self, graphics: &Graphics) {
         unsafe {
-            graphics.logical.instance.destroy_descriptor_pool(self.instance, None);
+            self.pools.iter()
+                .for_each(|(pool, _)| graphics.logical.instance.destroy_descriptor_pool(*pool, None));
         }
     }
 
-    fn configure_descriptor_sets(sets: &Vec<DescriptorSet>, uniform_buffers: &Vec<Buffer>, graphics: &Graphics) {
-        for i in 0..uniform_buffers.len() {
-            let info = uniform_buffers[i].descriptor_buffer_info();
-            let buffer_info = &[info];
-            let buffer_write = vk::WriteDescriptorSet::default()
-                .dst_set(sets[i])
-                .dst_binding(0)
-                .dst_array_element(0)
-                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .buffer_info(buffer_info);
-
-            unsafe { graphics.logical.instance.update_descriptor_sets(&[buffer_write], &[] as &[vk::CopyDescriptorSet]) };
-        }
+    /// Points each binding of every set at the descriptor of the same index, in a single update.
+    fn configure_descriptor_sets(sets: &Vec<DescriptorSet>, bindings: &[(u32, &[vk::DescriptorBufferInfo])], graphics: &Graphics) {
+        // The writes borrow the infos, one single element slice each
+        let writes = bindings.iter()
+            .flat_map(|(binding, infos)| infos.chunks(1)
+                .zip(sets)
+                .map(|(buffer_info, set)| vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(*binding)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(buffer_info)))
+            .collect::<Vec<_>>();
+        unsafe { graphics.logical.instance.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]) };
     }
 
-    fn create_descriptor_set_layout(graphics: &Graphics) -> Result<vk::DescriptorSetLayout> {
+    /// Layout with the MVP at binding 0, `Globals` when `globals` and a combined image sampler at `TEXTURE_BINDING` when `texture`.
+    /// Pass it to `with_layout` and to the pipelines drawing with its sets.
+    pub fn create_descriptor_set_layout(graphics: &Graphics, globals: bool, texture: bool) -> Result<vk::DescriptorSetLayout> {
         let binding = vk::DescriptorSetLayoutBinding::default()
             .binding(0)
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::VERTEX);
+        let mut bindings = vec![binding];
+        if globals {
+            bindings.push(vk::DescriptorSetLayoutBinding::default()
+                .binding(GLOBALS_BINDING)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT));
+        }
+        if texture {
+            bindings.push(vk::DescriptorSetLayoutBinding::default()
+                .binding(TEXTURE_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT));
+        }
 
-        let bindings = &[binding];
+        graphics.limits().validate_bindings(&bindings)?;
         let create_info = vk::DescriptorSetLayoutCreateInfo::default()
-            .bindings(bindings);
+            .bindings(&bindings);
         let layout = unsafe { graphics.logical.instance.create_descriptor_set_layout(&create_info, None)? };
         Ok(layout)
     }
@@ -0,0 +1,26 @@
+use crate::math::vector::Vec2;
+
+/// Binding of the optional `Globals` uniform buffer, next to the MVP at binding 0.
+pub const GLOBALS_BINDING: u32 = 1;
+
+/// Per-frame values shared by every draw, laid out to match this std140 block:
+/// `layout(binding = 1) uniform Globals { float time; float deltaTime; uint frame; vec2 resolution; } globals;`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Globals {
+    /// Seconds since the renderer started.
+    pub time: f32,
+    /// Seconds since the previous frame.
+    pub delta_time: f32,
+    pub frame: u32,
+    /// std140 aligns `vec2` to 8 bytes.
+    _padding: u32,
+    /// Size of the rendered image in pixels.
+    pub resolution: Vec2
+}
+
+impl Globals {
+    pub fn new(time: f32, delta_time: f32, frame: u32, resolution: Vec2) -> Self {
+        Self { time, delta_time, frame, _padding: 0, resolution }
+    }
+}
@@ -1 +1,2 @@
-pub mod descriptor_pool;
\ No newline at end of file
+pub mod descriptor_pool;
+pub mod globals;
\ No newline at end of file
@@ -0,0 +1,38 @@
+use ash::vk;
+use std::fmt::{self, Display, Formatter};
+
+use crate::resources::allocator::HeapBudget;
+
+/// Failures an application can recover from, e.g. by freeing resources and retrying.
+/// Returned wrapped in `anyhow::Error`, retrieve it with `downcast_ref::<VulcorError>()`.
+#[derive(Debug)]
+pub enum VulcorError {
+    /// `result` is either `ERROR_OUT_OF_DEVICE_MEMORY` or `ERROR_OUT_OF_HOST_MEMORY`.
+    /// `heaps` is empty when `VK_EXT_memory_budget` is not available.
+    OutOfMemory { result: vk::Result, requested: vk::DeviceSize, heaps: Vec<HeapBudget> },
+    /// `limit` is the name of the `VkPhysicalDeviceLimits` member, e.g. `maxPushConstantsSize`.
+    ExceedsDeviceLimit { limit: &'static str, requested: u64, max: u64 }
+}
+
+impl VulcorError {
+    pub fn is_out_of_memory(result: vk::Result) -> bool {
+        matches!(result, vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY)
+    }
+}
+
+impl Display for VulcorError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfMemory { result, requested, heaps } => {
+                write!(f, "{} while allocating {} bytes", result, requested)?;
+                for heap in heaps {
+                    write!(f, ", heap {}: {}/{} bytes used", heap.heap, heap.usage, heap.budget)?;
+                }
+                Ok(())
+            },
+            Self::ExceedsDeviceLimit { limit, requested, max } => write!(f, "{} of {} exceeds the device limit of {}", limit, requested, max)
+        }
+    }
+}
+
+impl std::error::Error for VulcorError {}
@@ -0,0 +1,19 @@
+//! Vulkan renderer drawing a `Scene` into a winit window, see `App` to run one and `Vulcor` for the renderer itself.
+
+pub mod swapchain;
+pub mod synchronous;
+pub mod core;
+pub mod pipeline;
+pub mod math;
+pub mod cmd;
+pub mod resources;
+pub mod descriptor;
+pub mod query;
+pub mod error;
+pub mod scene;
+pub mod overlay;
+pub mod benchmark;
+mod renderer;
+
+pub use renderer::{App, RecreationTrigger, RenderStatus, VulcorOptions, Vulcor, VulkanHandles};
+pub use scene::{FrameContext, Scene};
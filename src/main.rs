@@ -1,434 +1,58 @@
-mod swapchain;
-mod synchronous;
-mod core;
-mod pipeline;
-mod math;
-mod cmd;
-mod resources;
-mod descriptor;
+use anyhow::Result;
+use winit::event_loop::{ControlFlow, EventLoop};
 
-use anyhow::{anyhow, Result};
-use ash::{ext::debug_utils, vk::{self, Extent2D, Handle}, Device};
-use image::{GenericImageView, ImageReader};
-use cgmath::{point3, vec3, Deg, EuclideanSpace, Point3};
-use std::{error::Error, ffi::CString, io::Read, ptr::copy_nonoverlapping as memcpy, time::Instant};
-use log::{info};
-use winit::{
-    application::ApplicationHandler, event::WindowEvent, 
-    event_loop::{ActiveEventLoop, ControlFlow, EventLoop}, 
-    window::{Window, WindowId}
-};
+use vulcor::{core::{debug::ValidationFeatures, physical_device::HardwarePreferences}, App};
 
-use crate::{
-    cmd::command_pool::CmdPool, 
-    core::{context::VulkanContext, graphics::Graphics,  physical_device::QueueFamilyIndices}, 
-    descriptor::descriptor_pool::DescriptorPool, 
-    math::{matrix::{Mat4, MVP}, vector::Vec3}, 
-    pipeline::{render_pipeline::{RenderPipeline, Vertex, INDICES, VERTICES}, traits::VulkanPipeline}, 
-    resources::{buffer::Buffer, image::Image}, 
-    swapchain::{SwapchainConfig, SwapchainData}
-};
-
-
-struct App {
-    name: String,
-    vulcor: Option<Vulcor>,
-    minimized: bool,
-    start: Instant
-}
-
-impl App {
-    fn new() -> App {
-        Self { name: "Vulcor".to_string(), vulcor: None, minimized: false, start: Instant::now() }
-    }
-}
-
-struct Vulcor {
-    name: String,
-    window: Window,
-    context: VulkanContext,
-    messenger: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
-    graphics: Graphics,
-    presentation_queue: vk::Queue,
-    swapchain: SwapchainData,
-    render_pass: vk::RenderPass,
-    descriptor_pool: DescriptorPool,
-    pipeline: RenderPipeline,
-    framebuffers: Vec<vk::Framebuffer>,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
-    uniform_buffers: Vec<Buffer>,
-    texture_image: Image,
-    command_pool: CmdPool,
-    command_buffers: Vec<vk::CommandBuffer>,
-    sync: synchronous::RenderSync,
-    run: bool,
-    resized: bool,
-    start: Instant
+/// Frames requested with `--benchmark [frames]`, 1000 when the count is omitted.
+fn benchmark_frames(mut args: impl Iterator<Item = String>) -> Option<u32> {
+    args.position(|arg| arg == "--benchmark")?;
+    Some(args.next().and_then(|frames| frames.parse().ok()).unwrap_or(1000))
 }
 
-impl Vulcor {
-    fn new(window: Window, start: Instant) -> Result<Self, Box<dyn Error>> {
-        info!("Creating application");
-        let title = "Vulcor";
-        let context = VulkanContext::new(CString::new(title)?.as_c_str(), &window)?;
-        let messenger = core::debug::setup_debug_messenger(&context);
-        let graphics = Graphics::new(&context)?;
-        let queue_family = QueueFamilyIndices::new(&context, &graphics.physical.instance)?;
-        let presentation_queue = unsafe { graphics.logical.instance.get_device_queue(queue_family.presentation, 0) };
-        let swapchain = swapchain::SwapchainData::new(&context, &graphics.logical.instance, &graphics.physical.instance, &window)?;
-        let render_pass = Self::create_render_pass(&graphics.logical.instance, &swapchain.config)?;
-        let command_pool = CmdPool::new(&graphics.logical, queue_family.graphics)?;
-        let texture_image = unsafe { Self::create_texture_image(&context, &graphics)? };
-        let uniform_buffers = unsafe { Self::create_uniform_buffers(&context, &graphics, &swapchain)? };
-        let descriptor_pool = DescriptorPool::new(swapchain.images.len() as u32, &graphics, &uniform_buffers)?;
-        let pipeline = RenderPipeline::new(&graphics.logical.instance, &swapchain.config, &render_pass, descriptor_pool.layout)?;
-        let framebuffers = Self::create_framebuffers(&graphics, &swapchain, &render_pass)?;
-        let vertex_buffer = unsafe { Self::create_vertex_buffer(&context, &graphics, &command_pool)? };
-        let index_buffer = unsafe { Self::create_index_buffer(&context, &graphics, &command_pool)? };
-        let command_buffers = unsafe { command_pool.create_buffers(&graphics.logical, &render_pass, &pipeline, &framebuffers, &vertex_buffer, &index_buffer, &swapchain, &descriptor_pool.sets)? };
-        let sync = synchronous::RenderSync::new(&graphics, &swapchain)?;
-        Ok(Self{
-            name: title.to_string(),  
-            window,
-            context,
-            messenger,
-            graphics,
-            presentation_queue,
-            swapchain,
-            render_pass,
-            descriptor_pool,
-            pipeline,
-            framebuffers,
-            vertex_buffer,
-            index_buffer,
-            uniform_buffers,
-            texture_image,
-            command_pool,
-            command_buffers,
-            sync,
-            run: true,
-            resized: false,
-            start
-        })
-    }
-
-    fn recreate_swapchain(&mut self) -> Result<()> {
-        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
-        self.destroy_swapchain();
-        self.swapchain = swapchain::SwapchainData::new(&self.context, &self.graphics.logical.instance, &self.graphics.physical.instance, &self.window)?;
-        self.render_pass = Self::create_render_pass(&self.graphics.logical.instance, &self.swapchain.config)?;
-        self.pipeline = RenderPipeline::new(&self.graphics.logical.instance, &self.swapchain.config, &self.render_pass, self.descriptor_pool.layout)?;
-        self.framebuffers = Self::create_framebuffers(&self.graphics, &self.swapchain, &self.render_pass)?;
-        self.uniform_buffers = unsafe { Self::create_uniform_buffers(&self.context, &self.graphics, &self.swapchain)? };
-        self.descriptor_pool = DescriptorPool::new(self.swapchain.images.len() as u32, &self.graphics, &self.uniform_buffers)?;
-        self.command_buffers = unsafe { self.command_pool.create_buffers(&self.graphics.logical, &self.render_pass, &self.pipeline, &self.framebuffers, &self.vertex_buffer, &self.index_buffer, &self.swapchain, &self.descriptor_pool.sets)? };
-        self.sync = synchronous::RenderSync::new(&self.graphics, &self.swapchain)?;
-        Ok(())
-    }
-
-    // Potentially move in graphics module
-    unsafe fn create_vertex_buffer(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool) -> Result<Buffer> {
-        let vertex_buffer = Self::create_buffer(context, graphics, cmd_pool, &VERTICES, vk::BufferUsageFlags::VERTEX_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
-        Ok(vertex_buffer)
-    }
-
-    unsafe fn create_index_buffer(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool) -> Result<Buffer> {
-        let index_buffer = Self::create_buffer(context, graphics, cmd_pool, INDICES, vk::BufferUsageFlags::INDEX_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
-        Ok(index_buffer)
-    }
-
-    unsafe fn create_uniform_buffers(context: &VulkanContext, graphics: &Graphics, swapchain: &SwapchainData) -> Result<Vec<Buffer>> {
-        let buffers: Vec<Buffer> = swapchain.images.iter().filter_map({|_|
-            Buffer::new(
-                context, 
-                graphics, 
-                size_of::<MVP>() as u64,
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-                vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
-            ).ok()
-        }).collect();
-
-        Ok(buffers)
-    }
-
-    unsafe fn create_buffer<T>(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, data: &[T], usage: vk::BufferUsageFlags, props: vk::MemoryPropertyFlags) -> Result<Buffer> {
-        let size = (size_of::<T>() * data.len()) as u64;
-        let staging_buffer = Buffer::new(
-            context, 
-            graphics, 
-            size, 
-            vk::BufferUsageFlags::TRANSFER_SRC, 
-            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
-        )?;
-
-        let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
-        memcpy(data.as_ptr(), mem.cast(), data.len());
-        graphics.logical.instance.unmap_memory(staging_buffer.memory);
-
-        let new_buffer = Buffer::new(
-            context, 
-            graphics, 
-            size,
-            vk::BufferUsageFlags::TRANSFER_DST | usage,
-            props
-        )?;
-        graphics.copy_buffer(&staging_buffer.instance, &new_buffer.instance, size, cmd_pool)?;
-        staging_buffer.cleanup(graphics);
-        Ok(new_buffer)
-    }
-
-    unsafe fn create_texture_image(context: &VulkanContext, graphics: &Graphics) -> Result<Image> {
-        let img = ImageReader::open("resources/texture.png")?.decode()?;
-        let pixels = img.as_bytes();
-        let size = (size_of::<u8>() * pixels.len()) as u64;
-        let staging_buffer = Buffer::new(
-            context, 
-            graphics, 
-            size, 
-            vk::BufferUsageFlags::TRANSFER_SRC, 
-            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
-        )?;
-
-        let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
-        memcpy(pixels.as_ptr(), mem.cast(), pixels.len());
-        graphics.logical.instance.unmap_memory(staging_buffer.memory);
-
-        let image = Image::new(
-            context, 
-            graphics, 
-            img.dimensions(), 
-            size, 
-            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageTiling::OPTIMAL
-        )?;
-        Ok(image)
-    }
-
-    fn create_framebuffers(graphics: &Graphics, swapchain: &SwapchainData, render_pass: &vk::RenderPass) -> Result<Vec<vk::Framebuffer>> {
-        let framebuffers = swapchain.image_views.iter()
-            .map(|img| {
-                let attachments = &[*img];
-                let create_info: vk::FramebufferCreateInfo<'_> = vk::FramebufferCreateInfo::default()
-                    .render_pass(*render_pass)
-                    .attachments(attachments)
-                    .width(swapchain.config.extent.width)
-                    .height(swapchain.config.extent.height)
-                    .layers(1);
-                unsafe { graphics.logical.instance.create_framebuffer(&create_info, None).unwrap() }
-            })
-            .collect::<Vec<_>>();
-
-        Ok(framebuffers)
-    }
-
-    fn create_render_pass(logical_device: &Device, swapchain: &SwapchainConfig) -> Result<vk::RenderPass> {
-        let color_attachment = vk::AttachmentDescription::default()
-            .format(swapchain.format.format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
-        let color_attachment_ref = vk::AttachmentReference::default()
-            .attachment(0)
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
-        let color_attachments = &[color_attachment_ref];
-        let dependency = vk::SubpassDependency::default()
-            .src_subpass(vk::SUBPASS_EXTERNAL)
-            .dst_subpass(0)
-            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .src_access_mask(vk::AccessFlags::empty())
-            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
-        let subpass = vk::SubpassDescription::default()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(color_attachments);
-        let attachments = &[color_attachment];
-        let supbasses = &[subpass];
-        let dependencies = &[dependency];
-        let create_info  = vk::RenderPassCreateInfo::default()
-            .attachments(attachments)
-            .subpasses(supbasses)
-            .dependencies(dependencies);
-        let render_pass = unsafe { logical_device.create_render_pass(&create_info, None)? };
-        Ok(render_pass)
-    }
-
-    fn render(&mut self) -> Result<()> {
-        unsafe { self.graphics.logical.instance.wait_for_fences(&[self.sync.get_in_flight_fence()], true, u64::MAX)? };        
-        let result = unsafe { self.swapchain.loader.acquire_next_image(
-                self.swapchain.khr, 
-                u64::MAX, 
-                self.sync.get_image_available(), 
-                vk::Fence::null()
-            )
-        };
-
-        let image_index = match result {
-            Ok((image_index, _)) => image_index as usize,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return self.recreate_swapchain(),
-            Err(e) => return Err(anyhow!(e)),
-        };
-        
-        // TODO Possibly encapsulate in sync object 
-        let in_flight = self.sync.images_in_flight[image_index as usize];
-        if !in_flight.is_null() {
-            unsafe { self.graphics.logical.instance.wait_for_fences(&[in_flight], true, u64::MAX)? };
-        }
-        self.sync.update_image_in_flight(image_index);
-        unsafe { self.update_uniform_buffer(image_index)? };
-        let wait_semaphores = &[self.sync.get_image_available()];
-        let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = &[self.command_buffers[image_index]];
-        let signal_semaphores = &[self.sync.get_render_completed()];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(wait_semaphores)
-            .wait_dst_stage_mask(wait_stages)
-            .command_buffers(command_buffers)
-            .signal_semaphores(signal_semaphores);
-
-        self.sync.reset_fences(&self.graphics)?;
-        self.graphics.queue_submit(&vec![submit_info], self.sync.get_in_flight_fence())?;
-        let swapchains = &[self.swapchain.khr];
-        let image_indices = &[image_index as u32];
-        let present_info = vk::PresentInfoKHR::default()
-            .wait_semaphores(signal_semaphores)
-            .swapchains(swapchains)
-            .image_indices(image_indices);
-        let result = unsafe { self.swapchain.loader.queue_present(self.presentation_queue, &present_info) };
-        if self.resized {
-            self.resized = false;
-            self.recreate_swapchain()?;
-        } else {
-            match result {
-                //Ok(false) => self.recreate_swapchain()?,
-                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.recreate_swapchain()?,
-                Err(e) => return Err(anyhow!(e)),
-                _ => {}
-            }
-        }
-        self.sync.increment_frame();
-        Ok(())
-    }
-
-    unsafe fn update_uniform_buffer(&self, image_index: usize) -> Result<()> {
-        let time = self.start.elapsed().as_secs_f32();
-        let model = Mat4::from_axis_angle(Vec3::unit_z(), Deg(90.0) * time);
-        let view = Mat4::look_at_rh(point3(2.0, 2.0, 2.0), Point3::origin(), Vec3::unit_z());
-        let mut proj = cgmath::perspective(
-            Deg(45.0), 
-            self.swapchain.config.extent.width as f32 / self.swapchain.config.extent.height as f32,
-            0.1, 
-            10.0
-        );
-        // Invert y axis
-        proj[1][1] *= -1.0;
-
-        let mvp = MVP { model, view, proj };
-        let mem = self.graphics.logical.instance.map_memory(
-            self.uniform_buffers[image_index].memory, 
-            0, 
-            size_of::<MVP>() as u64, 
-            vk::MemoryMapFlags::empty()
-        )?;
-        memcpy(&mvp, mem.cast(), 1);
-        self.graphics.logical.instance.unmap_memory(self.uniform_buffers[image_index].memory);
-        Ok(())
-    }
-
-    fn cleanup(&mut self) {
-        println!("Cleaning up resources...");
-        let _ = unsafe { self.graphics.logical.instance.device_wait_idle() };
-        unsafe {
-            self.sync.cleanup(&self.graphics);
-            self.destroy_swapchain();
-            self.graphics.logical.instance.destroy_descriptor_set_layout(self.descriptor_pool.layout, None);
-            self.vertex_buffer.cleanup(&self.graphics);
-            self.index_buffer.cleanup(&self.graphics);
-            self.graphics.logical.instance.destroy_command_pool(self.command_pool.instance, None);
-            self.graphics.logical.instance.destroy_device(None);
-            if let Some((report, callback)) = self.messenger.as_ref().take() {
-                report.destroy_debug_utils_messenger(*callback, None);
-            }
-            self.context.cleanup();
-        }
-    }
-
-    fn destroy_swapchain(&mut self) {
-        unsafe {
-            self.descriptor_pool.cleanup(&self.graphics);
-            self.uniform_buffers.iter()
-                .for_each(|b| b.cleanup(&self.graphics));
-            self.framebuffers.iter()
-                .for_each(|f| self.graphics.logical.instance.destroy_framebuffer(*f, None));
-            self.graphics.logical.instance.free_command_buffers(self.command_pool.instance, &self.command_buffers);
-            self.pipeline.cleanup(&self.graphics.logical.instance);
-            self.graphics.logical.instance.destroy_render_pass(self.render_pass, None);
-            self.swapchain.cleanup(&self.graphics);
-        }
-    }
+/// Particles requested with `--particles [count]`, 4096 when the count is omitted.
+fn particle_count(mut args: impl Iterator<Item = String>) -> Option<u32> {
+    args.position(|arg| arg == "--particles")?;
+    Some(args.next().and_then(|count| count.parse().ok()).unwrap_or(4096))
 }
 
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        match self.vulcor {
-            None => {
-                let window_attributes = Window::default_attributes().with_title(self.name.as_str());
-                let window = event_loop.create_window(window_attributes).unwrap();
-                self.vulcor = match Vulcor::new(window, self.start) {
-                    Ok(vulcor) => Some(vulcor),
-                    Err(error) => panic!("FATAL ERROR ENCOUNTERED => {}", error)
-                };
-            },
+/// Validation modes enabled with `--best-practices`, `--sync-validation` and `--gpu-validation`, in debug builds only.
+fn validation_features(args: impl Iterator<Item = String>) -> ValidationFeatures {
+    let mut validation = ValidationFeatures::default();
+    for arg in args {
+        match arg.as_str() {
+            "--best-practices" => validation.best_practices = true,
+            "--sync-validation" => validation.synchronization = true,
+            "--gpu-validation" => validation.gpu_assisted = true,
             _ => ()
         }
     }
+    validation
+}
 
-    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
-        let app = self.vulcor.as_mut().unwrap();
-        if app.run {
-            let result = app.render();
-            if result.is_err() {
-                panic!("ERROR DURING RENDER");
-            }
-        }
-    }
-
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
-        match self.vulcor.as_mut() {
-            Some(instance) => {
-                match event {
-                    WindowEvent::RedrawRequested => {
-                        let result = instance.render();
-                        if result.is_err() { log::error!("Error occured on a render pass"); }
-                    },
-                    WindowEvent::CloseRequested => {
-                        instance.run = false;
-                        instance.cleanup();
-                        event_loop.exit();
-                    },
-                    WindowEvent::Resized(size) => { 
-                        self.minimized = size.width == 0 || size.height == 0;
-                        if !self.minimized {
-                            instance.resized = true; 
-                        }
-                    },
-                    _ => (),
-                }
-            }
-            _ => ()
-        }
-
+/// Device selection preferences, `--integrated` favouring the integrated GPU over a discrete one
+/// and `--allow-software` accepting CPU implementations such as lavapipe, e.g. on CI.
+/// `--separate-present` presents from another queue family than the graphics one when the device has one,
+/// and `--async-compute` steps the particles on a compute queue family other than the graphics one.
+fn hardware_preferences(args: impl Iterator<Item = String>) -> HardwarePreferences {
+    let args = args.collect::<Vec<_>>();
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
+    HardwarePreferences {
+        prefer_integrated: flag("--integrated"),
+        allow_software: flag("--allow-software"),
+        separate_present_family: flag("--separate-present"),
+        async_compute: flag("--async-compute")
     }
 }
 
 fn main() -> Result<()> {
     let mut app = App::new();
+    app.benchmark = benchmark_frames(std::env::args().skip(1));
+    app.options.validation = validation_features(std::env::args().skip(1));
+    app.options.hardware = hardware_preferences(std::env::args().skip(1));
+    app.options.particles = particle_count(std::env::args().skip(1));
+    app.hud = std::env::args().any(|arg| arg == "--hud");
+    app.options.hdr = std::env::args().any(|arg| arg == "--hdr");
+    app.options.shadows = std::env::args().any(|arg| arg == "--shadows");
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop.run_app(&mut app)?;
@@ -0,0 +1,114 @@
+use cgmath::{point3, EuclideanSpace, Point3, SquareMatrix};
+
+use crate::math::{geometry::{self, Frustum}, matrix::{self, ClipSpace, Mat4, Projection}, vector::Vec3};
+
+/// Blend of logarithmic and uniform cascade splits, see `matrix::cascade_splits`.
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+
+/// Part of the view range covered by one shadow cascade.
+#[derive(Copy, Clone, Debug)]
+pub struct Cascade {
+    /// View distance up to which this cascade is sampled, the previous one ends where it starts.
+    pub far: f32,
+    pub light_view_proj: Mat4
+}
+
+/// View and projection of the scene, kept in sync with the extent rendered to.
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vec3,
+    pub projection: Projection,
+    pub clip_space: ClipSpace,
+    viewport: (f32, f32)
+}
+
+impl Camera {
+    pub fn new(projection: Projection, viewport: (f32, f32)) -> Self {
+        let mut camera = Self { eye: point3(2.0, 2.0, 2.0), target: Point3::origin(), up: Vec3::unit_z(), projection, clip_space: ClipSpace::default(), viewport: (1.0, 1.0) };
+        camera.set_viewport(viewport.0, viewport.1);
+        camera
+    }
+
+    /// Updates the extent the projection maps to, so the aspect ratio follows resizes.
+    /// A zero sized extent, e.g. while minimized, keeps the previous one rather than dividing by zero.
+    pub fn set_viewport(&mut self, width: f32, height: f32) {
+        if width > 0.0 && height > 0.0 {
+            self.viewport = (width, height);
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.viewport.0 / self.viewport.1
+    }
+
+    /// 2D content is drawn in pixel space, so the view is left untouched.
+    pub fn view(&self) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { .. } => Mat4::look_at_rh(self.eye, self.target, self.up),
+            Projection::Orthographic2D => Mat4::identity()
+        }
+    }
+
+    pub fn proj(&self) -> Mat4 {
+        self.projection.matrix(self.viewport.0, self.viewport.1, self.clip_space)
+    }
+
+    /// Planes of the volume the camera sees, in world space, for culling with `geometry::cull`.
+    pub fn frustum(&self) -> Frustum {
+        // Orthographic2D ignores the clip space and always keeps Vulkan's depth range
+        let zero_to_one_depth = self.clip_space == ClipSpace::Vulkan || self.projection == Projection::Orthographic2D;
+        Frustum::from_view_proj(&(self.proj() * self.view()), zero_to_one_depth)
+    }
+
+    /// `count` shadow cascades splitting the view range, nearest first, each rendered into its own layer of a
+    /// `DepthTarget::layered` with `light_view_proj`. Empty for `Orthographic2D`, which has no depth range to split.
+    pub fn shadow_cascades(&self, light_dir: Vec3, count: usize) -> Vec<Cascade> {
+        let Projection::Perspective { fov, near, far } = self.projection else { return vec![] };
+        let view = self.view();
+        let mut split_near = near;
+        matrix::cascade_splits(near, far, count, CASCADE_SPLIT_LAMBDA).into_iter()
+            .map(|split_far| {
+                let proj = Projection::Perspective { fov, near: split_near, far: split_far }.matrix(self.viewport.0, self.viewport.1, self.clip_space);
+                let corners = geometry::frustum_corners(&(proj * view), self.clip_space == ClipSpace::Vulkan);
+                split_near = split_far;
+                Cascade { far: split_far, light_view_proj: matrix::light_view_proj(&corners, light_dir) }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+
+    fn perspective() -> Projection {
+        Projection::Perspective { fov: Deg(60.0), near: 0.1, far: 100.0 }
+    }
+
+    #[test]
+    fn aspect_follows_viewport() {
+        let widescreen = Camera::new(perspective(), (1920.0, 1080.0));
+        let standard = Camera::new(perspective(), (1024.0, 768.0));
+        assert!((widescreen.aspect() - 16.0 / 9.0).abs() < 1e-6);
+        assert!((standard.aspect() - 4.0 / 3.0).abs() < 1e-6);
+        assert_ne!(widescreen.proj(), standard.proj());
+        // Only the horizontal scale depends on the aspect ratio
+        assert_eq!(widescreen.proj().y, standard.proj().y);
+    }
+
+    #[test]
+    fn zero_sized_viewport_is_ignored() {
+        let mut camera = Camera::new(perspective(), (1920.0, 1080.0));
+        let proj = camera.proj();
+        camera.set_viewport(1920.0, 0.0);
+        camera.set_viewport(0.0, 0.0);
+        assert_eq!(camera.proj(), proj);
+        assert!((camera.aspect() - 16.0 / 9.0).abs() < 1e-6);
+
+        let minimized = Camera::new(perspective(), (0.0, 0.0));
+        assert_eq!(minimized.aspect(), 1.0);
+        assert!(minimized.proj().x.x.is_finite());
+    }
+}
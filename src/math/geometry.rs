@@ -0,0 +1,273 @@
+use cgmath::{point3, EuclideanSpace, InnerSpace, Matrix, Point3, SquareMatrix, Transform, Zero};
+
+use crate::math::{matrix::Mat4, vector::{Vec3, Vec4}};
+
+/// Below this squared cross product length a triangle is considered degenerate.
+const DEGENERATE_EPSILON: f32 = 1e-12;
+
+/// Per-vertex normals for an indexed triangle list, e.g. for meshes imported without normals.
+/// Face normals are accumulated weighted by triangle area, then normalized. Degenerate triangles and
+/// triangles with out of range indices are skipped, so vertices only used by those get a zero normal.
+/// Triangles are expected counter-clockwise when seen from the front, like `FrontFace::COUNTER_CLOCKWISE`.
+/// The normals are returned rather than written into the mesh because `Vertex` has no normal attribute,
+/// callers interleave them into their own `VertexFormat` alongside the positions.
+pub fn compute_smooth_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        if a >= positions.len() || b >= positions.len() || c >= positions.len() {
+            continue;
+        }
+        // The cross product is twice the triangle area long, which gives the area weighting for free
+        let face = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        if face.magnitude2() < DEGENERATE_EPSILON {
+            continue;
+        }
+        normals[a] += face;
+        normals[b] += face;
+        normals[c] += face;
+    }
+    normals.iter()
+        .map(|n| if n.magnitude2() < DEGENERATE_EPSILON { Vec3::zero() } else { n.normalize() })
+        .collect()
+}
+
+/// Sphere enclosing a mesh, cheap to test against the frustum before recording its draw.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+    /// Centered on the bounding box of `positions`, which is not the tightest sphere but close enough for culling.
+    /// An empty slice gives a zero sized sphere at the origin.
+    pub fn from_points(positions: &[Vec3]) -> Self {
+        let Some(first) = positions.first() else {
+            return Self { center: Vec3::zero(), radius: 0.0 };
+        };
+        let (min, max) = positions.iter().fold((*first, *first), |(min, max), p| (
+            Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z))
+        ));
+        let center = (min + max) * 0.5;
+        let radius = positions.iter().map(|p| (*p - center).magnitude2()).fold(0.0, f32::max).sqrt();
+        Self { center, radius }
+    }
+
+    /// The sphere after `model`, the radius grows by the largest axis scale so non-uniform scaling stays conservative.
+    pub fn transformed(&self, model: &Mat4) -> Self {
+        let center = model.transform_point(Point3::from_vec(self.center)).to_vec();
+        let scale = [model.x.truncate(), model.y.truncate(), model.z.truncate()].iter()
+            .map(|axis| axis.magnitude())
+            .fold(0.0, f32::max);
+        Self { center, radius: self.radius * scale }
+    }
+}
+
+/// Plane as `normal . p + distance = 0`, with the normal pointing towards the inside of the frustum.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32
+}
+
+impl Plane {
+    /// Normalizes `(a, b, c, d)` so that `signed_distance` is in world units.
+    fn from_coefficients(coefficients: Vec4) -> Self {
+        let length = coefficients.truncate().magnitude();
+        Self { normal: coefficients.truncate() / length, distance: coefficients.w / length }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// The six planes bounding what a view projection keeps: left, right, bottom, top, near and far.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6]
+}
+
+impl Frustum {
+    /// Planes of `view_proj` in world space, following Gribb and Hartmann. `zero_to_one_depth` selects Vulkan's
+    /// [0, 1] depth range for the near plane, OpenGL's [-1, 1] otherwise.
+    pub fn from_view_proj(view_proj: &Mat4, zero_to_one_depth: bool) -> Self {
+        let row = |i| view_proj.row(i);
+        let near = if zero_to_one_depth { row(2) } else { row(3) + row(2) };
+        let planes = [row(3) + row(0), row(3) - row(0), row(3) + row(1), row(3) - row(1), near, row(3) - row(2)]
+            .map(Plane::from_coefficients);
+        Self { planes }
+    }
+
+    /// False only when the sphere is entirely behind one of the planes. Spheres near a corner may still pass,
+    /// which only costs a draw that gets clipped anyway.
+    pub fn intersects(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+}
+
+/// World space corners of the volume `view_proj` keeps, near ones first. `zero_to_one_depth` as in `Frustum::from_view_proj`.
+/// A singular matrix, e.g. of a zero sized viewport, maps every corner to the origin.
+pub fn frustum_corners(view_proj: &Mat4, zero_to_one_depth: bool) -> [Point3<f32>; 8] {
+    let inverse = view_proj.invert().unwrap_or(Mat4::zero());
+    let near = if zero_to_one_depth { 0.0 } else { -1.0 };
+    let ndc = [
+        point3(-1.0, -1.0, near), point3(1.0, -1.0, near), point3(-1.0, 1.0, near), point3(1.0, 1.0, near),
+        point3(-1.0, -1.0, 1.0), point3(1.0, -1.0, 1.0), point3(-1.0, 1.0, 1.0), point3(1.0, 1.0, 1.0)
+    ];
+    ndc.map(|corner| {
+        let world = inverse * corner.to_homogeneous();
+        if world.w == 0.0 { Point3::origin() } else { Point3::from_homogeneous(world) }
+    })
+}
+
+/// Anything that can be culled, with its bounds already in world space.
+pub trait Bounded {
+    fn bounds(&self) -> BoundingSphere;
+}
+
+impl Bounded for BoundingSphere {
+    fn bounds(&self) -> BoundingSphere {
+        *self
+    }
+}
+
+/// The objects at least partly inside `frustum`, in their original order.
+/// Lazy and allocation free, so it can run while recording every frame.
+pub fn cull<'a, T: Bounded>(objects: &'a [T], frustum: &'a Frustum) -> impl Iterator<Item = &'a T> {
+    objects.iter().filter(|object| frustum.intersects(&object.bounds()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{point3, vec3, Deg};
+
+    use crate::math::matrix::{ClipSpace, Projection};
+
+    /// Camera at the origin looking down -z, seeing from 1 to 10 units away with a 90 degree field of view.
+    fn frustum() -> Frustum {
+        let view = Mat4::look_at_rh(point3(0.0, 0.0, 0.0), point3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0));
+        let proj = Projection::Perspective { fov: Deg(90.0), near: 1.0, far: 10.0 }.matrix(1.0, 1.0, ClipSpace::Vulkan);
+        Frustum::from_view_proj(&(proj * view), true)
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere { center: vec3(x, y, z), radius }
+    }
+
+    #[test]
+    fn contained_sphere_is_kept() {
+        assert!(frustum().intersects(&sphere(0.0, 0.0, -5.0, 1.0)));
+    }
+
+    #[test]
+    fn sphere_crossing_a_plane_is_kept() {
+        let frustum = frustum();
+        // Centers just outside the near, far and left planes, radius reaching back inside
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -0.5, 1.0)));
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -10.5, 1.0)));
+        assert!(frustum.intersects(&sphere(-5.5, 0.0, -5.0, 1.0)));
+    }
+
+    #[test]
+    fn sphere_outside_each_plane_is_culled() {
+        let frustum = frustum();
+        let outside = [
+            sphere(-8.0, 0.0, -5.0, 1.0),
+            sphere(8.0, 0.0, -5.0, 1.0),
+            sphere(0.0, -8.0, -5.0, 1.0),
+            sphere(0.0, 8.0, -5.0, 1.0),
+            sphere(0.0, 0.0, 2.0, 0.5),
+            sphere(0.0, 0.0, -12.0, 1.0)
+        ];
+        for (plane, sphere) in outside.iter().enumerate() {
+            assert!(!frustum.intersects(sphere), "plane {}", plane);
+        }
+    }
+
+    #[test]
+    fn cull_keeps_visible_objects_in_order() {
+        let frustum = frustum();
+        let objects = [sphere(0.0, 0.0, -5.0, 1.0), sphere(0.0, 0.0, 5.0, 1.0), sphere(1.0, 1.0, -3.0, 0.5)];
+        let visible = cull(&objects, &frustum).collect::<Vec<_>>();
+        assert_eq!(visible, vec![&objects[0], &objects[2]]);
+    }
+
+    #[test]
+    fn transformed_sphere_grows_with_scale() {
+        let model = Mat4::from_translation(vec3(1.0, 0.0, 0.0)) * Mat4::from_nonuniform_scale(1.0, 3.0, 2.0);
+        assert_eq!(sphere(0.0, 0.0, 0.0, 1.0).transformed(&model), sphere(1.0, 0.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn bounding_sphere_encloses_points() {
+        let points = [vec3(-1.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0)];
+        let bounds = BoundingSphere::from_points(&points);
+        assert_eq!(bounds.center, vec3(1.0, 0.5, 0.0));
+        assert!(points.iter().all(|p| (*p - bounds.center).magnitude() <= bounds.radius + 1e-6));
+    }
+
+    #[test]
+    fn frustum_corners_lie_on_near_and_far_planes() {
+        let view = Mat4::look_at_rh(point3(0.0, 0.0, 0.0), point3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0));
+        let proj = Projection::Perspective { fov: Deg(90.0), near: 1.0, far: 10.0 }.matrix(1.0, 1.0, ClipSpace::Vulkan);
+        let corners = frustum_corners(&(proj * view), true);
+        assert!(corners[..4].iter().all(|c| (c.z + 1.0).abs() < 1e-4 && (c.x.abs() - 1.0).abs() < 1e-4), "{:?}", corners);
+        assert!(corners[4..].iter().all(|c| (c.z + 10.0).abs() < 1e-3 && (c.y.abs() - 10.0).abs() < 1e-3), "{:?}", corners);
+    }
+
+    /// Unit cube with `shared` corners, or four corners per face so every vertex belongs to one face only.
+    fn cube(shared: bool) -> (Vec<Vec3>, Vec<u32>) {
+        let (x, y, z) = (Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z());
+        // (normal, u, v) with u x v == normal, so the quads below wind counter-clockwise seen from outside
+        let faces = [(x, y, z), (-x, z, y), (y, z, x), (-y, x, z), (z, x, y), (-z, y, x)];
+        let (mut positions, mut indices) = (Vec::new(), Vec::new());
+        for (normal, u, v) in faces {
+            let quad = [normal - u - v, normal + u - v, normal + u + v, normal - u + v].map(|corner| {
+                let existing = shared.then(|| positions.iter().position(|p| *p == corner)).flatten();
+                existing.unwrap_or_else(|| {
+                    positions.push(corner);
+                    positions.len() - 1
+                }) as u32
+            });
+            indices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn cube_faces_get_face_normals() {
+        let (positions, indices) = cube(false);
+        let normals = compute_smooth_normals(&positions, &indices);
+        assert_eq!(normals.len(), 24);
+        for (face, normal) in indices.chunks_exact(6).map(|quad| quad[0] as usize).zip(normals.chunks_exact(4)) {
+            let expected = (positions[face] + positions[face + 2]) / 2.0;
+            assert!(normal.iter().all(|n| (*n - expected).magnitude() < 1e-6), "{:?} != {:?}", normal, expected);
+        }
+    }
+
+    #[test]
+    fn shared_cube_corners_point_outwards() {
+        let (positions, indices) = cube(true);
+        let normals = compute_smooth_normals(&positions, &indices);
+        assert_eq!(normals.len(), 8);
+        for (position, normal) in positions.iter().zip(&normals) {
+            assert!((normal.magnitude() - 1.0).abs() < 1e-6, "{:?}", normal);
+            // Each component leans the same way as the corner, whatever the diagonal each face was split along
+            assert!((0..3).all(|i| normal[i] * position[i] > 0.0), "{:?} at {:?}", normal, position);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_are_skipped() {
+        let positions = [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(2.0, 0.0, 0.0), vec3(5.0, 5.0, 5.0)];
+        // A valid triangle, a collinear one through vertex 3 and one pointing past the end of `positions`
+        let normals = compute_smooth_normals(&positions, &[0, 1, 2, 0, 1, 3, 4, 2, 9]);
+        assert_eq!(&normals[..3], &[Vec3::unit_z(); 3]);
+        assert_eq!(normals[3], Vec3::zero());
+        assert_eq!(normals[4], Vec3::zero());
+    }
+}
@@ -1,4 +1,6 @@
-use cgmath::{point3, Deg};
+use cgmath::{point3, Deg, EuclideanSpace, InnerSpace, Point3, Transform};
+
+use crate::math::vector::Vec3;
 
 pub type Mat4 = cgmath::Matrix4<f32>;
 
@@ -9,3 +11,186 @@ pub struct MVP {
     pub view: Mat4,
     pub proj: Mat4,
 }
+
+/// Remaps OpenGL clip space, which cgmath targets, to Vulkan's: y points down and depth goes from [-1, 1] to [0, 1].
+/// Without it the near half of the depth range is clipped away and `LESS` compares fewer distinct values.
+pub const OPENGL_TO_VULKAN: Mat4 = Mat4::new(
+    1.0,  0.0, 0.0, 0.0,
+    0.0, -1.0, 0.0, 0.0,
+    0.0,  0.0, 0.5, 0.0,
+    0.0,  0.0, 0.5, 1.0
+);
+
+/// Clip space conventions a perspective projection is built for.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ClipSpace {
+    /// y down and depth in [0, 1], cgmath's matrix is multiplied by `OPENGL_TO_VULKAN`.
+    #[default]
+    Vulkan,
+    /// cgmath's matrix as is, for embedders applying the correction themselves, e.g. in a shader or their own matrices.
+    OpenGl
+}
+
+/// How the scene is projected onto the swapchain images.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective { fov: Deg<f32>, near: f32, far: f32 },
+    /// Pixel-space coordinates, with the origin at the top-left corner of the surface and y pointing down.
+    Orthographic2D
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self::Perspective { fov: Deg(45.0), near: 0.1, far: 10.0 }
+    }
+}
+
+impl Projection {
+    /// `width` and `height` are the logical extent, i.e. before any surface pre-rotation.
+    /// `clip_space` only applies to perspective projections, `Orthographic2D` is built for Vulkan directly.
+    pub fn matrix(&self, width: f32, height: f32, clip_space: ClipSpace) -> Mat4 {
+        match *self {
+            Self::Perspective { fov, near, far } => {
+                let proj = cgmath::perspective(fov, width / height, near, far);
+                match clip_space {
+                    ClipSpace::Vulkan => OPENGL_TO_VULKAN * proj,
+                    ClipSpace::OpenGl => proj
+                }
+            },
+            // Vulkan's clip space already points y down, so no inversion is needed for a top-left origin.
+            Self::Orthographic2D => ortho_2d(width, height)
+        }
+    }
+}
+
+/// Maps `[0, width] x [0, height]` to clip space, keeping z = 0 at depth 0.
+pub fn ortho_2d(width: f32, height: f32) -> Mat4 {
+    cgmath::ortho(0.0, width, 0.0, height, -1.0, 1.0)
+}
+
+/// Look direction and up vector of each cubemap face, in layer order +X, -X, +Y, -Y, +Z, -Z. These follow the
+/// face orientation of the spec, which assumes an unflipped y, see `cube_face_view_projs`.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+    (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0))
+];
+
+/// View projection of each cubemap face seen from `position`, in layer order, e.g. to render a reflection probe
+/// into a `CubeTarget`. Only depth is remapped to [0, 1], keeping y up so faces are not upside down when sampled.
+pub fn cube_face_view_projs(position: Point3<f32>, near: f32, far: f32) -> [Mat4; 6] {
+    let mut depth_to_vulkan = OPENGL_TO_VULKAN;
+    depth_to_vulkan.y.y = 1.0;
+    let proj = depth_to_vulkan * cgmath::perspective(Deg(90.0), 1.0, near, far);
+    CUBE_FACES.map(|(direction, up)| proj * Mat4::look_to_rh(position, direction, up))
+}
+
+/// Far distance of each of `count` shadow cascades splitting `[near, far]`, nearest first. `lambda` blends uniform
+/// splits at 0 with logarithmic ones at 1, which give nearby cascades more of the shadow map resolution.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Orthographic projection looking along `light_dir` that tightly covers `corners`, in Vulkan's clip space.
+/// Casters outside of the covered volume are clipped, even when they shadow it.
+pub fn light_view_proj(corners: &[Point3<f32>], light_dir: Vec3) -> Mat4 {
+    let center = Point3::centroid(corners);
+    let direction = light_dir.normalize();
+    let up = if direction.z.abs() > 0.99 { Vec3::unit_y() } else { Vec3::unit_z() };
+    let view = Mat4::look_at_rh(center - direction, center, up);
+    let light_space = corners.iter().map(|corner| view.transform_point(*corner));
+    let (min, max) = light_space.fold((point3(f32::MAX, f32::MAX, f32::MAX), point3(f32::MIN, f32::MIN, f32::MIN)), |(min, max), p| {
+        (point3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), point3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+    });
+    // The light looks down -z, so the nearest corner has the largest z
+    OPENGL_TO_VULKAN * cgmath::ortho(min.x, max.x, min.y, max.y, -max.z, -min.z) * view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec3, vec4, Vector4};
+
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 100.0;
+
+    fn perspective(clip_space: ClipSpace) -> Mat4 {
+        Projection::Perspective { fov: Deg(60.0), near: NEAR, far: FAR }.matrix(800.0, 600.0, clip_space)
+    }
+
+    fn ndc(clip: Vector4<f32>) -> Vector4<f32> {
+        clip / clip.w
+    }
+
+    #[test]
+    fn near_plane_maps_to_depth_zero() {
+        let point = ndc(perspective(ClipSpace::Vulkan) * vec4(0.0, 0.0, -NEAR, 1.0));
+        assert!(point.z.abs() < 1e-5, "{}", point.z);
+    }
+
+    #[test]
+    fn far_plane_maps_to_depth_one() {
+        let point = ndc(perspective(ClipSpace::Vulkan) * vec4(0.0, 0.0, -FAR, 1.0));
+        assert!((point.z - 1.0).abs() < 1e-5, "{}", point.z);
+
+        // Uncorrected matrices keep OpenGL's [-1, 1] range
+        let point = ndc(perspective(ClipSpace::OpenGl) * vec4(0.0, 0.0, -NEAR, 1.0));
+        assert!((point.z + 1.0).abs() < 1e-5, "{}", point.z);
+    }
+
+    #[test]
+    fn up_maps_to_negative_y() {
+        let point = ndc(perspective(ClipSpace::Vulkan) * vec4(0.0, 1.0, -1.0, 1.0));
+        assert!(point.y < 0.0);
+
+        let corrected = OPENGL_TO_VULKAN * vec4(0.5, 1.0, -1.0, 1.0);
+        assert_eq!(corrected, vec4(0.5, -1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn cascade_splits_end_at_far_plane() {
+        let splits = cascade_splits(NEAR, FAR, 4, 0.75);
+        assert_eq!(splits.len(), 4);
+        assert!((splits[3] - FAR).abs() < 1e-3, "{}", splits[3]);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", splits);
+
+        let uniform = cascade_splits(1.0, 9.0, 2, 0.0);
+        assert_eq!(uniform, vec![5.0, 9.0]);
+    }
+
+    #[test]
+    fn light_view_proj_covers_corners() {
+        let corners = [point3(-1.0, -2.0, 0.0), point3(3.0, 1.0, 0.5), point3(0.0, 0.0, 4.0), point3(2.0, -1.0, 1.0)];
+        let matrix = light_view_proj(&corners, vec3(1.0, 1.0, -1.0));
+        for corner in corners {
+            let point = ndc(matrix * corner.to_homogeneous());
+            assert!(point.x.abs() <= 1.0 + 1e-4 && point.y.abs() <= 1.0 + 1e-4, "{:?}", point);
+            assert!(point.z >= -1e-4 && point.z <= 1.0 + 1e-4, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn cube_faces_follow_the_spec_orientation() {
+        let faces = cube_face_view_projs(point3(0.0, 0.0, 0.0), 0.1, 10.0);
+        for (face, (direction, _)) in faces.iter().zip(CUBE_FACES) {
+            let center = ndc(face * (direction * 2.0).extend(1.0));
+            assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5, "{:?}", center);
+        }
+        // On +X the texture s axis runs along -z and t along -y, with t = 0 on the top row
+        let point = ndc(faces[0] * vec4(1.0, 0.5, -0.5, 1.0));
+        assert!(point.x > 0.0 && point.y < 0.0, "{:?}", point);
+        // On +Y, s runs along +x and t along +z
+        let point = ndc(faces[2] * vec4(0.5, 1.0, 0.5, 1.0));
+        assert!(point.x > 0.0 && point.y > 0.0, "{:?}", point);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod vector;
-pub mod matrix;
\ No newline at end of file
+pub mod matrix;
+pub mod camera;
+pub mod geometry;
\ No newline at end of file
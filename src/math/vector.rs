@@ -1,4 +1,5 @@
 use cgmath::{point3, vec3, Point3, Zero};
 
 pub type Vec2 = cgmath::Vector2<f32>;
-pub type Vec3 = cgmath::Vector3<f32>;
\ No newline at end of file
+pub type Vec3 = cgmath::Vector3<f32>;
+pub type Vec4 = cgmath::Vector4<f32>;
\ No newline at end of file
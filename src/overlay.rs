@@ -0,0 +1,153 @@
+use anyhow::Result;
+use ash::{vk, Device};
+use cgmath::SquareMatrix;
+
+use crate::{
+    cmd::command_pool::CmdPool,
+    core::{context::VulkanContext, graphics::Graphics},
+    descriptor::descriptor_pool::DescriptorPool,
+    math::{matrix::{Mat4, MVP}, vector::Vec2},
+    pipeline::{sprite_pipeline::SpritePipeline, traits::VulkanPipeline},
+    query::{gpu_timer::GpuTimer, pipeline_statistics::{PipelineStatistics, PipelineStatisticsQueryPool}},
+    resources::{text_overlay::TextOverlay, uniform_ring::UniformRing}
+};
+
+/// Glyphs the UI can draw per frame.
+const GLYPH_CAPACITY: usize = 4096;
+
+/// Window input forwarded to the `ui_callback` every frame, along with the frame time.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UiInput {
+    /// Seconds since the previous frame.
+    pub delta_time: f32,
+    /// GPU time in milliseconds of the last frame rendered to the same image, `None` until measured or without timestamps.
+    pub gpu_time: Option<f64>,
+    /// Draws of that frame's scene render pass, `None` without the `pipeline_statistics_query` feature.
+    pub statistics: Option<PipelineStatistics>,
+    /// Cursor position in physical pixels of the window, `None` while it is outside.
+    pub cursor: Option<Vec2>,
+    /// Whether the left mouse button is held.
+    pub pressed: bool
+}
+
+/// Measures the frames rendered to one swapchain image, recorded by `CmdPool::record_frame` around the whole frame.
+pub struct FrameQueries {
+    pub timer: GpuTimer,
+    /// Brackets the scene render pass, `None` without the `pipeline_statistics_query` feature.
+    pub statistics: Option<PipelineStatisticsQueryPool>,
+    /// Set once a frame recorded with the queries was submitted, they cannot be read before.
+    submitted: bool
+}
+
+impl FrameQueries {
+    fn new(context: &VulkanContext, graphics: &Graphics) -> Result<Self> {
+        let timer = GpuTimer::new(context, graphics)?;
+        let statistics = (graphics.logical.features.pipeline_statistics_query == vk::TRUE)
+            .then(|| PipelineStatisticsQueryPool::new(graphics))
+            .transpose()
+            .inspect_err(|_| timer.cleanup(graphics))?;
+        Ok(Self { timer, statistics, submitted: false })
+    }
+
+    /// Results of the last frame submitted with the queries, which must have completed.
+    fn read(&self, graphics: &Graphics, input: &mut UiInput) -> Result<()> {
+        if !self.submitted {
+            return Ok(());
+        }
+        input.gpu_time = self.timer.elapsed_ms(graphics)?;
+        if let Some(statistics) = &self.statistics {
+            input.statistics = statistics.results(graphics)?;
+        }
+        Ok(())
+    }
+
+    fn cleanup(&self, graphics: &Graphics) {
+        self.timer.cleanup(graphics);
+        if let Some(statistics) = &self.statistics {
+            statistics.cleanup(graphics);
+        }
+    }
+}
+
+/// Fills the overlay every frame, see `Vulcor::set_ui_callback`. Text queued before an error is still drawn.
+pub type UiCallback = Box<dyn FnMut(&mut TextOverlay, &UiInput) -> Result<()>>;
+
+/// Screen space UI drawn in the scene render pass after the scene, so it lands on top of whatever was drawn.
+/// Holds one vertex region, MVP and descriptor set per swapchain image, and is recreated along with the swapchain.
+pub struct Overlay {
+    pipeline: SpritePipeline,
+    text: TextOverlay,
+    /// `Orthographic2D` projection of each image, so the UI is laid out in pixels whatever the camera.
+    uniforms: UniformRing<MVP>,
+    descriptor_pool: DescriptorPool,
+    queries: Vec<FrameQueries>
+}
+
+impl Overlay {
+    /// Takes over `pipeline`, created with `set_layout`, the renderer's uniform layout, for the scene render pass.
+    /// `globals` are the descriptors of the renderer's `Globals`, which the sets share. `sampler` should filter with `NEAREST`
+    /// and outlives the overlay.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, pipeline: SpritePipeline, set_layout: vk::DescriptorSetLayout, globals: &[vk::DescriptorBufferInfo], sampler: vk::Sampler) -> Result<Self> {
+        let images = globals.len();
+        let text = TextOverlay::new(context, graphics, cmd_pool, &pipeline, sampler, GLYPH_CAPACITY, images)
+            .inspect_err(|_| pipeline.cleanup(&graphics.logical.instance))?;
+        let uniforms = UniformRing::new(context, graphics, images)
+            .inspect_err(|_| {
+                text.cleanup(graphics);
+                pipeline.cleanup(&graphics.logical.instance);
+            })?;
+        let descriptor_pool = DescriptorPool::with_layout(images as u32, graphics, &uniforms.descriptor_infos(), Some(globals), set_layout)
+            .inspect_err(|_| {
+                uniforms.cleanup(graphics);
+                text.cleanup(graphics);
+                pipeline.cleanup(&graphics.logical.instance);
+            })?;
+        let mut overlay = Self { pipeline, text, uniforms, descriptor_pool, queries: Vec::with_capacity(images) };
+        for _ in 0..images {
+            match FrameQueries::new(context, graphics) {
+                Ok(queries) => overlay.queries.push(queries),
+                Err(error) => {
+                    overlay.cleanup(graphics);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(overlay)
+    }
+
+    /// Discards the UI last drawn to `image_index` and runs `ui` for the new one, projected with `proj`, given `input` along
+    /// with the measurements of that image's last frame. The fence of the frame that last used `image_index` must have been
+    /// waited on, and the frame recorded next for it is expected to be submitted.
+    pub fn update(&mut self, graphics: &Graphics, image_index: usize, proj: Mat4, ui: &mut UiCallback, input: &UiInput) -> Result<()> {
+        let mut input = *input;
+        self.queries[image_index].read(graphics, &mut input)?;
+        self.queries[image_index].submitted = true;
+        self.uniforms.write(image_index, &MVP { model: Mat4::identity(), view: Mat4::identity(), proj });
+        self.text.begin_frame(image_index);
+        if let Err(error) = ui(&mut self.text, &input) {
+            log::warn!("UI callback failed => {}", error);
+        }
+        self.text.end_frame()
+    }
+
+    /// Records the UI of the last `update`, which must have been for `image_index`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside the scene render pass, and the overlay must outlive its execution.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, image_index: usize) {
+        self.text.record(device, command_buffer, &self.pipeline, self.descriptor_pool.sets[image_index]);
+    }
+
+    pub fn queries(&self, image_index: usize) -> &FrameQueries {
+        &self.queries[image_index]
+    }
+
+    /// The descriptor set layout and sampler given to `new` are left alive.
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.queries.iter().for_each(|q| q.cleanup(graphics));
+        self.descriptor_pool.cleanup(graphics);
+        self.uniforms.cleanup(graphics);
+        self.text.cleanup(graphics);
+        self.pipeline.cleanup(&graphics.logical.instance);
+    }
+}
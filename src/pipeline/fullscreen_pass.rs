@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Result};
+use std::{ffi::CStr, path::Path};
+use ash::{vk, Device};
+
+use crate::{core::graphics::Graphics, pipeline::shader::Shader};
+
+/// Vertex shader drawing a single triangle over the whole viewport from `gl_VertexIndex`, with the UV at location 0.
+pub const FULLSCREEN_VERTEX_SHADER: &str = "shaders/fullscreen.vert.spv";
+/// Fragment shader copying the input texture as is.
+pub const BLIT_FRAGMENT_SHADER: &str = "shaders/blit.frag.spv";
+/// Fragment shader tone mapping a linear input and encoding it to sRGB in the shader, for output targets without
+/// hardware encoding such as UNORM swapchains, see `SwapchainConfig::encodes_srgb`. sRGB targets use `BLIT_FRAGMENT_SHADER`.
+pub const ENCODE_SRGB_FRAGMENT_SHADER: &str = "shaders/encode_srgb.frag.spv";
+
+/// Draws a fullscreen triangle sampling one input texture, the building block of post-processing effects such as
+/// tone mapping or blur. The fragment shader reads `inUv` at location 0 and a `sampler2D` at set 0, binding 0.
+/// Passes are chained by rendering each one into a `RenderTarget` sampled by the next, the last one into the swapchain.
+/// The viewport is baked in, so the pass has to be recreated with the target it renders to.
+pub struct FullscreenPass {
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline
+}
+
+impl FullscreenPass {
+    /// Pipeline for `subpass` of `render_pass`, running the compiled `fragment_shader`, e.g. `BLIT_FRAGMENT_SHADER`, over `area`
+    /// of the target, e.g. a letterboxed `FixedResolution::destination`. The input has to be set with `set_input` before the first draw.
+    pub fn new<P: AsRef<Path>>(graphics: &Graphics, render_pass: vk::RenderPass, subpass: u32, area: vk::Rect2D, fragment_shader: P) -> Result<Self> {
+        let logical_device = &graphics.logical.instance;
+        let set_layout = Self::create_set_layout(logical_device)?;
+        let (descriptor_pool, descriptor_set) = Self::create_descriptor_set(logical_device, set_layout)
+            .inspect_err(|_| unsafe { logical_device.destroy_descriptor_set_layout(set_layout, None) })?;
+        let (layout, pipeline) = Self::create_pipeline(logical_device, area, render_pass, subpass, set_layout, fragment_shader.as_ref())
+            .inspect_err(|_| unsafe {
+                logical_device.destroy_descriptor_pool(descriptor_pool, None);
+                logical_device.destroy_descriptor_set_layout(set_layout, None);
+            })?;
+        Ok(Self { set_layout, descriptor_pool, descriptor_set, layout, pipeline })
+    }
+
+    /// Points the pass at the texture it samples, e.g. `RenderTarget::descriptor_image_info`.
+    /// The descriptor set is updated in place, so no command buffer drawing the pass may be pending.
+    pub fn set_input(&self, graphics: &Graphics, image_info: vk::DescriptorImageInfo) {
+        let image_infos = &[image_info];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(image_infos);
+        unsafe { graphics.logical.instance.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]) };
+    }
+
+    /// Draws the triangle inside the render pass the pipeline was created for, without any vertex buffer.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside that render pass, after `set_input`.
+    pub unsafe fn record_draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.layout, 0, &[self.descriptor_set], &[]);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        let device = &graphics.logical.instance;
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+
+    fn create_set_layout(logical_device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let bindings = &[binding];
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    fn create_descriptor_set(logical_device: &Device, set_layout: vk::DescriptorSetLayout) -> Result<(vk::DescriptorPool, vk::DescriptorSet)> {
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1);
+        let pool_sizes = &[pool_size];
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(1);
+        let pool = unsafe { logical_device.create_descriptor_pool(&create_info, None)? };
+        let layouts = &[set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(layouts);
+        let set = unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }
+            .inspect_err(|_| unsafe { logical_device.destroy_descriptor_pool(pool, None) })?[0];
+        Ok((pool, set))
+    }
+
+    fn create_pipeline(
+        logical_device: &Device,
+        area: vk::Rect2D,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        set_layout: vk::DescriptorSetLayout,
+        fragment_shader: &Path
+    ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let vert = Shader::new(FULLSCREEN_VERTEX_SHADER, logical_device)?;
+        let frag = Shader::new(fragment_shader, logical_device)
+            .inspect_err(|_| vert.cleanup(logical_device))?;
+        let main: &'static CStr = c"main";
+        let stages = &[
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert.instance)
+                .name(main),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag.instance)
+                .name(main)
+        ];
+
+        // Positions come from the vertex index
+        let vert_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .primitive_restart_enable(false);
+        let viewport = vk::Viewport::default()
+            .x(area.offset.x as f32)
+            .y(area.offset.y as f32)
+            .width(area.extent.width as f32)
+            .height(area.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        let viewports = &[viewport];
+        let scissors = &[area];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewports(viewports)
+            .scissors(scissors);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(false);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // Ignored unless the subpass has a depth attachment, e.g. the swapchain pass, which the triangle covers without testing
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(false);
+        let attachments = &[color_blend_attachment_state];
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+        let set_layouts = &[set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None) };
+        let pipeline = layout.map_err(|e| anyhow!(e)).and_then(|layout| {
+            let info = vk::GraphicsPipelineCreateInfo::default()
+                .stages(stages)
+                .vertex_input_state(&vert_input_state)
+                .input_assembly_state(&input_assembly)
+                .viewport_state(&viewport_state)
+                .rasterization_state(&rasterization)
+                .multisample_state(&multisample_state)
+                .depth_stencil_state(&depth_stencil)
+                .color_blend_state(&color_blend_state)
+                .layout(layout)
+                .render_pass(render_pass)
+                .subpass(subpass);
+            unsafe { logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None) }
+                .map(|pipelines| (layout, pipelines[0]))
+                .map_err(|(_, e)| {
+                    unsafe { logical_device.destroy_pipeline_layout(layout, None) };
+                    anyhow!(e)
+                })
+        });
+        vert.cleanup(logical_device);
+        frag.cleanup(logical_device);
+        pipeline
+    }
+}
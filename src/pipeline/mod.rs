@@ -1,3 +1,9 @@
 pub mod shader;
 pub mod traits;
-pub mod render_pipeline;
\ No newline at end of file
+pub mod render_pipeline;
+pub mod sprite_pipeline;
+pub mod particle_system;
+pub mod tone_mapping;
+pub mod shadow_pass;
+pub mod reflection_probe;
+pub mod fullscreen_pass;
\ No newline at end of file
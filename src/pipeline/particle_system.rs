@@ -0,0 +1,476 @@
+use anyhow::{anyhow, Result};
+use std::{ffi::CStr, ptr::copy_nonoverlapping as memcpy};
+use ash::{vk, Device};
+use cgmath::{vec2, vec4};
+
+use crate::{
+    cmd::command_pool::CmdPool,
+    core::{context::VulkanContext, graphics::{Graphics, QueueRole}, logical_device::GraphicsInterface},
+    math::vector::{Vec2, Vec4},
+    pipeline::{render_pipeline::PipelineError, shader::Shader},
+    resources::buffer::Buffer,
+    swapchain::SwapchainConfig
+};
+
+/// Must match `local_size_x` in `particles.comp`.
+const WORKGROUP_SIZE: u32 = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub color: Vec4
+}
+
+/// Push constants of `particles.comp`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Step {
+    delta_time: f32,
+    count: u32
+}
+
+/// Push constants of `particle.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Draw {
+    point_size: f32
+}
+
+/// Particles integrated by a compute shader, then drawn as points straight from the same storage buffer.
+/// Positions are in clip space and bounce off its edges. Points are 1 pixel large until `set_point_size`.
+pub struct ParticleSystem {
+    pub buffer: Buffer,
+    count: u32,
+    /// Written to `gl_PointSize` through a push constant, already snapped to the device range.
+    point_size: f32,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    set_layout: vk::DescriptorSetLayout,
+    compute_layout: vk::PipelineLayout,
+    compute_pipeline: vk::Pipeline,
+    graphics_layout: vk::PipelineLayout,
+    graphics_pipeline: vk::Pipeline
+}
+
+impl ParticleSystem {
+    /// `render_pass` is the pass the particles are drawn in, see `record_draw`.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, config: &SwapchainConfig, render_pass: &vk::RenderPass, count: u32) -> Result<Self> {
+        if count == 0 {
+            return Err(anyhow!("A particle system needs at least one particle."));
+        }
+        let logical_device = &graphics.logical.instance;
+        // Written on the compute queue and read by draws on the graphics one
+        let buffer = Buffer::new_shared(
+            context,
+            graphics,
+            (size_of::<Particle>() * count as usize) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            &[graphics.queue_family.graphics, graphics.compute_family()]
+        )?;
+        let set_layout = Self::create_set_layout(logical_device)?;
+        let (descriptor_pool, descriptor_set) = Self::create_descriptor_set(logical_device, set_layout, &buffer)?;
+        let (compute_layout, compute_pipeline) = Self::create_compute_pipeline(&graphics.logical, set_layout)?;
+        let (graphics_layout, graphics_pipeline) = Self::create_graphics_pipeline(&graphics.logical, config, render_pass)?;
+        let system = Self { buffer, count, point_size: 1.0, descriptor_pool, descriptor_set, set_layout, compute_layout, compute_pipeline, graphics_layout, graphics_pipeline };
+        system.reset(context, graphics, cmd_pool)?;
+        Ok(system)
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    /// Diameter of the points in pixels, taking effect from the next `record_draw`. Sizes other than 1.0 need
+    /// `largePoints`, and are clamped to `pointSizeRange` then snapped to `pointSizeGranularity`.
+    pub fn set_point_size(&mut self, graphics: &Graphics, size: f32) -> Result<()> {
+        if !size.is_finite() || size <= 0.0 {
+            return Err(anyhow!("{} is not a valid point size.", size));
+        }
+        if size != 1.0 && graphics.logical.features.large_points == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("largePoints")));
+        }
+        self.point_size = graphics.limits().snap_point_size(size);
+        Ok(())
+    }
+
+    /// Moves every particle back to the center, waiting for the device to stop using the buffer first.
+    pub fn reset(&self, context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool) -> Result<()> {
+        let particles = Self::initial_particles(self.count);
+        let size = (size_of::<Particle>() * particles.len()) as u64;
+        let staging_buffer = Buffer::new(
+            context,
+            graphics,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        unsafe {
+            let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
+            memcpy(particles.as_ptr(), mem.cast(), particles.len());
+            graphics.logical.instance.unmap_memory(staging_buffer.memory);
+            graphics.logical.instance.device_wait_idle()?;
+            graphics.copy_buffer(&staging_buffer.instance, &self.buffer.instance, size, cmd_pool, cmd_pool)?;
+        }
+        staging_buffer.cleanup(graphics);
+        Ok(())
+    }
+
+    /// Records the integration step, outside of a render pass and before `record_draw`. On the graphics queue the barrier
+    /// makes the written positions visible to the vertex input of the draw. On the async compute queue, which has no
+    /// vertex input stage, the semaphore the draw waits on does instead, see `ParticleUpdates`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, from a pool of a queue family with compute support.
+    pub unsafe fn record_update(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer, delta_time: f32) {
+        let device = &graphics.logical.instance;
+        let step = Step { delta_time, count: self.count };
+        let constants = std::slice::from_raw_parts((&step as *const Step).cast::<u8>(), size_of::<Step>());
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.compute_pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, self.compute_layout, 0, &[self.descriptor_set], &[]);
+        device.cmd_push_constants(command_buffer, self.compute_layout, vk::ShaderStageFlags::COMPUTE, 0, constants);
+        device.cmd_dispatch(command_buffer, self.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        if graphics.has_async_compute() {
+            return;
+        }
+        graphics.buffer_memory_barrier(
+            command_buffer,
+            self.buffer.instance,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+        );
+    }
+
+    /// Records the draw of every particle as a point, inside the render pass given on creation or to `recreate_pipeline`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside that render pass.
+    pub unsafe fn record_draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let draw = Draw { point_size: self.point_size };
+        let constants = std::slice::from_raw_parts((&draw as *const Draw).cast::<u8>(), size_of::<Draw>());
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.graphics_pipeline);
+        device.cmd_push_constants(command_buffer, self.graphics_layout, vk::ShaderStageFlags::VERTEX, 0, constants);
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.instance], &[0]);
+        device.cmd_draw(command_buffer, self.count, 1, 0, 0);
+    }
+
+    /// Rebuilds the draw pipeline for another render pass or extent, e.g. after the swapchain was recreated.
+    /// The device must be idle. The old pipeline is destroyed first, so a failure leaves nothing to destroy twice.
+    pub fn recreate_pipeline(&mut self, graphics: &Graphics, config: &SwapchainConfig, render_pass: &vk::RenderPass) -> Result<()> {
+        let device = &graphics.logical.instance;
+        unsafe {
+            device.destroy_pipeline(self.graphics_pipeline, None);
+            device.destroy_pipeline_layout(self.graphics_layout, None);
+        }
+        self.graphics_pipeline = vk::Pipeline::null();
+        self.graphics_layout = vk::PipelineLayout::null();
+        (self.graphics_layout, self.graphics_pipeline) = Self::create_graphics_pipeline(&graphics.logical, config, render_pass)?;
+        Ok(())
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        let device = &graphics.logical.instance;
+        unsafe {
+            device.destroy_pipeline(self.graphics_pipeline, None);
+            device.destroy_pipeline_layout(self.graphics_layout, None);
+            device.destroy_pipeline(self.compute_pipeline, None);
+            device.destroy_pipeline_layout(self.compute_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        self.buffer.cleanup(graphics);
+    }
+
+    /// Spreads the velocities with the golden angle, so the particles burst out evenly without a random generator.
+    fn initial_particles(count: u32) -> Vec<Particle> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+        (0..count)
+            .map(|i| {
+                let t = i as f32 / count as f32;
+                let angle = golden_angle * i as f32;
+                let speed = 0.1 + 0.4 * t;
+                Particle {
+                    position: vec2(0.0, 0.0),
+                    velocity: vec2(angle.cos(), angle.sin()) * speed,
+                    color: vec4(1.0, t, 1.0 - t, 1.0)
+                }
+            })
+            .collect()
+    }
+
+    fn create_set_layout(logical_device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE);
+        let bindings = &[binding];
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    fn create_descriptor_set(logical_device: &Device, set_layout: vk::DescriptorSetLayout, buffer: &Buffer) -> Result<(vk::DescriptorPool, vk::DescriptorSet)> {
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1);
+        let pool_sizes = &[pool_size];
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(1);
+        let pool = unsafe { logical_device.create_descriptor_pool(&create_info, None)? };
+        let layouts = &[set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(layouts);
+        let set = unsafe { logical_device.allocate_descriptor_sets(&allocate_info)?[0] };
+        let buffer_info = &[vk::DescriptorBufferInfo::default()
+            .buffer(buffer.instance)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(buffer_info);
+        unsafe { logical_device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]) };
+        Ok((pool, set))
+    }
+
+    fn create_compute_pipeline(device: &GraphicsInterface, set_layout: vk::DescriptorSetLayout) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let push_constant_ranges = &[vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Step>() as u32)];
+        device.limits.validate_push_constants(push_constant_ranges)?;
+        let logical_device = &device.instance;
+        let shader = Shader::new("shaders/particles.comp.spv", logical_device)?;
+        let set_layouts = &[set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
+
+        let main: &'static CStr = c"main";
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader.instance)
+            .name(main);
+        let info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(layout);
+        let pipeline = unsafe {
+            logical_device.create_compute_pipelines(vk::PipelineCache::null(), &[info], None)
+                .map_err(|(_, e)| anyhow!(e))?[0]
+        };
+        shader.cleanup(logical_device);
+        Ok((layout, pipeline))
+    }
+
+    fn create_graphics_pipeline(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let push_constant_ranges = &[vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(size_of::<Draw>() as u32)];
+        device.limits.validate_push_constants(push_constant_ranges)?;
+        let logical_device = &device.instance;
+        let vert = Shader::new("shaders/particle.vert.spv", logical_device)?;
+        let frag = Shader::new("shaders/particle.frag.spv", logical_device)?;
+        let main: &'static CStr = c"main";
+        let stages = &[
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert.instance)
+                .name(main),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag.instance)
+                .name(main)
+        ];
+
+        // Velocities are skipped, the stride walks over them
+        let binding_descriptions = &[vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Particle>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)];
+        let attribute_descriptions = &[
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(0),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset((size_of::<Vec2>() * 2) as u32)
+        ];
+        let vert_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(binding_descriptions)
+            .vertex_attribute_descriptions(attribute_descriptions);
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::POINT_LIST)
+            .primitive_restart_enable(false);
+
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(config.extent.width as f32)
+            .height(config.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        let scissor = vk::Rect2D::default()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(config.extent);
+        let viewports = &[viewport];
+        let scissors = &[scissor];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewports(viewports)
+            .scissors(scissors);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(false);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // Drawn over the scene, the state is still needed as its passes have a depth attachment
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(false);
+        let attachments = &[color_blend_attachment_state];
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(stages)
+            .vertex_input_state(&vert_input_state)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend_state)
+            .layout(layout)
+            .render_pass(*render_pass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)
+                .map_err(|(_, e)| anyhow!(e))?[0]
+        };
+        vert.cleanup(logical_device);
+        frag.cleanup(logical_device);
+        Ok((layout, pipeline))
+    }
+}
+
+/// Command buffers stepping a `ParticleSystem` before each frame, one per swapchain image so that the one recorded
+/// was last submitted with that image and is known to be complete once the image's fence was waited on.
+/// With async compute they run on the compute queue, overlapping with the rendering of the previous frame.
+pub struct ParticleUpdates {
+    pool: CmdPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    /// Signaled by the update of each image, waited on by the frame drawing it. Only created with async compute.
+    updated: Vec<vk::Semaphore>,
+    /// Signaled by the frame of each image once drawn, waited on by the next update before overwriting the particles.
+    drawn: Vec<vk::Semaphore>,
+    /// Signaled by the last frame and not waited on yet, `None` before the first frame.
+    last_drawn: Option<vk::Semaphore>
+}
+
+impl ParticleUpdates {
+    pub fn new(graphics: &Graphics, image_count: usize) -> Result<Self> {
+        let device = &graphics.logical.instance;
+        let pool = CmdPool::resettable(&graphics.logical, graphics.compute_family())?;
+        let mut updates = Self { pool, command_buffers: vec![], updated: vec![], drawn: vec![], last_drawn: None };
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(updates.pool.instance)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(image_count as u32);
+        let result = unsafe { device.allocate_command_buffers(&allocate_info) }.map_err(|e| anyhow!(e))
+            .and_then(|command_buffers| {
+                updates.command_buffers = command_buffers;
+                if graphics.has_async_compute() {
+                    for _ in 0..image_count {
+                        updates.updated.push(unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)? });
+                        updates.drawn.push(unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)? });
+                    }
+                }
+                Ok(())
+            });
+        result.inspect_err(|_| updates.cleanup(graphics))?;
+        Ok(updates)
+    }
+
+    /// Whether updates are submitted on their own with `submit`, rather than in the submission of the frame.
+    pub fn is_async(&self) -> bool {
+        !self.updated.is_empty()
+    }
+
+    /// Records the step of `delta_time` seconds into the buffer of `image_index`, to be submitted ahead of the frame
+    /// drawing the particles.
+    ///
+    /// # Safety
+    /// The fence of the frame that last rendered to `image_index` must have been waited on.
+    pub unsafe fn record(&self, graphics: &Graphics, particles: &ParticleSystem, image_index: usize, delta_time: f32) -> Result<vk::CommandBuffer> {
+        let device = &graphics.logical.instance;
+        let command_buffer = self.command_buffers[image_index];
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        particles.record_update(graphics, command_buffer, delta_time);
+        device.end_command_buffer(command_buffer)?;
+        Ok(command_buffer)
+    }
+
+    /// Submits `command_buffer` to the compute queue once the previous frame stopped reading the particles. Returns the
+    /// semaphore the frame of `image_index` must wait on at `VERTEX_INPUT`, then the one it must signal. Async only.
+    pub fn submit(&mut self, graphics: &Graphics, command_buffer: vk::CommandBuffer, image_index: usize) -> Result<(vk::Semaphore, vk::Semaphore)> {
+        let (updated, drawn) = (self.updated[image_index], self.drawn[image_index]);
+        let command_buffers = &[command_buffer];
+        let signal_semaphores = &[updated];
+        let last_drawn = self.last_drawn.take();
+        let wait_semaphores = last_drawn.as_slice();
+        let wait_stages = &[vk::PipelineStageFlags::COMPUTE_SHADER][..wait_semaphores.len()];
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(wait_semaphores)
+            .wait_dst_stage_mask(wait_stages)
+            .command_buffers(command_buffers)
+            .signal_semaphores(signal_semaphores);
+        graphics.submit(QueueRole::Compute, &[submit_info], vk::Fence::null())?;
+        self.last_drawn = Some(drawn);
+        Ok((updated, drawn))
+    }
+
+    /// Frees the command buffers along with their pool. The device must be idle.
+    pub fn cleanup(&self, graphics: &Graphics) {
+        let device = &graphics.logical.instance;
+        unsafe {
+            self.updated.iter().chain(&self.drawn).for_each(|s| device.destroy_semaphore(*s, None));
+            device.destroy_command_pool(self.pool.instance, None);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use ash::{vk, Device};
+use cgmath::{point3, Point3};
+
+use crate::{
+    cmd::command_pool::FrameScene,
+    core::{context::VulkanContext, graphics::Graphics},
+    math::matrix::{self, Mat4},
+    pipeline::{render_pipeline::{PipelineOptions, ProbeFace, RenderPipeline, VertexInput}, traits::VulkanPipeline},
+    resources::render_target::CubeTarget,
+    swapchain::SwapchainConfig
+};
+
+/// Width and height of each face of the probe.
+pub const PROBE_SIZE: u32 = 256;
+/// Color attachment and sampled with optimal tiling on every device.
+pub const PROBE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// Where the probe captures the scene from, above the center of the built-in quad.
+pub const PROBE_POSITION: Point3<f32> = point3(0.0, 0.0, 1.0);
+/// Near and far planes of every face, those of the default `Projection`.
+const PROBE_DEPTH_RANGE: (f32, f32) = (0.1, 10.0);
+
+/// Renders the mesh into the six faces of a `CubeTarget` seen from `PROBE_POSITION` before the scene pass, e.g. for
+/// reflections sampling it as a `samplerCube`. The faces are left in `SHADER_READ_ONLY_OPTIMAL`, see
+/// `CubeTarget::descriptor_image_info`.
+pub struct ReflectionProbe {
+    pub target: CubeTarget,
+    pipeline: RenderPipeline,
+    options: PipelineOptions,
+    /// Matrix of each face of `target` in layer order, pushed when the command buffers are recorded.
+    view_projs: [Mat4; 6]
+}
+
+impl ReflectionProbe {
+    /// `set_layout` and `vertex_input` are those of the scene pipeline, whose descriptor sets and buffers are reused.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, config: &SwapchainConfig, set_layout: vk::DescriptorSetLayout, vertex_input: VertexInput) -> Result<Self> {
+        let target = CubeTarget::new(context, graphics, PROBE_SIZE, PROBE_FORMAT)?;
+        let options = PipelineOptions { vertex_input, ..PipelineOptions::reflection_probe() };
+        let pipeline = Self::create_pipeline(graphics, config, &target, set_layout, &options)
+            .inspect_err(|_| target.cleanup(graphics))?;
+        let (near, far) = PROBE_DEPTH_RANGE;
+        let view_projs = matrix::cube_face_view_projs(PROBE_POSITION, near, far);
+        Ok(Self { target, pipeline, options, view_projs })
+    }
+
+    fn create_pipeline(graphics: &Graphics, config: &SwapchainConfig, target: &CubeTarget, set_layout: vk::DescriptorSetLayout, options: &PipelineOptions) -> Result<RenderPipeline> {
+        let mut config = config.clone();
+        config.extent = target.image.extent();
+        RenderPipeline::new(&graphics.logical, &config, &target.render_pass, set_layout, options)
+    }
+
+    /// Rebuilds the pipeline for another vertex input, e.g. after the mesh was replaced. The device must be idle.
+    /// On failure the old pipeline is kept, so `cleanup` still releases everything.
+    pub fn recreate_pipeline(&mut self, graphics: &Graphics, config: &SwapchainConfig, set_layout: vk::DescriptorSetLayout, vertex_input: VertexInput) -> Result<()> {
+        let options = PipelineOptions { vertex_input, ..PipelineOptions::reflection_probe() };
+        let pipeline = Self::create_pipeline(graphics, config, &self.target, set_layout, &options)?;
+        self.pipeline.cleanup(&graphics.logical.instance);
+        self.pipeline = pipeline;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Records one pass per face of `target`, cleared to the scene's clear color, drawing its mesh with the model matrix
+    /// of its descriptor set.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, and the scene's buffers must hold its mesh.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, scene: &FrameScene) {
+        for (face, view_proj) in self.view_projs.iter().enumerate() {
+            self.target.begin(device, command_buffer, face, scene.clear_color);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.instance());
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout(), 0, &[scene.descriptor_set], &[]);
+            ProbeFace { view_proj: (*view_proj).into() }.push(device, command_buffer, self.pipeline.layout());
+            scene.mesh.record_draw(device, command_buffer, scene.vertex_buffers, scene.index_buffer);
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.pipeline.cleanup(&graphics.logical.instance);
+        self.options.cleanup(&graphics.logical.instance);
+        self.target.cleanup(graphics);
+    }
+}
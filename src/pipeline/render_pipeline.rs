@@ -1,12 +1,13 @@
-use anyhow::{Result};
-use std::{ffi::CString};
+use anyhow::{anyhow, Result};
+use std::{ffi::CStr, fmt::{self, Display, Formatter}, path::PathBuf, time::Instant};
 use ash::{vk, Device};
 use cgmath::{vec2, vec3};
 
 use crate::{
+    core::{context::VulkanContext, logical_device::GraphicsInterface},
     math::vector::Vec2, 
     math::vector::Vec3,
-    pipeline::{shader::Shader, traits::VulkanPipeline}, 
+    pipeline::{shader::Shader, traits::{VertexFormat, VulkanPipeline}}, 
     swapchain::SwapchainConfig
 };
 
@@ -18,18 +19,34 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    const fn new(p: Vec2, color: Vec3) -> Self {
+    pub const fn new(p: Vec2, color: Vec3) -> Self {
         Self { pos: p, color }
     }
 
-    fn binding_description() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::default()
+    pub fn position(&self) -> Vec2 {
+        self.pos
+    }
+
+    /// Axis-aligned quad in pixel coordinates for `Projection::Orthographic2D`, wound to match `INDICES`.
+    pub fn quad(x: f32, y: f32, width: f32, height: f32, color: Vec3) -> [Self; 4] {
+        [
+            Self::new(vec2(x, y), color),
+            Self::new(vec2(x + width, y), color),
+            Self::new(vec2(x + width, y + height), color),
+            Self::new(vec2(x, y + height), color),
+        ]
+    }
+}
+
+impl VertexFormat for Vertex {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::default()
             .binding(0)
             .stride(size_of::<Vertex>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
+            .input_rate(vk::VertexInputRate::VERTEX)]
     }
 
-    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
         let p_desc = vk::VertexInputAttributeDescription::default()
             .binding(0)
             .location(0)
@@ -42,7 +59,125 @@ impl Vertex {
             .format(vk::Format::R32G32B32_SFLOAT)
             .offset(size_of::<Vec2>() as u32);
 
-        [p_desc, color_desc]
+        vec![p_desc, color_desc]
+    }
+}
+
+/// Same attributes as `Vertex`, with positions in binding 0 and colors in binding 1 instead of interleaved.
+pub struct SeparateAttributes;
+
+impl VertexFormat for SeparateAttributes {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        let positions = vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Vec2>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        let colors = vk::VertexInputBindingDescription::default()
+            .binding(1)
+            .stride(size_of::<Vec3>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        vec![positions, colors]
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        let p_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(0);
+
+        let color_desc = vk::VertexInputAttributeDescription::default()
+            .binding(1)
+            .location(1)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0);
+
+        vec![p_desc, color_desc]
+    }
+}
+
+/// Vertex bindings and attributes a pipeline reads, taken from a `VertexFormat`.
+#[derive(Clone, Debug)]
+pub struct VertexInput {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>
+}
+
+impl VertexInput {
+    pub fn of<V: VertexFormat>() -> Self {
+        Self { bindings: V::binding_descriptions(), attributes: V::attribute_descriptions() }
+    }
+
+    /// Single interleaved binding with one attribute per format at consecutive locations,
+    /// each placed right after the previous one, e.g. a `R32G32B32_SFLOAT` position followed by an
+    /// `A2B10G10R10_SNORM_PACK32` normal for a 16 byte vertex.
+    pub fn interleaved(formats: &[vk::Format]) -> Result<Self> {
+        let mut attributes = Vec::with_capacity(formats.len());
+        let mut offset = 0;
+        for (location, format) in formats.iter().enumerate() {
+            let size = vertex_format_size(*format).ok_or(anyhow!(PipelineError::UnsupportedVertexFormat(*format)))?;
+            attributes.push(vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(location as u32)
+                .format(*format)
+                .offset(offset));
+            offset += size;
+        }
+        let binding = vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(offset)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        Ok(Self { bindings: vec![binding], attributes })
+    }
+
+    /// One vertex buffer has to be bound per binding when drawing.
+    pub fn binding_count(&self) -> usize {
+        self.bindings.len()
+    }
+
+    /// Every attribute format must be usable from a vertex buffer on the device, see `bufferFeatures`.
+    pub fn validate(&self, context: &VulkanContext, physical_device: vk::PhysicalDevice) -> Result<()> {
+        for attribute in &self.attributes {
+            let properties = unsafe { context.instance.get_physical_device_format_properties(physical_device, attribute.format) };
+            if !properties.buffer_features.contains(vk::FormatFeatureFlags::VERTEX_BUFFER) {
+                return Err(anyhow!(PipelineError::UnsupportedVertexFormat(attribute.format)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether pipelines built for `self` cannot read vertices laid out as `other`.
+    pub fn differs_from(&self, other: &VertexInput) -> bool {
+        let binding_differs = |a: &vk::VertexInputBindingDescription, b: &vk::VertexInputBindingDescription|
+            a.binding != b.binding || a.stride != b.stride || a.input_rate != b.input_rate;
+        let attribute_differs = |a: &vk::VertexInputAttributeDescription, b: &vk::VertexInputAttributeDescription|
+            a.binding != b.binding || a.location != b.location || a.format != b.format || a.offset != b.offset;
+        self.bindings.len() != other.bindings.len()
+            || self.attributes.len() != other.attributes.len()
+            || self.bindings.iter().zip(&other.bindings).any(|(a, b)| binding_differs(a, b))
+            || self.attributes.iter().zip(&other.attributes).any(|(a, b)| attribute_differs(a, b))
+    }
+}
+
+/// Bytes an attribute of `format` takes in a vertex buffer, `None` for formats not supported by `VertexInput::interleaved`.
+pub fn vertex_format_size(format: vk::Format) -> Option<u32> {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT => Some(4),
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => Some(8),
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => Some(12),
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT => Some(16),
+        vk::Format::R16G16_SFLOAT | vk::Format::R16G16_UNORM | vk::Format::R16G16_SNORM => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SNORM => Some(8),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8B8A8_UINT => Some(4),
+        // Packed formats hold all components in a single 32 bit word, e.g. normals and tangents
+        vk::Format::A2B10G10R10_SNORM_PACK32 | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_SNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => Some(4),
+        _ => None
+    }
+}
+
+impl Default for VertexInput {
+    fn default() -> Self {
+        Self::of::<Vertex>()
     }
 }
 
@@ -55,115 +190,611 @@ pub static VERTICES: [Vertex; 4] = [
 
 pub static INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
 
+#[derive(Debug)]
+pub enum PipelineError {
+    UnsupportedFeature(&'static str),
+    UnsupportedVertexFormat(vk::Format),
+    /// Logic operations replace blending, an attachment cannot use both.
+    LogicOpWithBlending,
+    /// A flipped viewport was requested on a Vulkan 1.0 device without `VK_KHR_maintenance1`.
+    NegativeViewportUnsupported,
+    /// Line widths must be positive and finite, before being clamped to the device range.
+    InvalidLineWidth(f32)
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedFeature(name) => write!(f, "The `{}` device feature is required but was not enabled.", name),
+            Self::UnsupportedVertexFormat(format) => write!(f, "{:?} cannot be read from a vertex buffer.", format),
+            Self::LogicOpWithBlending => write!(f, "A logic operation cannot be combined with blending."),
+            Self::NegativeViewportUnsupported => write!(f, "Flipping the viewport needs Vulkan 1.1 or `VK_KHR_maintenance1`, the device has neither."),
+            Self::InvalidLineWidth(width) => write!(f, "{} is not a valid line width.", width),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Tessellation control and evaluation stages, drawn as patches of `patch_control_points` vertices.
+pub struct Tessellation {
+    pub control: Shader,
+    pub evaluation: Shader,
+    pub patch_control_points: u32
+}
+
+/// Compiled SPIR-V of the vertex and fragment stages, looked up like `Shader::new`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderPaths {
+    pub vertex: PathBuf,
+    pub fragment: PathBuf
+}
+
+impl ShaderPaths {
+    pub fn new(vertex: impl Into<PathBuf>, fragment: impl Into<PathBuf>) -> Self {
+        Self { vertex: vertex.into(), fragment: fragment.into() }
+    }
+}
+
+/// The vertex color shaders.
+impl Default for ShaderPaths {
+    fn default() -> Self {
+        Self::new("shaders/shader.vert.spv", "shaders/shader.frag.spv")
+    }
+}
+
+/// How the fragment color is combined with what a color attachment already holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the attachment.
+    #[default]
+    Opaque,
+    /// Classic transparency, `src * src_alpha + dst * (1 - src_alpha)`.
+    Alpha,
+    /// Adds the color weighted by its alpha, e.g. for emissive or particle targets.
+    Additive
+}
+
+impl BlendMode {
+    pub fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .alpha_blend_op(vk::BlendOp::ADD);
+        match self {
+            Self::Opaque => state
+                .blend_enable(false)
+                .src_color_blend_factor(vk::BlendFactor::ONE)
+                .dst_color_blend_factor(vk::BlendFactor::ZERO)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ZERO),
+            Self::Alpha => state
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            Self::Additive => state
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        }
+    }
+}
+
+/// Depth test of a pipeline rendering into a subpass with a depth attachment.
+/// Reverse-Z, which spreads precision evenly over the view distance, compares with `GREATER` and only works
+/// together with a depth clear value of 0.0 and a projection mapping the near plane to 1 and the far plane to 0.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthTest {
+    pub compare_op: vk::CompareOp,
+    /// Disabled for geometry tested against the depth of earlier draws without occluding later ones, e.g. transparent
+    /// surfaces or decals drawn with `LESS_OR_EQUAL` over the same geometry.
+    pub write: bool
+}
+
+impl Default for DepthTest {
+    fn default() -> Self {
+        Self { compare_op: vk::CompareOp::LESS, write: true }
+    }
+}
+
+/// Push constants of `PipelineOptions::inverted_hull`, read by both outline shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub color: [f32; 4],
+    /// Factor the mesh is scaled by around its origin, slightly above 1 for a thin outline.
+    pub scale: f32
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self { color: [0.0, 0.0, 0.0, 1.0], scale: 1.05 }
+    }
+}
+
+impl Outline {
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<Self>() as u32)
+    }
+
+    /// Pushes the outline for the draws that follow, `layout` being that of an `inverted_hull` pipeline.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording with a pipeline of `layout` bound.
+    pub unsafe fn push(&self, device: &Device, command_buffer: vk::CommandBuffer, layout: vk::PipelineLayout) {
+        let range = Self::push_constant_range();
+        let bytes = std::slice::from_raw_parts((self as *const Self).cast::<u8>(), std::mem::size_of::<Self>());
+        device.cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, bytes);
+    }
+}
+
+/// Push constants of `PipelineOptions::shadow_caster`, the light matrix of the shadow map layer drawn into.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowCaster {
+    pub light_view_proj: [[f32; 4]; 4]
+}
+
+impl ShadowCaster {
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(std::mem::size_of::<Self>() as u32)
+    }
+
+    /// Pushes the light matrix for the draws that follow, `layout` being that of a `shadow_caster` pipeline.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording with a pipeline of `layout` bound.
+    pub unsafe fn push(&self, device: &Device, command_buffer: vk::CommandBuffer, layout: vk::PipelineLayout) {
+        let range = Self::push_constant_range();
+        let bytes = std::slice::from_raw_parts((self as *const Self).cast::<u8>(), std::mem::size_of::<Self>());
+        device.cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, bytes);
+    }
+}
+
+/// Push constants of `PipelineOptions::reflection_probe`, the view projection of the cubemap face drawn into.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProbeFace {
+    pub view_proj: [[f32; 4]; 4]
+}
+
+impl ProbeFace {
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(std::mem::size_of::<Self>() as u32)
+    }
+
+    /// Pushes the face's matrix for the draws that follow, `layout` being that of a `reflection_probe` pipeline.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording with a pipeline of `layout` bound.
+    pub unsafe fn push(&self, device: &Device, command_buffer: vk::CommandBuffer, layout: vk::PipelineLayout) {
+        let range = Self::push_constant_range();
+        let bytes = std::slice::from_raw_parts((self as *const Self).cast::<u8>(), std::mem::size_of::<Self>());
+        device.cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, bytes);
+    }
+}
+
+/// Optional pipeline features. The default is the plain vertex + fragment pipeline.
+#[derive(Default)]
+pub struct PipelineOptions {
+    /// Vertex color shaders by default, a textured pipeline also needs a layout with `TEXTURE_BINDING`.
+    pub shaders: ShaderPaths,
+    /// Interleaved `Vertex` by default.
+    pub vertex_input: VertexInput,
+    pub tessellation: Option<Tessellation>,
+    pub geometry: Option<Shader>,
+    /// Constant factor, clamp and slope factor applied to fragment depth, typically to avoid shadow acne.
+    pub depth_bias: Option<(f32, f32, f32)>,
+    /// Tests and writes depth, `None` for subpasses without a depth attachment unless `depth_only` is set.
+    pub depth_test: Option<DepthTest>,
+    /// Discards fragments whose stored depth falls outside `(min, max)`.
+    pub depth_bounds: Option<(f32, f32)>,
+    /// Clamps fragment depth to the near and far planes instead of clipping, as needed by shadow maps.
+    pub depth_clamp: bool,
+    /// Combines fragment colors with the attachment bitwise, e.g. `XOR` for UI cursors, instead of writing them.
+    /// Replaces blending on every attachment and only applies to integer and normalized formats, not to float or sRGB ones.
+    pub logic_op: Option<vk::LogicOp>,
+    /// One mode per color attachment of the subpass, in attachment order. Empty means a single opaque attachment.
+    pub blend: Vec<BlendMode>,
+    /// Flips y with a negative viewport height, so content authored for a y up clip space renders upright
+    /// without touching its matrices. Needs Vulkan 1.1 or `VK_KHR_maintenance1`, see `negative_viewport_height`.
+    pub flip_viewport_y: bool,
+    /// Width of rasterized lines in pixels, 1.0 when `None`. Other widths need `wideLines` and are clamped to
+    /// `lineWidthRange` then snapped to `lineWidthGranularity`, see `DeviceLimits::snap_line_width`.
+    pub line_width: Option<f32>,
+    /// Shades at least this fraction of the samples of each pixel separately instead of once per pixel, clamped to
+    /// [0, 1]. Reduces aliasing inside triangles, e.g. of alpha tested foliage, when rendering with several samples,
+    /// at the cost of running the fragment shader up to once per sample. Needs `sampleRateShading`.
+    pub sample_shading: Option<f32>,
+    /// Faces culled before rasterization, back faces when `None`.
+    pub cull_mode: Option<vk::CullModeFlags>,
+    /// Push constants the shaders read, in the pipeline layout next to the descriptor set layout.
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    /// Writes depth only, for shadow maps rendered with `render_target::create_depth_only_render_pass`.
+    /// The fragment shader of `shaders` is not loaded, no color attachment is written and depth is tested with
+    /// `depth_test`, the default `LESS` with writes when it is `None`.
+    pub depth_only: bool,
+    /// Allow other pipelines to derive from this one.
+    pub allow_derivatives: bool,
+    /// Existing pipeline to derive from, which must have been created with `allow_derivatives`.
+    pub base_pipeline: Option<vk::Pipeline>
+}
+
+impl PipelineOptions {
+    /// Inverted hull outline of meshes with their position at location 0, drawn along with the mesh: the mesh is scaled up by `Outline::scale`
+    /// in a solid color with its front faces culled, so only the rim of its back faces shows around the original.
+    /// Needs closed meshes, planar ones have no back faces facing the camera.
+    pub fn inverted_hull() -> Self {
+        Self {
+            shaders: ShaderPaths::new("shaders/outline.vert.spv", "shaders/outline.frag.spv"),
+            cull_mode: Some(vk::CullModeFlags::FRONT),
+            depth_test: Some(DepthTest::default()),
+            push_constant_ranges: vec![Outline::push_constant_range()],
+            ..Self::default()
+        }
+    }
+
+    /// Renders meshes with their position at location 0 into a shadow map, transformed by their model matrix and the
+    /// light matrix pushed as `ShadowCaster`. Both faces are drawn, planar meshes cast shadows from either side, and
+    /// a slope scaled depth bias keeps lit surfaces from shadowing themselves.
+    pub fn shadow_caster() -> Self {
+        Self {
+            // Depth-only pipelines load no fragment shader
+            shaders: ShaderPaths::new("shaders/shadow.vert.spv", ""),
+            depth_only: true,
+            depth_bias: Some((1.25, 0.0, 1.75)),
+            cull_mode: Some(vk::CullModeFlags::NONE),
+            push_constant_ranges: vec![ShadowCaster::push_constant_range()],
+            ..Self::default()
+        }
+    }
+
+    /// Vertex colored meshes drawn into a face of a `CubeTarget` with the matrix pushed as `ProbeFace`, in place of the
+    /// camera's view and projection. The faces keep y up, which flips the winding, so no faces are culled.
+    pub fn reflection_probe() -> Self {
+        Self {
+            shaders: ShaderPaths::new("shaders/probe.vert.spv", "shaders/shader.frag.spv"),
+            depth_test: Some(DepthTest::default()),
+            cull_mode: Some(vk::CullModeFlags::NONE),
+            push_constant_ranges: vec![ProbeFace::push_constant_range()],
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self, features: &vk::PhysicalDeviceFeatures) -> Result<()> {
+        if self.tessellation.is_some() && features.tessellation_shader == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("tessellationShader")));
+        }
+        if self.geometry.is_some() && features.geometry_shader == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("geometryShader")));
+        }
+        if self.depth_bias.is_some_and(|(_, clamp, _)| clamp != 0.0) && features.depth_bias_clamp == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("depthBiasClamp")));
+        }
+        if self.depth_bounds.is_some() && features.depth_bounds == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("depthBounds")));
+        }
+        if self.depth_clamp && features.depth_clamp == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("depthClamp")));
+        }
+        if self.sample_shading.is_some() && features.sample_rate_shading == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("sampleRateShading")));
+        }
+        if self.logic_op.is_some() && features.logic_op == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("logicOp")));
+        }
+        let blend = self.blend_modes();
+        if self.logic_op.is_some() && blend.iter().any(|mode| *mode != BlendMode::Opaque) {
+            return Err(anyhow!(PipelineError::LogicOpWithBlending));
+        }
+        if let Some(width) = self.line_width {
+            if !width.is_finite() || width <= 0.0 {
+                return Err(anyhow!(PipelineError::InvalidLineWidth(width)));
+            }
+            if width != 1.0 && features.wide_lines == vk::FALSE {
+                return Err(anyhow!(PipelineError::UnsupportedFeature("wideLines")));
+            }
+        }
+        if blend.windows(2).any(|pair| pair[0] != pair[1]) && features.independent_blend == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("independentBlend")));
+        }
+        Ok(())
+    }
+
+    /// `blend`, or a single opaque attachment when it is empty. Depth-only pipelines have no attachment to blend.
+    pub fn blend_modes(&self) -> Vec<BlendMode> {
+        match (self.depth_only, self.blend.is_empty()) {
+            (true, _) => vec![],
+            (false, true) => vec![BlendMode::Opaque],
+            (false, false) => self.blend.clone()
+        }
+    }
+
+    fn create_flags(&self) -> vk::PipelineCreateFlags {
+        let mut flags = vk::PipelineCreateFlags::empty();
+        if self.allow_derivatives { flags |= vk::PipelineCreateFlags::ALLOW_DERIVATIVES; }
+        if self.base_pipeline.is_some() { flags |= vk::PipelineCreateFlags::DERIVATIVE; }
+        flags
+    }
+
+    fn depth_stencil_state(&self) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+        // Scene passes always have a depth attachment, which then requires a state even with every test disabled
+        let depth_test = self.depth_test.or(self.depth_only.then(DepthTest::default));
+        let (min, max) = self.depth_bounds.unwrap_or((0.0, 1.0));
+        let test = depth_test.unwrap_or_default();
+        vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(depth_test.is_some())
+            .depth_write_enable(depth_test.is_some() && test.write)
+            .depth_compare_op(test.compare_op)
+            .depth_bounds_test_enable(self.depth_bounds.is_some())
+            .min_depth_bounds(min)
+            .max_depth_bounds(max)
+    }
+
+    pub fn cleanup(&self, logical_device: &Device) {
+        if let Some(tessellation) = &self.tessellation {
+            tessellation.control.cleanup(logical_device);
+            tessellation.evaluation.cleanup(logical_device);
+        }
+        if let Some(geometry) = &self.geometry {
+            geometry.cleanup(logical_device);
+        }
+    }
+}
+
+/// Per-pipeline state derived from a set of options, kept alive until the pipelines are created.
+struct PipelineStates<'a> {
+    stages: Vec<vk::PipelineShaderStageCreateInfo<'a>>,
+    vertex_input: vk::PipelineVertexInputStateCreateInfo<'a>,
+    input_assembly: vk::PipelineInputAssemblyStateCreateInfo<'a>,
+    tessellation: Option<vk::PipelineTessellationStateCreateInfo<'a>>,
+    rasterization: vk::PipelineRasterizationStateCreateInfo<'a>,
+    depth_stencil: vk::PipelineDepthStencilStateCreateInfo<'a>,
+    layout: vk::PipelineLayout,
+    flags: vk::PipelineCreateFlags
+}
+
+impl<'a> PipelineStates<'a> {
+    fn new(device: &GraphicsInterface, options: &'a PipelineOptions, vert: &Shader, frag: Option<&Shader>, set_layout: vk::DescriptorSetLayout, flags: vk::PipelineCreateFlags) -> Result<Self> {
+        let main: &'static CStr = c"main";
+        let mut stages = vec![
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert.instance)
+                .name(main)
+        ];
+        if let Some(frag) = frag {
+            stages.push(vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag.instance)
+                .name(main));
+        }
+        if let Some(tessellation) = &options.tessellation {
+            stages.push(vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::TESSELLATION_CONTROL)
+                .module(tessellation.control.instance)
+                .name(main));
+            stages.push(vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::TESSELLATION_EVALUATION)
+                .module(tessellation.evaluation.instance)
+                .name(main));
+        }
+        if let Some(geometry) = &options.geometry {
+            stages.push(vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::GEOMETRY)
+                .module(geometry.instance)
+                .name(main));
+        }
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&options.vertex_input.bindings)
+            .vertex_attribute_descriptions(&options.vertex_input.attributes);
+
+        // Tessellation consumes patches rather than assembled triangles
+        let topology = if options.tessellation.is_some() { vk::PrimitiveTopology::PATCH_LIST } else { vk::PrimitiveTopology::TRIANGLE_LIST };
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(topology)
+            .primitive_restart_enable(false);
+        let tessellation = options.tessellation.as_ref().map(|t| 
+            vk::PipelineTessellationStateCreateInfo::default()
+                .patch_control_points(t.patch_control_points)
+        );
+
+        let (bias_constant, bias_clamp, bias_slope) = options.depth_bias.unwrap_or((0.0, 0.0, 0.0));
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(options.depth_clamp)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(options.line_width.map_or(1.0, |width| device.limits.snap_line_width(width)))
+            .cull_mode(options.cull_mode.unwrap_or(vk::CullModeFlags::BACK))
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(options.depth_bias.is_some())
+            .depth_bias_constant_factor(bias_constant)
+            .depth_bias_clamp(bias_clamp)
+            .depth_bias_slope_factor(bias_slope);
+
+        let depth_stencil = options.depth_stencil_state();
+
+        let layout = RenderPipeline::create_layout(&device.instance, set_layout, &options.push_constant_ranges)?;
+        Ok(Self { stages, vertex_input, input_assembly, tessellation, rasterization, depth_stencil, layout, flags })
+    }
+}
+
 pub struct RenderPipeline {
     vk_instance: vk::Pipeline,
     vk_layout: vk::PipelineLayout
 }
 
 impl RenderPipeline {
-    fn create_layout(logical_device: &Device, set_layout: vk::DescriptorSetLayout) -> Result<vk::PipelineLayout> {
-        let set_layouts = &[set_layout];
-        let layout_info = vk::PipelineLayoutCreateInfo::default()
-            .set_layouts(set_layouts);
-        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
-        Ok(layout)
+    /// Pipeline running `shaders` with otherwise default options, for a descriptor set of `set_layout`.
+    pub fn with_shaders(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, shaders: ShaderPaths) -> Result<Self> {
+        let options = PipelineOptions { shaders, ..Default::default() };
+        Self::new(device, config, render_pass, set_layout, &options)
     }
-}
 
-impl VulkanPipeline for RenderPipeline {
-    fn new(logical_device: &Device, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout) -> Result<Self> {
-        let vert = Shader::new("shaders/shader.vert.spv", logical_device)?;
-        let frag = Shader::new("shaders/shader.frag.spv", logical_device)?;
-        let main = CString::new("main")?;
-        let vert_stage = vk::PipelineShaderStageCreateInfo::default()
-            .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vert.instance)
-            .name(main.as_c_str());
-        let frag_stage = vk::PipelineShaderStageCreateInfo::default()
-            .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(frag.instance)
-            .name(main.as_c_str());
-
-        let binding_descriptions = &[Vertex::binding_description()];
-        let attribute_descriptions = Vertex::attribute_descriptions();
-        let vert_input_state = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_binding_descriptions(binding_descriptions)
-            .vertex_attribute_descriptions(&attribute_descriptions);
-        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false);
+    /// Creates `base` and `variants` deriving from it in a single `create_graphics_pipelines` call.
+    /// The returned pipelines are in the same order, starting with the base.
+    pub fn create_many(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, base: &PipelineOptions, variants: &[PipelineOptions]) -> Result<Vec<Self>> {
+        let options = std::iter::once(base).chain(variants.iter()).collect::<Vec<_>>();
+        Self::create_pipelines(device, config, render_pass, set_layout, &options, true)
+    }
+
+    fn create_pipelines(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, options: &[&PipelineOptions], derive_from_first: bool) -> Result<Vec<Self>> {
+        for variant in options {
+            variant.validate(&device.features)?;
+            if variant.flip_viewport_y && !device.negative_viewport_height {
+                return Err(anyhow!(PipelineError::NegativeViewportUnsupported));
+            }
+        }
+        // Every variant binds a single descriptor set
+        device.limits.validate_set_layout_count(1)?;
+        let logical_device = &device.instance;
+        let shaders = options.iter()
+            .map(|variant| {
+                let vert = Shader::new(&variant.shaders.vertex, logical_device)?;
+                let frag = match variant.depth_only {
+                    true => None,
+                    false => Some(Shader::new(&variant.shaders.fragment, logical_device).inspect_err(|_| vert.cleanup(logical_device))?)
+                };
+                Ok((vert, frag))
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        let viewport = vk::Viewport::default()
-            .x(0.0)
-            .y(0.0)
-            .width(config.extent.width as f32)
-            .height(config.extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0);
+        let (width, height) = (config.extent.width as f32, config.extent.height as f32);
+        let viewports = options.iter()
+            .map(|variant| {
+                // Starting from the bottom edge with a negative height maps clip space y up to the top of the image
+                let (y, height) = if variant.flip_viewport_y { (height, -height) } else { (0.0, height) };
+                [vk::Viewport::default()
+                    .x(0.0)
+                    .y(y)
+                    .width(width)
+                    .height(height)
+                    .min_depth(0.0)
+                    .max_depth(1.0)]
+            })
+            .collect::<Vec<_>>();
         let scissor = vk::Rect2D::default()
             .offset(vk::Offset2D { x: 0, y: 0 })
             .extent(config.extent);
-        let viewports = &[viewport];
         let scissors = &[scissor];
-        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
-            .viewports(viewports)
-            .scissors(scissors);
-        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .depth_bias_enable(false);
+        let viewport_states = viewports.iter()
+            .map(|viewports| vk::PipelineViewportStateCreateInfo::default()
+                .viewports(viewports)
+                .scissors(scissors))
+            .collect::<Vec<_>>();
 
-        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let multisample_states = options.iter()
+            .map(|variant| vk::PipelineMultisampleStateCreateInfo::default()
+                .sample_shading_enable(variant.sample_shading.is_some())
+                .min_sample_shading(variant.sample_shading.map_or(0.0, |fraction| fraction.clamp(0.0, 1.0)))
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1))
+            .collect::<Vec<_>>();
 
-        // Blending can be changed here
-        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(vk::ColorComponentFlags::RGBA)
-            .blend_enable(false)
-            .src_color_blend_factor(vk::BlendFactor::ONE)
-            .dst_color_blend_factor(vk::BlendFactor::ZERO)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-            .alpha_blend_op(vk::BlendOp::ADD);
-        let attachments = &[color_blend_attachment_state];
-        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
-            .logic_op_enable(false)
-            .logic_op(vk::LogicOp::COPY)
-            .attachments(attachments)
-            .blend_constants([0.0, 0.0, 0.0, 0.0]);
-        
-        let layout = Self::create_layout(logical_device, set_layout)?;
-        let stages = &[vert_stage, frag_stage];
-        let graphics_pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(stages)
-            .vertex_input_state(&vert_input_state)
-            .input_assembly_state(&input_assembly_state)
-            .viewport_state(&viewport_state)
-            .rasterization_state(&rasterization_state)
-            .multisample_state(&multisample_state)
-            .color_blend_state(&color_blend_state)
-            .layout(layout)
-            .render_pass(*render_pass)
-            .subpass(0)
-            .base_pipeline_handle(vk::Pipeline::null())
-            .base_pipeline_index(-1);
-        let pipeline = unsafe { 
+        let blend_attachments = options.iter()
+            .map(|variant| {
+                let attachments = variant.blend_modes().into_iter().map(BlendMode::attachment_state).collect::<Vec<_>>();
+                device.limits.validate_color_attachments(attachments.len() as u32)?;
+                Ok(attachments)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let color_blend_states = options.iter().zip(blend_attachments.iter())
+            .map(|(variant, attachments)| vk::PipelineColorBlendStateCreateInfo::default()
+                .logic_op_enable(variant.logic_op.is_some())
+                .logic_op(variant.logic_op.unwrap_or(vk::LogicOp::COPY))
+                .attachments(attachments)
+                .blend_constants([0.0, 0.0, 0.0, 0.0]))
+            .collect::<Vec<_>>();
+
+        let states = options.iter().zip(shaders.iter()).enumerate()
+            .map(|(i, (variant, (vert, frag)))| {
+                let flags = match (derive_from_first, i) {
+                    (false, _) => variant.create_flags(),
+                    (true, 0) => vk::PipelineCreateFlags::ALLOW_DERIVATIVES,
+                    (true, _) => vk::PipelineCreateFlags::DERIVATIVE
+                };
+                PipelineStates::new(device, variant, vert, frag.as_ref(), set_layout, flags)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let graphics_pipeline_infos = states.iter().zip(options.iter()).enumerate()
+            .map(|(i, (state, variant))| {
+                let (base_handle, base_index) = match (derive_from_first, i) {
+                    (false, _) => (variant.base_pipeline.unwrap_or(vk::Pipeline::null()), -1),
+                    (true, 0) => (vk::Pipeline::null(), -1),
+                    (true, _) => (vk::Pipeline::null(), 0)
+                };
+                let mut info = vk::GraphicsPipelineCreateInfo::default()
+                    .flags(state.flags)
+                    .stages(&state.stages)
+                    .vertex_input_state(&state.vertex_input)
+                    .input_assembly_state(&state.input_assembly)
+                    .viewport_state(&viewport_states[i])
+                    .rasterization_state(&state.rasterization)
+                    .multisample_state(&multisample_states[i])
+                    .depth_stencil_state(&state.depth_stencil)
+                    .color_blend_state(&color_blend_states[i])
+                    .layout(state.layout)
+                    .render_pass(*render_pass)
+                    .subpass(0)
+                    .base_pipeline_handle(base_handle)
+                    .base_pipeline_index(base_index);
+                if let Some(tessellation) = &state.tessellation {
+                    info = info.tessellation_state(tessellation);
+                }
+                info
+            })
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let pipelines = unsafe { 
             logical_device.create_graphics_pipelines(
                 vk::PipelineCache::null(), 
-                &[graphics_pipeline_info], 
+                &graphics_pipeline_infos, 
                 None
-            ).as_ref().unwrap()[0]
+            ).map_err(|(_, e)| anyhow!(e))?
         };
-        unsafe { logical_device.destroy_shader_module(vert.instance, None) };
-        unsafe { logical_device.destroy_shader_module(frag.instance, None) };
+        log::debug!("Created {} pipeline(s) in {:?}", pipelines.len(), start.elapsed());
+        shaders.iter().for_each(|(vert, frag)| {
+            vert.cleanup(logical_device);
+            if let Some(frag) = frag {
+                frag.cleanup(logical_device);
+            }
+        });
+
+        Ok(pipelines.into_iter()
+            .zip(states.iter())
+            .map(|(pipeline, state)| Self { vk_instance: pipeline, vk_layout: state.layout })
+            .collect())
+    }
+
+    fn create_layout(logical_device: &Device, set_layout: vk::DescriptorSetLayout, push_constant_ranges: &[vk::PushConstantRange]) -> Result<vk::PipelineLayout> {
+        let set_layouts = &[set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
+        Ok(layout)
+    }
+}
 
-        Ok(Self{vk_instance: pipeline, vk_layout: layout})
+impl VulkanPipeline for RenderPipeline {
+    fn new(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, options: &PipelineOptions) -> Result<Self> {
+        let mut pipelines = Self::create_pipelines(device, config, render_pass, set_layout, &[options], false)?;
+        Ok(pipelines.remove(0))
     }
 
     fn instance(&self) -> vk::Pipeline {
@@ -179,4 +810,56 @@ impl VulkanPipeline for RenderPipeline {
         unsafe { logical_device.destroy_pipeline_layout(self.vk_layout, None); }
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth_state(depth_test: Option<DepthTest>) -> (vk::Bool32, vk::Bool32, vk::CompareOp) {
+        let state = PipelineOptions { depth_test, ..PipelineOptions::default() }.depth_stencil_state();
+        (state.depth_test_enable, state.depth_write_enable, state.depth_compare_op)
+    }
+
+    #[test]
+    fn default_depth_test_is_less_with_writes() {
+        assert_eq!(depth_state(Some(DepthTest::default())), (vk::TRUE, vk::TRUE, vk::CompareOp::LESS));
+    }
+
+    #[test]
+    fn reverse_z_compares_greater() {
+        let test = DepthTest { compare_op: vk::CompareOp::GREATER, ..DepthTest::default() };
+        assert_eq!(depth_state(Some(test)), (vk::TRUE, vk::TRUE, vk::CompareOp::GREATER));
+    }
+
+    #[test]
+    fn writes_can_be_disabled() {
+        let test = DepthTest { compare_op: vk::CompareOp::LESS_OR_EQUAL, write: false };
+        assert_eq!(depth_state(Some(test)), (vk::TRUE, vk::FALSE, vk::CompareOp::LESS_OR_EQUAL));
+        assert_eq!(depth_state(None), (vk::FALSE, vk::FALSE, vk::CompareOp::LESS));
+    }
+
+    #[test]
+    fn depth_only_tests_by_default() {
+        let state = PipelineOptions { depth_only: true, ..PipelineOptions::default() }.depth_stencil_state();
+        assert_eq!((state.depth_test_enable, state.depth_write_enable), (vk::TRUE, vk::TRUE));
+    }
+
+    #[test]
+    fn shadow_caster_writes_depth_only() {
+        let options = PipelineOptions::shadow_caster();
+        let state = options.depth_stencil_state();
+        assert_eq!((state.depth_test_enable, state.depth_write_enable, state.depth_compare_op), (vk::TRUE, vk::TRUE, vk::CompareOp::LESS));
+        assert!(options.blend_modes().is_empty());
+        assert_eq!(options.push_constant_ranges[0].size, 64);
+    }
+
+    #[test]
+    fn reflection_probe_draws_both_faces_with_depth() {
+        let options = PipelineOptions::reflection_probe();
+        let state = options.depth_stencil_state();
+        assert_eq!((state.depth_test_enable, state.depth_write_enable), (vk::TRUE, vk::TRUE));
+        assert_eq!(options.cull_mode, Some(vk::CullModeFlags::NONE));
+        assert_eq!(options.blend_modes().len(), 1);
+    }
+}
@@ -1,29 +1,173 @@
-use anyhow::{Result};
-use std::{env, path::PathBuf};
+use anyhow::{anyhow, Result};
+use std::{env, fmt::{self, Display, Formatter}, io::Cursor, path::{Path, PathBuf}};
 use ash::{vk, Device};
 
+const SPIRV_MAGIC: u32 = 0x07230203;
+/// Directory holding compiled shaders, searched first by file name.
+const SHADER_DIR_VAR: &str = "VULCOR_SHADER_DIR";
+
+#[derive(Debug)]
+pub enum ShaderError {
+    NotFound(Vec<PathBuf>),
+    Empty,
+    MisalignedLength(usize),
+    InvalidMagic(u32),
+    /// The code could not be converted to words, e.g. because it was read from a failing stream.
+    Unreadable(std::io::Error)
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(searched) => write!(f, "Shader not found, searched => {:?}", searched),
+            Self::Empty => write!(f, "Shader code is empty."),
+            Self::MisalignedLength(len) => write!(f, "Shader code length ({} bytes) is not a multiple of 4, the file is likely truncated.", len),
+            Self::InvalidMagic(magic) => write!(f, "Shader code starts with {:#010x} instead of the SPIR-V magic number, was the shader compiled?", magic),
+            Self::Unreadable(error) => write!(f, "Shader code could not be read as SPIR-V words => {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
 pub struct Shader {
     pub instance: vk::ShaderModule
 }
 impl Shader {
-    pub fn new<P: AsRef<std::path::Path>>(path: P, logical_device: &Device) -> Result<Self> {
-        let code = Self::read_shader_file(path)?;
+    pub fn new<P: AsRef<Path>>(path: P, logical_device: &Device) -> Result<Self> {
+        Self::new_searching(path, &Self::search_paths(), logical_device)
+    }
+
+    /// Loads the first `root/path` that exists, trying `roots` in order.
+    pub fn new_searching<P: AsRef<Path>>(path: P, roots: &[PathBuf], logical_device: &Device) -> Result<Self> {
+        let target = Self::find_shader_file(path.as_ref(), roots)?;
+        let code = Self::read_shader_file(target)?;
         let instance = Self::create_shader_module(logical_device, &code)?;
         Ok(Self{instance})
     }
 
-    fn read_shader_file<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<u32>> {
-        let current_dir = env::current_dir()?;
-        let mut target = PathBuf::from(current_dir);
-        target.push(path);
+    /// Default roots, after `$VULCOR_SHADER_DIR`: the working directory, the executable directory and the crate directory.
+    pub fn search_paths() -> Vec<PathBuf> {
+        let mut roots = vec![];
+        if let Ok(current_dir) = env::current_dir() {
+            roots.push(current_dir);
+        }
+        if let Some(exe_dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+            roots.push(exe_dir);
+        }
+        roots.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        roots
+    }
+
+    fn find_shader_file(path: &Path, roots: &[PathBuf]) -> Result<PathBuf> {
+        let mut candidates = vec![];
+        if let (Some(dir), Some(name)) = (env::var_os(SHADER_DIR_VAR), path.file_name()) {
+            candidates.push(PathBuf::from(dir).join(name));
+        }
+        candidates.extend(roots.iter().map(|root| root.join(path)));
+        match candidates.iter().find(|candidate| candidate.is_file()) {
+            Some(found) => Ok(found.clone()),
+            None => Err(anyhow!(ShaderError::NotFound(candidates)))
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &Device) {
+        unsafe { logical_device.destroy_shader_module(self.instance, None) };
+    }
+
+    fn read_shader_file(target: PathBuf) -> Result<Vec<u32>> {
         log::debug!("Loading shader => {}", target.to_string_lossy());
-        let mut file = std::fs::File::open(target)?;
-        Ok(ash::util::read_spv(&mut file)?)
+        let bytes = std::fs::read(&target)?;
+        Self::parse_spirv(&bytes)
+    }
+
+    /// Converts `bytes` to SPIR-V words in the host endianness. The length and magic number are checked before
+    /// `read_spv` does, which would only report a generic I/O error.
+    fn parse_spirv(bytes: &[u8]) -> Result<Vec<u32>> {
+        let Some(first_word) = bytes.first_chunk::<4>() else {
+            return Err(anyhow!(if bytes.is_empty() { ShaderError::Empty } else { ShaderError::MisalignedLength(bytes.len()) }));
+        };
+        if !bytes.len().is_multiple_of(4) {
+            return Err(anyhow!(ShaderError::MisalignedLength(bytes.len())));
+        }
+        // Modules written on a host of the other endianness are swapped by `read_spv`
+        let magic = u32::from_le_bytes(*first_word);
+        if magic != SPIRV_MAGIC && magic.swap_bytes() != SPIRV_MAGIC {
+            return Err(anyhow!(ShaderError::InvalidMagic(magic)));
+        }
+        ash::util::read_spv(&mut Cursor::new(bytes)).map_err(|error| anyhow!(ShaderError::Unreadable(error)))
+    }
+
+    /// Rejects code that is not SPIR-V, rather than letting the driver build an invalid module.
+    fn validate_spirv(code: &[u32]) -> Result<()> {
+        match code.first() {
+            None => Err(anyhow!(ShaderError::Empty)),
+            Some(&magic) if magic != SPIRV_MAGIC => Err(anyhow!(ShaderError::InvalidMagic(magic))),
+            _ => Ok(())
+        }
     }
 
     fn create_shader_module(logical_device: &Device, code: &[u32]) -> Result<vk::ShaderModule> {
+        Self::validate_spirv(code)?;
         let create_info = vk::ShaderModuleCreateInfo::default().code(code);
         let module = unsafe { logical_device.create_shader_module(&create_info, None)? };
         Ok(module)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<u8> {
+        // Magic, version 1.0, generator, bound and schema, as written at the start of every module
+        [SPIRV_MAGIC, 0x0001_0000, 0, 1, 0].iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn shader_error(result: Result<Vec<u32>>) -> ShaderError {
+        result.err().unwrap().downcast::<ShaderError>().unwrap()
+    }
+
+    #[test]
+    fn valid_header_is_parsed() {
+        let code = Shader::parse_spirv(&header()).unwrap();
+        assert_eq!(code.len(), 5);
+        assert_eq!(code[0], SPIRV_MAGIC);
+
+        // Big endian modules are swapped to the host order
+        let swapped = header().chunks(4).flat_map(|word| word.iter().rev().copied().collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(Shader::parse_spirv(&swapped).unwrap(), code);
+    }
+
+    #[test]
+    fn empty_code_is_rejected() {
+        assert!(matches!(shader_error(Shader::parse_spirv(&[])), ShaderError::Empty));
+        assert!(matches!(Shader::validate_spirv(&[]).err().unwrap().downcast::<ShaderError>().unwrap(), ShaderError::Empty));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let garbage = b"not a shader, just text".repeat(4);
+        let length = garbage.len() / 4 * 4;
+        assert!(matches!(shader_error(Shader::parse_spirv(&garbage[..length])), ShaderError::InvalidMagic(_)));
+    }
+
+    #[test]
+    fn truncated_code_is_rejected() {
+        let mut code = header();
+        code.pop();
+        assert!(matches!(shader_error(Shader::parse_spirv(&code)), ShaderError::MisalignedLength(19)));
+    }
+
+    #[test]
+    fn shader_file_is_read_and_checked() {
+        let path = env::temp_dir().join(format!("vulcor-shader-{}.spv", std::process::id()));
+        std::fs::write(&path, header()).unwrap();
+        let valid = Shader::read_shader_file(path.clone());
+        std::fs::write(&path, [0u8; 6]).unwrap();
+        let truncated = Shader::read_shader_file(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(valid.unwrap()[0], SPIRV_MAGIC);
+        assert!(matches!(shader_error(truncated), ShaderError::MisalignedLength(6)));
+    }
+}
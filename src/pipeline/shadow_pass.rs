@@ -0,0 +1,93 @@
+use anyhow::Result;
+use ash::{vk, Device};
+
+use crate::{
+    cmd::command_pool::MeshRange,
+    core::{context::VulkanContext, graphics::Graphics},
+    math::{camera::{Camera, Cascade}, vector::Vec3},
+    pipeline::{render_pipeline::{PipelineOptions, RenderPipeline, ShadowCaster, VertexInput}, traits::VulkanPipeline},
+    resources::{buffer::Buffer, render_target::DepthTarget},
+    swapchain::SwapchainConfig
+};
+
+/// Width and height of each layer of the shadow map.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+/// Layers of the shadow map, one per cascade of `Camera::shadow_cascades`.
+pub const SHADOW_CASCADES: u32 = 4;
+/// Sampleable as a depth attachment on every device, unlike the 32-bit formats.
+pub const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D16_UNORM;
+/// Direction the light shines in, from above and slightly behind the default camera.
+pub const LIGHT_DIR: Vec3 = Vec3::new(-0.3, -0.2, -1.0);
+
+/// Renders the mesh from the light into a layered `DepthTarget` before the scene pass, one cascade of what the camera
+/// sees per layer. The map is left in `DEPTH_STENCIL_READ_ONLY_OPTIMAL`, see `DepthTarget::descriptor_image_info`.
+pub struct ShadowPass {
+    pub target: DepthTarget,
+    pipeline: RenderPipeline,
+    options: PipelineOptions,
+    /// Light matrix of each layer of `target`, pushed when the command buffers are recorded.
+    cascades: Vec<Cascade>
+}
+
+impl ShadowPass {
+    /// `set_layout` and `vertex_input` are those of the scene pipeline, whose descriptor sets and buffers are reused.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, config: &SwapchainConfig, set_layout: vk::DescriptorSetLayout, vertex_input: VertexInput, camera: &Camera) -> Result<Self> {
+        let extent = vk::Extent2D { width: SHADOW_MAP_SIZE, height: SHADOW_MAP_SIZE };
+        let target = DepthTarget::layered(context, graphics, extent, SHADOW_MAP_FORMAT, SHADOW_CASCADES)?;
+        let options = PipelineOptions { vertex_input, ..PipelineOptions::shadow_caster() };
+        let pipeline = Self::create_pipeline(graphics, config, &target, set_layout, &options)
+            .inspect_err(|_| target.cleanup(graphics))?;
+        let mut shadows = Self { target, pipeline, options, cascades: vec![] };
+        shadows.update(camera);
+        Ok(shadows)
+    }
+
+    fn create_pipeline(graphics: &Graphics, config: &SwapchainConfig, target: &DepthTarget, set_layout: vk::DescriptorSetLayout, options: &PipelineOptions) -> Result<RenderPipeline> {
+        let mut config = config.clone();
+        config.extent = target.extent;
+        RenderPipeline::new(&graphics.logical, &config, &target.render_pass, set_layout, options)
+    }
+
+    /// Fits the cascades to what `camera` sees, e.g. after a resize changed its aspect ratio. Their matrices are pushed
+    /// by `record`, so command buffers have to be recorded again. 2D content casts no shadows.
+    pub fn update(&mut self, camera: &Camera) {
+        self.cascades = camera.shadow_cascades(LIGHT_DIR, SHADOW_CASCADES as usize);
+    }
+
+    /// Nearest first, the cascade at index `i` being rendered into layer `i` of `target`.
+    pub fn cascades(&self) -> &[Cascade] {
+        &self.cascades
+    }
+
+    /// Rebuilds the pipeline for another vertex input, e.g. after the mesh was replaced. The device must be idle.
+    /// On failure the old pipeline is kept, so `cleanup` still releases everything.
+    pub fn recreate_pipeline(&mut self, graphics: &Graphics, config: &SwapchainConfig, set_layout: vk::DescriptorSetLayout, vertex_input: VertexInput) -> Result<()> {
+        let options = PipelineOptions { vertex_input, ..PipelineOptions::shadow_caster() };
+        let pipeline = Self::create_pipeline(graphics, config, &self.target, set_layout, &options)?;
+        self.pipeline.cleanup(&graphics.logical.instance);
+        self.pipeline = pipeline;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Records one depth pass per cascade into its layer of `target`, drawing `mesh` with the model matrix of `descriptor_set`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, and the buffers must hold the range.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, mesh: MeshRange, vertex_buffers: &[Buffer], index_buffer: Option<&Buffer>) {
+        for (layer, cascade) in self.cascades.iter().enumerate() {
+            self.target.begin(device, command_buffer, layer);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.instance());
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.layout(), 0, &[descriptor_set], &[]);
+            ShadowCaster { light_view_proj: cascade.light_view_proj.into() }.push(device, command_buffer, self.pipeline.layout());
+            mesh.record_draw(device, command_buffer, vertex_buffers, index_buffer);
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.pipeline.cleanup(&graphics.logical.instance);
+        self.options.cleanup(&graphics.logical.instance);
+        self.target.cleanup(graphics);
+    }
+}
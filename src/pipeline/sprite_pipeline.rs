@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use std::{ffi::CStr, time::Instant};
+use ash::{vk, Device};
+use cgmath::vec2;
+
+use crate::{
+    core::logical_device::GraphicsInterface,
+    math::vector::{Vec2, Vec4},
+    pipeline::{render_pipeline::{BlendMode, PipelineOptions}, shader::Shader, traits::{VertexFormat, VulkanPipeline}},
+    swapchain::SwapchainConfig
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SpriteVertex {
+    pos: Vec2,
+    uv: Vec2,
+    color: Vec4,
+}
+
+impl SpriteVertex {
+    pub const fn new(pos: Vec2, uv: Vec2, color: Vec4) -> Self {
+        Self { pos, uv, color }
+    }
+
+    /// Two counter-clockwise triangles covering `position` to `position + size`, in pixel coordinates.
+    /// `uv` holds the min and max texture coordinates.
+    pub fn quad(position: Vec2, size: Vec2, uv: (Vec2, Vec2), color: Vec4) -> [Self; 6] {
+        let (min, max) = (position, position + size);
+        let (uv_min, uv_max) = uv;
+        let top_left = Self::new(min, uv_min, color);
+        let top_right = Self::new(vec2(max.x, min.y), vec2(uv_max.x, uv_min.y), color);
+        let bottom_right = Self::new(max, uv_max, color);
+        let bottom_left = Self::new(vec2(min.x, max.y), vec2(uv_min.x, uv_max.y), color);
+        [top_left, top_right, bottom_right, bottom_right, bottom_left, top_left]
+    }
+}
+
+impl VertexFormat for SpriteVertex {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<SpriteVertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)]
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        let p_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(0);
+
+        let uv_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(size_of::<Vec2>() as u32);
+
+        let color_desc = vk::VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((size_of::<Vec2>() * 2) as u32);
+        vec![p_desc, uv_desc, color_desc]
+    }
+}
+
+/// Alpha blended pipeline drawing textured `SpriteVertex` quads.
+/// Set 0 is the uniform layout shared with the main pipeline, set 1 holds the sprite texture.
+pub struct SpritePipeline {
+    vk_instance: vk::Pipeline,
+    vk_layout: vk::PipelineLayout,
+    pub texture_layout: vk::DescriptorSetLayout
+}
+
+impl SpritePipeline {
+    fn create_texture_layout(logical_device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let bindings = &[binding];
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    fn create_layout(logical_device: &Device, set_layouts: &[vk::DescriptorSetLayout]) -> Result<vk::PipelineLayout> {
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
+        Ok(layout)
+    }
+}
+
+impl VulkanPipeline for SpritePipeline {
+    /// Only the options validated by every pipeline apply, sprites have no tessellation or geometry stage.
+    fn new(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, options: &PipelineOptions) -> Result<Self> {
+        options.validate(&device.features)?;
+        let logical_device = &device.instance;
+        let vert = Shader::new("shaders/sprite.vert.spv", logical_device)?;
+        let frag = Shader::new("shaders/sprite.frag.spv", logical_device)?;
+        let main: &'static CStr = c"main";
+        let stages = &[
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert.instance)
+                .name(main),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag.instance)
+                .name(main)
+        ];
+
+        let binding_descriptions = SpriteVertex::binding_descriptions();
+        let attribute_descriptions = SpriteVertex::attribute_descriptions();
+        let vert_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&binding_descriptions)
+            .vertex_attribute_descriptions(&attribute_descriptions);
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .primitive_restart_enable(false);
+
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(config.extent.width as f32)
+            .height(config.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        let scissor = vk::Rect2D::default()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(config.extent);
+        let viewports = &[viewport];
+        let scissors = &[scissor];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewports(viewports)
+            .scissors(scissors);
+
+        // Sprites may be mirrored with negative sizes, so neither face is culled
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(false);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // Sprites are drawn on top in screen space, without testing against the scene's depth attachment
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let attachments = &[BlendMode::Alpha.attachment_state()];
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+        let texture_layout = Self::create_texture_layout(logical_device)?;
+        let layout = Self::create_layout(logical_device, &[set_layout, texture_layout])?;
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(stages)
+            .vertex_input_state(&vert_input_state)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend_state)
+            .layout(layout)
+            .render_pass(*render_pass)
+            .subpass(0);
+
+        let start = Instant::now();
+        let pipeline = unsafe {
+            logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)
+                .map_err(|(_, e)| anyhow!(e))?[0]
+        };
+        log::debug!("Created sprite pipeline in {:?}", start.elapsed());
+        vert.cleanup(logical_device);
+        frag.cleanup(logical_device);
+        Ok(Self { vk_instance: pipeline, vk_layout: layout, texture_layout })
+    }
+
+    fn instance(&self) -> vk::Pipeline {
+        self.vk_instance
+    }
+
+    fn layout(&self) -> vk::PipelineLayout {
+        self.vk_layout
+    }
+
+    fn cleanup(&self, logical_device: &Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.vk_instance, None);
+            logical_device.destroy_pipeline_layout(self.vk_layout, None);
+            logical_device.destroy_descriptor_set_layout(self.texture_layout, None);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{anyhow, Result};
+use std::ffi::CStr;
+use ash::{vk, Device};
+
+use crate::{
+    core::{context::VulkanContext, graphics::Graphics},
+    pipeline::{fullscreen_pass::FULLSCREEN_VERTEX_SHADER, shader::Shader},
+    resources::{image::{self, Image}, render_target::{self, DepthAttachment}},
+    swapchain::SwapchainData
+};
+
+/// Format the scene is rendered in during the first subpass.
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+const HDR_ATTACHMENT: u32 = 0;
+const OUTPUT_ATTACHMENT: u32 = 1;
+const DEPTH_ATTACHMENT: u32 = 2;
+/// Subpass the scene pipelines must be created for.
+pub const SCENE_SUBPASS: u32 = 0;
+const TONE_MAP_SUBPASS: u32 = 1;
+
+/// Render pass rendering the scene in HDR in subpass 0, then tone mapping it to the swapchain image in subpass 1.
+/// The HDR color is read back as an input attachment with a by-region dependency, so on tiled GPUs it never leaves tile memory.
+/// The scene subpass tests against a shared `DepthAttachment`. The attachments are sized after the swapchain,
+/// the pass has to be recreated with it.
+pub struct ToneMapPass {
+    pub render_pass: vk::RenderPass,
+    hdr: Image,
+    hdr_view: vk::ImageView,
+    framebuffers: Vec<vk::Framebuffer>,
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline
+}
+
+impl ToneMapPass {
+    pub fn new(context: &VulkanContext, graphics: &Graphics, swapchain: &SwapchainData, depth: &DepthAttachment) -> Result<Self> {
+        let logical_device = &graphics.logical.instance;
+        let extent = swapchain.extent();
+        let render_pass = Self::create_render_pass(logical_device, swapchain.format(), depth)?;
+        let hdr = Image::new_transient(
+            context,
+            graphics,
+            (extent.width, extent.height),
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+            HDR_FORMAT,
+            vk::SampleCountFlags::TYPE_1
+        )?;
+        let hdr_view = Self::create_hdr_view(logical_device, &hdr)?;
+        let framebuffers = (0..swapchain.image_count())
+            .map(|i| {
+                let attachments = &[hdr_view, swapchain.image_views[i], depth.view];
+                let create_info = vk::FramebufferCreateInfo::default()
+                    .render_pass(render_pass)
+                    .attachments(attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1);
+                unsafe { logical_device.create_framebuffer(&create_info, None) }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let set_layout = Self::create_set_layout(logical_device)?;
+        let (descriptor_pool, descriptor_set) = Self::create_descriptor_set(logical_device, set_layout, hdr_view)?;
+        let (layout, pipeline) = Self::create_pipeline(logical_device, extent, render_pass, set_layout)?;
+        Ok(Self { render_pass, hdr, hdr_view, framebuffers, set_layout, descriptor_pool, descriptor_set, layout, pipeline })
+    }
+
+    pub fn framebuffer(&self, image_index: usize) -> vk::Framebuffer {
+        self.framebuffers[image_index]
+    }
+
+    /// Clear values in attachment order, the output is entirely overwritten and is not cleared.
+    pub fn clear_values(clear_color: [f32; 4]) -> [vk::ClearValue; 3] {
+        [
+            vk::ClearValue { color: vk::ClearColorValue { float32: clear_color } },
+            vk::ClearValue::default(),
+            vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }
+        ]
+    }
+
+    /// Moves on to the tone mapping subpass and draws it. Recorded after the scene, before `cmd_end_render_pass`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording in the scene subpass of `render_pass`.
+    pub unsafe fn record_tone_map(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.layout, 0, &[self.descriptor_set], &[]);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        let device = &graphics.logical.instance;
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.framebuffers.iter()
+                .for_each(|f| device.destroy_framebuffer(*f, None));
+            device.destroy_image_view(self.hdr_view, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+        self.hdr.cleanup(graphics);
+    }
+
+    fn create_render_pass(logical_device: &Device, output_format: vk::Format, depth: &DepthAttachment) -> Result<vk::RenderPass> {
+        let hdr_attachment = vk::AttachmentDescription::default()
+            .format(HDR_FORMAT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let output_attachment = vk::AttachmentDescription::default()
+            .format(output_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+
+        let hdr_write = &[vk::AttachmentReference::default()
+            .attachment(HDR_ATTACHMENT)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let hdr_read = &[vk::AttachmentReference::default()
+            .attachment(HDR_ATTACHMENT)
+            .layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let output_write = &[vk::AttachmentReference::default()
+            .attachment(OUTPUT_ATTACHMENT)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let depth_reference = DepthAttachment::reference(DEPTH_ATTACHMENT);
+        let subpasses = &[
+            vk::SubpassDescription::default()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(hdr_write)
+                .depth_stencil_attachment(&depth_reference),
+            vk::SubpassDescription::default()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .input_attachments(hdr_read)
+                .color_attachments(output_write)
+        ];
+        let dependencies = &[
+            // The HDR and depth attachments are shared by all images, the previous frame must be done with them
+            vk::SubpassDependency::default()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(SCENE_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER | DepthAttachment::STAGES)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | DepthAttachment::STAGES)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            // The acquired swapchain image is only written in the second subpass
+            vk::SubpassDependency::default()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(TONE_MAP_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE),
+            // Each fragment only reads the HDR texel at its own position, so the dependency can be framebuffer-local
+            vk::SubpassDependency::default()
+                .src_subpass(SCENE_SUBPASS)
+                .dst_subpass(TONE_MAP_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+        ];
+        let attachments = &[hdr_attachment, output_attachment, depth.description()];
+        render_target::validate_sample_counts(attachments)?;
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(attachments)
+            .subpasses(subpasses)
+            .dependencies(dependencies);
+        let render_pass = unsafe { logical_device.create_render_pass(&create_info, None)? };
+        Ok(render_pass)
+    }
+
+    fn create_hdr_view(logical_device: &Device, hdr: &Image) -> Result<vk::ImageView> {
+        image::create_image_view(logical_device, hdr.instance, HDR_FORMAT, vk::ImageAspectFlags::COLOR, 1)
+    }
+
+    fn create_set_layout(logical_device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let bindings = &[binding];
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    fn create_descriptor_set(logical_device: &Device, set_layout: vk::DescriptorSetLayout, hdr_view: vk::ImageView) -> Result<(vk::DescriptorPool, vk::DescriptorSet)> {
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::INPUT_ATTACHMENT)
+            .descriptor_count(1);
+        let pool_sizes = &[pool_size];
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(1);
+        let pool = unsafe { logical_device.create_descriptor_pool(&create_info, None)? };
+        let layouts = &[set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(layouts);
+        let set = unsafe { logical_device.allocate_descriptor_sets(&allocate_info)?[0] };
+        // Input attachments are read with `subpassLoad`, without a sampler
+        let image_info = &[vk::DescriptorImageInfo::default()
+            .image_view(hdr_view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .image_info(image_info);
+        unsafe { logical_device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]) };
+        Ok((pool, set))
+    }
+
+    fn create_pipeline(logical_device: &Device, extent: vk::Extent2D, render_pass: vk::RenderPass, set_layout: vk::DescriptorSetLayout) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let vert = Shader::new(FULLSCREEN_VERTEX_SHADER, logical_device)?;
+        let frag = Shader::new("shaders/tonemap.frag.spv", logical_device)?;
+        let main: &'static CStr = c"main";
+        let stages = &[
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert.instance)
+                .name(main),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag.instance)
+                .name(main)
+        ];
+
+        let vert_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .primitive_restart_enable(false);
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        let scissor = vk::Rect2D::default()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(extent);
+        let viewports = &[viewport];
+        let scissors = &[scissor];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewports(viewports)
+            .scissors(scissors);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(false);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(false);
+        let attachments = &[color_blend_attachment_state];
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+        let set_layouts = &[set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None)? };
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(stages)
+            .vertex_input_state(&vert_input_state)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample_state)
+            .color_blend_state(&color_blend_state)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(TONE_MAP_SUBPASS);
+        let pipeline = unsafe {
+            logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)
+                .map_err(|(_, e)| anyhow!(e))?[0]
+        };
+        vert.cleanup(logical_device);
+        frag.cleanup(logical_device);
+        Ok((layout, pipeline))
+    }
+}
@@ -1,11 +1,16 @@
 use anyhow::{Result};
 use ash::{vk, Device};
 
-use crate::swapchain::SwapchainConfig;
+use crate::{core::logical_device::GraphicsInterface, pipeline::render_pipeline::PipelineOptions, swapchain::SwapchainConfig};
 
 pub trait VulkanPipeline {
-    fn new(logical_device: &Device, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout) -> Result<Self> where Self: Sized;
+    fn new(device: &GraphicsInterface, config: &SwapchainConfig, render_pass: &vk::RenderPass, set_layout: vk::DescriptorSetLayout, options: &PipelineOptions) -> Result<Self> where Self: Sized;
     fn instance(&self) -> vk::Pipeline;
     fn layout(&self) -> vk::PipelineLayout;
     fn cleanup(&self, device: &Device);
-}
\ No newline at end of file
+}
+/// Describes how vertices are laid out in memory, as one interleaved binding or one binding per attribute stream.
+pub trait VertexFormat {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription>;
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
+}
@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Result};
+use ash::{vk, Device};
+
+use crate::core::{context::VulkanContext, graphics::Graphics};
+
+const QUERY_COUNT: u32 = 2;
+
+/// Measures the GPU time between two points of a command buffer with timestamp queries.
+/// Timestamps are recorded on the graphics queue family. When it reports no valid timestamp bits, the timer does nothing.
+pub struct GpuTimer {
+    pub pool: vk::QueryPool,
+    timestamp_period: f32,
+    /// `timestampValidBits` of the graphics queue family, only the low bits of a timestamp are meaningful.
+    valid_bits: u32
+}
+
+impl GpuTimer {
+    pub fn new(context: &VulkanContext, graphics: &Graphics) -> Result<Self> {
+        let properties = graphics.physical.properties();
+        let families = unsafe { context.instance.get_physical_device_queue_family_properties(graphics.physical.instance) };
+        let valid_bits = families[graphics.queue_family.graphics as usize].timestamp_valid_bits;
+        if valid_bits == 0 {
+            log::warn!("Queue family {} does not support timestamps, GPU timings are disabled", graphics.queue_family.graphics);
+        }
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(QUERY_COUNT);
+        let pool = unsafe { graphics.logical.instance.create_query_pool(&create_info, None)? };
+        Ok(Self { pool, timestamp_period: properties.limits.timestamp_period, valid_bits })
+    }
+
+    /// Number of nanoseconds between two increments of the timestamp counter, as reported by the device.
+    pub fn timestamp_period(&self) -> f32 {
+        self.timestamp_period
+    }
+
+    /// Whether the queue can write timestamps, recording and reading back are no-ops otherwise.
+    pub fn supported(&self) -> bool {
+        self.valid_bits > 0
+    }
+
+    /// Recorded before `begin`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass.
+    pub unsafe fn reset(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if !self.supported() { return; }
+        device.cmd_reset_query_pool(command_buffer, self.pool, 0, QUERY_COUNT);
+    }
+
+    /// Writes the timestamp the frame starts at.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, after `reset`.
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if !self.supported() { return; }
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.pool, 0);
+    }
+
+    /// Writes the timestamp the frame ends at.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, after `begin`.
+    pub unsafe fn end(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if !self.supported() { return; }
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, self.pool, 1);
+    }
+
+    /// Raw duration in nanoseconds, or `None` while the queries are still pending or timestamps are unsupported.
+    pub fn elapsed_ns(&self, graphics: &Graphics) -> Result<Option<u64>> {
+        if !self.supported() {
+            return Ok(None);
+        }
+        let mut timestamps = [0u64; QUERY_COUNT as usize];
+        let result = unsafe { graphics.logical.instance.get_query_pool_results(self.pool, 0, &mut timestamps, vk::QueryResultFlags::TYPE_64) };
+        match result {
+            Ok(()) => {
+                // Bits above the valid ones are undefined, masking the difference also handles a counter wrapping around
+                let ticks = timestamps[1].wrapping_sub(timestamps[0]) & self.mask();
+                Ok(Some((ticks as f64 * self.timestamp_period as f64) as u64))
+            },
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(e) => Err(anyhow!(e))
+        }
+    }
+
+    pub fn elapsed_ms(&self, graphics: &Graphics) -> Result<Option<f64>> {
+        Ok(self.elapsed_ns(graphics)?.map(|ns| ns as f64 / 1_000_000.0))
+    }
+
+    fn mask(&self) -> u64 {
+        if self.valid_bits >= 64 { u64::MAX } else { (1 << self.valid_bits) - 1 }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.destroy_query_pool(self.pool, None) };
+    }
+}
\ No newline at end of file
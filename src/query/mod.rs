@@ -0,0 +1,2 @@
+pub mod gpu_timer;
+pub mod pipeline_statistics;
\ No newline at end of file
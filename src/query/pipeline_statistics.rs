@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+use ash::{vk, Device};
+use std::fmt::{self, Display, Formatter};
+
+use crate::{core::graphics::Graphics, pipeline::render_pipeline::PipelineError};
+
+/// Counters collected, results are written in the order of their bits.
+const STATISTICS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+);
+const COUNTER_COUNT: usize = 6;
+
+/// Draw complexity of the commands recorded between `begin` and `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    pub clipping_invocations: u64,
+    /// Primitives left after clipping, i.e. that reached rasterization.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64
+}
+
+impl Display for PipelineStatistics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "vertices: {}, primitives: {} ({} after clipping), vertex invocations: {}, fragment invocations: {}",
+            self.input_vertices, self.input_primitives, self.clipping_primitives, self.vertex_shader_invocations, self.fragment_shader_invocations
+        )
+    }
+}
+
+/// Pipeline statistics query bracketing a render pass, read back like the `GpuTimer` timestamps.
+pub struct PipelineStatisticsQueryPool {
+    pub pool: vk::QueryPool
+}
+
+impl PipelineStatisticsQueryPool {
+    pub fn new(graphics: &Graphics) -> Result<Self> {
+        if graphics.logical.features.pipeline_statistics_query == vk::FALSE {
+            return Err(anyhow!(PipelineError::UnsupportedFeature("pipelineStatisticsQuery")));
+        }
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::PIPELINE_STATISTICS)
+            .pipeline_statistics(STATISTICS)
+            .query_count(1);
+        let pool = unsafe { graphics.logical.instance.create_query_pool(&create_info, None)? };
+        Ok(Self { pool })
+    }
+
+    /// Recorded before `begin`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass.
+    pub unsafe fn reset(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_reset_query_pool(command_buffer, self.pool, 0, 1);
+    }
+
+    /// Recorded before `cmd_begin_render_pass`, with `end` after `cmd_end_render_pass`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, after `reset`.
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_begin_query(command_buffer, self.pool, 0, vk::QueryControlFlags::empty());
+    }
+
+    /// Stops counting, the results can be read once the command buffer completed.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, after `begin`.
+    pub unsafe fn end(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_end_query(command_buffer, self.pool, 0);
+    }
+
+    /// Counters of the last completed query, or `None` while it is still pending.
+    pub fn results(&self, graphics: &Graphics) -> Result<Option<PipelineStatistics>> {
+        let mut counters = [[0u64; COUNTER_COUNT]; 1];
+        let result = unsafe { graphics.logical.instance.get_query_pool_results(self.pool, 0, &mut counters, vk::QueryResultFlags::TYPE_64) };
+        match result {
+            Ok(()) => {
+                let [input_vertices, input_primitives, vertex_shader_invocations, clipping_invocations, clipping_primitives, fragment_shader_invocations] = counters[0];
+                Ok(Some(PipelineStatistics {
+                    input_vertices,
+                    input_primitives,
+                    vertex_shader_invocations,
+                    clipping_invocations,
+                    clipping_primitives,
+                    fragment_shader_invocations
+                }))
+            },
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(e) => Err(anyhow!(e))
+        }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.destroy_query_pool(self.pool, None) };
+    }
+}
@@ -0,0 +1,1649 @@
+use anyhow::{anyhow, Result};
+use ash::{vk::{self, Extent2D, Handle}, Device};
+use image::{GenericImageView, ImageReader};
+use cgmath::{vec2, vec3, Deg, SquareMatrix};
+use std::{error::Error, ffi::CString, io::Read, mem::{self, ManuallyDrop}, path::Path, ptr::{self, copy_nonoverlapping as memcpy}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+use log::{info};
+use winit::{
+    application::ApplicationHandler, event::{ElementState, MouseButton, WindowEvent}, 
+    event_loop::ActiveEventLoop, 
+    window::{Window, WindowId}
+};
+
+use crate::{
+    cmd::command_pool::{CmdPool, FrameScene, FrameTargets, MeshRange, PresentBlit, Recording}, 
+    core::{context::{Portability, VulkanContext}, debug::{DebugMessenger, ValidationFeatures}, graphics::Graphics, logical_device::DeviceFeatures, physical_device::{HardwarePreferences, QueueFamilyIndices}}, 
+    descriptor::{descriptor_pool::DescriptorPool, globals::Globals}, 
+    math::{camera::Camera, geometry::BoundingSphere, matrix::{Mat4, Projection, MVP}, vector::{Vec2, Vec3, Vec4}}, 
+    overlay::{Overlay, UiCallback, UiInput}, 
+    pipeline::{fullscreen_pass::{self, FullscreenPass}, particle_system::{ParticleSystem, ParticleUpdates}, reflection_probe::ReflectionProbe, shadow_pass::ShadowPass, sprite_pipeline::SpritePipeline, tone_mapping::ToneMapPass, render_pipeline::{DepthTest, Outline, PipelineOptions, RenderPipeline, SeparateAttributes, Vertex, VertexInput, INDICES, VERTICES}, traits::VulkanPipeline}, 
+    resources::{allocator::{MemoryAllocator, MemoryReport}, buffer::Buffer, mesh::{GpuMesh, Mesh}, capture::{self, CaptureError}, image::Image, loader::{self, ColorSpace, ResourceLoader}, render_target::{self, DepthAttachment, RenderTarget}, sampler::{SamplerBuilder, SamplerCache, SamplerPreset}, text_overlay::TextOverlay, uniform_ring::UniformRing}, 
+    benchmark::{BenchmarkStats, FrameStats, GpuFrameTimes, PipelineCreationStats}, swapchain, synchronous, 
+    scene::{ClearScene, MeshScene, Scene}, 
+    swapchain::{FixedResolution, PowerPreference, SwapchainConfig, SwapchainData, SwapchainOptions}
+};
+
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Window resizes are only applied once no new resize event arrived for this long.
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+/// Attempts at creating a swapchain when the surface is transiently unusable, e.g. during rapid resizes.
+const SWAPCHAIN_ATTEMPTS: u32 = 5;
+const SWAPCHAIN_RETRY_DELAY: Duration = Duration::from_millis(20);
+/// Attempts at creating a new surface after `ERROR_SURFACE_LOST_KHR`, e.g. while a compositor restarts.
+const SURFACE_RECOVERY_ATTEMPTS: u32 = 5;
+const SURFACE_RECOVERY_DELAY: Duration = Duration::from_millis(100);
+/// Blocks of `swapchain_memory`, enough for three 1080p offscreen targets in one.
+const SWAPCHAIN_MEMORY_BLOCK_SIZE: vk::DeviceSize = 32 * 1024 * 1024;
+
+/// Winit application creating a `Vulcor` for its window once resumed and rendering it until the window closes.
+pub struct App {
+    name: String,
+    vulcor: Option<Vulcor>,
+    /// Handed to `Vulcor` once the window exists.
+    scene: Option<Box<dyn Scene>>,
+    /// Frames to benchmark before exiting, given by `--benchmark [frames]`.
+    pub benchmark: Option<u32>,
+    /// Draws frame measurements and the cursor over the scene, given by `--hud`.
+    pub hud: bool,
+    /// Handed to `Vulcor` along with the scene.
+    pub options: VulcorOptions,
+    /// Set from any thread to close the renderer, see `shutdown_handle`.
+    shutdown: Arc<AtomicBool>,
+    minimized: bool,
+    start: Instant
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App {
+    pub fn new() -> App {
+        Self::with_scene(Box::new(MeshScene))
+    }
+
+    /// Application rendering `scene` instead of the built-in mesh.
+    pub fn with_scene(scene: Box<dyn Scene>) -> App {
+        Self { name: "Vulcor".to_string(), vulcor: None, scene: Some(scene), benchmark: None, hud: false, options: VulcorOptions::default(), shutdown: Arc::default(), minimized: false, start: Instant::now() }
+    }
+
+    /// Flag that, once set, cleans up and exits the event loop before the next frame, e.g. from a watchdog thread.
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shutdown)
+    }
+
+    /// Stops rendering, destroys every resource and leaves the event loop. Cleanup runs once however often this is reached.
+    fn exit(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(vulcor) = self.vulcor.as_mut() {
+            vulcor.run = false;
+            vulcor.cleanup();
+        }
+        event_loop.exit();
+    }
+}
+
+/// Raw Vulkan handles for interop with other libraries (UI, profilers).
+/// They remain owned by `Vulcor`: the borrow keeps them from outliving it, and they must not be destroyed by the embedder.
+pub struct VulkanHandles<'a> {
+    pub instance: &'a ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: &'a Device,
+    pub graphics_queue: vk::Queue,
+    pub graphics_family: u32,
+    pub queue_families: &'a QueueFamilyIndices
+}
+
+/// Whether a frame can currently be rendered, and why not otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderStatus {
+    Ready,
+    /// Rendering was stopped, typically because the window is closing.
+    Stopped,
+    /// The window has a zero sized client area, there is nothing to present to.
+    Minimized,
+    /// A submission returned `ERROR_DEVICE_LOST`, the device has to be recreated.
+    DeviceLost,
+    /// The surface returned `ERROR_SURFACE_LOST_KHR`, the instance surface has to be recreated.
+    SurfaceLost
+}
+
+/// Why the swapchain was recreated, logged to tell a resize from a driver asking for it over and over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecreationTrigger {
+    /// The window was resized or moved to a monitor of another scale factor.
+    Resize,
+    /// Acquire or present returned `ERROR_OUT_OF_DATE_KHR`.
+    OutOfDate,
+    /// Present returned `SUBOPTIMAL_KHR`.
+    Suboptimal,
+    /// The surface was lost and replaced.
+    SurfaceLost,
+    /// A setting baked into the swapchain or its pipelines changed, e.g. the present mode or vertex layout.
+    Settings
+}
+
+/// Settings the renderer is created with, given to `Vulcor::new`. The device and swapchain ones are kept when
+/// the device is recreated, the instance ones only apply once.
+#[derive(Clone, Debug, Default)]
+pub struct VulcorOptions {
+    /// Heavier validation modes, e.g. best-practices or GPU-assisted validation, off by default.
+    pub validation: ValidationFeatures,
+    pub portability: Portability,
+    /// Which physical device gets selected, e.g. `prefer_integrated` to save power on laptops.
+    pub hardware: HardwarePreferences,
+    pub swapchain: SwapchainOptions,
+    pub device_features: DeviceFeatures,
+    /// Particles simulated by a compute shader and drawn over the mesh, none when `None`.
+    pub particles: Option<u32>,
+    /// Staging buffers of textures loaded in the background alive at once, `loader::DEFAULT_MAX_IN_FLIGHT_UPLOADS` when `None`.
+    pub max_in_flight_uploads: Option<usize>,
+    /// Renders the scene in `tone_mapping::HDR_FORMAT` and tone maps it into the swapchain images, UI included.
+    /// Ignored at a fixed resolution.
+    pub hdr: bool,
+    /// Renders `shadow_pass::SHADOW_CASCADES` cascaded shadow maps of the mesh from `shadow_pass::LIGHT_DIR` before each frame's scene pass.
+    pub shadows: bool,
+    /// Renders the mesh into the six faces of a cubemap around `reflection_probe::PROBE_POSITION` before each frame's
+    /// scene pass, see `ReflectionProbe`.
+    pub reflection_probe: bool
+}
+
+/// Callback given to `Vulcor::set_on_swapchain_recreated`.
+type SwapchainRecreated = Box<dyn FnMut(&SwapchainConfig)>;
+
+/// Buffers the renderer's mesh is drawn from.
+enum Geometry {
+    /// Vertices and indices packed into one buffer by `Mesh::upload`, e.g. the built-in quad.
+    Packed(GpuMesh),
+    /// One buffer per binding of the vertex input, without index buffer for non-indexed geometry.
+    Separate { vertex_buffers: Vec<Buffer>, index_buffer: Option<Buffer> }
+}
+
+impl Geometry {
+    fn vertex_buffers(&self) -> &[Buffer] {
+        match self {
+            Self::Packed(mesh) => std::slice::from_ref(&mesh.buffer),
+            Self::Separate { vertex_buffers, .. } => vertex_buffers
+        }
+    }
+
+    fn index_buffer(&self) -> Option<&Buffer> {
+        match self {
+            Self::Packed(mesh) => Some(&mesh.buffer),
+            Self::Separate { index_buffer, .. } => index_buffer.as_ref()
+        }
+    }
+
+    fn cleanup(&self, graphics: &Graphics) {
+        match self {
+            Self::Packed(mesh) => mesh.cleanup(graphics),
+            Self::Separate { vertex_buffers, index_buffer } => vertex_buffers.iter().chain(index_buffer).for_each(|b| b.cleanup(graphics))
+        }
+    }
+}
+
+pub struct Vulcor {
+    name: String,
+    window: Window,
+    context: VulkanContext,
+    messenger: Option<DebugMessenger>,
+    graphics: Graphics,
+    /// Physical device selection, applied again by `recreate_device`.
+    hardware: HardwarePreferences,
+    device_features: DeviceFeatures,
+    swapchain: SwapchainData,
+    swapchain_options: SwapchainOptions,
+    render_pass: vk::RenderPass,
+    descriptor_pool: DescriptorPool,
+    pipeline: RenderPipeline,
+    pipeline_options: PipelineOptions,
+    /// Pipelines added through `add_pipeline`, in order, recreated with the swapchain from `variant_options`.
+    variants: Vec<RenderPipeline>,
+    variant_options: Vec<PipelineOptions>,
+    framebuffers: Vec<vk::Framebuffer>,
+    /// Shared by the render passes of all images, large enough for both the swapchain and the offscreen targets.
+    depth: DepthAttachment,
+    /// Scene targets at the fixed resolution, empty when rendering at the window size.
+    offscreen_targets: Vec<RenderTarget>,
+    /// Backs `offscreen_targets`, which are all destroyed together, freed along with the swapchain.
+    swapchain_memory: MemoryAllocator,
+    /// Scale each offscreen target into its swapchain image, empty when rendering at the window size.
+    present_passes: Vec<FullscreenPass>,
+    /// Scene pass of `VulcorOptions::hdr`, replacing `render_pass` when rendering at the window size.
+    tone_map: Option<ToneMapPass>,
+    hdr: bool,
+    /// Laid out as `pipeline_options.vertex_input`. Non-indexed geometry, e.g. procedurally generated vertex streams,
+    /// is drawn from `mesh.vertex_count` vertices.
+    geometry: Geometry,
+    mesh: MeshRange,
+    particles: Option<ParticleSystem>,
+    /// See `VulcorOptions::shadows`, its cascades follow `camera`.
+    shadows: Option<ShadowPass>,
+    /// See `VulcorOptions::reflection_probe`.
+    probe: Option<ReflectionProbe>,
+    /// Index of the `inverted_hull` variant and the outline it draws, see `set_outline`.
+    outline: Option<(usize, Outline)>,
+    /// Drawn over the scene while `ui_callback` is set, recreated with the swapchain.
+    overlay: Option<Overlay>,
+    /// See `set_ui_callback`.
+    ui_callback: Option<UiCallback>,
+    /// Forwarded from the window events to `ui_callback`.
+    ui_input: UiInput,
+    /// Steps `particles` before each frame, recreated with the swapchain as it holds one buffer per image.
+    particle_updates: Option<ParticleUpdates>,
+    /// Times every frame while `run_benchmark` runs on a device supporting timestamps, recreated with the swapchain.
+    gpu_frame_times: Option<GpuFrameTimes>,
+    /// Bounds of the mesh before the model matrix, `None` when its vertex type has no position to read.
+    bounds: Option<BoundingSphere>,
+    camera: Camera,
+    /// MVP of each swapchain image, in the slot of the image's descriptor set.
+    uniforms: UniformRing<MVP>,
+    /// `Globals` of each swapchain image, bound next to the MVP.
+    globals: UniformRing<Globals>,
+    texture_image: Image,
+    loader: ResourceLoader,
+    /// Samplers created through `SamplerBuilder`, shared by every texture using the same settings.
+    samplers: SamplerCache,
+    command_pool: CmdPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    recording: Recording,
+    clear_color: [f32; 4],
+    /// Updated and recorded every frame, see `set_scene`.
+    scene: Box<dyn Scene>,
+    on_surface_lost: Option<Box<dyn FnMut()>>,
+    /// See `set_on_swapchain_recreated`.
+    on_swapchain_recreated: Option<SwapchainRecreated>,
+    /// Set by `destroy_swapchain` until `rebuild_swapchain` succeeds, so a failed rebuild is not destroyed twice.
+    swapchain_destroyed: bool,
+    sync: synchronous::RenderSync,
+    /// Requested frames in flight, `sync` may use fewer when the swapchain has fewer images.
+    frames_in_flight: usize,
+    /// Swapchain image handed to the presentation engine last, the one `screenshot` captures.
+    last_presented: Option<usize>,
+    /// Frames rendered so far and when the last one started, for `Globals`.
+    frame: u32,
+    last_frame: Instant,
+    run: bool,
+    /// Unrecoverable loss reported by the last failed frame, if any.
+    lost: Option<RenderStatus>,
+    resized: bool,
+    pending_resize: Option<Instant>,
+    /// Swapchains created after the first one, a steadily growing count points at a recreation loop.
+    swapchain_recreations: u32,
+    start: Instant,
+    /// Set once `cleanup` ran, so dropping afterwards does not destroy anything twice.
+    cleaned_up: bool
+}
+
+impl Vulcor {
+    fn new(window: Window, start: Instant, scene: Box<dyn Scene>, options: VulcorOptions) -> Result<Self, Box<dyn Error>> {
+        info!("Creating application");
+        let title = "Vulcor";
+        let context = VulkanContext::new(CString::new(title)?.as_c_str(), &window, &options.validation, options.portability)?;
+        let messenger = crate::core::debug::setup_debug_messenger(&context, &options.validation);
+        Self::with_context(title.to_string(), window, context, messenger, start, options, scene)
+    }
+
+    /// Creates the device and everything built on it, on top of an existing instance and surface.
+    fn with_context(
+        name: String,
+        window: Window,
+        context: VulkanContext,
+        messenger: Option<DebugMessenger>,
+        start: Instant,
+        options: VulcorOptions,
+        scene: Box<dyn Scene>
+    ) -> Result<Self, Box<dyn Error>> {
+        let VulcorOptions { hardware, swapchain: swapchain_options, device_features, particles: particle_count, max_in_flight_uploads, hdr, shadows: shadows_enabled, reflection_probe, .. } = options;
+        // The instance is handed over to this function, so it is destroyed here when anything fails
+        let graphics = Graphics::new(&context, &hardware, &device_features)
+            .inspect_err(|_| unsafe { Self::destroy_instance(&context, &messenger) })?;
+        // Declared ahead of the rollback so that they outlive the teardown steps borrowing them
+        let (swapchain, depth, render_pass, command_pool, texture_image, mut loader, uniforms, globals, descriptor_pool);
+        let (offscreen_targets, present_passes, tone_map, pipeline, framebuffers, quad, particles, particle_updates, shadows, probe, command_buffers, sync);
+        let mut swapchain_memory = MemoryAllocator::new(SWAPCHAIN_MEMORY_BLOCK_SIZE);
+        let mut rollback = Rollback::new(&graphics);
+        rollback.push(|_| unsafe { Self::destroy_instance(&context, &messenger) });
+        rollback.push(|graphics| unsafe { graphics.logical.instance.destroy_device(None) });
+
+        swapchain = swapchain::SwapchainData::new(&context, &graphics, Some(Self::window_extent(&window)), &swapchain_options, None)?;
+        rollback.push(|graphics| swapchain.cleanup(graphics));
+        let depth_format = DepthAttachment::find_format(&context, &graphics)?;
+        // The color attachments are single-sampled, a depth attachment with any other count fails render pass creation
+        depth = DepthAttachment::new(&context, &graphics, Self::depth_extent(&swapchain, &swapchain_options), depth_format, vk::SampleCountFlags::TYPE_1)?;
+        rollback.push(|graphics| depth.cleanup(graphics));
+        render_pass = Self::create_render_pass(&graphics.logical.instance, &swapchain.config, &depth)?;
+        rollback.push(|graphics| unsafe { graphics.logical.instance.destroy_render_pass(render_pass, None) });
+        command_pool = CmdPool::resettable(&graphics.logical, graphics.queue_family.graphics)?;
+        rollback.push(|graphics| unsafe { graphics.logical.instance.destroy_command_pool(command_pool.instance, None) });
+        texture_image = unsafe { Self::create_texture_image(&context, &graphics)? };
+        rollback.push(|graphics| texture_image.cleanup(graphics));
+        loader = ResourceLoader::new(&graphics, max_in_flight_uploads.unwrap_or(loader::DEFAULT_MAX_IN_FLIGHT_UPLOADS))?;
+        rollback.push(|graphics| loader.cleanup(graphics));
+        uniforms = UniformRing::new(&context, &graphics, swapchain.image_count())?;
+        rollback.push(|graphics| uniforms.cleanup(graphics));
+        globals = UniformRing::new(&context, &graphics, swapchain.image_count())?;
+        rollback.push(|graphics| globals.cleanup(graphics));
+        descriptor_pool = DescriptorPool::new(swapchain.image_count() as u32, &graphics, &uniforms.descriptor_infos(), Some(&globals.descriptor_infos()))?;
+        rollback.push(|graphics| unsafe {
+            descriptor_pool.cleanup(graphics);
+            graphics.logical.instance.destroy_descriptor_set_layout(descriptor_pool.layout, None);
+        });
+        let pipeline_options = PipelineOptions { depth_test: Some(DepthTest::default()), ..PipelineOptions::default() };
+        offscreen_targets = Self::create_offscreen_targets(&context, &graphics, &mut swapchain_memory, &swapchain, &swapchain_options, &depth)
+            .inspect_err(|_| swapchain_memory.cleanup(&graphics))?;
+        rollback.push(|graphics| swapchain_memory.cleanup(graphics));
+        rollback.push(|graphics| offscreen_targets.iter().for_each(|t| t.cleanup(graphics)));
+        // The cache only holds this sampler until it is handed over below
+        let mut samplers = SamplerCache::default();
+        let present_sampler = samplers.preset(&graphics, SamplerPreset::LinearClampEdge)?;
+        rollback.push(move |graphics| unsafe { graphics.logical.instance.destroy_sampler(present_sampler, None) });
+        present_passes = Self::create_present_passes(&graphics, &swapchain, &swapchain_options, &render_pass, &offscreen_targets, present_sampler)?;
+        rollback.push(|graphics| present_passes.iter().for_each(|p| p.cleanup(graphics)));
+        tone_map = Self::create_tone_map(&context, &graphics, &swapchain, &swapchain_options, hdr, &depth)?;
+        rollback.push(|graphics| tone_map.iter().for_each(|t| t.cleanup(graphics)));
+        let (scene_config, scene_pass) = Self::scene_target(&swapchain, &render_pass, &offscreen_targets, tone_map.as_ref());
+        pipeline = RenderPipeline::new(&graphics.logical, &scene_config, scene_pass, descriptor_pool.layout, &pipeline_options)?;
+        rollback.push(|graphics| pipeline.cleanup(&graphics.logical.instance));
+        framebuffers = Self::create_framebuffers(&graphics, &swapchain, &render_pass, &depth)?;
+        rollback.push(|graphics| framebuffers.iter().for_each(|f| unsafe { graphics.logical.instance.destroy_framebuffer(*f, None) }));
+        quad = Mesh::new(VERTICES.to_vec(), INDICES.to_vec()).upload(&context, &graphics, &command_pool)?;
+        rollback.push(|graphics| quad.cleanup(graphics));
+        particles = match particle_count {
+            Some(count) => Some(ParticleSystem::new(&context, &graphics, &command_pool, &scene_config, scene_pass, count)?),
+            None => None
+        };
+        rollback.push(|graphics| particles.iter().for_each(|p| p.cleanup(graphics)));
+        particle_updates = match particles {
+            Some(_) => Some(ParticleUpdates::new(&graphics, swapchain.image_count())?),
+            None => None
+        };
+        rollback.push(|graphics| particle_updates.iter().for_each(|u| u.cleanup(graphics)));
+        let mesh = quad.range();
+        let bounds = Self::vertex_bounds(&VERTICES);
+        let camera = Camera::new(Projection::default(), Self::logical_extent(&swapchain.config, &swapchain_options));
+        shadows = match shadows_enabled {
+            true => Some(ShadowPass::new(&context, &graphics, &swapchain.config, descriptor_pool.layout, pipeline_options.vertex_input.clone(), &camera)?),
+            false => None
+        };
+        rollback.push(|graphics| shadows.iter().for_each(|s| s.cleanup(graphics)));
+        probe = match reflection_probe {
+            true => Some(ReflectionProbe::new(&context, &graphics, &swapchain.config, descriptor_pool.layout, pipeline_options.vertex_input.clone())?),
+            false => None
+        };
+        rollback.push(|graphics| probe.iter().for_each(|p| p.cleanup(graphics)));
+        let blit = PresentBlit::new(&offscreen_targets, &present_passes);
+        let frame_scene = |i: usize| FrameScene {
+            pipeline: &pipeline,
+            variants: &[],
+            vertex_buffers: std::slice::from_ref(&quad.buffer),
+            index_buffer: Some(&quad.buffer),
+            mesh,
+            descriptor_set: descriptor_pool.sets[i],
+            clear_color: CLEAR_COLOR,
+            frustum: camera.frustum(),
+            // The quad is centered on the origin, which the model matrix only rotates around
+            bounds: Some(bounds),
+            particles: particles.as_ref(),
+            shadows: shadows.as_ref(),
+            probe: probe.as_ref(),
+            outline: None,
+            overlay: None,
+            timer: None,
+            scene: scene.as_ref()
+        };
+        // Freed along with their pool
+        let targets = FrameTargets { render_pass: &render_pass, framebuffers: &framebuffers, swapchain: &swapchain, blit: blit.as_ref(), tone_map: tone_map.as_ref() };
+        command_buffers = unsafe { command_pool.create_buffers(&graphics.logical, &targets, frame_scene)? };
+        sync = synchronous::RenderSync::new(&graphics, &swapchain, synchronous::DEFAULT_FRAMES_IN_FLIGHT)?;
+        rollback.disarm();
+        Ok(Self{
+            name,
+            window,
+            context,
+            messenger,
+            graphics,
+            hardware,
+            device_features,
+            swapchain,
+            swapchain_options,
+            render_pass,
+            descriptor_pool,
+            pipeline,
+            pipeline_options,
+            variants: vec![],
+            variant_options: vec![],
+            framebuffers,
+            depth,
+            offscreen_targets,
+            swapchain_memory,
+            present_passes,
+            tone_map,
+            hdr,
+            geometry: Geometry::Packed(quad),
+            mesh,
+            particles,
+            shadows,
+            probe,
+            outline: None,
+            overlay: None,
+            ui_callback: None,
+            ui_input: UiInput::default(),
+            particle_updates,
+            gpu_frame_times: None,
+            bounds: Some(bounds),
+            camera,
+            uniforms,
+            globals,
+            texture_image,
+            loader,
+            samplers,
+            command_pool,
+            command_buffers,
+            recording: Recording::default(),
+            clear_color: CLEAR_COLOR,
+            scene,
+            on_surface_lost: None,
+            on_swapchain_recreated: None,
+            swapchain_destroyed: false,
+            sync,
+            frames_in_flight: synchronous::DEFAULT_FRAMES_IN_FLIGHT,
+            last_presented: None,
+            frame: 0,
+            last_frame: Instant::now(),
+            run: true,
+            lost: None,
+            resized: false,
+            pending_resize: None,
+            swapchain_recreations: 0,
+            start,
+            cleaned_up: false
+        })
+    }
+
+    pub fn handles(&self) -> VulkanHandles<'_> {
+        VulkanHandles {
+            instance: &self.context.instance,
+            physical_device: self.graphics.physical.instance,
+            device: &self.graphics.logical.instance,
+            graphics_queue: self.graphics.queue,
+            graphics_family: self.graphics.queue_family.graphics,
+            queue_families: &self.graphics.queue_family
+        }
+    }
+
+    /// Blocks until every queue of the device is idle. Call it before destroying resources of your own that
+    /// frames in flight may still use, e.g. buffers or pipelines created from `handles()`, or before shutting down.
+    pub fn wait_idle(&self) -> Result<()> {
+        unsafe { self.graphics.logical.instance.device_wait_idle()? };
+        Ok(())
+    }
+
+    /// Blocks until the graphics queue is idle, leaving transfer and async compute work running.
+    /// Enough when the resources only ever were used by rendering, `wait_idle` is the safe choice otherwise.
+    pub fn queue_wait_idle(&self) -> Result<()> {
+        unsafe { self.graphics.logical.instance.queue_wait_idle(self.graphics.queue)? };
+        Ok(())
+    }
+
+    /// Panics if the validation layer reported any warning or error since the messenger was created.
+    /// Messages are only captured with the `validation-harness` feature in builds with validation enabled.
+    #[cfg(feature = "validation-harness")]
+    pub fn assert_no_validation_errors(&self) {
+        if let Some(log) = self.messenger.as_ref().and_then(|m| m.log()) {
+            crate::core::debug::assert_no_validation_errors(log);
+        }
+    }
+
+    fn recreate_swapchain(&mut self, trigger: RecreationTrigger) -> Result<()> {
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        self.destroy_swapchain();
+        self.rebuild_swapchain(trigger)
+    }
+
+    /// Creates the swapchain and everything depending on it, after `destroy_swapchain`.
+    fn rebuild_swapchain(&mut self, trigger: RecreationTrigger) -> Result<()> {
+        let previous_extent = self.swapchain.config.extent;
+        self.swapchain = self.create_swapchain_with_retry()?;
+        self.swapchain_recreations += 1;
+        let extent = self.swapchain.config.extent;
+        log::debug!(
+            "Recreated swapchain #{} on {:?}, {}x{} -> {}x{}",
+            self.swapchain_recreations, trigger, previous_extent.width, previous_extent.height, extent.width, extent.height
+        );
+        // The format and sample count do not depend on the swapchain, the destroyed attachment still knows them
+        let depth_extent = Self::depth_extent(&self.swapchain, &self.swapchain_options);
+        self.depth = DepthAttachment::new(&self.context, &self.graphics, depth_extent, self.depth.format, self.depth.samples)?;
+        self.render_pass = Self::create_render_pass(&self.graphics.logical.instance, &self.swapchain.config, &self.depth)?;
+        self.offscreen_targets = Self::create_offscreen_targets(&self.context, &self.graphics, &mut self.swapchain_memory, &self.swapchain, &self.swapchain_options, &self.depth)?;
+        let present_sampler = self.samplers.preset(&self.graphics, SamplerPreset::LinearClampEdge)?;
+        self.present_passes = Self::create_present_passes(&self.graphics, &self.swapchain, &self.swapchain_options, &self.render_pass, &self.offscreen_targets, present_sampler)?;
+        self.tone_map = Self::create_tone_map(&self.context, &self.graphics, &self.swapchain, &self.swapchain_options, self.hdr, &self.depth)?;
+        let (scene_config, scene_pass) = Self::scene_target(&self.swapchain, &self.render_pass, &self.offscreen_targets, self.tone_map.as_ref());
+        self.pipeline = RenderPipeline::new(&self.graphics.logical, &scene_config, scene_pass, self.descriptor_pool.layout, &self.pipeline_options)?;
+        self.variants = self.variant_options.iter()
+            .map(|options| RenderPipeline::new(&self.graphics.logical, &scene_config, scene_pass, self.descriptor_pool.layout, options))
+            .collect::<Result<_>>()?;
+        if let Some(particles) = self.particles.as_mut() {
+            particles.recreate_pipeline(&self.graphics, &scene_config, scene_pass)?;
+            self.particle_updates = Some(ParticleUpdates::new(&self.graphics, self.swapchain.image_count())?);
+        }
+        if let Some(shadows) = self.shadows.as_mut() {
+            shadows.recreate_pipeline(&self.graphics, &self.swapchain.config, self.descriptor_pool.layout, self.pipeline_options.vertex_input.clone())?;
+        }
+        if let Some(probe) = self.probe.as_mut() {
+            probe.recreate_pipeline(&self.graphics, &self.swapchain.config, self.descriptor_pool.layout, self.pipeline_options.vertex_input.clone())?;
+        }
+        self.framebuffers = Self::create_framebuffers(&self.graphics, &self.swapchain, &self.render_pass, &self.depth)?;
+        self.uniforms = UniformRing::new(&self.context, &self.graphics, self.swapchain.image_count())?;
+        self.globals = UniformRing::new(&self.context, &self.graphics, self.swapchain.image_count())?;
+        self.descriptor_pool = DescriptorPool::with_layout(self.swapchain.image_count() as u32, &self.graphics, &self.uniforms.descriptor_infos(), Some(&self.globals.descriptor_infos()), self.descriptor_pool.layout)?;
+        if self.ui_callback.is_some() {
+            self.overlay = Some(self.create_overlay()?);
+        }
+        if let Some(gpu_frame_times) = self.gpu_frame_times.as_mut() {
+            gpu_frame_times.recreate(&self.context, &self.graphics, self.swapchain.image_count())?;
+        }
+        // The shadow pass pushes light matrices fit to the camera's aspect ratio while recording
+        let (width, height) = Self::logical_extent(&self.swapchain.config, &self.swapchain_options);
+        self.camera.set_viewport(width, height);
+        if let Some(shadows) = self.shadows.as_mut() {
+            shadows.update(&self.camera);
+        }
+        self.rerecord_command_buffers()?;
+        // The device is idle, the previous semaphores and fences can go
+        self.sync.cleanup(&self.graphics);
+        self.sync = synchronous::RenderSync::new(&self.graphics, &self.swapchain, self.frames_in_flight)?;
+        self.last_presented = None;
+        self.swapchain_destroyed = false;
+        if let Some(on_swapchain_recreated) = self.on_swapchain_recreated.as_mut() {
+            on_swapchain_recreated(&self.swapchain.config);
+        }
+        Ok(())
+    }
+
+    /// Creates the swapchain, retrying on errors that resolve themselves once the window settles.
+    /// Every attempt queries the surface capabilities again, so the new extent is picked up.
+    fn create_swapchain_with_retry(&self) -> Result<swapchain::SwapchainData> {
+        let cached = &self.swapchain.config.support;
+        let mut attempt = 1;
+        loop {
+            match swapchain::SwapchainData::new(&self.context, &self.graphics, Some(Self::window_extent(&self.window)), &self.swapchain_options, Some(cached)) {
+                Err(e) if attempt < SWAPCHAIN_ATTEMPTS && Self::is_transient_surface_error(&e) => {
+                    log::warn!("Swapchain creation failed with {}, retrying ({}/{})", e, attempt, SWAPCHAIN_ATTEMPTS - 1);
+                    std::thread::sleep(SWAPCHAIN_RETRY_DELAY);
+                },
+                result => return result
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Client area of `window` in physical pixels, used when the surface lets the swapchain pick its extent.
+    /// Swapchain, render targets and viewports are all sized in physical pixels, never in logical ones,
+    /// so the image stays sharp on HiDPI displays. Divide by `scale_factor` for sizes in logical units.
+    fn window_extent(window: &Window) -> vk::Extent2D {
+        let size = window.inner_size();
+        vk::Extent2D { width: size.width, height: size.height }
+    }
+
+    /// Times the swapchain was recreated since the renderer was created, each logged at debug level with its trigger.
+    pub fn swapchain_recreations(&self) -> u32 {
+        self.swapchain_recreations
+    }
+
+    /// Physical pixels per logical pixel of the monitor the window is on, e.g. 2.0 on a Retina display.
+    pub fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
+    fn is_transient_surface_error(error: &anyhow::Error) -> bool {
+        error.downcast_ref::<vk::Result>()
+            .is_some_and(|e| matches!(*e, vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::ERROR_NATIVE_WINDOW_IN_USE_KHR))
+    }
+
+    /// Extent the scene is rendered at before any surface pre-rotation, i.e. as the user sees it.
+    /// Logical refers to the orientation here, the extent is still in physical pixels.
+    fn logical_extent(config: &SwapchainConfig, options: &SwapchainOptions) -> (f32, f32) {
+        if let Some(resolution) = options.fixed_resolution {
+            return (resolution.extent.width as f32, resolution.extent.height as f32);
+        }
+        let extent = config.extent;
+        if config.is_rotated() {
+            (extent.height as f32, extent.width as f32)
+        } else {
+            (extent.width as f32, extent.height as f32)
+        }
+    }
+
+    /// Tears down the logical device and recreates it with `features`, keeping the window, instance and surface.
+    /// Everything owned by the old device is rebuilt from the defaults: a mesh set with `replace_mesh`, a dropped texture,
+    /// and the pipeline options are lost and have to be set again. Swapchain options are kept.
+    pub fn recreate_device(mut self, features: DeviceFeatures) -> Result<Self, Box<dyn Error>> {
+        info!("Recreating logical device with {:?}", features);
+        self.destroy_device();
+        // `Drop` must not run on the old renderer, its device is gone and the instance moves to the new one.
+        // Only the moved fields are read, the remaining ones are plain handles and the vectors that held them.
+        let this = ManuallyDrop::new(self);
+        let (name, window, context, messenger, start, swapchain_options, scene) = unsafe {(
+            ptr::read(&this.name),
+            ptr::read(&this.window),
+            ptr::read(&this.context),
+            ptr::read(&this.messenger),
+            this.start,
+            ptr::read(&this.swapchain_options),
+            ptr::read(&this.scene)
+        )};
+        let particles = this.particles.as_ref().map(ParticleSystem::count);
+        let max_in_flight_uploads = Some(this.loader.max_in_flight());
+        let options = VulcorOptions { hardware: this.hardware, swapchain: swapchain_options, device_features: features, particles, max_in_flight_uploads, hdr: this.hdr, ..VulcorOptions::default() };
+        Self::with_context(name, window, context, messenger, start, options, scene)
+    }
+
+    /// Adds a pipeline sharing the renderer's descriptor set layout, e.g. with other `shaders`.
+    /// Scenes bind it through `FrameScene::variants` at the returned index.
+    pub fn add_pipeline(&mut self, options: PipelineOptions) -> Result<usize> {
+        options.vertex_input.validate(&self.context, self.graphics.physical.instance)?;
+        let (scene_config, scene_pass) = Self::scene_target(&self.swapchain, &self.render_pass, &self.offscreen_targets, self.tone_map.as_ref());
+        let pipeline = RenderPipeline::new(&self.graphics.logical, &scene_config, scene_pass, self.descriptor_pool.layout, &options)?;
+        self.variants.push(pipeline);
+        self.variant_options.push(options);
+        Ok(self.variants.len() - 1)
+    }
+
+    /// Replaces what gets drawn every frame. Pre-recorded command buffers are recorded again with it.
+    pub fn set_scene(&mut self, scene: Box<dyn Scene>) -> Result<()> {
+        self.scene = scene;
+        self.rerecord_command_buffers()
+    }
+
+    /// Optional features requested when the logical device was created.
+    pub fn device_features(&self) -> &DeviceFeatures {
+        &self.device_features
+    }
+
+    /// Sampler for `builder`, created on first use and destroyed with the device.
+    pub fn sampler(&mut self, builder: SamplerBuilder) -> Result<vk::Sampler> {
+        self.samplers.get(&self.graphics, builder)
+    }
+
+    /// Saves the last presented frame to `path` as RGBA, swizzling BGRA swapchain formats.
+    /// The swapchain must have been created with `TRANSFER_SRC` in `SwapchainOptions::extra_usage`.
+    /// Regions covered by other windows are undefined unless `SwapchainOptions::clipped` is disabled.
+    pub fn screenshot(&self, path: &Path) -> Result<()> {
+        if !self.swapchain.config.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            return Err(anyhow!(CaptureError::NotTransferSource));
+        }
+        let Some(index) = self.last_presented else {
+            return Err(anyhow!("No frame was presented yet."));
+        };
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        let image = self.swapchain.image(index).ok_or_else(|| anyhow!("Swapchain image {} does not exist.", index))?;
+        let pixels = capture::capture_image(
+            &self.context,
+            &self.graphics,
+            &self.command_pool,
+            image,
+            self.swapchain.format(),
+            self.swapchain.extent(),
+            vk::ImageLayout::PRESENT_SRC_KHR
+        )?;
+        pixels.save(path)?;
+        Ok(())
+    }
+
+    /// Device memory currently allocated, e.g. "buffers: 12.0MB, images: 48.0MB, other: 0.0MB" once formatted.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.graphics.memory_usage.report()
+    }
+
+    /// Number of frames the CPU may record ahead of the GPU, clamped to the swapchain image count.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) -> Result<()> {
+        unsafe { self.graphics.logical.instance.device_wait_idle()? };
+        self.frames_in_flight = frames_in_flight;
+        self.sync.cleanup(&self.graphics);
+        self.sync = synchronous::RenderSync::new(&self.graphics, &self.swapchain, frames_in_flight)?;
+        Ok(())
+    }
+
+    /// Switches the present mode policy, e.g. when the machine goes from AC power to battery.
+    pub fn set_power_preference(&mut self, power_preference: PowerPreference) -> Result<()> {
+        if self.swapchain_options.power_preference == power_preference {
+            return Ok(());
+        }
+        self.swapchain_options.power_preference = power_preference;
+        self.recreate_swapchain(RecreationTrigger::Settings)
+    }
+
+    /// Renders `frames` frames as fast as the present mode allows, then restores the power preference.
+    /// CPU frame times are measured from the start of one frame to the start of the next, GPU frame times with
+    /// timestamps around the commands of each frame when the graphics queue supports them.
+    pub fn run_benchmark(&mut self, frames: u32) -> Result<Option<BenchmarkStats>> {
+        let power_preference = self.swapchain_options.power_preference;
+        self.set_power_preference(PowerPreference::Unthrottled)?;
+        info!("Benchmarking {} frames with {:?}", frames, self.swapchain.config.present_mode);
+        self.gpu_frame_times = GpuFrameTimes::new(&self.context, &self.graphics, self.swapchain.image_count(), frames as usize)?;
+        // Pre-recorded command buffers are recorded again with the timers, and once more without them afterwards
+        let rerecord = self.recording == Recording::PreRecorded && self.gpu_frame_times.is_some();
+        if rerecord {
+            unsafe { self.graphics.logical.instance.device_wait_idle()? };
+            self.rerecord_command_buffers()?;
+        }
+        let cpu_times = self.render_frames(frames);
+        unsafe { self.graphics.logical.instance.device_wait_idle()? };
+        let gpu_times = self.gpu_frame_times.take().map(|times| times.finish(&self.graphics)).transpose()?;
+        if rerecord {
+            self.rerecord_command_buffers()?;
+        }
+        self.set_power_preference(power_preference)?;
+        Ok(FrameStats::new(cpu_times?).map(|cpu| BenchmarkStats { cpu, gpu: gpu_times.and_then(FrameStats::new) }))
+    }
+
+    /// CPU time of each of up to `frames` frames, fewer when the window stops being renderable.
+    fn render_frames(&mut self, frames: u32) -> Result<Vec<Duration>> {
+        let mut frame_times = Vec::with_capacity(frames as usize);
+        for _ in 0..frames {
+            if !self.is_renderable() {
+                break;
+            }
+            let start = Instant::now();
+            self.render()?;
+            frame_times.push(start.elapsed());
+        }
+        Ok(frame_times)
+    }
+
+    /// Creates the scene pipeline and the added variants, or an outline variant when there are none, separately
+    /// then through `RenderPipeline::create_many`, destroying them right away. Shader modules are created by both.
+    pub fn benchmark_pipeline_creation(&self) -> Result<PipelineCreationStats> {
+        let (scene_config, scene_pass) = Self::scene_target(&self.swapchain, &self.render_pass, &self.offscreen_targets, self.tone_map.as_ref());
+        let layout = self.descriptor_pool.layout;
+        let outline = PipelineOptions { vertex_input: self.pipeline_options.vertex_input.clone(), ..PipelineOptions::inverted_hull() };
+        let variants = match self.variant_options.is_empty() {
+            true => std::slice::from_ref(&outline),
+            false => &self.variant_options
+        };
+
+        let start = Instant::now();
+        let separate = std::iter::once(&self.pipeline_options).chain(variants)
+            .map(|options| RenderPipeline::new(&self.graphics.logical, &scene_config, scene_pass, layout, options))
+            .collect::<Vec<_>>();
+        let separate_time = start.elapsed();
+        let start = Instant::now();
+        let batched = RenderPipeline::create_many(&self.graphics.logical, &scene_config, scene_pass, layout, &self.pipeline_options, variants);
+        let batched_time = start.elapsed();
+
+        separate.iter().flatten().chain(batched.iter().flatten())
+            .for_each(|pipeline| pipeline.cleanup(&self.graphics.logical.instance));
+        outline.cleanup(&self.graphics.logical.instance);
+        separate.into_iter().collect::<Result<Vec<_>>>()?;
+        batched?;
+        Ok(PipelineCreationStats { pipelines: variants.len() + 1, separate: separate_time, batched: batched_time })
+    }
+
+    /// Renders at `resolution` whatever the window size, or at the window size with `None`.
+    pub fn set_fixed_resolution(&mut self, resolution: Option<FixedResolution>) -> Result<()> {
+        self.swapchain_options.fixed_resolution = resolution;
+        self.recreate_swapchain(RecreationTrigger::Settings)
+    }
+
+    /// `Recording::PreRecorded` skips recording every frame, but content changes then require `recreate_swapchain`.
+    pub fn set_recording(&mut self, recording: Recording) {
+        self.recording = recording;
+    }
+
+    /// Outlines the mesh with an inverted hull, see `PipelineOptions::inverted_hull`. The pipeline is added as a variant
+    /// the first time and kept when the outline is removed. Pre-recorded command buffers need `recreate_swapchain`.
+    pub fn set_outline(&mut self, outline: Option<Outline>) -> Result<()> {
+        let variant = match self.outline {
+            Some((variant, _)) => variant,
+            None if outline.is_none() => return Ok(()),
+            None => self.add_pipeline(PipelineOptions { vertex_input: self.pipeline_options.vertex_input.clone(), ..PipelineOptions::inverted_hull() })?
+        };
+        self.outline = outline.map(|outline| (variant, outline));
+        Ok(())
+    }
+
+    /// Draws a UI over every frame, filled by `ui_callback` with text in pixels of the scene target, given the window input.
+    /// Replaces the previous callback. The UI is only drawn with `Recording::PerFrame`, as it changes every frame.
+    pub fn set_ui_callback(&mut self, ui_callback: impl FnMut(&mut TextOverlay, &UiInput) -> Result<()> + 'static) -> Result<()> {
+        if self.overlay.is_none() {
+            self.overlay = Some(self.create_overlay()?);
+        }
+        self.ui_callback = Some(Box::new(ui_callback));
+        Ok(())
+    }
+
+    fn create_overlay(&mut self) -> Result<Overlay> {
+        let sampler = self.samplers.preset(&self.graphics, SamplerPreset::NearestClamp)?;
+        let (scene_config, scene_pass) = Self::scene_target(&self.swapchain, &self.render_pass, &self.offscreen_targets, self.tone_map.as_ref());
+        let pipeline = SpritePipeline::new(&self.graphics.logical, &scene_config, scene_pass, self.descriptor_pool.layout, &PipelineOptions::default())?;
+        Overlay::new(&self.context, &self.graphics, &self.command_pool, pipeline, self.descriptor_pool.layout, &self.globals.descriptor_infos(), sampler)
+    }
+
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        self.clear_color = clear_color;
+    }
+
+    /// Switching to `Projection::Orthographic2D` draws vertices at pixel coordinates, e.g. a `Vertex::quad`.
+    /// With shadows enabled, the command buffers are recorded again with the cascades fit to the new projection.
+    pub fn set_projection(&mut self, projection: Projection) -> Result<()> {
+        self.camera.projection = projection;
+        let Some(shadows) = self.shadows.as_mut() else { return Ok(()) };
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        shadows.update(&self.camera);
+        self.rerecord_command_buffers()
+    }
+
+    /// Replaces the rendered geometry at runtime, re-recording the command buffers to draw it.
+    pub fn replace_mesh(&mut self, vertices: &[Vertex], indices: &[u16]) -> Result<()> {
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        let mesh = Mesh::new(vertices.to_vec(), indices.to_vec()).upload(&self.context, &self.graphics, &self.command_pool)?;
+        self.bounds = Some(Self::vertex_bounds(vertices));
+        let range = mesh.range();
+        self.set_geometry(VertexInput::of::<Vertex>(), Geometry::Packed(mesh), range)
+    }
+
+    /// Same as `replace_mesh`, with positions and colors uploaded to separate buffers, one binding each.
+    pub fn replace_mesh_separate(&mut self, positions: &[Vec2], colors: &[Vec3], indices: &[u16]) -> Result<()> {
+        if positions.len() != colors.len() {
+            return Err(anyhow!("{} positions were given for {} colors.", positions.len(), colors.len()));
+        }
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        let position_buffer = Buffer::device_local(&self.context, &self.graphics, &self.command_pool, positions, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        let color_buffer = Buffer::device_local(&self.context, &self.graphics, &self.command_pool, colors, vk::BufferUsageFlags::VERTEX_BUFFER)
+            .inspect_err(|_| position_buffer.cleanup(&self.graphics))?;
+        let positions = positions.iter().map(|p| p.extend(0.0)).collect::<Vec<_>>();
+        self.bounds = Some(BoundingSphere::from_points(&positions));
+        self.set_vertex_buffers(VertexInput::of::<SeparateAttributes>(), vec![position_buffer, color_buffer], positions.len(), Some(indices))
+    }
+
+    /// Same as `replace_mesh` for any vertex type laid out as described by `vertex_input`,
+    /// e.g. one built with `VertexInput::interleaved` to pack normals into `A2B10G10R10_SNORM_PACK32`.
+    pub fn replace_mesh_with<T: Copy>(&mut self, vertex_input: VertexInput, vertices: &[T], indices: &[u16]) -> Result<()> {
+        vertex_input.validate(&self.context, self.graphics.physical.instance)?;
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        let vertex_buffer = Buffer::device_local(&self.context, &self.graphics, &self.command_pool, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        self.bounds = None;
+        self.set_vertex_buffers(vertex_input, vec![vertex_buffer], vertices.len(), Some(indices))
+    }
+
+    /// Same as `replace_mesh_with` without an index buffer, the vertices are drawn in order,
+    /// e.g. procedurally generated geometry where indexing would only repeat `0..n`.
+    pub fn replace_mesh_unindexed<T: Copy>(&mut self, vertex_input: VertexInput, vertices: &[T]) -> Result<()> {
+        vertex_input.validate(&self.context, self.graphics.physical.instance)?;
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        let vertex_buffer = Buffer::device_local(&self.context, &self.graphics, &self.command_pool, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        self.bounds = None;
+        self.set_vertex_buffers(vertex_input, vec![vertex_buffer], vertices.len(), None)
+    }
+
+    /// Swaps in `vertex_buffers`, along with an index buffer created from `indices` when given.
+    fn set_vertex_buffers(&mut self, vertex_input: VertexInput, vertex_buffers: Vec<Buffer>, vertex_count: usize, indices: Option<&[u16]>) -> Result<()> {
+        let index_buffer = match indices {
+            Some(indices) => Some(Buffer::device_local(&self.context, &self.graphics, &self.command_pool, indices, vk::BufferUsageFlags::INDEX_BUFFER)?),
+            None => None
+        };
+        let mesh = MeshRange::whole(indices.map_or(0, |i| i.len() as u32), vertex_count as u32);
+        self.set_geometry(vertex_input, Geometry::Separate { vertex_buffers, index_buffer }, mesh)
+    }
+
+    /// Swaps in new geometry, rebuilding the pipeline when the vertex layout changed.
+    fn set_geometry(&mut self, vertex_input: VertexInput, geometry: Geometry, mesh: MeshRange) -> Result<()> {
+        self.geometry.cleanup(&self.graphics);
+        self.geometry = geometry;
+        self.mesh = mesh;
+
+        let layout_changed = self.pipeline_options.vertex_input.differs_from(&vertex_input);
+        self.pipeline_options.vertex_input = vertex_input;
+        if layout_changed {
+            return self.recreate_swapchain(RecreationTrigger::Settings);
+        }
+        self.rerecord_command_buffers()
+    }
+
+    /// Recreates the uniform buffers and their descriptor sets, e.g. after the set of drawn objects changed.
+    /// The swapchain, render pass and pipeline are kept, as the descriptor set layout does not change.
+    pub fn rebuild_descriptors(&mut self) -> Result<()> {
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        self.descriptor_pool.cleanup(&self.graphics);
+        self.uniforms.cleanup(&self.graphics);
+        self.globals.cleanup(&self.graphics);
+        self.uniforms = UniformRing::new(&self.context, &self.graphics, self.swapchain.image_count())?;
+        self.globals = UniformRing::new(&self.context, &self.graphics, self.swapchain.image_count())?;
+        self.descriptor_pool = DescriptorPool::with_layout(self.swapchain.image_count() as u32, &self.graphics, &self.uniforms.descriptor_infos(), Some(&self.globals.descriptor_infos()), self.descriptor_pool.layout)?;
+        self.rerecord_command_buffers()
+    }
+
+    /// Pre-recorded command buffers reference the bound resources, so they are recorded again after those changed.
+    fn rerecord_command_buffers(&mut self) -> Result<()> {
+        let blit = PresentBlit::new(&self.offscreen_targets, &self.present_passes);
+        let targets = FrameTargets { render_pass: &self.render_pass, framebuffers: &self.framebuffers, swapchain: &self.swapchain, blit: blit.as_ref(), tone_map: self.tone_map.as_ref() };
+        self.command_buffers = unsafe { self.command_pool.recreate_buffers(&self.graphics.logical, &self.command_buffers, &targets, |i| self.frame_scene(i))? };
+        Ok(())
+    }
+
+    unsafe fn create_texture_image(context: &VulkanContext, graphics: &Graphics) -> Result<Image> {
+        let img = ImageReader::open("resources/texture.png")?.decode()?;
+        let pixels = img.as_bytes();
+        let size = size_of_val(pixels) as u64;
+        let staging_buffer = Buffer::new(
+            context, 
+            graphics, 
+            size, 
+            vk::BufferUsageFlags::TRANSFER_SRC, 
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+
+        let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(pixels.as_ptr(), mem.cast(), pixels.len());
+        graphics.logical.instance.unmap_memory(staging_buffer.memory);
+
+        let image = Image::new(
+            context, 
+            graphics, 
+            img.dimensions(), 
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::ImageTiling::OPTIMAL
+        )?;
+        Ok(image)
+    }
+
+    fn create_offscreen_targets(context: &VulkanContext, graphics: &Graphics, memory: &mut MemoryAllocator, swapchain: &SwapchainData, options: &SwapchainOptions, depth: &DepthAttachment) -> Result<Vec<RenderTarget>> {
+        let Some(resolution) = options.fixed_resolution else { return Ok(vec![]) };
+        (0..swapchain.image_count())
+            .map(|_| RenderTarget::new_in(context, graphics, memory, resolution.extent, swapchain.format(), Some(depth)))
+            .collect()
+    }
+
+    /// Draws each of `targets` letterboxed into the swapchain image of the same index, in the swapchain `render_pass`.
+    fn create_present_passes(graphics: &Graphics, swapchain: &SwapchainData, options: &SwapchainOptions, render_pass: &vk::RenderPass, targets: &[RenderTarget], sampler: vk::Sampler) -> Result<Vec<FullscreenPass>> {
+        let Some(resolution) = options.fixed_resolution else { return Ok(vec![]) };
+        let area = resolution.destination(swapchain.config.extent);
+        targets.iter()
+            .map(|target| {
+                let pass = FullscreenPass::new(graphics, *render_pass, 0, area, fullscreen_pass::BLIT_FRAGMENT_SHADER)?;
+                pass.set_input(graphics, target.descriptor_image_info(sampler));
+                Ok(pass)
+            })
+            .collect()
+    }
+
+    /// Covers the swapchain framebuffers as well as the offscreen ones, attachments may be larger than a framebuffer.
+    fn depth_extent(swapchain: &SwapchainData, options: &SwapchainOptions) -> vk::Extent2D {
+        let extent = swapchain.config.extent;
+        options.fixed_resolution.map_or(extent, |resolution| vk::Extent2D {
+            width: extent.width.max(resolution.extent.width),
+            height: extent.height.max(resolution.extent.height)
+        })
+    }
+
+    /// Configuration and render pass the scene pipeline is built for, the offscreen ones at a fixed resolution.
+    /// Offscreen render passes only differ in their attachment layouts, so the first one is compatible with all targets.
+    fn scene_target<'a>(swapchain: &SwapchainData, render_pass: &'a vk::RenderPass, offscreen_targets: &'a [RenderTarget], tone_map: Option<&'a ToneMapPass>) -> (SwapchainConfig, &'a vk::RenderPass) {
+        match (offscreen_targets.first(), tone_map) {
+            (Some(target), _) => {
+                let mut config = swapchain.config.clone();
+                config.extent = target.extent;
+                (config, &target.render_pass)
+            },
+            (None, Some(tone_map)) => (swapchain.config.clone(), &tone_map.render_pass),
+            (None, None) => (swapchain.config.clone(), render_pass)
+        }
+    }
+
+    /// HDR scene pass of `VulcorOptions::hdr`, `None` when disabled or rendering at a fixed resolution.
+    fn create_tone_map(context: &VulkanContext, graphics: &Graphics, swapchain: &SwapchainData, options: &SwapchainOptions, hdr: bool, depth: &DepthAttachment) -> Result<Option<ToneMapPass>> {
+        if !hdr || options.fixed_resolution.is_some() {
+            return Ok(None);
+        }
+        ToneMapPass::new(context, graphics, swapchain, depth).map(Some)
+    }
+
+    fn create_framebuffers(graphics: &Graphics, swapchain: &SwapchainData, render_pass: &vk::RenderPass, depth: &DepthAttachment) -> Result<Vec<vk::Framebuffer>> {
+        let framebuffers = swapchain.image_views.iter()
+            .map(|img| {
+                let attachments = &[*img, depth.view];
+                let create_info: vk::FramebufferCreateInfo<'_> = vk::FramebufferCreateInfo::default()
+                    .render_pass(*render_pass)
+                    .attachments(attachments)
+                    .width(swapchain.config.extent.width)
+                    .height(swapchain.config.extent.height)
+                    .layers(1);
+                unsafe { graphics.logical.instance.create_framebuffer(&create_info, None) }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(framebuffers)
+    }
+
+    fn create_render_pass(logical_device: &Device, swapchain: &SwapchainConfig, depth: &DepthAttachment) -> Result<vk::RenderPass> {
+        let color_attachment = vk::AttachmentDescription::default()
+            .format(swapchain.format.format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        let color_attachment_ref = vk::AttachmentReference::default()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let color_attachments = &[color_attachment_ref];
+        let depth_attachment_ref = DepthAttachment::reference(1);
+        // The depth attachment is shared by all images, the previous frame's tests must be done before it is cleared
+        let dependency = vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | DepthAttachment::STAGES)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | DepthAttachment::STAGES)
+            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+        let subpass = vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(color_attachments)
+            .depth_stencil_attachment(&depth_attachment_ref);
+        let attachments = &[color_attachment, depth.description()];
+        render_target::validate_sample_counts(attachments)?;
+        let supbasses = &[subpass];
+        let dependencies = &[dependency];
+        let create_info  = vk::RenderPassCreateInfo::default()
+            .attachments(attachments)
+            .subpasses(supbasses)
+            .dependencies(dependencies);
+        let render_pass = unsafe { logical_device.create_render_pass(&create_info, None)? };
+        Ok(render_pass)
+    }
+
+    /// Swaps in the textures whose background upload completed.
+    fn poll_resources(&mut self) -> Result<()> {
+        for (handle, result) in self.loader.poll(&self.context, &self.graphics)? {
+            match result {
+                Ok(image) => {
+                    info!("Texture upload completed => {:?}", handle);
+                    let previous = std::mem::replace(&mut self.texture_image, image);
+                    previous.cleanup(&self.graphics);
+                },
+                Err(error) => log::error!("Failed to load texture {:?} => {}", handle, error)
+            }
+        }
+        Ok(())
+    }
+
+    /// Summarizes whether `render` can do any work, checked before issuing a frame.
+    pub fn status(&self) -> RenderStatus {
+        if let Some(lost) = self.lost {
+            return lost;
+        }
+        if !self.run {
+            return RenderStatus::Stopped;
+        }
+        let size = self.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return RenderStatus::Minimized;
+        }
+        RenderStatus::Ready
+    }
+
+    pub fn is_renderable(&self) -> bool {
+        self.status() == RenderStatus::Ready
+    }
+
+    /// Renders and presents a single frame cleared to `color`, with the render pass begun and ended but nothing bound
+    /// or drawn. Failures then come from the swapchain, synchronization or present path rather than a pipeline.
+    /// The scene, clear color and recording mode are restored afterwards.
+    pub fn render_clear(&mut self, color: [f32; 4]) -> Result<()> {
+        let scene = mem::replace(&mut self.scene, Box::new(ClearScene));
+        let clear_color = mem::replace(&mut self.clear_color, color);
+        let recording = mem::replace(&mut self.recording, Recording::PerFrame);
+        let result = self.render();
+        self.scene = scene;
+        self.clear_color = clear_color;
+        self.recording = recording;
+        if recording == Recording::PreRecorded {
+            // The buffer of the presented image now only clears, the others may still be pending
+            unsafe { self.graphics.logical.instance.device_wait_idle()? };
+            self.rerecord_command_buffers()?;
+        }
+        result
+    }
+
+    fn render(&mut self) -> Result<()> {
+        let result = self.render_frame();
+        if let Some(error) = result.as_ref().err().and_then(|e| e.downcast_ref::<vk::Result>()) {
+            match *error {
+                vk::Result::ERROR_DEVICE_LOST => self.lost = Some(RenderStatus::DeviceLost),
+                vk::Result::ERROR_SURFACE_LOST_KHR => {
+                    self.lost = Some(RenderStatus::SurfaceLost);
+                    if let Some(on_surface_lost) = self.on_surface_lost.as_mut() {
+                        on_surface_lost();
+                    }
+                    return self.recover_surface();
+                },
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// Called when the surface is lost, e.g. on a monitor hotplug or a compositor restart, before it is recreated.
+    pub fn set_on_surface_lost(&mut self, callback: impl FnMut() + 'static) {
+        self.on_surface_lost = Some(Box::new(callback));
+    }
+
+    /// Called at the end of every swapchain recreation, e.g. on a resize, a format change or a lost surface, with the new
+    /// extent and format. It runs after `device_wait_idle` with nothing in flight, so resources tied to the previous
+    /// swapchain can be destroyed and recreated right away.
+    pub fn set_on_swapchain_recreated(&mut self, callback: impl FnMut(&SwapchainConfig) + 'static) {
+        self.on_swapchain_recreated = Some(Box::new(callback));
+    }
+
+    /// Replaces a lost surface with a new one for the same window and rebuilds the swapchain on it.
+    /// Nothing is destroyed until a new surface able to present is created, so the renderer stays intact when every attempt fails.
+    fn recover_surface(&mut self) -> Result<()> {
+        let mut attempt = 1;
+        let surface = loop {
+            match self.create_presentable_surface() {
+                Ok(surface) => break surface,
+                Err(e) if attempt < SURFACE_RECOVERY_ATTEMPTS => {
+                    log::warn!("Surface recreation failed with {}, retrying ({}/{})", e, attempt, SURFACE_RECOVERY_ATTEMPTS - 1);
+                    std::thread::sleep(SURFACE_RECOVERY_DELAY);
+                },
+                Err(e) => return Err(e)
+            }
+            attempt += 1;
+        };
+        unsafe { self.graphics.logical.instance.device_wait_idle()?; }
+        // The old swapchain has to go before the surface it was created for
+        self.destroy_swapchain();
+        unsafe { self.context.replace_surface(surface) };
+        // Formats and present modes may differ on the new surface
+        self.swapchain.config.support = swapchain::SwapchainSupport::new(&self.context, &self.graphics.physical.instance)?;
+        self.rebuild_swapchain(RecreationTrigger::SurfaceLost)?;
+        self.lost = None;
+        info!("Recovered from a lost surface");
+        Ok(())
+    }
+
+    /// New surface for the window, checked to be presentable from the presentation queue family.
+    fn create_presentable_surface(&self) -> Result<vk::SurfaceKHR> {
+        let surface = self.context.create_replacement_surface(&self.window).map_err(|e| anyhow!(e.to_string()))?;
+        let family = self.graphics.queue_family.presentation;
+        let supported = unsafe { self.context.surface_loader.get_physical_device_surface_support(self.graphics.physical.instance, family, surface) };
+        match supported {
+            Ok(true) => Ok(surface),
+            result => {
+                unsafe { self.context.surface_loader.destroy_surface(surface, None) };
+                result?;
+                Err(anyhow!("Queue family {} cannot present to the new surface.", family))
+            }
+        }
+    }
+
+    fn render_frame(&mut self) -> Result<()> {
+        unsafe { self.graphics.logical.instance.wait_for_fences(&[self.sync.get_in_flight_fence()], true, u64::MAX)? };        
+        self.poll_resources()?;
+        let image_available = self.sync.next_image_available();
+        let result = unsafe { self.swapchain.loader.acquire_next_image(
+                self.swapchain.khr, 
+                u64::MAX, 
+                image_available, 
+                vk::Fence::null()
+            )
+        };
+
+        let image_index = match result {
+            Ok((image_index, _)) => image_index as usize,
+            Err(e) => {
+                self.sync.acquire_failed(image_available);
+                return match e {
+                    vk::Result::ERROR_OUT_OF_DATE_KHR => self.recreate_swapchain(RecreationTrigger::OutOfDate),
+                    e => Err(anyhow!(e))
+                };
+            }
+        };
+        self.sync.image_acquired(image_index, image_available);
+        
+        // TODO Possibly encapsulate in sync object 
+        let in_flight = self.sync.images_in_flight[image_index as usize];
+        if !in_flight.is_null() {
+            unsafe { self.graphics.logical.instance.wait_for_fences(&[in_flight], true, u64::MAX)? };
+        }
+        self.sync.update_image_in_flight(image_index);
+        if let Some(gpu_frame_times) = self.gpu_frame_times.as_mut() {
+            // The last frame submitted for this image has completed, before its timestamps are reset by the next one
+            gpu_frame_times.collect(&self.graphics, image_index)?;
+        }
+        let now = Instant::now();
+        let delta_time = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.scene.update(delta_time);
+        unsafe { self.update_uniform_buffer(image_index)? };
+        unsafe { self.update_globals(image_index, delta_time)? };
+        if self.recording == Recording::PerFrame {
+            self.update_overlay(image_index, delta_time)?;
+            unsafe { self.record_frame(image_index)? };
+        }
+        let frame = self.command_buffers[image_index];
+        let update = match (&self.particles, &self.particle_updates) {
+            (Some(particles), Some(updates)) => Some(unsafe { updates.record(&self.graphics, particles, image_index, delta_time)? }),
+            _ => None
+        };
+        // Without async compute the particles are stepped right before the frame drawing them, in the same submission.
+        // Otherwise the frame waits on the compute queue and signals when the particles can be written again.
+        let mut command_buffers = [frame, vk::CommandBuffer::null()];
+        let mut wait_semaphores = [self.sync.get_image_available(image_index), vk::Semaphore::null()];
+        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::PipelineStageFlags::VERTEX_INPUT];
+        let mut signal_semaphores = [self.sync.get_render_completed(image_index), vk::Semaphore::null()];
+        let (mut command_buffer_count, mut wait_count, mut signal_count) = (1, 1, 1);
+        match (update, self.particle_updates.as_mut()) {
+            (Some(update), Some(updates)) if updates.is_async() => {
+                (wait_semaphores[1], signal_semaphores[1]) = updates.submit(&self.graphics, update, image_index)?;
+                (wait_count, signal_count) = (2, 2);
+            },
+            (Some(update), _) => {
+                command_buffers = [update, frame];
+                command_buffer_count = 2;
+            },
+            (None, _) => ()
+        }
+        let signal_semaphores = &signal_semaphores[..signal_count];
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores[..wait_count])
+            .wait_dst_stage_mask(&wait_stages[..wait_count])
+            .command_buffers(&command_buffers[..command_buffer_count])
+            .signal_semaphores(signal_semaphores);
+
+        self.sync.reset_fences(&self.graphics)?;
+        self.graphics.queue_submit(&[submit_info], self.sync.get_in_flight_fence())?;
+        if let Some(gpu_frame_times) = self.gpu_frame_times.as_mut() {
+            gpu_frame_times.submitted(image_index);
+        }
+        // The presentation queue may belong to another family: the semaphore orders the present after the
+        // submission across queues, and concurrent sharing of the swapchain images avoids an ownership transfer
+        let swapchains = &[self.swapchain.khr];
+        let image_indices = &[image_index as u32];
+        // The overall result only reports the most severe outcome, each swapchain gets its own entry here.
+        // Everything submitted each frame lives on the stack, so a steady render loop does not allocate
+        let mut results = [vk::Result::SUCCESS; 1];
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores[..1])
+            .swapchains(swapchains)
+            .image_indices(image_indices)
+            .results(&mut results);
+        let result = unsafe { self.swapchain.loader.queue_present(self.graphics.present_queue, &present_info) };
+        match result {
+            Ok(_) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {},
+            Err(e) => return Err(anyhow!(e))
+        }
+        let [swapchain_result] = results;
+        if swapchain_result == vk::Result::SUCCESS || swapchain_result == vk::Result::SUBOPTIMAL_KHR {
+            self.last_presented = Some(image_index);
+        }
+        if self.pending_resize.is_some_and(|at| at.elapsed() >= RESIZE_DEBOUNCE) {
+            self.pending_resize = None;
+            self.resized = true;
+        }
+        // A suboptimal swapchain warrants a recreation just like an out of date one
+        let trigger = match swapchain_result {
+            _ if self.resized => Some(RecreationTrigger::Resize),
+            vk::Result::ERROR_OUT_OF_DATE_KHR => Some(RecreationTrigger::OutOfDate),
+            vk::Result::SUBOPTIMAL_KHR => Some(RecreationTrigger::Suboptimal),
+            _ => None
+        };
+        if let Some(trigger) = trigger {
+            self.resized = false;
+            self.recreate_swapchain(trigger)?;
+        }
+        self.sync.increment_frame();
+        Ok(())
+    }
+
+    /// The fence of the frame that last used `image_index` must have been waited on.
+    unsafe fn record_frame(&self, image_index: usize) -> Result<()> {
+        let scene = self.frame_scene(image_index);
+        let blit = PresentBlit::new(&self.offscreen_targets, &self.present_passes);
+        let targets = FrameTargets { render_pass: &self.render_pass, framebuffers: &self.framebuffers, swapchain: &self.swapchain, blit: blit.as_ref(), tone_map: self.tone_map.as_ref() };
+        self.command_pool.record_frame(&self.graphics.logical, self.command_buffers[image_index], image_index, &targets, &scene)
+    }
+
+    /// What is recorded for `image_index`, with the mesh bounds moved by the current model matrix.
+    fn frame_scene(&self, image_index: usize) -> FrameScene<'_> {
+        let model = self.model();
+        FrameScene {
+            pipeline: &self.pipeline,
+            variants: &self.variants,
+            vertex_buffers: self.geometry.vertex_buffers(),
+            index_buffer: self.geometry.index_buffer(),
+            mesh: self.mesh,
+            descriptor_set: self.descriptor_pool.sets[image_index],
+            clear_color: self.clear_color,
+            frustum: self.camera.frustum(),
+            bounds: self.bounds.map(|bounds| bounds.transformed(&model)),
+            particles: self.particles.as_ref(),
+            shadows: self.shadows.as_ref(),
+            probe: self.probe.as_ref(),
+            outline: self.outline.map(|(variant, outline)| (&self.variants[variant], outline)),
+            // Updated right before recording, pre-recorded buffers would draw whatever the UI held back then
+            overlay: self.overlay.as_ref().filter(|_| self.recording == Recording::PerFrame),
+            timer: self.gpu_frame_times.as_ref().map(|times| times.timer(image_index)),
+            scene: self.scene.as_ref()
+        }
+    }
+
+    /// Spins the mesh around z in 3D, 2D content stays in place.
+    fn model(&self) -> Mat4 {
+        match self.camera.projection {
+            Projection::Perspective { .. } => {
+                let time = self.start.elapsed().as_secs_f32();
+                Mat4::from_axis_angle(Vec3::unit_z(), Deg(90.0) * time)
+            },
+            Projection::Orthographic2D => Mat4::identity()
+        }
+    }
+
+    /// Bounds of the built-in vertex layout, which lies in the z = 0 plane.
+    fn vertex_bounds(vertices: &[Vertex]) -> BoundingSphere {
+        let positions = vertices.iter().map(|v| v.position().extend(0.0)).collect::<Vec<_>>();
+        BoundingSphere::from_points(&positions)
+    }
+
+    unsafe fn update_uniform_buffer(&self, image_index: usize) -> Result<()> {
+        let model = self.model();
+        let view = self.camera.view();
+        let proj = self.pre_rotated(self.camera.proj());
+        self.uniforms.write(image_index, &MVP { model, view, proj });
+        Ok(())
+    }
+
+    fn pre_rotated(&self, proj: Mat4) -> Mat4 {
+        match self.swapchain_options.fixed_resolution {
+            // The present pass does not rotate, so the offscreen image is never pre-rotated
+            Some(_) => proj,
+            // Compensate for the presentation engine rotating the image
+            None => Mat4::from_angle_z(Deg(self.swapchain.config.pre_rotation_degrees())) * proj
+        }
+    }
+
+    /// Runs `ui_callback` for the UI drawn over `image_index`, laid out in pixels of the scene target.
+    fn update_overlay(&mut self, image_index: usize, delta_time: f32) -> Result<()> {
+        let (width, height) = Self::logical_extent(&self.swapchain.config, &self.swapchain_options);
+        let proj = self.pre_rotated(Projection::Orthographic2D.matrix(width, height, self.camera.clip_space));
+        let (Some(overlay), Some(ui_callback)) = (self.overlay.as_mut(), self.ui_callback.as_mut()) else { return Ok(()) };
+        self.ui_input.delta_time = delta_time;
+        overlay.update(&self.graphics, image_index, proj, ui_callback, &self.ui_input)
+    }
+
+    unsafe fn update_globals(&mut self, image_index: usize, delta_time: f32) -> Result<()> {
+        let (width, height) = Self::logical_extent(&self.swapchain.config, &self.swapchain_options);
+        let globals = Globals::new(self.start.elapsed().as_secs_f32(), delta_time, self.frame, vec2(width, height));
+        self.frame = self.frame.wrapping_add(1);
+        self.globals.write(image_index, &globals);
+        Ok(())
+    }
+
+    /// Destroys every resource and the instance. Runs once, later calls and the drop do nothing.
+    fn cleanup(&mut self) {
+        if self.cleaned_up {
+            return;
+        }
+        self.cleaned_up = true;
+        println!("Cleaning up resources...");
+        self.destroy_device();
+        unsafe { Self::destroy_instance(&self.context, &self.messenger) };
+    }
+
+    /// The messenger and the surface are children of the instance, which goes last.
+    unsafe fn destroy_instance(context: &VulkanContext, messenger: &Option<DebugMessenger>) {
+        if let Some(messenger) = messenger {
+            messenger.cleanup();
+        }
+        context.cleanup();
+    }
+
+    /// Destroys the logical device and every resource created from it, leaving the instance and surface alive.
+    /// Objects are destroyed after everything that uses them, ending with the command pool and the device.
+    fn destroy_device(&mut self) {
+        // Nothing below may still be in use by the GPU, including the semaphores and fences of in-flight frames
+        let _ = unsafe { self.graphics.logical.instance.device_wait_idle() };
+        unsafe {
+            self.sync.cleanup(&self.graphics);
+            if let Some(gpu_frame_times) = self.gpu_frame_times.take() {
+                gpu_frame_times.cleanup(&self.graphics);
+            }
+            // Recorded commands reference the pipeline, render pass, framebuffers and descriptor sets of the swapchain
+            self.graphics.logical.instance.free_command_buffers(self.command_pool.instance, &self.command_buffers);
+            self.destroy_swapchain();
+            self.pipeline_options.cleanup(&self.graphics.logical.instance);
+            self.variant_options.iter()
+                .for_each(|o| o.cleanup(&self.graphics.logical.instance));
+            // Sampled images and their samplers are only referenced by the descriptor sets freed with the swapchain
+            self.loader.cleanup(&self.graphics);
+            self.samplers.cleanup(&self.graphics);
+            self.texture_image.cleanup(&self.graphics);
+            if let Some(particles) = &self.particles {
+                particles.cleanup(&self.graphics);
+            }
+            if let Some(shadows) = &self.shadows {
+                shadows.cleanup(&self.graphics);
+            }
+            if let Some(probe) = &self.probe {
+                probe.cleanup(&self.graphics);
+            }
+            self.geometry.cleanup(&self.graphics);
+            self.graphics.logical.instance.destroy_descriptor_set_layout(self.descriptor_pool.layout, None);
+            // Command buffers were freed first, the pool can go now
+            self.graphics.logical.instance.destroy_command_pool(self.command_pool.instance, None);
+            self.graphics.logical.instance.destroy_device(None);
+        }
+    }
+
+    /// Destroys everything sized after or referencing the swapchain images, in reverse order of use.
+    /// The command buffers are kept to be re-recorded, they must not be submitted until then.
+    fn destroy_swapchain(&mut self) {
+        if self.swapchain_destroyed {
+            return;
+        }
+        self.swapchain_destroyed = true;
+        unsafe {
+            self.pipeline.cleanup(&self.graphics.logical.instance);
+            self.variants.drain(..)
+                .for_each(|p| p.cleanup(&self.graphics.logical.instance));
+            if let Some(particle_updates) = self.particle_updates.take() {
+                particle_updates.cleanup(&self.graphics);
+            }
+            // Shares the descriptor set layout and the `Globals` destroyed below
+            if let Some(overlay) = self.overlay.take() {
+                overlay.cleanup(&self.graphics);
+            }
+            self.framebuffers.iter()
+                .for_each(|f| self.graphics.logical.instance.destroy_framebuffer(*f, None));
+            self.present_passes.drain(..)
+                .for_each(|p| p.cleanup(&self.graphics));
+            self.offscreen_targets.iter()
+                .for_each(|t| t.cleanup(&self.graphics));
+            self.swapchain_memory.cleanup(&self.graphics);
+            if let Some(tone_map) = self.tone_map.take() {
+                tone_map.cleanup(&self.graphics);
+            }
+            self.depth.cleanup(&self.graphics);
+            self.graphics.logical.instance.destroy_render_pass(self.render_pass, None);
+            // Framebuffers were the last users of the swapchain image views
+            self.swapchain.cleanup(&self.graphics);
+            self.uniforms.cleanup(&self.graphics);
+            self.globals.cleanup(&self.graphics);
+            self.descriptor_pool.cleanup(&self.graphics);
+        }
+    }
+}
+
+/// Teardown of what `Vulcor::with_context` created so far, run in reverse order when a later step fails
+/// so that a failed initialization leaks nothing. Disarmed once the renderer owns everything.
+struct Rollback<'a> {
+    graphics: &'a Graphics,
+    steps: Vec<RollbackStep<'a>>
+}
+
+/// Destroys one object created by `Vulcor::with_context`.
+type RollbackStep<'a> = Box<dyn FnOnce(&Graphics) + 'a>;
+
+impl<'a> Rollback<'a> {
+    fn new(graphics: &'a Graphics) -> Self {
+        Self { graphics, steps: vec![] }
+    }
+
+    fn push(&mut self, step: impl FnOnce(&Graphics) + 'a) {
+        self.steps.push(Box::new(step));
+    }
+
+    fn disarm(mut self) {
+        self.steps.clear();
+    }
+}
+
+impl Drop for Rollback<'_> {
+    fn drop(&mut self) {
+        if self.steps.is_empty() {
+            return;
+        }
+        // Uploads submitted during initialization may still be running
+        let _ = unsafe { self.graphics.logical.instance.device_wait_idle() };
+        while let Some(step) = self.steps.pop() {
+            step(self.graphics);
+        }
+    }
+}
+
+impl Drop for Vulcor {
+    /// Tears down on any exit path, e.g. a panic while rendering or an event loop error.
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        match self.vulcor {
+            None => {
+                let window_attributes = Window::default_attributes().with_title(self.name.as_str());
+                let window = event_loop.create_window(window_attributes).unwrap();
+                let scene = self.scene.take().unwrap_or_else(|| Box::new(MeshScene));
+                self.vulcor = match Vulcor::new(window, self.start, scene, self.options.clone()) {
+                    Ok(vulcor) => Some(vulcor),
+                    // Whatever was created has already been destroyed
+                    Err(error) => {
+                        log::error!("FATAL ERROR ENCOUNTERED => {}", error);
+                        return event_loop.exit();
+                    }
+                };
+                if self.hud {
+                    let vulcor = self.vulcor.as_mut().unwrap();
+                    if let Err(error) = vulcor.set_ui_callback(frame_stats_hud) {
+                        log::error!("Failed to create the HUD => {}", error);
+                    }
+                }
+                if let Some(frames) = self.benchmark {
+                    let vulcor = self.vulcor.as_mut().unwrap();
+                    match vulcor.run_benchmark(frames) {
+                        Ok(Some(stats)) => println!("{}", stats),
+                        Ok(None) => log::warn!("No frame was rendered during the benchmark"),
+                        Err(error) => log::error!("Benchmark failed => {}", error)
+                    }
+                    match vulcor.benchmark_pipeline_creation() {
+                        Ok(stats) => println!("{}", stats),
+                        Err(error) => log::error!("Pipeline creation benchmark failed => {}", error)
+                    }
+                    self.exit(event_loop);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.shutdown.load(Ordering::Acquire) {
+            return self.exit(event_loop);
+        }
+        let Some(app) = self.vulcor.as_mut() else { return };
+        if app.is_renderable() {
+            let result = app.render();
+            if result.is_err() {
+                panic!("ERROR DURING RENDER");
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match self.vulcor.as_mut() {
+            Some(instance) => {
+                match event {
+                    WindowEvent::RedrawRequested => {
+                        if !instance.is_renderable() { return; }
+                        let result = instance.render();
+                        if result.is_err() { log::error!("Error occured on a render pass"); }
+                    },
+                    WindowEvent::CloseRequested => self.exit(event_loop),
+                    WindowEvent::CursorMoved { position, .. } => instance.ui_input.cursor = Some(vec2(position.x as f32, position.y as f32)),
+                    WindowEvent::CursorLeft { .. } => instance.ui_input.cursor = None,
+                    WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => instance.ui_input.pressed = state == ElementState::Pressed,
+                    WindowEvent::DroppedFile(path) => {
+                        let is_obj = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+                        let is_png = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png"));
+                        if is_png {
+                            match instance.loader.request_texture(&path, ColorSpace::Srgb) {
+                                Ok(handle) => info!("Loading texture {:?} => {}", handle, path.display()),
+                                Err(error) => log::error!("Failed to request {} => {}", path.display(), error)
+                            }
+                            return;
+                        }
+                        if !is_obj {
+                            log::warn!("Unsupported dropped file => {}", path.display());
+                            return;
+                        }
+                        let result = crate::resources::model::load_obj(&path)
+                            .and_then(|(vertices, indices)| instance.replace_mesh(&vertices, &indices));
+                        match result {
+                            Ok(()) => info!("Loaded model => {}", path.display()),
+                            Err(error) => log::error!("Failed to load {} => {}", path.display(), error)
+                        }
+                    },
+                    WindowEvent::Resized(size) => { 
+                        self.minimized = size.width == 0 || size.height == 0;
+                        if !self.minimized {
+                            // Coalesce the burst of events fired while dragging the window edge
+                            instance.pending_resize = Some(Instant::now());
+                        }
+                    },
+                    WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        // Moving to a monitor of another DPI keeps the logical size, so the physical size changes
+                        // even when no `Resized` follows, e.g. when the platform does not resize the window itself
+                        info!("Scale factor changed => {}", scale_factor);
+                        instance.pending_resize = Some(Instant::now());
+                    },
+                    _ => (),
+                }
+            }
+            _ => ()
+        }
+
+    }
+}
+
+/// CPU and GPU frame times, draw counts and the cursor position in the top left corner, shown with `--hud`.
+/// Highlighted while the mouse is pressed.
+fn frame_stats_hud(text: &mut TextOverlay, input: &UiInput) -> Result<()> {
+    let gpu_time = input.gpu_time.map_or("-".to_string(), |ms| format!("{:.2} ms", ms));
+    let mut lines = format!("cpu {:.2} ms\ngpu {}", input.delta_time * 1000.0, gpu_time);
+    if let Some(statistics) = input.statistics {
+        lines += &format!("\n{} vertices\n{} fragments", statistics.input_vertices, statistics.fragment_shader_invocations);
+    }
+    let cursor = input.cursor.map_or("-".to_string(), |cursor| format!("{:.0}, {:.0}", cursor.x, cursor.y));
+    lines += &format!("\ncursor {}", cursor);
+    let color = if input.pressed { Vec4::new(1.0, 0.8, 0.2, 1.0) } else { Vec4::new(1.0, 1.0, 1.0, 1.0) };
+    text.draw_text(8.0, 8.0, &lines, color)
+}
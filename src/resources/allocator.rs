@@ -0,0 +1,329 @@
+use ash::{ext::memory_budget, vk};
+use anyhow::{anyhow, Result};
+use std::{fmt::{self, Display, Formatter}, sync::atomic::{AtomicU64, Ordering}};
+
+use crate::{core::{context::VulkanContext, graphics::Graphics, physical_device::GraphicsHardware}, error::VulcorError, resources::buffer::Buffer};
+
+/// Region of a larger device memory block that a resource binds to.
+#[derive(Clone, Copy, Debug)]
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub memory_type_index: u32
+}
+
+/// Kind of resource a device memory allocation backs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    Buffer,
+    Image,
+    Other
+}
+
+/// Bytes of device memory currently allocated through the crate, by kind of resource.
+/// Allocator blocks count as buffers when linear and as images otherwise, whatever their occupancy.
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    buffers: AtomicU64,
+    images: AtomicU64,
+    other: AtomicU64
+}
+
+impl MemoryUsage {
+    pub fn allocated(&self, kind: MemoryKind, bytes: vk::DeviceSize) {
+        self.counter(kind).fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn freed(&self, kind: MemoryKind, bytes: vk::DeviceSize) {
+        self.counter(kind).fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> MemoryReport {
+        MemoryReport {
+            buffers: self.buffers.load(Ordering::Relaxed),
+            images: self.images.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed)
+        }
+    }
+
+    fn counter(&self, kind: MemoryKind) -> &AtomicU64 {
+        match kind {
+            MemoryKind::Buffer => &self.buffers,
+            MemoryKind::Image => &self.images,
+            MemoryKind::Other => &self.other
+        }
+    }
+}
+
+/// Snapshot of `MemoryUsage`, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub buffers: u64,
+    pub images: u64,
+    pub other: u64
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> u64 {
+        self.buffers + self.images + self.other
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        write!(f, "buffers: {:.1}MB, images: {:.1}MB, other: {:.1}MB", mb(self.buffers), mb(self.images), mb(self.other))
+    }
+}
+
+/// Budget and usage of a memory heap as reported by `VK_EXT_memory_budget`, for the whole process.
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub heap: u32,
+    pub budget: vk::DeviceSize,
+    pub usage: vk::DeviceSize
+}
+
+/// Per-heap budgets, empty when the device does not expose `VK_EXT_memory_budget`.
+pub fn heap_budgets(context: &VulkanContext, graphics: &Graphics) -> Vec<HeapBudget> {
+    if !GraphicsHardware::extension_supported(context, &graphics.physical.instance, memory_budget::NAME) {
+        return vec![];
+    }
+    let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut properties = vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget);
+    unsafe { context.instance.get_physical_device_memory_properties2(graphics.physical.instance, &mut properties) };
+    let heap_count = properties.memory_properties.memory_heap_count as usize;
+    (0..heap_count)
+        .map(|i| HeapBudget { heap: i as u32, budget: budget.heap_budget[i], usage: budget.heap_usage[i] })
+        .collect()
+}
+
+/// Allocates device memory, turning out of memory results into `VulcorError::OutOfMemory`.
+pub fn allocate_memory(context: &VulkanContext, graphics: &Graphics, info: &vk::MemoryAllocateInfo) -> Result<vk::DeviceMemory> {
+    unsafe { graphics.logical.instance.allocate_memory(info, None) }
+        .map_err(|result| allocation_error(result, info.allocation_size, || heap_budgets(context, graphics)))
+}
+
+/// Error for a failed allocation of `requested` bytes. `heaps` is only queried for out of memory results.
+fn allocation_error(result: vk::Result, requested: vk::DeviceSize, heaps: impl FnOnce() -> Vec<HeapBudget>) -> anyhow::Error {
+    match VulcorError::is_out_of_memory(result) {
+        true => anyhow!(VulcorError::OutOfMemory { result, requested, heaps: heaps() }),
+        false => anyhow!(result)
+    }
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    linear: bool,
+    size: vk::DeviceSize,
+    used: vk::DeviceSize
+}
+
+/// Linear sub-allocator carving allocations out of large blocks, one set of blocks per memory type.
+/// Linear (buffers) and optimal (images) resources never share a block, which sidesteps `bufferImageGranularity`.
+/// Allocations are only released all at once through `cleanup`.
+pub struct MemoryAllocator {
+    blocks: Vec<MemoryBlock>,
+    block_size: vk::DeviceSize
+}
+
+impl MemoryAllocator {
+    pub fn new(block_size: vk::DeviceSize) -> Self {
+        Self { blocks: vec![], block_size }
+    }
+
+    pub fn allocate(&mut self, context: &VulkanContext, graphics: &Graphics, reqs: vk::MemoryRequirements, props: vk::MemoryPropertyFlags, linear: bool) -> Result<Allocation> {
+        let memory_type_index = Buffer::get_memory_type_index(graphics.physical.memory_properties(), props, reqs)?;
+        let block = self.blocks.iter_mut()
+            .find(|b| b.memory_type_index == memory_type_index && b.linear == linear && Self::aligned(b.used, reqs.alignment) + reqs.size <= b.size);
+        let block = match block {
+            Some(block) => block,
+            None => {
+                // Resources larger than a block get a dedicated one
+                let size = self.block_size.max(reqs.size);
+                let mem_info = vk::MemoryAllocateInfo::default()
+                    .allocation_size(size)
+                    .memory_type_index(memory_type_index);
+                let memory = allocate_memory(context, graphics, &mem_info)?;
+                graphics.memory_usage.allocated(Self::block_kind(linear), size);
+                log::debug!("Allocated memory block of {} bytes for memory type {}", size, memory_type_index);
+                self.blocks.push(MemoryBlock { memory, memory_type_index, linear, size, used: 0 });
+                self.blocks.last_mut().unwrap()
+            }
+        };
+
+        let offset = Self::aligned(block.used, reqs.alignment);
+        block.used = offset + reqs.size;
+        Ok(Allocation { memory: block.memory, offset, size: reqs.size, memory_type_index })
+    }
+
+    /// Number of `DeviceMemory` objects currently held by the allocator.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn cleanup(&mut self, graphics: &Graphics) {
+        self.blocks.drain(..).for_each(|b| {
+            unsafe { graphics.logical.instance.free_memory(b.memory, None) };
+            graphics.memory_usage.freed(Self::block_kind(b.linear), b.size);
+        });
+    }
+
+    fn block_kind(linear: bool) -> MemoryKind {
+        if linear { MemoryKind::Buffer } else { MemoryKind::Image }
+    }
+
+    fn aligned(offset: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+        if alignment == 0 { offset } else { offset.div_ceil(alignment) * alignment }
+    }
+}
+
+/// Passes of a frame, in recording order, during which a transient resource holds contents. Both ends are included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lifetime {
+    pub first_pass: usize,
+    pub last_pass: usize
+}
+
+impl Lifetime {
+    pub fn new(first_pass: usize, last_pass: usize) -> Self {
+        Self { first_pass, last_pass: last_pass.max(first_pass) }
+    }
+
+    pub fn overlaps(&self, other: &Lifetime) -> bool {
+        self.first_pass <= other.last_pass && other.first_pass <= self.last_pass
+    }
+}
+
+/// Placement of transient resources in a single region of memory, where resources alive in disjoint passes share bytes.
+#[derive(Clone, Debug)]
+pub struct AliasingPlan {
+    /// Offset of each resource within the region, in the order they were planned.
+    pub offsets: Vec<vk::DeviceSize>,
+    /// Requirements of the region: the peak memory of the resources, their largest alignment and the memory types all of them accept.
+    pub requirements: vk::MemoryRequirements,
+    /// Bytes the resources would take each in their own region, one after the other.
+    pub unaliased_size: vk::DeviceSize
+}
+
+impl AliasingPlan {
+    /// Places the largest resources first, each at the lowest offset that overlaps no placed resource alive during one of its passes.
+    pub fn new(resources: &[(vk::MemoryRequirements, Lifetime)]) -> Self {
+        let mut order = (0..resources.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse(resources[i].0.size));
+        let mut offsets = vec![0; resources.len()];
+        for (placed, &i) in order.iter().enumerate() {
+            let (reqs, lifetime) = resources[i];
+            let live = order[..placed].iter().copied().filter(|&j| resources[j].1.overlaps(&lifetime)).collect::<Vec<_>>();
+            let end = |j: usize| offsets[j] + resources[j].0.size;
+            // Right after the highest live resource always fits, so the lowest fit is among the ends of the live resources
+            let offset = std::iter::once(0).chain(live.iter().map(|&j| end(j)))
+                .map(|offset| MemoryAllocator::aligned(offset, reqs.alignment))
+                .filter(|&offset| live.iter().all(|&j| offset + reqs.size <= offsets[j] || offset >= end(j)))
+                .min()
+                .unwrap_or(0);
+            offsets[i] = offset;
+        }
+        let requirements = vk::MemoryRequirements {
+            size: resources.iter().zip(&offsets).map(|((reqs, _), offset)| offset + reqs.size).max().unwrap_or(0),
+            alignment: resources.iter().map(|(reqs, _)| reqs.alignment).max().unwrap_or(1),
+            memory_type_bits: resources.iter().fold(u32::MAX, |bits, (reqs, _)| bits & reqs.memory_type_bits)
+        };
+        let unaliased_size = resources.iter().fold(0, |end, (reqs, _)| MemoryAllocator::aligned(end, reqs.alignment) + reqs.size);
+        Self { offsets, requirements, unaliased_size }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heaps() -> Vec<HeapBudget> {
+        vec![HeapBudget { heap: 0, budget: 1024, usage: 1000 }]
+    }
+
+    #[test]
+    fn out_of_memory_results_are_reported_with_heaps() {
+        for expected in [vk::Result::ERROR_OUT_OF_DEVICE_MEMORY, vk::Result::ERROR_OUT_OF_HOST_MEMORY] {
+            let error = allocation_error(expected, 256, heaps);
+            match error.downcast_ref() {
+                Some(VulcorError::OutOfMemory { result, requested, heaps }) => {
+                    assert_eq!((*result, *requested, heaps.len()), (expected, 256, 1));
+                }
+                other => panic!("{:?} mapped to {:?}", expected, other)
+            }
+        }
+    }
+
+    #[test]
+    fn other_results_are_kept() {
+        let error = allocation_error(vk::Result::ERROR_TOO_MANY_OBJECTS, 256, || panic!("heaps queried"));
+        assert_eq!(error.downcast_ref(), Some(&vk::Result::ERROR_TOO_MANY_OBJECTS));
+    }
+
+    fn reqs(size: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::MemoryRequirements {
+        vk::MemoryRequirements { size, alignment, memory_type_bits: 0b11 }
+    }
+
+    /// Resources alive in the same pass never share a byte.
+    fn assert_no_live_overlap(resources: &[(vk::MemoryRequirements, Lifetime)], plan: &AliasingPlan) {
+        for (i, (a, a_life)) in resources.iter().enumerate() {
+            for (j, (b, b_life)) in resources.iter().enumerate().skip(i + 1) {
+                let (a_start, b_start) = (plan.offsets[i], plan.offsets[j]);
+                let disjoint = a_start + a.size <= b_start || b_start + b.size <= a_start;
+                assert!(disjoint || !a_life.overlaps(b_life), "{} and {} overlap in {:?}", i, j, plan);
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_memory() {
+        let resources = [(reqs(1024, 256), Lifetime::new(0, 1)), (reqs(1024, 256), Lifetime::new(2, 3))];
+        let plan = AliasingPlan::new(&resources);
+        assert_eq!(plan.offsets, vec![0, 0]);
+        assert_eq!((plan.requirements.size, plan.unaliased_size), (1024, 2048));
+    }
+
+    #[test]
+    fn overlapping_lifetimes_are_kept_apart() {
+        let resources = [(reqs(1000, 256), Lifetime::new(0, 2)), (reqs(512, 256), Lifetime::new(2, 3))];
+        let plan = AliasingPlan::new(&resources);
+        assert_eq!(plan.offsets, vec![0, 1024]);
+        assert_eq!((plan.requirements.size, plan.requirements.alignment, plan.requirements.memory_type_bits), (1536, 256, 0b11));
+        assert_no_live_overlap(&resources, &plan);
+    }
+
+    #[test]
+    fn post_chain_peaks_below_unaliased() {
+        // 1080p bloom: scene -> bright pass -> horizontal blur -> vertical blur -> composite -> FXAA into the swapchain
+        let full_hdr = reqs(1920 * 1080 * 8, 256);
+        let half_hdr = reqs(960 * 540 * 8, 256);
+        let full_ldr = reqs(1920 * 1080 * 4, 256);
+        let resources = [
+            (full_hdr, Lifetime::new(0, 4)),
+            (half_hdr, Lifetime::new(1, 2)),
+            (half_hdr, Lifetime::new(2, 3)),
+            (half_hdr, Lifetime::new(3, 4)),
+            (full_ldr, Lifetime::new(4, 5))
+        ];
+        let plan = AliasingPlan::new(&resources);
+        assert_no_live_overlap(&resources, &plan);
+        assert_eq!(plan.unaliased_size, 37_324_800);
+        // The composite pass needs the scene, the blurred bloom and its output at once, nothing less fits
+        assert_eq!(plan.requirements.size, 29_030_400);
+        assert!(plan.offsets.iter().all(|offset| offset % 256 == 0));
+    }
+
+    #[test]
+    fn memory_types_are_intersected() {
+        let resources = [
+            (vk::MemoryRequirements { size: 64, alignment: 64, memory_type_bits: 0b0110 }, Lifetime::new(0, 0)),
+            (vk::MemoryRequirements { size: 64, alignment: 128, memory_type_bits: 0b0011 }, Lifetime::new(1, 1))
+        ];
+        let plan = AliasingPlan::new(&resources);
+        assert_eq!((plan.requirements.memory_type_bits, plan.requirements.alignment), (0b0010, 128));
+        assert_eq!(AliasingPlan::new(&[]).requirements.size, 0);
+    }
+}
@@ -1,31 +1,194 @@
 use ash::vk;
 use anyhow::{anyhow, Result};
+use std::ptr::copy_nonoverlapping as memcpy;
 
-use crate::{core::{context::VulkanContext, graphics::Graphics}};
+use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, graphics::Graphics}, resources::allocator::{self, MemoryAllocator, MemoryKind}};
 
 pub struct Buffer {
     pub instance: vk::Buffer, 
     pub memory: vk::DeviceMemory,
-    size: u64
+    /// Offset of the buffer within `memory`, non-zero for sub-allocated buffers.
+    pub offset: vk::DeviceSize,
+    size: u64,
+    /// Size of the dedicated allocation, which may exceed `size`.
+    allocation_size: vk::DeviceSize,
+    /// Flags of the memory type actually picked, which may have more than were requested, e.g. `HOST_COHERENT`.
+    properties: vk::MemoryPropertyFlags,
+    dedicated: bool
 }
 
 impl Buffer {
     pub fn new(context: &VulkanContext, graphics: &Graphics, size: vk::DeviceSize, usage: vk::BufferUsageFlags, props: vk::MemoryPropertyFlags) -> Result<Self> {
-        let mem = unsafe { context.instance.get_physical_device_memory_properties(graphics.physical.instance) };
-        let create_info = vk::BufferCreateInfo::default()
+        Self::new_shared(context, graphics, size, usage, props, &[])
+    }
+
+    /// Same as `new`, accessed concurrently by the queue families of `families` without ownership transfers,
+    /// e.g. the graphics and async compute ones. Fewer than two distinct families create an exclusive buffer.
+    pub fn new_shared(context: &VulkanContext, graphics: &Graphics, size: vk::DeviceSize, usage: vk::BufferUsageFlags, props: vk::MemoryPropertyFlags, families: &[u32]) -> Result<Self> {
+        let mut create_info = vk::BufferCreateInfo::default()
             .size(size)
             .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        if families.iter().any(|family| *family != families[0]) {
+            create_info = create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(families);
+        }
         let buffer = unsafe { graphics.logical.instance.create_buffer(&create_info, None)? };
 
         let reqs = unsafe { graphics.logical.instance.get_buffer_memory_requirements(buffer) };
+        let memory_type_index = Self::get_memory_type_index(graphics.physical.memory_properties(), props, reqs)
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_buffer(buffer, None) })?;
         let mem_info = vk::MemoryAllocateInfo::default()
             .allocation_size(reqs.size)
-            .memory_type_index(Self::get_memory_type_index(mem, props, reqs)?);
-        let buffer_mem = unsafe { graphics.logical.instance.allocate_memory(&mem_info, None)? };
+            .memory_type_index(memory_type_index);
+        let buffer_mem = allocator::allocate_memory(context, graphics, &mem_info)
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_buffer(buffer, None) })?;
+        graphics.memory_usage.allocated(MemoryKind::Buffer, reqs.size);
         unsafe { graphics.logical.instance.bind_buffer_memory(buffer, buffer_mem, 0)? };
 
-        Ok(Self { instance: buffer, memory: buffer_mem, size })
+        let properties = graphics.physical.memory_properties().memory_types[memory_type_index as usize].property_flags;
+        Ok(Self { instance: buffer, memory: buffer_mem, offset: 0, size, allocation_size: reqs.size, properties, dedicated: true })
+    }
+
+    /// Creates a buffer bound to a region of a block owned by `allocator` instead of a dedicated allocation.
+    /// The memory is released by the allocator, and mapping must account for `offset`.
+    pub fn new_in(context: &VulkanContext, graphics: &Graphics, allocator: &mut MemoryAllocator, size: vk::DeviceSize, usage: vk::BufferUsageFlags, props: vk::MemoryPropertyFlags) -> Result<Self> {
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe { graphics.logical.instance.create_buffer(&create_info, None)? };
+
+        let reqs = unsafe { graphics.logical.instance.get_buffer_memory_requirements(buffer) };
+        let allocation = allocator.allocate(context, graphics, reqs, props, true)?;
+        unsafe { graphics.logical.instance.bind_buffer_memory(buffer, allocation.memory, allocation.offset)? };
+
+        let properties = graphics.physical.memory_properties().memory_types[allocation.memory_type_index as usize].property_flags;
+        Ok(Self { instance: buffer, memory: allocation.memory, offset: allocation.offset, size, allocation_size: allocation.size, properties, dedicated: false })
+    }
+
+    /// Copies `data` through a host visible staging buffer into a new `DEVICE_LOCAL` buffer with `usage`, e.g. vertices
+    /// drawn every frame. Blocks until the copy completed, the staging buffer is destroyed before returning, even on failure.
+    pub fn device_local<T: Copy>(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, data: &[T], usage: vk::BufferUsageFlags) -> Result<Self> {
+        let size = size_of_val(data) as vk::DeviceSize;
+        let staging_buffer = Buffer::new(
+            context,
+            graphics,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        let result = unsafe { staging_buffer.write(graphics, data) }
+            .and_then(|_| Buffer::new(context, graphics, size, vk::BufferUsageFlags::TRANSFER_DST | usage, vk::MemoryPropertyFlags::DEVICE_LOCAL))
+            .and_then(|buffer| {
+                unsafe { graphics.copy_buffer(&staging_buffer.instance, &buffer.instance, size, cmd_pool, cmd_pool) }
+                    .inspect_err(|_| buffer.cleanup(graphics))?;
+                Ok(buffer)
+            });
+        staging_buffer.cleanup(graphics);
+        result
+    }
+
+    /// Maps the host visible memory of a dedicated buffer and copies `data` to its start.
+    unsafe fn write<T: Copy>(&self, graphics: &Graphics, data: &[T]) -> Result<()> {
+        let mem = graphics.logical.instance.map_memory(self.memory, 0, size_of_val(data) as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+        memcpy(data.as_ptr(), mem.cast(), data.len());
+        graphics.logical.instance.unmap_memory(self.memory);
+        Ok(())
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn memory_properties(&self) -> vk::MemoryPropertyFlags {
+        self.properties
+    }
+
+    /// Reads the first `count` elements back to the CPU, e.g. compute results. Host visible buffers are mapped directly,
+    /// other buffers are copied into a staging buffer on the queue of `cmd_pool` first and must have `TRANSFER_SRC` usage.
+    /// The GPU work writing the buffer must have been submitted to that queue before, or have completed.
+    /// A sub-allocated buffer maps its block, which must not be mapped elsewhere at the same time.
+    pub fn read_to_vec<T: Copy>(&self, context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, count: usize) -> Result<Vec<T>> {
+        let size = (count * size_of::<T>()) as vk::DeviceSize;
+        if size > self.size {
+            return Err(anyhow!("Cannot read {} bytes from a buffer of {} bytes.", size, self.size));
+        }
+        if self.properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return unsafe { self.read_mapped(graphics, count) };
+        }
+        let staging_buffer = Buffer::new(
+            context,
+            graphics,
+            size.max(1),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        let result = unsafe { self.copy_for_read(graphics, cmd_pool, &staging_buffer, size) }
+            .and_then(|_| unsafe { staging_buffer.read_mapped(graphics, count) });
+        staging_buffer.cleanup(graphics);
+        result
+    }
+
+    /// Copies into `staging_buffer`, making earlier writes from any stage visible to the copy and the copy visible to the host.
+    unsafe fn copy_for_read(&self, graphics: &Graphics, cmd_pool: &CmdPool, staging_buffer: &Buffer, size: vk::DeviceSize) -> Result<()> {
+        let device = &graphics.logical.instance;
+        let command_buffer = graphics.begin_command_once(cmd_pool)?;
+        let barrier = |src_access, dst_access| [vk::MemoryBarrier::default().src_access_mask(src_access).dst_access_mask(dst_access)];
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &barrier(vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::TRANSFER_READ),
+            &[] as &[vk::BufferMemoryBarrier],
+            &[] as &[vk::ImageMemoryBarrier]
+        );
+        let region = vk::BufferCopy::default().size(size);
+        device.cmd_copy_buffer(command_buffer, self.instance, staging_buffer.instance, &[region]);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &barrier(vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::HOST_READ),
+            &[] as &[vk::BufferMemoryBarrier],
+            &[] as &[vk::ImageMemoryBarrier]
+        );
+        graphics.end_command_once(cmd_pool, command_buffer)
+    }
+
+    /// Maps the memory of a host visible buffer and copies `count` elements out, invalidating non-coherent memory first.
+    unsafe fn read_mapped<T: Copy>(&self, graphics: &Graphics, count: usize) -> Result<Vec<T>> {
+        let device = &graphics.logical.instance;
+        let size = (count * size_of::<T>()) as vk::DeviceSize;
+        // Invalidated ranges must be aligned to `nonCoherentAtomSize`, so the mapping covers whole atoms
+        let atom = graphics.physical.properties().limits.non_coherent_atom_size.max(1);
+        let (start, length) = match self.dedicated {
+            true => (0, vk::WHOLE_SIZE),
+            false => {
+                let start = self.offset / atom * atom;
+                (start, (self.offset + size).div_ceil(atom) * atom - start)
+            }
+        };
+        let mapped = device.map_memory(self.memory, start, length, vk::MemoryMapFlags::empty())?;
+        if !self.properties.contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+            let range = vk::MappedMemoryRange::default()
+                .memory(self.memory)
+                .offset(start)
+                .size(length);
+            if let Err(e) = device.invalidate_mapped_memory_ranges(&[range]) {
+                device.unmap_memory(self.memory);
+                return Err(anyhow!(e));
+            }
+        }
+        // Copied as bytes, the mapping offset does not have to be aligned for `T`
+        let mut data = Vec::<T>::with_capacity(count);
+        memcpy(mapped.cast::<u8>().add((self.offset - start) as usize), data.as_mut_ptr().cast::<u8>(), size as usize);
+        data.set_len(count);
+        device.unmap_memory(self.memory);
+        Ok(data)
     }
 
     pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
@@ -38,11 +201,14 @@ impl Buffer {
     pub fn cleanup(&self, graphics: &Graphics) {
         unsafe {
             graphics.logical.instance.destroy_buffer(self.instance, None);
-            graphics.logical.instance.free_memory(self.memory, None);
+            if self.dedicated {
+                graphics.logical.instance.free_memory(self.memory, None);
+                graphics.memory_usage.freed(MemoryKind::Buffer, self.allocation_size);
+            }
         }
     }
 
-    fn get_memory_type_index(mem: vk::PhysicalDeviceMemoryProperties, props: vk::MemoryPropertyFlags, reqs: vk::MemoryRequirements) -> Result<u32> {
+    pub fn get_memory_type_index(mem: &vk::PhysicalDeviceMemoryProperties, props: vk::MemoryPropertyFlags, reqs: vk::MemoryRequirements) -> Result<u32> {
         (0..mem.memory_type_count)
             .find(|i| { 
                 let suitable = (reqs.memory_type_bits & (1 << i)) != 0;
@@ -0,0 +1,193 @@
+use ash::vk;
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use std::fmt::{self, Display, Formatter};
+
+use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, graphics::Graphics}, resources::{buffer::Buffer, image::Image}};
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8 bit RGBA and BGRA formats can be encoded.
+    UnsupportedFormat(vk::Format),
+    /// The image was not created with `TRANSFER_SRC`, e.g. a swapchain without it in `SwapchainOptions::extra_usage`.
+    NotTransferSource
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => write!(f, "Images in {:?} cannot be captured.", format),
+            Self::NotTransferSource => write!(f, "The image cannot be captured without the TRANSFER_SRC usage."),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// Order of the color channels of an 8 bit per channel format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentOrder {
+    Rgba,
+    Bgra
+}
+
+impl ComponentOrder {
+    pub fn of(format: vk::Format) -> Result<Self> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(Self::Rgba),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(Self::Bgra),
+            _ => Err(anyhow!(CaptureError::UnsupportedFormat(format)))
+        }
+    }
+
+    /// Reorders tightly packed pixels in place so they read as RGBA.
+    pub fn to_rgba(self, pixels: &mut [u8]) {
+        if self == Self::Bgra {
+            pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+        }
+    }
+}
+
+/// Copies `image` to host memory and returns it as RGBA, whatever the component order of `format`.
+/// `layout` is the layout of the image before the copy, which it is returned to afterwards.
+pub fn capture_image(
+    context: &VulkanContext,
+    graphics: &Graphics,
+    cmd_pool: &CmdPool,
+    image: vk::Image,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    layout: vk::ImageLayout
+) -> Result<RgbaImage> {
+    let order = ComponentOrder::of(format)?;
+    let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+    let buffer = Buffer::new(
+        context,
+        graphics,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+    )?;
+    let result = unsafe { copy_to_buffer(graphics, cmd_pool, image, &buffer, extent, layout) }
+        .and_then(|_| unsafe {
+            let mapped = graphics.logical.instance.map_memory(buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
+            let mut pixels = std::slice::from_raw_parts(mapped.cast::<u8>(), size as usize).to_vec();
+            graphics.logical.instance.unmap_memory(buffer.memory);
+            order.to_rgba(&mut pixels);
+            RgbaImage::from_raw(extent.width, extent.height, pixels).ok_or_else(|| anyhow!("Captured pixels do not match the extent."))
+        });
+    buffer.cleanup(graphics);
+    result
+}
+
+/// Reads a linearly tiled, host visible `image` as RGBA without any copy on the device.
+/// GPU writes to it must be complete and visible to the host, and the image in the `GENERAL` or `PREINITIALIZED` layout.
+pub fn capture_linear_image(graphics: &Graphics, image: &Image) -> Result<RgbaImage> {
+    let order = ComponentOrder::of(image.format())?;
+    let mut pixels = read_linear_image(graphics, image, 4)?;
+    order.to_rgba(&mut pixels);
+    let extent = image.extent();
+    RgbaImage::from_raw(extent.width, extent.height, pixels).ok_or_else(|| anyhow!("Captured pixels do not match the extent."))
+}
+
+/// Copies the first mip level of a linearly tiled, host visible `image` into tightly packed rows.
+/// Drivers may pad each row to a row pitch larger than `width * bytes_per_pixel`, reading the
+/// mapping as one contiguous block would then skew the image.
+pub fn read_linear_image(graphics: &Graphics, image: &Image, bytes_per_pixel: u32) -> Result<Vec<u8>> {
+    let layout = image.subresource_layout(graphics);
+    let extent = image.extent();
+    let row_size = extent.width as usize * bytes_per_pixel as usize;
+    let mut pixels = Vec::with_capacity(row_size * extent.height as usize);
+    unsafe {
+        let device = &graphics.logical.instance;
+        let mapped = device.map_memory(image.memory, image.offset + layout.offset, layout.size, vk::MemoryMapFlags::empty())?;
+        for y in 0..extent.height as usize {
+            let row = mapped.cast::<u8>().add(y * layout.row_pitch as usize);
+            pixels.extend_from_slice(std::slice::from_raw_parts(row, row_size));
+        }
+        device.unmap_memory(image.memory);
+    }
+    Ok(pixels)
+}
+
+unsafe fn copy_to_buffer(graphics: &Graphics, cmd_pool: &CmdPool, image: vk::Image, buffer: &Buffer, extent: vk::Extent2D, layout: vk::ImageLayout) -> Result<()> {
+    let device = &graphics.logical.instance;
+    let command_buffer = graphics.begin_command_once(cmd_pool)?;
+    let to_transfer = layout_barrier(image, layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::ALL_COMMANDS,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[to_transfer]
+    );
+    let subresource = vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+    let region = vk::BufferImageCopy::default()
+        .image_subresource(subresource)
+        .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+    device.cmd_copy_image_to_buffer(command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, buffer.instance, &[region]);
+    let restore = layout_barrier(image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout)
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[restore]
+    );
+    // Waits for the queue to be idle, so the buffer is filled on return
+    graphics.end_command_once(cmd_pool, command_buffer)
+}
+
+fn layout_barrier<'a>(image: vk::Image, old: vk::ImageLayout, new: vk::ImageLayout) -> vk::ImageMemoryBarrier<'a> {
+    let subresource = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+    vk::ImageMemoryBarrier::default()
+        .old_layout(old)
+        .new_layout(new)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bgra_pixels_are_swizzled_to_rgba() {
+        let order = ComponentOrder::of(vk::Format::B8G8R8A8_UNORM).unwrap();
+        let mut pixels = [10, 20, 30, 255, 1, 2, 3, 4];
+        order.to_rgba(&mut pixels);
+        assert_eq!(pixels, [30, 20, 10, 255, 3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn rgba_pixels_are_kept() {
+        let order = ComponentOrder::of(vk::Format::R8G8B8A8_SRGB).unwrap();
+        let mut pixels = [10, 20, 30, 255];
+        order.to_rgba(&mut pixels);
+        assert_eq!(pixels, [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn other_formats_are_unsupported() {
+        let error = ComponentOrder::of(vk::Format::R16G16B16A16_SFLOAT).unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(CaptureError::UnsupportedFormat(vk::Format::R16G16B16A16_SFLOAT))));
+    }
+}
@@ -1,12 +1,189 @@
-use ash::vk;
+use ash::{vk, Device};
 use anyhow::{anyhow, Result};
+use std::fmt::{self, Display, Formatter};
 
-use crate::{core::{context::VulkanContext, graphics::Graphics}};
+use crate::{core::{context::VulkanContext, graphics::Graphics}, resources::allocator::{self, AliasingPlan, Lifetime, MemoryAllocator, MemoryKind}};
+
+#[derive(Debug)]
+pub enum ImageError {
+    ExceedsMaxDimension { extent: (u32, u32), max: u32 },
+    ExceedsMaxArrayLayers { layers: u32, max: u32 }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::ExceedsMaxDimension { extent, max } => write!(f, "{}x{} exceeds device max of {}", extent.0, extent.1, max),
+            Self::ExceedsMaxArrayLayers { layers, max } => write!(f, "{} array layers exceed device max of {}", layers, max),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Aspect a view of `format` covers: depth and stencil formats expose their depth and stencil planes, everything else is color.
+pub fn aspect_for(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR
+    }
+}
+
+/// Whether the hardware decodes `format` from sRGB to linear on reads and encodes it back on writes and blending.
+pub fn is_srgb(format: vk::Format) -> bool {
+    matches!(format,
+        vk::Format::R8_SRGB | vk::Format::R8G8_SRGB | vk::Format::R8G8B8_SRGB | vk::Format::B8G8R8_SRGB
+        | vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+        | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK)
+}
+
+/// Levels of a full mip chain down to 1x1, `floor(log2(max(width, height))) + 1`, e.g. 10 for 512x512.
+/// A zero sized extent still gets its base level.
+pub fn mip_levels_for(extent: (u32, u32)) -> u32 {
+    32 - extent.0.max(extent.1).max(1).leading_zeros()
+}
+
+/// Swizzle leaving every channel in place, the default of views.
+pub const IDENTITY_SWIZZLE: vk::ComponentMapping = vk::ComponentMapping {
+    r: vk::ComponentSwizzle::IDENTITY,
+    g: vk::ComponentSwizzle::IDENTITY,
+    b: vk::ComponentSwizzle::IDENTITY,
+    a: vk::ComponentSwizzle::IDENTITY,
+};
+
+/// Broadcasts the red channel of a single channel format, e.g. `R8_UNORM`, to RGB with an opaque alpha.
+pub const GRAYSCALE_SWIZZLE: vk::ComponentMapping = vk::ComponentMapping {
+    r: vk::ComponentSwizzle::R,
+    g: vk::ComponentSwizzle::R,
+    b: vk::ComponentSwizzle::R,
+    a: vk::ComponentSwizzle::ONE,
+};
+
+/// Reads the red channel of a single channel format as alpha over white, e.g. for font atlases.
+pub const ALPHA_SWIZZLE: vk::ComponentMapping = vk::ComponentMapping {
+    r: vk::ComponentSwizzle::ONE,
+    g: vk::ComponentSwizzle::ONE,
+    b: vk::ComponentSwizzle::ONE,
+    a: vk::ComponentSwizzle::R,
+};
+
+/// Swaps red and blue, so BGRA data uploaded to an RGBA image samples with the right channel order.
+pub const BGRA_SWIZZLE: vk::ComponentMapping = vk::ComponentMapping {
+    r: vk::ComponentSwizzle::B,
+    g: vk::ComponentSwizzle::IDENTITY,
+    b: vk::ComponentSwizzle::R,
+    a: vk::ComponentSwizzle::IDENTITY,
+};
+
+/// 2D view over the first `mip_levels` levels and the single layer of `image`.
+/// Views sampled in shaders of a combined depth stencil format must pick one of the two aspects.
+pub fn create_image_view(device: &Device, image: vk::Image, format: vk::Format, aspect: vk::ImageAspectFlags, mip_levels: u32) -> Result<vk::ImageView> {
+    create_view(device, image, vk::ImageViewType::TYPE_2D, format, subresource_range(aspect, mip_levels, 1), IDENTITY_SWIZZLE)
+}
+
+/// `TYPE_2D_ARRAY` view over the first `layers` layers of `image`, sampled as a `sampler2DArray`
+/// through a `COMBINED_IMAGE_SAMPLER` descriptor, e.g. terrain splat maps or sprite sheets.
+pub fn create_array_view(device: &Device, image: vk::Image, format: vk::Format, aspect: vk::ImageAspectFlags, mip_levels: u32, layers: u32) -> Result<vk::ImageView> {
+    create_view(device, image, vk::ImageViewType::TYPE_2D_ARRAY, format, subresource_range(aspect, mip_levels, layers), IDENTITY_SWIZZLE)
+}
+
+/// `CUBE` view over the 6 layers of a `CUBE_COMPATIBLE` image, sampled as a `samplerCube`.
+pub fn create_cube_view(device: &Device, image: vk::Image, format: vk::Format, mip_levels: u32) -> Result<vk::ImageView> {
+    create_view(device, image, vk::ImageViewType::CUBE, format, subresource_range(aspect_for(format), mip_levels, 6), IDENTITY_SWIZZLE)
+}
+
+/// `TYPE_2D` view of the first mip level of `layer` alone, to render into one layer of an array or cube image.
+pub fn create_layer_view(device: &Device, image: vk::Image, format: vk::Format, aspect: vk::ImageAspectFlags, layer: u32) -> Result<vk::ImageView> {
+    let range = vk::ImageSubresourceRange { base_array_layer: layer, ..subresource_range(aspect, 1, 1) };
+    create_view(device, image, vk::ImageViewType::TYPE_2D, format, range, IDENTITY_SWIZZLE)
+}
+
+/// First `mip_levels` levels and `layers` layers of `aspect`.
+pub fn subresource_range(aspect: vk::ImageAspectFlags, mip_levels: u32, layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: aspect,
+        base_mip_level: 0,
+        level_count: mip_levels,
+        base_array_layer: 0,
+        layer_count: layers,
+    }
+}
+
+/// View of any type whose channels are remapped by `components` when sampled, e.g. `GRAYSCALE_SWIZZLE`.
+/// Swizzling only applies to color aspects, depth and stencil views must keep `IDENTITY_SWIZZLE`.
+pub fn create_view(
+    device: &Device,
+    image: vk::Image,
+    view_type: vk::ImageViewType,
+    format: vk::Format,
+    range: vk::ImageSubresourceRange,
+    components: vk::ComponentMapping
+) -> Result<vk::ImageView> {
+    let info = vk::ImageViewCreateInfo::default()
+        .view_type(view_type)
+        .format(format)
+        .components(components)
+        .subresource_range(range)
+        .image(image);
+    let view = unsafe { device.create_image_view(&info, None)? };
+    Ok(view)
+}
+
+/// Everything an image is created from besides the device, see `Image::with_desc`.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDesc {
+    pub extent: (u32, u32),
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub props: vk::MemoryPropertyFlags,
+    pub tiling: vk::ImageTiling,
+    /// Layers of a 2D texture array, 1 for a plain 2D image.
+    pub array_layers: u32,
+    /// See `mip_levels_for` for a full mip chain.
+    pub mip_levels: u32,
+    /// `UNDEFINED` unless pixels are written through a mapping before the first transition, see `Image::new_host_linear`.
+    pub initial_layout: vk::ImageLayout,
+    /// Above `TYPE_1` for multisampled attachments, which must match the other attachments of their subpass.
+    pub samples: vk::SampleCountFlags,
+    /// `CUBE_COMPATIBLE` for cubemaps of 6 square layers, see `create_cube_view`.
+    pub flags: vk::ImageCreateFlags
+}
+
+impl ImageDesc {
+    /// Optimally tiled image of a single layer and mip level, starting `UNDEFINED`.
+    pub fn new(extent: (u32, u32), format: vk::Format, usage: vk::ImageUsageFlags, props: vk::MemoryPropertyFlags) -> Self {
+        Self {
+            extent,
+            format,
+            usage,
+            props,
+            tiling: vk::ImageTiling::OPTIMAL,
+            array_layers: 1,
+            mip_levels: 1,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            samples: vk::SampleCountFlags::TYPE_1,
+            flags: vk::ImageCreateFlags::empty()
+        }
+    }
+}
 
 pub struct Image {
     pub instance: vk::Image, 
     pub memory: vk::DeviceMemory,
-    size: u64
+    /// Offset of the image within `memory`, non-zero for sub-allocated images.
+    pub offset: vk::DeviceSize,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    mip_levels: u32,
+    array_layers: u32,
+    /// Bytes of memory bound to the image, as required by the device.
+    allocation_size: vk::DeviceSize,
+    dedicated: bool
 }
 
 impl Image {
@@ -14,38 +191,245 @@ impl Image {
         context: &VulkanContext, 
         graphics: &Graphics, 
         extent: (u32, u32), 
-        size: vk::DeviceSize, 
         usage: vk::ImageUsageFlags, 
         props: vk::MemoryPropertyFlags,
         format: vk::Format,
         tiling: vk::ImageTiling
     ) -> Result<Self> {
-        let mem = unsafe { context.instance.get_physical_device_memory_properties(graphics.physical.instance) };
-        let info = vk::ImageCreateInfo::default()
-            .image_type(vk::ImageType::TYPE_2D)
-            .extent(vk::Extent3D { width: extent.1, height: extent.1, depth: 1 })
-            .mip_levels(1)
-            .array_layers(1)
-            .format(format)
-            .tiling(tiling)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .usage(usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .flags(vk::ImageCreateFlags::empty());
-        let img = unsafe { graphics.logical.instance.create_image(&info, None)? };
-        
+        Self::with_desc(context, graphics, &ImageDesc { tiling, ..ImageDesc::new(extent, format, usage, props) })
+    }
+
+    /// Linearly tiled image in host visible memory, starting `PREINITIALIZED` so that pixels written
+    /// through a mapping survive the first layout transition. Rows are `subresource_layout` row pitch apart.
+    pub fn new_host_linear(context: &VulkanContext, graphics: &Graphics, extent: (u32, u32), usage: vk::ImageUsageFlags, format: vk::Format) -> Result<Self> {
+        let desc = ImageDesc {
+            tiling: vk::ImageTiling::LINEAR,
+            initial_layout: vk::ImageLayout::PREINITIALIZED,
+            ..ImageDesc::new(extent, format, usage, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+        };
+        Self::with_desc(context, graphics, &desc)
+    }
+
+    /// Image with its own allocation, e.g. a 2D texture array of `array_layers` layers each with `mip_levels` levels.
+    /// Fails when the extent exceeds `maxImageDimension2D` or the layers exceed `maxImageArrayLayers`.
+    pub fn with_desc(context: &VulkanContext, graphics: &Graphics, desc: &ImageDesc) -> Result<Self> {
+        Self::validate(graphics, desc.extent, desc.array_layers)?;
+        let img = Self::create_image(graphics, desc)?;
         let reqs = unsafe { graphics.logical.instance.get_image_memory_requirements(img) };
+        let memory_type_index = Self::select_memory_type_index(graphics.physical.memory_properties(), desc.props, reqs)
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_image(img, None) })?;
         let mem_info = vk::MemoryAllocateInfo::default()
             .allocation_size(reqs.size)
-            .memory_type_index(Self::get_memory_type_index(mem, props, reqs)?);
-        let img_mem = unsafe { graphics.logical.instance.allocate_memory(&mem_info, None)? };
+            .memory_type_index(memory_type_index);
+        let img_mem = allocator::allocate_memory(context, graphics, &mem_info)
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_image(img, None) })?;
+        graphics.memory_usage.allocated(MemoryKind::Image, reqs.size);
         unsafe { graphics.logical.instance.bind_image_memory(img, img_mem, 0)? };
 
-        Ok(Self { instance: img, memory: img_mem, size })
+        Ok(Self {
+            instance: img,
+            memory: img_mem,
+            offset: 0,
+            extent: Self::extent_2d(desc.extent),
+            format: desc.format,
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+            allocation_size: reqs.size,
+            dedicated: true
+        })
+    }
+
+    /// Creates an image bound to a region of a block owned by `allocator` instead of a dedicated allocation.
+    pub fn new_in(context: &VulkanContext, graphics: &Graphics, allocator: &mut MemoryAllocator, desc: &ImageDesc) -> Result<Self> {
+        Self::validate(graphics, desc.extent, desc.array_layers)?;
+        let img = Self::create_image(graphics, desc)?;
+        let reqs = unsafe { graphics.logical.instance.get_image_memory_requirements(img) };
+        let allocation = allocator.allocate(context, graphics, reqs, desc.props, desc.tiling == vk::ImageTiling::LINEAR)
+            .inspect_err(|_| unsafe { graphics.logical.instance.destroy_image(img, None) })?;
+        unsafe { graphics.logical.instance.bind_image_memory(img, allocation.memory, allocation.offset)? };
+
+        Ok(Self {
+            instance: img,
+            memory: allocation.memory,
+            offset: allocation.offset,
+            extent: Self::extent_2d(desc.extent),
+            format: desc.format,
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+            allocation_size: allocation.size,
+            dedicated: false
+        })
+    }
+
+    /// Creates optimally tiled images sharing a single region of `allocator`, where images alive in disjoint passes,
+    /// e.g. the scratch targets of successive post-processing passes, alias the same memory. The images are returned
+    /// in the order of `descs`, along with the plan comparing the bytes taken to `AliasingPlan::unaliased_size`.
+    /// The contents of an image are undefined when its first pass begins, which must transition it from `UNDEFINED`.
+    pub fn new_aliased(context: &VulkanContext, graphics: &Graphics, allocator: &mut MemoryAllocator, descs: &[(ImageDesc, Lifetime)]) -> Result<(Vec<Self>, AliasingPlan)> {
+        if descs.iter().any(|(desc, _)| desc.tiling == vk::ImageTiling::LINEAR) {
+            return Err(anyhow!("Linear images cannot be aliased with optimal ones."));
+        }
+        let mut images = Vec::with_capacity(descs.len());
+        let destroy = |images: &[vk::Image]| images.iter().for_each(|img| unsafe { graphics.logical.instance.destroy_image(*img, None) });
+        for (desc, _) in descs {
+            let img = Self::validate(graphics, desc.extent, desc.array_layers)
+                .and_then(|_| Self::create_image(graphics, desc))
+                .inspect_err(|_| destroy(&images))?;
+            images.push(img);
+        }
+        let resources = images.iter().zip(descs)
+            .map(|(img, (_, lifetime))| (unsafe { graphics.logical.instance.get_image_memory_requirements(*img) }, *lifetime))
+            .collect::<Vec<_>>();
+        let plan = AliasingPlan::new(&resources);
+        if images.is_empty() {
+            return Ok((vec![], plan));
+        }
+        // The region has every property any of the images asks for
+        let props = descs.iter().fold(vk::MemoryPropertyFlags::empty(), |props, (desc, _)| props | desc.props);
+        let allocation = allocator.allocate(context, graphics, plan.requirements, props, false)
+            .inspect_err(|_| destroy(&images))?;
+        for (img, offset) in images.iter().zip(&plan.offsets) {
+            unsafe { graphics.logical.instance.bind_image_memory(*img, allocation.memory, allocation.offset + offset) }
+                .inspect_err(|_| destroy(&images))?;
+        }
+        log::debug!("Aliased {} transient images into {} bytes instead of {}", images.len(), plan.requirements.size, plan.unaliased_size);
+
+        let images = images.into_iter().zip(descs).zip(resources.iter().zip(&plan.offsets))
+            .map(|((img, (desc, _)), ((reqs, _), offset))| Self {
+                instance: img,
+                memory: allocation.memory,
+                offset: allocation.offset + offset,
+                extent: Self::extent_2d(desc.extent),
+                format: desc.format,
+                mip_levels: desc.mip_levels,
+                array_layers: desc.array_layers,
+                allocation_size: reqs.size,
+                dedicated: false
+            })
+            .collect();
+        Ok((images, plan))
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    pub fn allocation_size(&self) -> vk::DeviceSize {
+        self.allocation_size
+    }
+
+    /// View over every mip level and layer, with the aspect matching the image format.
+    /// Images of several layers get a `TYPE_2D_ARRAY` view, the others a `TYPE_2D` one.
+    pub fn create_view(&self, graphics: &Graphics) -> Result<vk::ImageView> {
+        self.create_swizzled_view(graphics, IDENTITY_SWIZZLE)
+    }
+
+    /// Same as `create_view` with the channels remapped by `components`, e.g. `ALPHA_SWIZZLE` for a single channel font atlas.
+    pub fn create_swizzled_view(&self, graphics: &Graphics, components: vk::ComponentMapping) -> Result<vk::ImageView> {
+        let view_type = match self.array_layers {
+            1 => vk::ImageViewType::TYPE_2D,
+            _ => vk::ImageViewType::TYPE_2D_ARRAY
+        };
+        let range = subresource_range(aspect_for(self.format), self.mip_levels, self.array_layers);
+        create_view(&graphics.logical.instance, self.instance, view_type, self.format, range, components)
+    }
+
+    /// Offset and row pitch of the color subresource, only meaningful for `LINEAR` tiling.
+    pub fn subresource_layout(&self, graphics: &Graphics) -> vk::SubresourceLayout {
+        let subresource = vk::ImageSubresource::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .array_layer(0);
+        unsafe { graphics.logical.instance.get_image_subresource_layout(self.instance, subresource) }
+    }
+
+    fn extent_2d(extent: (u32, u32)) -> vk::Extent2D {
+        vk::Extent2D { width: extent.0, height: extent.1 }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe {
+            graphics.logical.instance.destroy_image(self.instance, None);
+            if self.dedicated {
+                graphics.logical.instance.free_memory(self.memory, None);
+                graphics.memory_usage.freed(MemoryKind::Image, self.allocation_size);
+            }
+        }
+    }
+
+    fn validate(graphics: &Graphics, extent: (u32, u32), array_layers: u32) -> Result<()> {
+        let limits = &graphics.physical.properties().limits;
+        let max = limits.max_image_dimension2_d;
+        if extent.0 > max || extent.1 > max {
+            return Err(anyhow!(ImageError::ExceedsMaxDimension { extent, max }));
+        }
+        if array_layers > limits.max_image_array_layers {
+            return Err(anyhow!(ImageError::ExceedsMaxArrayLayers { layers: array_layers, max: limits.max_image_array_layers }));
+        }
+        Ok(())
+    }
+
+    fn create_image(graphics: &Graphics, desc: &ImageDesc) -> Result<vk::Image> {
+        let info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D { width: desc.extent.0, height: desc.extent.1, depth: 1 })
+            .mip_levels(desc.mip_levels)
+            .array_layers(desc.array_layers)
+            .format(desc.format)
+            .tiling(desc.tiling)
+            .initial_layout(desc.initial_layout)
+            .usage(desc.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .samples(desc.samples)
+            .flags(desc.flags);
+        let img = unsafe { graphics.logical.instance.create_image(&info, None)? };
+        Ok(img)
+    }
+
+    /// Creates an attachment that is never stored, backed by lazily-allocated memory when the device offers it.
+    pub fn new_transient(
+        context: &VulkanContext, 
+        graphics: &Graphics, 
+        extent: (u32, u32), 
+        usage: vk::ImageUsageFlags, 
+        format: vk::Format,
+        samples: vk::SampleCountFlags
+    ) -> Result<Self> {
+        let desc = ImageDesc {
+            samples,
+            ..ImageDesc::new(
+                extent,
+                format,
+                usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED
+            )
+        };
+        Self::with_desc(context, graphics, &desc)
+    }
+
+    fn select_memory_type_index(mem: &vk::PhysicalDeviceMemoryProperties, props: vk::MemoryPropertyFlags, reqs: vk::MemoryRequirements) -> Result<u32> {
+        match Self::get_memory_type_index(mem, props, reqs) {
+            Err(_) if props.contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED) => {
+                log::info!("Lazily allocated memory unavailable, falling back to device local memory.");
+                Self::get_memory_type_index(mem, vk::MemoryPropertyFlags::DEVICE_LOCAL, reqs)
+            },
+            result => result
+        }
     }
 
-    fn get_memory_type_index(mem: vk::PhysicalDeviceMemoryProperties, props: vk::MemoryPropertyFlags, reqs: vk::MemoryRequirements) -> Result<u32> {
+    fn get_memory_type_index(mem: &vk::PhysicalDeviceMemoryProperties, props: vk::MemoryPropertyFlags, reqs: vk::MemoryRequirements) -> Result<u32> {
         (0..mem.memory_type_count)
             .find(|i| { 
                 let suitable = (reqs.memory_type_bits & (1 << i)) != 0;
@@ -54,4 +438,26 @@ impl Image {
             })
             .ok_or_else(|| anyhow!("No suitable memory type found."))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_levels_cover_full_chain() {
+        assert_eq!(mip_levels_for((1, 1)), 1);
+        assert_eq!(mip_levels_for((0, 0)), 1);
+        assert_eq!(mip_levels_for((512, 512)), 10);
+        assert_eq!(mip_levels_for((1024, 256)), 11);
+        assert_eq!(mip_levels_for((300, 100)), 9);
+    }
+
+    #[test]
+    fn aspect_matches_format_planes() {
+        assert_eq!(aspect_for(vk::Format::D32_SFLOAT), vk::ImageAspectFlags::DEPTH);
+        assert_eq!(aspect_for(vk::Format::D24_UNORM_S8_UINT), vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+        assert_eq!(aspect_for(vk::Format::S8_UINT), vk::ImageAspectFlags::STENCIL);
+        assert_eq!(aspect_for(vk::Format::B8G8R8A8_SRGB), vk::ImageAspectFlags::COLOR);
+    }
+}
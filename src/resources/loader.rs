@@ -0,0 +1,346 @@
+use ash::{vk, Device};
+use anyhow::{anyhow, Result};
+use image::ImageReader;
+use std::{path::{Path, PathBuf}, ptr::copy_nonoverlapping as memcpy, sync::{mpsc::{self, Receiver, Sender}, Arc, Condvar, Mutex}, thread::{self, JoinHandle}};
+
+use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, graphics::Graphics}, resources::{buffer::Buffer, image::Image}};
+
+/// Staging buffers alive at once unless configured otherwise, see `ResourceLoader::new`.
+pub const DEFAULT_MAX_IN_FLIGHT_UPLOADS: usize = 8;
+
+/// Identifies a load request until its resource is returned by `ResourceLoader::poll`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LoadHandle(u64);
+
+/// How the texels of a texture are encoded, which picks the format of the uploaded image.
+/// Anything displayed as color (albedo, diffuse, emissive, UI) is authored in sRGB and is sampled back in linear space.
+/// Data maps (normals, roughness, metalness, ambient occlusion, height) store raw values that must not be decoded.
+/// Shaders then light and blend in linear space, and the sRGB swapchain encodes their output on write. Sampling a
+/// color texture uploaded as `Linear` skips the decode, and the swapchain encoding it again washes it out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// `R8G8B8A8_SRGB`, decoded to linear by the sampler.
+    #[default]
+    Srgb,
+    /// `R8G8B8A8_UNORM`, sampled as stored.
+    Linear
+}
+
+impl ColorSpace {
+    pub fn format(self) -> vk::Format {
+        match self {
+            Self::Srgb => vk::Format::R8G8B8A8_SRGB,
+            Self::Linear => vk::Format::R8G8B8A8_UNORM
+        }
+    }
+}
+
+/// Pixels decoded and copied into host visible memory by the worker, waiting for the GPU copy.
+#[derive(Copy, Clone)]
+struct StagedTexture {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    extent: (u32, u32),
+    format: vk::Format
+}
+
+/// Counts the staging buffers alive, from the worker creating one until `poll` destroys it once its copy is done.
+/// The worker waits for a free slot before decoding the next texture, which bounds the staging memory.
+struct StagingSlots {
+    /// Buffers in flight, and whether the loader is shutting down.
+    state: Mutex<(usize, bool)>,
+    freed: Condvar,
+    max: usize
+}
+
+impl StagingSlots {
+    fn new(max: usize) -> Self {
+        Self { state: Mutex::new((0, false)), freed: Condvar::new(), max: max.max(1) }
+    }
+
+    /// Blocks until a slot is free and takes it, false once shut down.
+    fn acquire(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut state = self.freed.wait_while(state, |(in_flight, shut_down)| *in_flight >= self.max && !*shut_down)
+            .unwrap_or_else(|e| e.into_inner());
+        if state.1 {
+            return false;
+        }
+        state.0 += 1;
+        true
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0 = state.0.saturating_sub(1);
+        self.freed.notify_one();
+    }
+
+    /// Wakes the worker if it waits for a slot, it then stops.
+    fn shut_down(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1 = true;
+        self.freed.notify_all();
+    }
+}
+
+struct Upload {
+    handle: LoadHandle,
+    staged: StagedTexture,
+    image: Image,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence
+}
+
+/// Loads textures without blocking the render loop.
+/// Decoding and staging happen on a worker thread, the copy is then submitted on the transfer queue and tracked with a fence.
+/// All queue submissions stay on the thread calling `poll`, so the queues need no extra synchronization.
+/// At most `max_in_flight` staging buffers exist at once, further requests wait until `poll` frees earlier ones.
+pub struct ResourceLoader {
+    transfer_pool: CmdPool,
+    graphics_pool: CmdPool,
+    requests: Option<Sender<(LoadHandle, PathBuf, ColorSpace)>>,
+    staged: Receiver<(LoadHandle, Result<StagedTexture>)>,
+    uploads: Vec<Upload>,
+    slots: Arc<StagingSlots>,
+    next_handle: u64,
+    worker: Option<JoinHandle<()>>
+}
+
+impl ResourceLoader {
+    /// `max_in_flight` bounds the staging buffers alive at once, as uploads are only waited on by `poll` it should
+    /// be at least the number of textures expected to finish between two polls. Zero is treated as one.
+    pub fn new(graphics: &Graphics, max_in_flight: usize) -> Result<Self> {
+        let transfer_pool = CmdPool::new(&graphics.logical, graphics.queue_family.transfer)?;
+        let graphics_pool = CmdPool::new(&graphics.logical, graphics.queue_family.graphics)?;
+        // Copied out of the hardware description so the worker owns it
+        let memory_properties = *graphics.physical.memory_properties();
+        let device = graphics.logical.instance.clone();
+        let (requests, pending) = mpsc::channel::<(LoadHandle, PathBuf, ColorSpace)>();
+        let (completed, staged) = mpsc::channel();
+        let slots = Arc::new(StagingSlots::new(max_in_flight));
+        let worker_slots = slots.clone();
+        let worker = thread::Builder::new()
+            .name("vulcor-loader".to_string())
+            .spawn(move || {
+                // The loop ends once the loader drops its sender, or while waiting for a slot once it shuts down
+                for (handle, path, color_space) in pending {
+                    if !worker_slots.acquire() {
+                        break;
+                    }
+                    let result = Self::stage_texture(&device, &memory_properties, &path, color_space);
+                    if result.is_err() {
+                        worker_slots.release();
+                    }
+                    if completed.send((handle, result)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(Self { transfer_pool, graphics_pool, requests: Some(requests), staged, uploads: vec![], slots, next_handle: 0, worker: Some(worker) })
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.slots.max
+    }
+
+    /// Use `ColorSpace::Linear` for data maps such as normal or roughness maps, `ColorSpace::Srgb` for colors.
+    pub fn request_texture<P: AsRef<Path>>(&mut self, path: P, color_space: ColorSpace) -> Result<LoadHandle> {
+        let handle = LoadHandle(self.next_handle);
+        self.next_handle += 1;
+        let requests = self.requests.as_ref().ok_or_else(|| anyhow!("The resource loader was shut down."))?;
+        requests.send((handle, path.as_ref().to_path_buf(), color_space)).map_err(|_| anyhow!("The resource loader worker stopped."))?;
+        Ok(handle)
+    }
+
+    /// Submits the uploads of newly staged textures and returns the ones the GPU finished copying.
+    /// Returned images are in `SHADER_READ_ONLY_OPTIMAL` layout and owned by the graphics queue family.
+    pub fn poll(&mut self, context: &VulkanContext, graphics: &Graphics) -> Result<Vec<(LoadHandle, Result<Image>)>> {
+        let mut ready = vec![];
+        while let Ok((handle, result)) = self.staged.try_recv() {
+            let staged = match result {
+                Ok(staged) => staged,
+                Err(e) => {
+                    ready.push((handle, Err(e)));
+                    continue;
+                }
+            };
+            match unsafe { self.submit_upload(context, graphics, handle, staged) } {
+                Ok(upload) => self.uploads.push(upload),
+                Err(e) => {
+                    unsafe { self.destroy_staged(graphics, staged) };
+                    ready.push((handle, Err(e)));
+                }
+            }
+        }
+
+        let mut index = 0;
+        while index < self.uploads.len() {
+            let signaled = unsafe { graphics.logical.instance.get_fence_status(self.uploads[index].fence)? };
+            if !signaled {
+                index += 1;
+                continue;
+            }
+            let upload = self.uploads.swap_remove(index);
+            let result = unsafe { self.finish_upload(graphics, &upload) };
+            unsafe { self.destroy_upload(graphics, &upload) };
+            ready.push((upload.handle, result.map(|_| upload.image)));
+        }
+        Ok(ready)
+    }
+
+    /// Stops the worker, then waits for pending uploads before releasing them.
+    pub fn cleanup(&mut self, graphics: &Graphics) {
+        self.requests = None;
+        self.slots.shut_down();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        let device = &graphics.logical.instance;
+        unsafe {
+            while let Ok((_, result)) = self.staged.try_recv() {
+                if let Ok(staged) = result {
+                    self.destroy_staged(graphics, staged);
+                }
+            }
+            for upload in std::mem::take(&mut self.uploads) {
+                let _ = device.wait_for_fences(&[upload.fence], true, u64::MAX);
+                self.destroy_upload(graphics, &upload);
+                upload.image.cleanup(graphics);
+            }
+            device.destroy_command_pool(self.transfer_pool.instance, None);
+            device.destroy_command_pool(self.graphics_pool.instance, None);
+        }
+    }
+
+    fn stage_texture(device: &Device, memory_properties: &vk::PhysicalDeviceMemoryProperties, path: &Path, color_space: ColorSpace) -> Result<StagedTexture> {
+        let pixels = ImageReader::open(path)?.decode()?.to_rgba8();
+        let extent = pixels.dimensions();
+        let size = pixels.len() as vk::DeviceSize;
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        unsafe {
+            let buffer = device.create_buffer(&create_info, None)?;
+            let reqs = device.get_buffer_memory_requirements(buffer);
+            let props = vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE;
+            let mem_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(reqs.size)
+                .memory_type_index(Buffer::get_memory_type_index(memory_properties, props, reqs)?);
+            let memory = device.allocate_memory(&mem_info, None)?;
+            device.bind_buffer_memory(buffer, memory, 0)?;
+            let mem = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+            memcpy(pixels.as_ptr(), mem.cast(), pixels.len());
+            device.unmap_memory(memory);
+            Ok(StagedTexture { buffer, memory, extent, format: color_space.format() })
+        }
+    }
+
+    unsafe fn submit_upload(&self, context: &VulkanContext, graphics: &Graphics, handle: LoadHandle, staged: StagedTexture) -> Result<Upload> {
+        let device = &graphics.logical.instance;
+        let image = Image::new(
+            context,
+            graphics,
+            staged.extent,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            staged.format,
+            vk::ImageTiling::OPTIMAL
+        )?;
+        let command_buffer = graphics.begin_command_once(&self.transfer_pool)?;
+        let to_transfer = Self::layout_barrier(&image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[to_transfer]
+        );
+        let subresource = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(subresource)
+            .image_extent(vk::Extent3D { width: staged.extent.0, height: staged.extent.1, depth: 1 });
+        device.cmd_copy_buffer_to_image(command_buffer, staged.buffer, image.instance, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+        // Also releases the image to the graphics family when the transfer queue belongs to another family
+        let release = Self::layout_barrier(&image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(self.transfer_pool.queue_family)
+            .dst_queue_family_index(self.graphics_pool.queue_family)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[release]
+        );
+        device.end_command_buffer(command_buffer)?;
+
+        let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+        let command_buffers = &[command_buffer];
+        let submit_info = vk::SubmitInfo::default().command_buffers(command_buffers);
+        let queue = device.get_device_queue(self.transfer_pool.queue_family, 0);
+        device.queue_submit(queue, &[submit_info], fence)?;
+        Ok(Upload { handle, staged, image, command_buffer, fence })
+    }
+
+    /// Acquires the image on the graphics family, matching the release recorded with the copy.
+    unsafe fn finish_upload(&self, graphics: &Graphics, upload: &Upload) -> Result<()> {
+        if self.transfer_pool.queue_family == self.graphics_pool.queue_family {
+            return Ok(());
+        }
+        let command_buffer = graphics.begin_command_once(&self.graphics_pool)?;
+        let acquire = Self::layout_barrier(&upload.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(self.transfer_pool.queue_family)
+            .dst_queue_family_index(self.graphics_pool.queue_family)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        graphics.logical.instance.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[acquire]
+        );
+        graphics.end_command_once(&self.graphics_pool, command_buffer)
+    }
+
+    unsafe fn destroy_upload(&self, graphics: &Graphics, upload: &Upload) {
+        let device = &graphics.logical.instance;
+        device.destroy_fence(upload.fence, None);
+        device.free_command_buffers(self.transfer_pool.instance, &[upload.command_buffer]);
+        self.destroy_staged(graphics, upload.staged);
+    }
+
+    /// Also frees the staging slot, letting the worker stage the next texture.
+    unsafe fn destroy_staged(&self, graphics: &Graphics, staged: StagedTexture) {
+        graphics.logical.instance.destroy_buffer(staged.buffer, None);
+        graphics.logical.instance.free_memory(staged.memory, None);
+        self.slots.release();
+    }
+
+    fn layout_barrier<'a>(image: &Image, old: vk::ImageLayout, new: vk::ImageLayout) -> vk::ImageMemoryBarrier<'a> {
+        let subresource = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        vk::ImageMemoryBarrier::default()
+            .old_layout(old)
+            .new_layout(new)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image.instance)
+            .subresource_range(subresource)
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use ash::vk;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::{cmd::command_pool::{CmdPool, MeshRange}, core::{context::VulkanContext, graphics::Graphics}, resources::buffer::Buffer};
+
+/// Indexed geometry on the CPU, with vertices of any layout matching the pipeline's `VertexInput`.
+pub struct Mesh<V> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<u16>
+}
+
+impl<V: Copy> Mesh<V> {
+    pub fn new(vertices: Vec<V>, indices: Vec<u16>) -> Self {
+        Self { vertices, indices }
+    }
+
+    /// Packs vertices then indices into one staging buffer and copies them into a single device local buffer used
+    /// as both vertex and index buffer, halving the allocations and transfers of separate buffers.
+    pub fn upload(&self, context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool) -> Result<GpuMesh> {
+        let vertices_size = (size_of::<V>() * self.vertices.len()) as vk::DeviceSize;
+        // Index buffer offsets must be a multiple of the index size
+        let index_offset = vertices_size.next_multiple_of(size_of::<u16>() as vk::DeviceSize);
+        let size = index_offset + (size_of::<u16>() * self.indices.len()) as vk::DeviceSize;
+        let staging_buffer = Buffer::new(
+            context,
+            graphics,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        let result = unsafe { Self::fill(graphics, &staging_buffer, &self.vertices, &self.indices, index_offset, size) }
+            .and_then(|_| Buffer::new(
+                context,
+                graphics,
+                size,
+                vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL
+            ))
+            .and_then(|buffer| {
+                unsafe { graphics.copy_buffer(&staging_buffer.instance, &buffer.instance, size, cmd_pool, cmd_pool) }
+                    .inspect_err(|_| buffer.cleanup(graphics))?;
+                Ok(buffer)
+            });
+        staging_buffer.cleanup(graphics);
+        Ok(GpuMesh {
+            buffer: result?,
+            vertex_offset: 0,
+            index_offset,
+            vertex_count: self.vertices.len() as u32,
+            index_count: self.indices.len() as u32
+        })
+    }
+
+    unsafe fn fill(graphics: &Graphics, staging_buffer: &Buffer, vertices: &[V], indices: &[u16], index_offset: vk::DeviceSize, size: vk::DeviceSize) -> Result<()> {
+        let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(vertices.as_ptr(), mem.cast(), vertices.len());
+        memcpy(indices.as_ptr(), mem.cast::<u8>().add(index_offset as usize).cast(), indices.len());
+        graphics.logical.instance.unmap_memory(staging_buffer.memory);
+        Ok(())
+    }
+}
+
+/// Mesh uploaded by `Mesh::upload`, vertices and indices living in two regions of `buffer`.
+pub struct GpuMesh {
+    pub buffer: Buffer,
+    /// Offset to pass to `cmd_bind_vertex_buffers`.
+    pub vertex_offset: vk::DeviceSize,
+    /// Offset to pass to `cmd_bind_index_buffer`, right after the vertices.
+    pub index_offset: vk::DeviceSize,
+    pub vertex_count: u32,
+    pub index_count: u32
+}
+
+impl GpuMesh {
+    /// Both regions of `buffer`, to draw the mesh through `FrameScene::mesh`.
+    pub fn range(&self) -> MeshRange {
+        MeshRange {
+            vertex_buffer_offset: self.vertex_offset,
+            index_buffer_offset: self.index_offset,
+            ..MeshRange::whole(self.index_count, self.vertex_count)
+        }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.buffer.cleanup(graphics);
+    }
+}
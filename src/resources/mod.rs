@@ -1,2 +1,14 @@
+pub mod allocator;
 pub mod buffer;
-pub mod image;
\ No newline at end of file
+pub mod capture;
+pub mod image;
+pub mod loader;
+pub mod mesh;
+pub mod model;
+pub mod render_target;
+pub mod ring_buffer;
+pub mod sampler;
+pub mod sprite_batch;
+pub mod text_overlay;
+pub mod texture;
+pub mod uniform_ring;
\ No newline at end of file
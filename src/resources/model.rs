@@ -0,0 +1,32 @@
+use anyhow::{anyhow, Result};
+use cgmath::{vec2, vec3};
+use std::path::Path;
+
+use crate::pipeline::render_pipeline::Vertex;
+
+/// Loads every model of an OBJ file into a single indexed mesh.
+/// Vertices are flattened on the XY plane, and vertex colors default to white when the file has none.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<(Vec<Vertex>, Vec<u16>)> {
+    let options = tobj::LoadOptions { single_index: true, triangulate: true, ..Default::default() };
+    let (models, _) = tobj::load_obj(path.as_ref(), &options)?;
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for model in models {
+        let mesh = &model.mesh;
+        let base = vertices.len() as u32;
+        for i in 0..mesh.positions.len() / 3 {
+            let color = if mesh.vertex_color.len() >= (i + 1) * 3 {
+                vec3(mesh.vertex_color[i * 3], mesh.vertex_color[i * 3 + 1], mesh.vertex_color[i * 3 + 2])
+            } else {
+                vec3(1.0, 1.0, 1.0)
+            };
+            vertices.push(Vertex::new(vec2(mesh.positions[i * 3], mesh.positions[i * 3 + 1]), color));
+        }
+        for index in &mesh.indices {
+            let index = u16::try_from(base + index)
+                .map_err(|_| anyhow!("{} has too many vertices for 16 bit indices.", path.as_ref().display()))?;
+            indices.push(index);
+        }
+    }
+    Ok((vertices, indices))
+}
\ No newline at end of file
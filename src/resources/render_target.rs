@@ -0,0 +1,538 @@
+use ash::{vk, Device};
+use anyhow::{anyhow, Result};
+use std::fmt::{self, Display, Formatter};
+
+use crate::{core::{context::VulkanContext, graphics::Graphics}, resources::{allocator::MemoryAllocator, image::{self, Image, ImageDesc}}};
+
+#[derive(Debug)]
+pub enum RenderTargetError {
+    /// The format has no depth aspect, or cannot be both a depth attachment and sampled with optimal tiling.
+    UnsupportedDepthFormat(vk::Format),
+    /// None of `DepthAttachment::FORMATS` can be a depth attachment with optimal tiling.
+    NoDepthAttachmentFormat,
+    /// Attachments of the same subpass must share a sample count, e.g. a depth buffer must follow the MSAA color target.
+    SampleCountMismatch { expected: vk::SampleCountFlags, found: vk::SampleCountFlags }
+}
+
+impl Display for RenderTargetError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedDepthFormat(format) => write!(f, "{:?} cannot be rendered to as depth and sampled on this device.", format),
+            Self::NoDepthAttachmentFormat => write!(f, "No depth format can be rendered to on this device."),
+            Self::SampleCountMismatch { expected, found } => write!(f, "Attachment has {:?} samples where the render pass uses {:?}.", found, expected)
+        }
+    }
+}
+
+impl std::error::Error for RenderTargetError {}
+
+/// Rejects `attachments` of a single subpass whose sample counts differ, which the driver would otherwise fail on.
+pub fn validate_sample_counts(attachments: &[vk::AttachmentDescription]) -> Result<(), RenderTargetError> {
+    let Some(expected) = attachments.first().map(|a| a.samples) else { return Ok(()) };
+    match attachments.iter().find(|a| a.samples != expected) {
+        Some(attachment) => Err(RenderTargetError::SampleCountMismatch { expected, found: attachment.samples }),
+        None => Ok(())
+    }
+}
+
+/// Offscreen color target that can be rendered to in one pass and sampled in a later one.
+pub struct RenderTarget {
+    pub image: Image,
+    pub view: vk::ImageView,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format
+}
+
+impl RenderTarget {
+    /// With `depth`, which must cover `extent`, the pass also tests against it.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, format: vk::Format, depth: Option<&DepthAttachment>) -> Result<Self> {
+        let image = Image::with_desc(context, graphics, &Self::image_desc(extent, format))?;
+        Self::with_image(graphics, image, extent, format, depth)
+    }
+
+    /// Like `new`, with the image bound to a block of `allocator`, which frees it once every target sharing it is destroyed.
+    pub fn new_in(context: &VulkanContext, graphics: &Graphics, allocator: &mut MemoryAllocator, extent: vk::Extent2D, format: vk::Format, depth: Option<&DepthAttachment>) -> Result<Self> {
+        let image = Image::new_in(context, graphics, allocator, &Self::image_desc(extent, format))?;
+        Self::with_image(graphics, image, extent, format, depth)
+    }
+
+    fn image_desc(extent: vk::Extent2D, format: vk::Format) -> ImageDesc {
+        ImageDesc::new(
+            (extent.width, extent.height),
+            format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL
+        )
+    }
+
+    fn with_image(graphics: &Graphics, image: Image, extent: vk::Extent2D, format: vk::Format, depth: Option<&DepthAttachment>) -> Result<Self> {
+        let view = image.create_view(graphics)?;
+        let render_pass = Self::create_render_pass(graphics, format, depth)?;
+        let attachments = match depth {
+            Some(depth) => vec![view, depth.view],
+            None => vec![view]
+        };
+        let framebuffer = Self::create_framebuffer(graphics, render_pass, &attachments, extent)?;
+        Ok(Self { image, view, render_pass, framebuffer, extent, format })
+    }
+
+    /// View to bind as a combined image sampler once the target has been rendered.
+    pub fn sampled_view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn descriptor_image_info(&self, sampler: vk::Sampler) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// Rebuilds the target for a new extent, typically after the swapchain was resized along with `depth`.
+    pub fn recreate(&mut self, context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, depth: Option<&DepthAttachment>) -> Result<()> {
+        self.cleanup(graphics);
+        *self = Self::new(context, graphics, extent, self.format, depth)?;
+        Ok(())
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe {
+            graphics.logical.instance.destroy_framebuffer(self.framebuffer, None);
+            graphics.logical.instance.destroy_render_pass(self.render_pass, None);
+            graphics.logical.instance.destroy_image_view(self.view, None);
+        }
+        self.image.cleanup(graphics);
+    }
+
+    fn create_render_pass(graphics: &Graphics, format: vk::Format, depth: Option<&DepthAttachment>) -> Result<vk::RenderPass> {
+        let color_attachment = vk::AttachmentDescription::default()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let color_attachment_ref = vk::AttachmentReference::default()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let color_attachments = &[color_attachment_ref];
+        let depth_attachment_ref = DepthAttachment::reference(1);
+        let mut subpass = vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(color_attachments);
+        if depth.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth_attachment_ref);
+        }
+        // Previous reads must finish before writing, and writes must land before the next pass samples the target
+        let dependencies = &[
+            vk::SubpassDependency::default()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | DepthAttachment::STAGES)
+                .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | DepthAttachment::STAGES)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            vk::SubpassDependency::default()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        ];
+        let mut attachments = vec![color_attachment];
+        attachments.extend(depth.map(DepthAttachment::description));
+        validate_sample_counts(&attachments)?;
+        let subpasses = &[subpass];
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(subpasses)
+            .dependencies(dependencies);
+        let render_pass = unsafe { graphics.logical.instance.create_render_pass(&create_info, None)? };
+        Ok(render_pass)
+    }
+
+    fn create_framebuffer(graphics: &Graphics, render_pass: vk::RenderPass, attachments: &[vk::ImageView], extent: vk::Extent2D) -> Result<vk::Framebuffer> {
+        let create_info = vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass)
+            .attachments(attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { graphics.logical.instance.create_framebuffer(&create_info, None)? };
+        Ok(framebuffer)
+    }
+}
+
+/// Depth buffer of a scene pass. It is cleared on load and never stored, so tiled GPUs can keep it in lazily
+/// allocated memory that is never backed.
+pub struct DepthAttachment {
+    pub image: Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    /// Those of the color attachments it is rendered with.
+    pub samples: vk::SampleCountFlags
+}
+
+impl DepthAttachment {
+    /// Candidates by decreasing precision, the first the device can attach with optimal tiling is used.
+    pub const FORMATS: [vk::Format; 3] = [vk::Format::D32_SFLOAT, vk::Format::D24_UNORM_S8_UINT, vk::Format::D16_UNORM];
+    /// Stages reading and writing the attachment, the load clear happens in the early one.
+    pub const STAGES: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+        vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS.as_raw() | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS.as_raw()
+    );
+
+    pub fn new(context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, format: vk::Format, samples: vk::SampleCountFlags) -> Result<Self> {
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+        let image = Image::new_transient(context, graphics, (extent.width, extent.height), usage, format, samples)?;
+        let view = image::create_image_view(&graphics.logical.instance, image.instance, format, image::aspect_for(format), 1)
+            .inspect_err(|_| image.cleanup(graphics))?;
+        Ok(Self { image, view, format, samples })
+    }
+
+    /// First of `FORMATS` supported as a depth attachment.
+    pub fn find_format(context: &VulkanContext, graphics: &Graphics) -> Result<vk::Format> {
+        Self::FORMATS.into_iter()
+            .find(|format| {
+                let properties = unsafe { context.instance.get_physical_device_format_properties(graphics.physical.instance, *format) };
+                properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .ok_or_else(|| anyhow!(RenderTargetError::NoDepthAttachmentFormat))
+    }
+
+    /// Attachment cleared to the far plane and discarded once the pass ends.
+    pub fn description(&self) -> vk::AttachmentDescription {
+        vk::AttachmentDescription::default()
+            .format(self.format)
+            .samples(self.samples)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+    }
+
+    pub fn reference(attachment: u32) -> vk::AttachmentReference {
+        vk::AttachmentReference::default()
+            .attachment(attachment)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.destroy_image_view(self.view, None) };
+        self.image.cleanup(graphics);
+    }
+}
+
+/// Depth target rendered without any color attachment, e.g. a shadow map sampled by a later pass.
+/// Draw into it with pipelines created with `PipelineOptions::depth_only`. Layered targets hold one shadow map per
+/// layer, e.g. per cascade of `Camera::shadow_cascades`, each rendered in its own pass and sampled together.
+pub struct DepthTarget {
+    pub image: Image,
+    /// Covers the depth aspect of every layer, as a `TYPE_2D_ARRAY` view for layered targets.
+    pub view: vk::ImageView,
+    /// One per layer, attached by the framebuffer of the same index.
+    pub layer_views: Vec<vk::ImageView>,
+    pub render_pass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format
+}
+
+impl DepthTarget {
+    pub fn new(context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, format: vk::Format) -> Result<Self> {
+        Self::layered(context, graphics, extent, format, 1)
+    }
+
+    /// Target of `layers` array layers sharing one render pass, sampled as a `sampler2DArrayShadow`.
+    pub fn layered(context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, format: vk::Format, layers: u32) -> Result<Self> {
+        Self::validate_format(context, graphics, format)?;
+        let (image, view, layer_views) = Self::create_image(context, graphics, extent, format, layers)?;
+        let render_pass = create_depth_only_render_pass(graphics, format)
+            .inspect_err(|_| Self::destroy_image(graphics, &image, view, &layer_views))?;
+        let framebuffers = Self::create_framebuffers(graphics, render_pass, &layer_views, extent)
+            .inspect_err(|_| {
+                unsafe { graphics.logical.instance.destroy_render_pass(render_pass, None) };
+                Self::destroy_image(graphics, &image, view, &layer_views);
+            })?;
+        Ok(Self { image, view, layer_views, render_pass, framebuffers, extent, format })
+    }
+
+    /// The image starts out `UNDEFINED`, the render pass clears it and transitions it to the attachment layout.
+    fn create_image(context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D, format: vk::Format, layers: u32) -> Result<(Image, vk::ImageView, Vec<vk::ImageView>)> {
+        let desc = ImageDesc {
+            array_layers: layers,
+            ..ImageDesc::new(
+                (extent.width, extent.height),
+                format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL
+            )
+        };
+        let image = Image::with_desc(context, graphics, &desc)?;
+        let device = &graphics.logical.instance;
+        let view = match layers {
+            1 => image::create_image_view(device, image.instance, format, vk::ImageAspectFlags::DEPTH, 1),
+            _ => image::create_array_view(device, image.instance, format, vk::ImageAspectFlags::DEPTH, 1, layers)
+        }.inspect_err(|_| image.cleanup(graphics))?;
+        let mut layer_views = Vec::with_capacity(layers as usize);
+        for layer in 0..layers {
+            let layer_view = image::create_layer_view(device, image.instance, format, vk::ImageAspectFlags::DEPTH, layer)
+                .inspect_err(|_| Self::destroy_image(graphics, &image, view, &layer_views))?;
+            layer_views.push(layer_view);
+        }
+        Ok((image, view, layer_views))
+    }
+
+    fn create_framebuffers(graphics: &Graphics, render_pass: vk::RenderPass, layer_views: &[vk::ImageView], extent: vk::Extent2D) -> Result<Vec<vk::Framebuffer>> {
+        let mut framebuffers = Vec::with_capacity(layer_views.len());
+        for view in layer_views {
+            let framebuffer = RenderTarget::create_framebuffer(graphics, render_pass, &[*view], extent)
+                .inspect_err(|_| framebuffers.iter().for_each(|f| unsafe { graphics.logical.instance.destroy_framebuffer(*f, None) }))?;
+            framebuffers.push(framebuffer);
+        }
+        Ok(framebuffers)
+    }
+
+    /// Sampling needs `SAMPLED_IMAGE` and rendering `DEPTH_STENCIL_ATTACHMENT`, both with optimal tiling.
+    fn validate_format(context: &VulkanContext, graphics: &Graphics, format: vk::Format) -> Result<()> {
+        let properties = unsafe { context.instance.get_physical_device_format_properties(graphics.physical.instance, format) };
+        let required = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+        if !image::aspect_for(format).contains(vk::ImageAspectFlags::DEPTH) || !properties.optimal_tiling_features.contains(required) {
+            return Err(anyhow!(RenderTargetError::UnsupportedDepthFormat(format)));
+        }
+        Ok(())
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.image.array_layers()
+    }
+
+    /// Begins the render pass on `layer`, cleared to the far plane. Set the viewport and scissor to `extent` unless
+    /// the pipeline was built for it, draw with the layer's light matrix, then end the pass.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, and `layer` must be below `layers`.
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer, layer: usize) {
+        let clear_values = &[vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }];
+        let begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffers[layer])
+            .render_area(vk::Rect2D { offset: vk::Offset2D::default(), extent: self.extent })
+            .clear_values(clear_values);
+        device.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE);
+    }
+
+    /// Bind with a sampler such as `SamplerPreset::ShadowCompare` once the pass has ended.
+    pub fn descriptor_image_info(&self, sampler: vk::Sampler) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+    }
+
+    /// Rebuilds the image, views and framebuffers for a new extent, e.g. when the shadow map resolution changes, and
+    /// destroys the old ones. The render pass does not depend on the extent and is kept. The GPU must be done with
+    /// the old target. On failure the target is left untouched, so `cleanup` still releases everything.
+    pub fn recreate(&mut self, context: &VulkanContext, graphics: &Graphics, extent: vk::Extent2D) -> Result<()> {
+        let (image, view, layer_views) = Self::create_image(context, graphics, extent, self.format, self.layers())?;
+        let framebuffers = Self::create_framebuffers(graphics, self.render_pass, &layer_views, extent)
+            .inspect_err(|_| Self::destroy_image(graphics, &image, view, &layer_views))?;
+        self.destroy_framebuffers(graphics);
+        Self::destroy_image(graphics, &self.image, self.view, &self.layer_views);
+        self.image = image;
+        self.view = view;
+        self.layer_views = layer_views;
+        self.framebuffers = framebuffers;
+        self.extent = extent;
+        Ok(())
+    }
+
+    fn destroy_image(graphics: &Graphics, image: &Image, view: vk::ImageView, layer_views: &[vk::ImageView]) {
+        unsafe {
+            layer_views.iter().for_each(|v| graphics.logical.instance.destroy_image_view(*v, None));
+            graphics.logical.instance.destroy_image_view(view, None);
+        }
+        image.cleanup(graphics);
+    }
+
+    fn destroy_framebuffers(&self, graphics: &Graphics) {
+        self.framebuffers.iter().for_each(|f| unsafe { graphics.logical.instance.destroy_framebuffer(*f, None) });
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.destroy_framebuffers(graphics);
+        unsafe { graphics.logical.instance.destroy_render_pass(self.render_pass, None) };
+        Self::destroy_image(graphics, &self.image, self.view, &self.layer_views);
+    }
+}
+
+/// Cubemap rendered one face at a time, e.g. a reflection probe re-rendered every frame with the matrices of
+/// `matrix::cube_face_view_projs` and sampled as a `samplerCube`. The faces share a transient depth attachment.
+pub struct CubeTarget {
+    pub image: Image,
+    /// `CUBE` view over every face, to sample once all of them were rendered.
+    pub view: vk::ImageView,
+    /// One per face in layer order, attached by the framebuffer of the same index.
+    pub face_views: Vec<vk::ImageView>,
+    pub depth: DepthAttachment,
+    pub render_pass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    /// Width and height of every face.
+    pub size: u32,
+    pub format: vk::Format
+}
+
+impl CubeTarget {
+    pub const FACES: u32 = 6;
+
+    pub fn new(context: &VulkanContext, graphics: &Graphics, size: u32, format: vk::Format) -> Result<Self> {
+        let desc = ImageDesc {
+            array_layers: Self::FACES,
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            ..ImageDesc::new((size, size), format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        };
+        let image = Image::with_desc(context, graphics, &desc)?;
+        let depth = DepthAttachment::find_format(context, graphics)
+            .and_then(|depth_format| DepthAttachment::new(context, graphics, image.extent(), depth_format, vk::SampleCountFlags::TYPE_1))
+            .inspect_err(|_| image.cleanup(graphics))?;
+        let mut target = Self {
+            image,
+            view: vk::ImageView::null(),
+            face_views: Vec::with_capacity(Self::FACES as usize),
+            depth,
+            render_pass: vk::RenderPass::null(),
+            framebuffers: Vec::with_capacity(Self::FACES as usize),
+            size,
+            format
+        };
+        // Destroying null handles is a no-op, so cleanup also releases a partially built target
+        target.create_faces(graphics).inspect_err(|_| target.cleanup(graphics))?;
+        Ok(target)
+    }
+
+    fn create_faces(&mut self, graphics: &Graphics) -> Result<()> {
+        let device = &graphics.logical.instance;
+        self.view = image::create_cube_view(device, self.image.instance, self.format, 1)?;
+        self.render_pass = RenderTarget::create_render_pass(graphics, self.format, Some(&self.depth))?;
+        for face in 0..Self::FACES {
+            let face_view = image::create_layer_view(device, self.image.instance, self.format, vk::ImageAspectFlags::COLOR, face)?;
+            self.face_views.push(face_view);
+            let framebuffer = RenderTarget::create_framebuffer(graphics, self.render_pass, &[face_view, self.depth.view], self.image.extent())?;
+            self.framebuffers.push(framebuffer);
+        }
+        Ok(())
+    }
+
+    /// Begins the render pass on `face`, in layer order +X, -X, +Y, -Y, +Z, -Z, clearing it to `clear_color`.
+    /// Pipelines drawing into it must be built for the square extent of the faces.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording outside of a render pass, and `face` must be below 6.
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer, face: usize, clear_color: [f32; 4]) {
+        let clear_values = &[
+            vk::ClearValue { color: vk::ClearColorValue { float32: clear_color } },
+            vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }
+        ];
+        let begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffers[face])
+            .render_area(vk::Rect2D { offset: vk::Offset2D::default(), extent: self.image.extent() })
+            .clear_values(clear_values);
+        device.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE);
+    }
+
+    pub fn descriptor_image_info(&self, sampler: vk::Sampler) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        let device = &graphics.logical.instance;
+        unsafe {
+            self.framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+            self.face_views.iter().for_each(|v| device.destroy_image_view(*v, None));
+            device.destroy_render_pass(self.render_pass, None);
+            device.destroy_image_view(self.view, None);
+        }
+        self.depth.cleanup(graphics);
+        self.image.cleanup(graphics);
+    }
+}
+
+/// Single subpass writing a depth attachment of `format` and no color, cleared to 1.0 and left in
+/// `DEPTH_STENCIL_READ_ONLY_OPTIMAL` so that a later pass can sample it.
+pub fn create_depth_only_render_pass(graphics: &Graphics, format: vk::Format) -> Result<vk::RenderPass> {
+    let depth_attachment = vk::AttachmentDescription::default()
+        .format(format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+    let depth_attachment_ref = vk::AttachmentReference::default()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = vk::SubpassDescription::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref);
+    // Previous samples must finish before depth is cleared, and depth must be written before the next pass samples it
+    let dependencies = &[
+        vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        vk::SubpassDependency::default()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+    ];
+    let attachments = &[depth_attachment];
+    let subpasses = &[subpass];
+    let create_info = vk::RenderPassCreateInfo::default()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    let render_pass = unsafe { graphics.logical.instance.create_render_pass(&create_info, None)? };
+    Ok(render_pass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(samples: vk::SampleCountFlags) -> vk::AttachmentDescription {
+        vk::AttachmentDescription::default().samples(samples)
+    }
+
+    #[test]
+    fn mismatched_sample_counts_are_rejected() {
+        let attachments = [attachment(vk::SampleCountFlags::TYPE_4), attachment(vk::SampleCountFlags::TYPE_1)];
+        assert!(matches!(
+            validate_sample_counts(&attachments),
+            Err(RenderTargetError::SampleCountMismatch { expected: vk::SampleCountFlags::TYPE_4, found: vk::SampleCountFlags::TYPE_1 })
+        ));
+    }
+
+    #[test]
+    fn matching_sample_counts_are_accepted() {
+        let attachments = [attachment(vk::SampleCountFlags::TYPE_4), attachment(vk::SampleCountFlags::TYPE_4)];
+        assert!(validate_sample_counts(&attachments).is_ok());
+        assert!(validate_sample_counts(&[]).is_ok());
+    }
+}
@@ -0,0 +1,70 @@
+use ash::vk;
+use anyhow::{anyhow, Result};
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::{core::{context::VulkanContext, graphics::Graphics}, resources::buffer::Buffer};
+
+/// Persistently mapped vertex buffer split in one region per frame in flight.
+/// Vertices are accumulated every frame and drawn in a single call, while the GPU reads the regions of previous frames.
+pub struct RingBuffer {
+    pub buffer: Buffer,
+    mapped: *mut u8,
+    region_size: vk::DeviceSize,
+    regions: usize,
+    region: usize,
+    offset: vk::DeviceSize
+}
+
+impl RingBuffer {
+    pub fn new(context: &VulkanContext, graphics: &Graphics, region_size: vk::DeviceSize, regions: usize) -> Result<Self> {
+        let buffer = Buffer::new(
+            context, 
+            graphics, 
+            region_size * regions as u64, 
+            vk::BufferUsageFlags::VERTEX_BUFFER, 
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        let mapped = unsafe { graphics.logical.instance.map_memory(buffer.memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())? };
+        Ok(Self { buffer, mapped: mapped.cast(), region_size, regions, region: 0, offset: 0 })
+    }
+
+    /// Switches to the region owned by `frame` and discards the vertices it held.
+    /// The caller must have waited on the fence of that frame before writing to it again.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.region = frame % self.regions;
+        self.offset = 0;
+    }
+
+    pub fn push<T: Copy>(&mut self, vertices: &[T]) -> Result<()> {
+        let size = size_of_val(vertices) as u64;
+        if self.offset + size > self.region_size {
+            return Err(anyhow!("Ring buffer region overflow ({} of {} bytes).", self.offset + size, self.region_size));
+        }
+        unsafe {
+            let dst = self.mapped.add((self.region_offset() + self.offset) as usize);
+            memcpy(vertices.as_ptr(), dst.cast(), vertices.len());
+        }
+        self.offset += size;
+        Ok(())
+    }
+
+    pub fn region_offset(&self) -> vk::DeviceSize {
+        self.region_size * self.region as u64
+    }
+
+    /// Records a single draw of every vertex pushed this frame, assuming a tightly packed `stride`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass, with a pipeline reading `stride` sized vertices from binding 0 bound.
+    pub unsafe fn draw(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer, stride: u32) {
+        let count = (self.offset / stride as u64) as u32;
+        if count == 0 { return; }
+        graphics.logical.instance.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.instance], &[self.region_offset()]);
+        graphics.logical.instance.cmd_draw(command_buffer, count, 1, 0, 0);
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.unmap_memory(self.buffer.memory) };
+        self.buffer.cleanup(graphics);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,248 @@
+use ash::vk;
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, fmt::{self, Display, Formatter}, hash::{Hash, Hasher}};
+
+use crate::core::graphics::Graphics;
+
+#[derive(Debug)]
+pub enum SamplerError {
+    /// Custom border colors need `VK_EXT_custom_border_color`, which is never enabled.
+    UnsupportedBorderColor(vk::BorderColor)
+}
+
+impl Display for SamplerError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedBorderColor(color) => write!(f, "{:?} is not a supported border color.", color)
+        }
+    }
+}
+
+impl std::error::Error for SamplerError {}
+
+/// Common sampler configurations, none of which use anisotropic filtering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerPreset {
+    /// Trilinear filtering with tiling, for most material textures.
+    LinearRepeat,
+    /// Point sampling clamped to the edges, for pixel art and lookup tables.
+    NearestClamp,
+    /// Trilinear filtering clamped to the edges, for render targets and UI.
+    LinearClampEdge,
+    /// Depth comparison with `LESS`, returning the filtered result of the test for PCF shadows.
+    ShadowCompare
+}
+
+/// Describes a sampler. Identical builders share the same sampler in a `SamplerCache`.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerBuilder {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    /// Wrapping outside of [0, 1] along U, `REPEAT` for tiling textures and `CLAMP_TO_EDGE` to avoid seams on UI.
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// Color read outside of the texture along axes using `CLAMP_TO_BORDER`, e.g. transparent black for decals.
+    pub border_color: vk::BorderColor,
+    pub compare_op: Option<vk::CompareOp>,
+    /// Added to the level of detail computed by the shader, positive values blur and negative values sharpen.
+    pub mip_lod_bias: f32,
+    /// Lowest mip level sampled.
+    pub min_lod: f32,
+    /// Highest mip level sampled, all of them with `None`.
+    pub max_lod: Option<f32>,
+    /// Mip count of the textures sampled, which `max_lod` is clamped to when known.
+    pub mip_levels: Option<u32>,
+    /// Anisotropic filtering, clamped to `maxSamplerAnisotropy` and ignored when the device does not support it.
+    pub max_anisotropy: Option<f32>
+}
+
+impl SamplerBuilder {
+    /// Floats are compared bitwise so that builders can key the cache.
+    fn key(&self) -> impl PartialEq + Hash {
+        (
+            (self.mag_filter, self.min_filter, self.mipmap_mode, self.address_mode_u, self.address_mode_v, self.address_mode_w, self.border_color, self.compare_op),
+            (self.mip_lod_bias.to_bits(), self.min_lod.to_bits(), self.max_lod.map(f32::to_bits), self.mip_levels, self.max_anisotropy.map(f32::to_bits))
+        )
+    }
+}
+
+impl PartialEq for SamplerBuilder {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerBuilder {}
+
+impl Hash for SamplerBuilder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl SamplerBuilder {
+    pub fn preset(preset: SamplerPreset) -> Self {
+        let linear = Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+            compare_op: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: None,
+            mip_levels: None,
+            max_anisotropy: None
+        };
+        match preset {
+            SamplerPreset::LinearRepeat => linear,
+            SamplerPreset::NearestClamp => Self {
+                mag_filter: vk::Filter::NEAREST,
+                min_filter: vk::Filter::NEAREST,
+                mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+                ..linear.address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            },
+            SamplerPreset::LinearClampEdge => linear.address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+            // Outside of the shadow map everything is lit
+            SamplerPreset::ShadowCompare => Self {
+                mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+                border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+                compare_op: Some(vk::CompareOp::LESS),
+                max_lod: Some(0.0),
+                ..linear.address_mode(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            }
+        }
+    }
+
+    /// Sets the same mode on the U, V and W axes.
+    pub fn address_mode(self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u(address_mode)
+            .address_mode_v(address_mode)
+            .address_mode_w(address_mode)
+    }
+
+    pub fn address_mode_u(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self
+    }
+
+    pub fn address_mode_v(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    pub fn address_mode_w(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_w = address_mode;
+        self
+    }
+
+    pub fn border_color(mut self, border_color: vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    pub fn mip_lod_bias(mut self, mip_lod_bias: f32) -> Self {
+        self.mip_lod_bias = mip_lod_bias;
+        self
+    }
+
+    pub fn min_lod(mut self, min_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self
+    }
+
+    pub fn max_lod(mut self, max_lod: Option<f32>) -> Self {
+        self.max_lod = max_lod;
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = Some(mip_levels);
+        self
+    }
+
+    /// Enables anisotropic filtering up to `max_anisotropy` samples.
+    pub fn max_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    /// Enables anisotropic filtering with as many samples as the device allows.
+    pub fn anisotropic(self) -> Self {
+        self.max_anisotropy(f32::MAX)
+    }
+
+    /// Only the six core border colors are accepted, and only checked when an axis clamps to the border.
+    pub fn validate(&self) -> Result<()> {
+        let clamps_to_border = [self.address_mode_u, self.address_mode_v, self.address_mode_w].contains(&vk::SamplerAddressMode::CLAMP_TO_BORDER);
+        let core_color = matches!(self.border_color,
+            vk::BorderColor::FLOAT_TRANSPARENT_BLACK | vk::BorderColor::INT_TRANSPARENT_BLACK |
+            vk::BorderColor::FLOAT_OPAQUE_BLACK | vk::BorderColor::INT_OPAQUE_BLACK |
+            vk::BorderColor::FLOAT_OPAQUE_WHITE | vk::BorderColor::INT_OPAQUE_WHITE);
+        if clamps_to_border && !core_color {
+            return Err(anyhow!(SamplerError::UnsupportedBorderColor(self.border_color)));
+        }
+        Ok(())
+    }
+
+    /// `device_max_anisotropy` is `GraphicsInterface::max_sampler_anisotropy`.
+    pub fn create_info(&self, device_max_anisotropy: Option<f32>) -> vk::SamplerCreateInfo<'static> {
+        let anisotropy = self.max_anisotropy
+            .zip(device_max_anisotropy)
+            .map(|(requested, max)| requested.clamp(1.0, max));
+        let highest_lod = self.mip_levels.map(|levels| levels.saturating_sub(1) as f32);
+        let max_lod = match (self.max_lod, highest_lod) {
+            (Some(lod), Some(highest)) => lod.min(highest),
+            (lod, highest) => lod.or(highest).unwrap_or(vk::LOD_CLAMP_NONE)
+        };
+        vk::SamplerCreateInfo::default()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode_u)
+            .address_mode_v(self.address_mode_v)
+            .address_mode_w(self.address_mode_w)
+            .mip_lod_bias(self.mip_lod_bias)
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1.0))
+            .compare_enable(self.compare_op.is_some())
+            .compare_op(self.compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+            .min_lod(self.min_lod.min(max_lod))
+            .max_lod(max_lod)
+            .border_color(self.border_color)
+            .unnormalized_coordinates(false)
+    }
+}
+
+/// Creates each distinct sampler once and hands out the same handle for identical builders.
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerBuilder, vk::Sampler>
+}
+
+impl SamplerCache {
+    pub fn get(&mut self, graphics: &Graphics, builder: SamplerBuilder) -> Result<vk::Sampler> {
+        if let Some(sampler) = self.samplers.get(&builder) {
+            return Ok(*sampler);
+        }
+        builder.validate()?;
+        let sampler = unsafe { graphics.logical.instance.create_sampler(&builder.create_info(graphics.logical.max_sampler_anisotropy), None)? };
+        self.samplers.insert(builder, sampler);
+        Ok(sampler)
+    }
+
+    pub fn preset(&mut self, graphics: &Graphics, preset: SamplerPreset) -> Result<vk::Sampler> {
+        self.get(graphics, SamplerBuilder::preset(preset))
+    }
+
+    pub fn cleanup(&mut self, graphics: &Graphics) {
+        for (_, sampler) in self.samplers.drain() {
+            unsafe { graphics.logical.instance.destroy_sampler(sampler, None) };
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use ash::{vk, Device};
+use anyhow::{anyhow, Result};
+use cgmath::vec2;
+
+use crate::{
+    core::{context::VulkanContext, graphics::Graphics},
+    math::vector::{Vec2, Vec4},
+    pipeline::{sprite_pipeline::{SpritePipeline, SpriteVertex}, traits::VulkanPipeline},
+    resources::ring_buffer::RingBuffer
+};
+
+const VERTICES_PER_SPRITE: usize = 6;
+
+/// Textured quad in pixel coordinates, meant for `Projection::Orthographic2D`.
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Min and max texture coordinates, to draw a region of an atlas.
+    pub uv: (Vec2, Vec2),
+    pub color: Vec4
+}
+
+impl Sprite {
+    /// Covers the whole texture, untinted.
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        Self { position, size, uv: (vec2(0.0, 0.0), vec2(1.0, 1.0)), color: Vec4::new(1.0, 1.0, 1.0, 1.0) }
+    }
+}
+
+/// Consecutive vertices sharing a texture, drawn in a single call.
+struct Batch {
+    texture: vk::DescriptorSet,
+    first_vertex: u32,
+    vertex_count: u32
+}
+
+/// Accumulates sprites every frame into a persistently mapped vertex buffer and draws them with one call per texture.
+/// Sprites are grouped by texture, so draw order, and thus blending, is only preserved among sprites of the same texture.
+pub struct SpriteBatch {
+    vertices: RingBuffer,
+    descriptor_pool: vk::DescriptorPool,
+    capacity: usize,
+    sprites: Vec<(vk::DescriptorSet, Sprite)>,
+    batches: Vec<Batch>
+}
+
+impl SpriteBatch {
+    /// `capacity` sprites can be drawn per frame, for each of the `frames` in flight.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, capacity: usize, frames: usize, max_textures: u32) -> Result<Self> {
+        let region_size = (capacity * VERTICES_PER_SPRITE * size_of::<SpriteVertex>()) as vk::DeviceSize;
+        let vertices = RingBuffer::new(context, graphics, region_size, frames)?;
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(max_textures);
+        let pool_sizes = &[pool_size];
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(max_textures);
+        let descriptor_pool = unsafe { graphics.logical.instance.create_descriptor_pool(&create_info, None)? };
+        Ok(Self { vertices, descriptor_pool, capacity, sprites: Vec::with_capacity(capacity), batches: vec![] })
+    }
+
+    /// Allocates the descriptor set used to draw sprites with `view`, which must be in `SHADER_READ_ONLY_OPTIMAL` layout.
+    pub fn texture_set(&self, graphics: &Graphics, pipeline: &SpritePipeline, view: vk::ImageView, sampler: vk::Sampler) -> Result<vk::DescriptorSet> {
+        let layouts = &[pipeline.texture_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(layouts);
+        let set = unsafe { graphics.logical.instance.allocate_descriptor_sets(&allocate_info)?[0] };
+        let image_info = &[vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(image_info);
+        unsafe { graphics.logical.instance.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]) };
+        Ok(set)
+    }
+
+    /// Discards the sprites of the previous frame. The fence of `frame` must have been waited on.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.vertices.begin_frame(frame);
+        self.sprites.clear();
+        self.batches.clear();
+    }
+
+    pub fn draw(&mut self, texture: vk::DescriptorSet, sprite: Sprite) -> Result<()> {
+        if self.sprites.len() == self.capacity {
+            return Err(anyhow!("Sprite batch is full ({} sprites).", self.capacity));
+        }
+        self.sprites.push((texture, sprite));
+        Ok(())
+    }
+
+    /// Writes the vertices of every sprite drawn this frame and returns the number of draw calls they need.
+    pub fn end_frame(&mut self) -> Result<usize> {
+        self.sprites.sort_by_key(|(texture, _)| *texture);
+        let mut vertices = Vec::with_capacity(self.sprites.len() * VERTICES_PER_SPRITE);
+        for (texture, sprite) in &self.sprites {
+            let first_vertex = vertices.len() as u32;
+            vertices.extend(SpriteVertex::quad(sprite.position, sprite.size, sprite.uv, sprite.color));
+            match self.batches.last_mut() {
+                Some(batch) if batch.texture == *texture => batch.vertex_count += VERTICES_PER_SPRITE as u32,
+                _ => self.batches.push(Batch { texture: *texture, first_vertex, vertex_count: VERTICES_PER_SPRITE as u32 })
+            }
+        }
+        self.vertices.push(&vertices)?;
+        Ok(self.batches.len())
+    }
+
+    /// Records the batches of the current frame. `uniform_set` is bound to set 0, as for the main pipeline.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside the render pass `pipeline` was created for, and the batch must outlive its execution.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, pipeline: &SpritePipeline, uniform_set: vk::DescriptorSet) {
+        if self.batches.is_empty() { return; }
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.instance());
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertices.buffer.instance], &[self.vertices.region_offset()]);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout(), 0, &[uniform_set], &[]);
+        for batch in &self.batches {
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.layout(), 1, &[batch.texture], &[]);
+            device.cmd_draw(command_buffer, batch.vertex_count, 1, batch.first_vertex, 0);
+        }
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.destroy_descriptor_pool(self.descriptor_pool, None) };
+        self.vertices.cleanup(graphics);
+    }
+}
@@ -0,0 +1,197 @@
+use ash::{vk, Device};
+use anyhow::Result;
+use cgmath::vec2;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    cmd::command_pool::CmdPool,
+    core::{context::VulkanContext, graphics::Graphics},
+    math::vector::{Vec2, Vec4},
+    pipeline::sprite_pipeline::SpritePipeline,
+    resources::{image::Image, loader::ColorSpace, sprite_batch::{Sprite, SpriteBatch}, texture}
+};
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+/// Glyphs are a texel apart in the atlas so that neighbours never bleed in, and as far apart on screen.
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+/// Drawn for characters the font has no glyph for.
+const FALLBACK: char = '?';
+
+/// Baked 3x5 font, enough for diagnostics such as frame times and device names. Lowercase is drawn as uppercase.
+const GLYPHS: &[(char, [&str; 5])] = &[
+    (' ', ["...", "...", "...", "...", "..."]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", ".##", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", "..#", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    (',', ["...", "...", "...", ".#.", "#.."]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('+', ["...", ".#.", "###", ".#.", "..."]),
+    ('=', ["...", "###", "...", "###", "..."]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('(', [".#.", "#..", "#..", "#..", ".#."]),
+    (')', [".#.", "..#", "..#", "..#", ".#."]),
+    ('[', ["##.", "#..", "#..", "#..", "##."]),
+    (']', [".##", "..#", "..#", "..#", ".##"]),
+    ('%', ["#.#", "..#", ".#.", "#..", "#.#"]),
+    ('!', [".#.", ".#.", ".#.", "...", ".#."]),
+    ('?', ["###", "..#", ".#.", "...", ".#."]),
+    ('\'', [".#.", ".#.", "...", "...", "..."])
+];
+
+/// Bitmap font text drawn as alpha blended sprites in pixel coordinates, e.g. frame times or the device name.
+/// Text is collected every frame between `begin_frame` and `end_frame`, then recorded after the scene so that it
+/// lands on top of it, see `Overlay`. Set 0 must hold an `Orthographic2D` MVP for the text to stay in screen space.
+pub struct TextOverlay {
+    sprites: SpriteBatch,
+    atlas: Image,
+    atlas_view: vk::ImageView,
+    texture: vk::DescriptorSet,
+    /// Pixels per font texel, 2 draws glyphs 6x10 pixels large.
+    scale: f32
+}
+
+impl TextOverlay {
+    /// `capacity` glyphs can be drawn per frame, for each of the `frames` in flight. `sampler` should filter
+    /// with `NEAREST`, e.g. `SamplerPreset::NearestClamp`, to keep the glyph edges sharp at any scale.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, pipeline: &SpritePipeline, sampler: vk::Sampler, capacity: usize, frames: usize) -> Result<Self> {
+        let atlas = texture::create_texture(context, graphics, cmd_pool, &Self::bake_atlas(), ColorSpace::Linear)?;
+        let atlas_view = atlas.create_view(graphics)
+            .inspect_err(|_| atlas.cleanup(graphics))?;
+        let sprites = SpriteBatch::new(context, graphics, capacity, frames, 1)
+            .inspect_err(|_| Self::destroy_atlas(graphics, &atlas, atlas_view))?;
+        let texture = sprites.texture_set(graphics, pipeline, atlas_view, sampler)
+            .inspect_err(|_| {
+                sprites.cleanup(graphics);
+                Self::destroy_atlas(graphics, &atlas, atlas_view);
+            })?;
+        Ok(Self { sprites, atlas, atlas_view, texture, scale: 2.0 })
+    }
+
+    /// Glyphs in a single row of cells, opaque white where set so that the sprite color tints them.
+    fn bake_atlas() -> RgbaImage {
+        let mut atlas = RgbaImage::new(CELL_WIDTH * GLYPHS.len() as u32, CELL_HEIGHT);
+        for (i, (_, rows)) in GLYPHS.iter().enumerate() {
+            for (y, row) in rows.iter().enumerate() {
+                for (x, texel) in row.chars().enumerate() {
+                    if texel == '#' {
+                        atlas.put_pixel(i as u32 * CELL_WIDTH + x as u32, y as u32, Rgba([255, 255, 255, 255]));
+                    }
+                }
+            }
+        }
+        atlas
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Multiplying by `Vulcor::scale_factor` keeps the text the same apparent size on HiDPI displays.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// Discards the text of the previous frame. The fence of `frame` must have been waited on.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.sprites.begin_frame(frame);
+    }
+
+    /// Queues `text` with its top left corner at `x`, `y` in pixels. Newlines start a line below `x`.
+    /// Fails once the glyphs drawn this frame exceed the capacity, the text queued so far is kept.
+    pub fn draw_text(&mut self, x: f32, y: f32, text: &str, color: Vec4) -> Result<()> {
+        let advance = vec2(CELL_WIDTH as f32, CELL_HEIGHT as f32) * self.scale;
+        let size = vec2(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32) * self.scale;
+        let mut cursor = vec2(x, y);
+        for c in text.chars() {
+            if c == '\n' {
+                cursor = vec2(x, cursor.y + advance.y);
+                continue;
+            }
+            let glyph = Self::glyph_index(c);
+            if GLYPHS[glyph].0 != ' ' {
+                let sprite = Sprite { position: cursor, size, uv: Self::glyph_uv(glyph), color };
+                self.sprites.draw(self.texture, sprite)?;
+            }
+            cursor.x += advance.x;
+        }
+        Ok(())
+    }
+
+    fn glyph_index(c: char) -> usize {
+        let c = c.to_ascii_uppercase();
+        GLYPHS.iter().position(|(glyph, _)| *glyph == c)
+            .or_else(|| GLYPHS.iter().position(|(glyph, _)| *glyph == FALLBACK))
+            .unwrap_or(0)
+    }
+
+    fn glyph_uv(index: usize) -> (Vec2, Vec2) {
+        let width = (CELL_WIDTH * GLYPHS.len() as u32) as f32;
+        let left = (index as u32 * CELL_WIDTH) as f32;
+        let min = vec2(left / width, 0.0);
+        let max = vec2((left + GLYPH_WIDTH as f32) / width, GLYPH_HEIGHT as f32 / CELL_HEIGHT as f32);
+        (min, max)
+    }
+
+    /// Writes the glyphs queued this frame to the vertex buffer, before recording.
+    pub fn end_frame(&mut self) -> Result<()> {
+        self.sprites.end_frame()?;
+        Ok(())
+    }
+
+    /// Records the text of the current frame in a single draw, `uniform_set` is bound to set 0 as for `SpriteBatch`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside the render pass `pipeline` was created for, and the overlay must outlive its execution.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, pipeline: &SpritePipeline, uniform_set: vk::DescriptorSet) {
+        self.sprites.record(device, command_buffer, pipeline, uniform_set);
+    }
+
+    fn destroy_atlas(graphics: &Graphics, atlas: &Image, view: vk::ImageView) {
+        unsafe { graphics.logical.instance.destroy_image_view(view, None) };
+        atlas.cleanup(graphics);
+    }
+
+    /// The sampler passed to `new` is not owned by the overlay and outlives it.
+    pub fn cleanup(&self, graphics: &Graphics) {
+        self.sprites.cleanup(graphics);
+        Self::destroy_atlas(graphics, &self.atlas, self.atlas_view);
+    }
+}
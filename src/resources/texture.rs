@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+use ::image::{ImageReader, RgbaImage};
+use std::{path::Path, ptr::copy_nonoverlapping as memcpy};
+
+use crate::{cmd::command_pool::CmdPool, core::{context::VulkanContext, graphics::Graphics}, resources::{buffer::Buffer, image::{self, Image, ImageDesc}, loader::ColorSpace}};
+
+/// How texture pixels reach the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureTiling {
+    /// Copy from a staging buffer into an optimally tiled device local image.
+    #[default]
+    Optimal,
+    /// Write straight into a linearly tiled host visible image when the format can be sampled that way,
+    /// skipping the staging copy. Worth it for small textures or on integrated GPUs sharing host memory.
+    PreferLinear
+}
+
+/// Loads `path` synchronously into an image ready to be sampled in `SHADER_READ_ONLY_OPTIMAL`.
+/// `PreferLinear` falls back to the optimal path when the format does not support linear sampling.
+pub fn load_texture<P: AsRef<Path>>(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, path: P, color_space: ColorSpace, tiling: TextureTiling) -> Result<Image> {
+    let pixels = ImageReader::open(path)?.decode()?.to_rgba8();
+    let format = color_space.format();
+    if tiling == TextureTiling::PreferLinear && supports_linear_sampling(context, graphics, format) {
+        return upload_linear(context, graphics, cmd_pool, &pixels, format);
+    }
+    upload_optimal(context, graphics, cmd_pool, &[&pixels], format)
+}
+
+/// Uploads decoded or generated `pixels` into an optimally tiled image ready to be sampled, e.g. a baked font atlas.
+pub fn create_texture(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, pixels: &RgbaImage, color_space: ColorSpace) -> Result<Image> {
+    upload_optimal(context, graphics, cmd_pool, &[pixels], color_space.format())
+}
+
+/// Uploads `layers` into consecutive layers of a 2D texture array, whose `create_view` is a `TYPE_2D_ARRAY` view.
+/// Every layer must have the same dimensions.
+pub fn create_texture_array(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, layers: &[RgbaImage], color_space: ColorSpace) -> Result<Image> {
+    let layers = layers.iter().collect::<Vec<_>>();
+    upload_optimal(context, graphics, cmd_pool, &layers, color_space.format())
+}
+
+fn supports_linear_sampling(context: &VulkanContext, graphics: &Graphics, format: vk::Format) -> bool {
+    let properties = unsafe { context.instance.get_physical_device_format_properties(graphics.physical.instance, format) };
+    properties.linear_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+}
+
+/// Whether mip levels of `format` can be generated by blitting each level from the previous one with a linear filter.
+fn supports_mip_generation(context: &VulkanContext, graphics: &Graphics, format: vk::Format) -> bool {
+    let properties = unsafe { context.instance.get_physical_device_format_properties(graphics.physical.instance, format) };
+    properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+}
+
+fn upload_linear(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, pixels: &RgbaImage, format: vk::Format) -> Result<Image> {
+    let image = Image::new_host_linear(context, graphics, pixels.dimensions(), vk::ImageUsageFlags::SAMPLED, format)?;
+    let layout = image.subresource_layout(graphics);
+    let row_size = pixels.width() as usize * 4;
+    unsafe {
+        let device = &graphics.logical.instance;
+        let mem = device.map_memory(image.memory, image.offset + layout.offset, layout.size, vk::MemoryMapFlags::empty())?;
+        // Rows are padded to the row pitch chosen by the driver, which is rarely the tightly packed width
+        for (y, row) in pixels.as_raw().chunks_exact(row_size).enumerate() {
+            memcpy(row.as_ptr(), mem.cast::<u8>().add(y * layout.row_pitch as usize), row_size);
+        }
+        device.unmap_memory(image.memory);
+    }
+    let barrier = layout_barrier(&image, 0, 1, vk::ImageLayout::PREINITIALIZED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .src_access_mask(vk::AccessFlags::HOST_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    record_once(graphics, cmd_pool, vk::PipelineStageFlags::HOST, vk::PipelineStageFlags::FRAGMENT_SHADER, barrier, |_| {})?;
+    Ok(image)
+}
+
+/// Uploads each of `layers` into the array layer of the same index, one region of the staging buffer each.
+/// The image gets a full mip chain, see `image::mip_levels_for`, generated from the uploaded level when the format
+/// can be blitted, and only the base level otherwise.
+fn upload_optimal(context: &VulkanContext, graphics: &Graphics, cmd_pool: &CmdPool, layers: &[&RgbaImage], format: vk::Format) -> Result<Image> {
+    let dimensions = layers.first().ok_or_else(|| anyhow!("A texture needs at least one layer."))?.dimensions();
+    if let Some(layer) = layers.iter().find(|l| l.dimensions() != dimensions) {
+        return Err(anyhow!("Texture layers differ in size, {:?} and {:?}.", dimensions, layer.dimensions()));
+    }
+    let layer_size = layers[0].len();
+    let size = (layer_size * layers.len()) as vk::DeviceSize;
+    let staging_buffer = Buffer::new(
+        context,
+        graphics,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+    )?;
+    unsafe {
+        let mem = graphics.logical.instance.map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())?;
+        for (i, layer) in layers.iter().enumerate() {
+            memcpy(layer.as_ptr(), mem.cast::<u8>().add(i * layer_size), layer_size);
+        }
+        graphics.logical.instance.unmap_memory(staging_buffer.memory);
+    }
+    let mip_levels = match supports_mip_generation(context, graphics, format) {
+        true => image::mip_levels_for(dimensions),
+        false => 1
+    };
+    let desc = ImageDesc {
+        array_layers: layers.len() as u32,
+        mip_levels,
+        ..ImageDesc::new(
+            dimensions,
+            format,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL
+        )
+    };
+    let image = Image::with_desc(context, graphics, &desc)
+        .inspect_err(|_| staging_buffer.cleanup(graphics))?;
+    let layer_count = image.array_layers();
+    let to_transfer = layout_barrier(&image, 0, mip_levels, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+    let result = record_once(graphics, cmd_pool, vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER, to_transfer, |command_buffer| unsafe {
+        let regions = (0..layer_count)
+            .map(|layer| {
+                let subresource = vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(layer)
+                    .layer_count(1);
+                vk::BufferImageCopy::default()
+                    .buffer_offset(layer as vk::DeviceSize * layer_size as vk::DeviceSize)
+                    .image_subresource(subresource)
+                    .image_extent(vk::Extent3D { width: dimensions.0, height: dimensions.1, depth: 1 })
+            })
+            .collect::<Vec<_>>();
+        let device = &graphics.logical.instance;
+        device.cmd_copy_buffer_to_image(command_buffer, staging_buffer.instance, image.instance, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+        generate_mips(device, command_buffer, &image);
+    });
+    staging_buffer.cleanup(graphics);
+    result.inspect_err(|_| image.cleanup(graphics))?;
+    Ok(image)
+}
+
+/// Fills every level past the first by blitting the previous one at half its size, leaving all levels in
+/// `SHADER_READ_ONLY_OPTIMAL`. Expects every level in `TRANSFER_DST_OPTIMAL` with the base level written.
+fn generate_mips(device: &ash::Device, command_buffer: vk::CommandBuffer, image: &Image) {
+    let extent = image.extent();
+    let mip_extent = |level: u32| vk::Offset3D { x: (extent.width >> level).max(1) as i32, y: (extent.height >> level).max(1) as i32, z: 1 };
+    let subresource = |level: u32| vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(level)
+        .base_array_layer(0)
+        .layer_count(image.array_layers());
+    let record_barrier = |src_stage, dst_stage, barrier: vk::ImageMemoryBarrier| unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier]
+        );
+    };
+    for level in 1..image.mip_levels() {
+        // The previous level was just written, by the copy or the last blit, and is now read
+        record_barrier(vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER,
+            layout_barrier(image, level - 1, 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ));
+        let blit = vk::ImageBlit::default()
+            .src_subresource(subresource(level - 1))
+            .src_offsets([vk::Offset3D::default(), mip_extent(level - 1)])
+            .dst_subresource(subresource(level))
+            .dst_offsets([vk::Offset3D::default(), mip_extent(level)]);
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                image.instance,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image.instance,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::LINEAR
+            );
+        }
+        record_barrier(vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+            layout_barrier(image, level - 1, 1, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ));
+    }
+    record_barrier(vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER,
+        layout_barrier(image, image.mip_levels() - 1, 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ));
+}
+
+/// Records `barrier` followed by `commands` into a one-time command buffer and waits for it.
+fn record_once(graphics: &Graphics, cmd_pool: &CmdPool, src_stage: vk::PipelineStageFlags, dst_stage: vk::PipelineStageFlags, barrier: vk::ImageMemoryBarrier, commands: impl FnOnce(vk::CommandBuffer)) -> Result<()> {
+    let command_buffer = graphics.begin_command_once(cmd_pool)?;
+    unsafe {
+        graphics.logical.instance.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier]
+        );
+    }
+    commands(command_buffer);
+    graphics.end_command_once(cmd_pool, command_buffer)
+}
+
+/// Barrier over `level_count` mip levels from `base_level` of every layer of `image`.
+fn layout_barrier<'a>(image: &Image, base_level: u32, level_count: u32, old: vk::ImageLayout, new: vk::ImageLayout) -> vk::ImageMemoryBarrier<'a> {
+    let subresource = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_level)
+        .level_count(level_count)
+        .base_array_layer(0)
+        .layer_count(image.array_layers());
+    vk::ImageMemoryBarrier::default()
+        .old_layout(old)
+        .new_layout(new)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image.instance)
+        .subresource_range(subresource)
+}
@@ -0,0 +1,71 @@
+use ash::vk;
+use anyhow::Result;
+use std::{marker::PhantomData, ptr::copy_nonoverlapping as memcpy};
+
+use crate::{core::{context::VulkanContext, graphics::Graphics}, resources::buffer::Buffer};
+
+/// Persistently mapped uniform buffer holding one `T` per slot, each at a multiple of `minUniformBufferOffsetAlignment`.
+/// A slot is written by the CPU while the GPU reads the others, so there must be at least one per frame in flight
+/// and the caller must have waited on the fence of the frame that last read a slot before writing it again.
+pub struct UniformRing<T> {
+    pub buffer: Buffer,
+    mapped: *mut u8,
+    stride: vk::DeviceSize,
+    slots: usize,
+    _marker: PhantomData<T>
+}
+
+impl<T: Copy> UniformRing<T> {
+    pub fn new(context: &VulkanContext, graphics: &Graphics, slots: usize) -> Result<Self> {
+        let properties = graphics.physical.properties();
+        let alignment = properties.limits.min_uniform_buffer_offset_alignment.max(1);
+        let stride = (size_of::<T>() as vk::DeviceSize).next_multiple_of(alignment);
+        let buffer = Buffer::new(
+            context,
+            graphics,
+            stride * slots as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE
+        )?;
+        let mapped = unsafe { graphics.logical.instance.map_memory(buffer.memory, buffer.offset, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) }
+            .inspect_err(|_| buffer.cleanup(graphics))?;
+        Ok(Self { buffer, mapped: mapped.cast(), stride, slots, _marker: PhantomData })
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Copies `value` into `slot`, visible to the GPU without a flush as the memory is coherent.
+    pub fn write(&self, slot: usize, value: &T) {
+        assert!(slot < self.slots, "uniform ring slot {} out of {}", slot, self.slots);
+        unsafe { memcpy(value, self.mapped.add(self.offset(slot) as usize).cast(), 1) };
+    }
+
+    /// Last value written to `slot`, e.g. to check what a frame was rendered with.
+    pub fn read(&self, slot: usize) -> T {
+        assert!(slot < self.slots, "uniform ring slot {} out of {}", slot, self.slots);
+        unsafe { self.mapped.add(self.offset(slot) as usize).cast::<T>().read_unaligned() }
+    }
+
+    pub fn descriptor_info(&self, slot: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.instance)
+            .offset(self.offset(slot))
+            .range(size_of::<T>() as vk::DeviceSize)
+    }
+
+    /// One descriptor per slot, in order, e.g. for one descriptor set each.
+    pub fn descriptor_infos(&self) -> Vec<vk::DescriptorBufferInfo> {
+        (0..self.slots).map(|slot| self.descriptor_info(slot)).collect()
+    }
+
+    fn offset(&self, slot: usize) -> vk::DeviceSize {
+        self.stride * slot as u64
+    }
+
+    pub fn cleanup(&self, graphics: &Graphics) {
+        unsafe { graphics.logical.instance.unmap_memory(self.buffer.memory) };
+        self.buffer.cleanup(graphics);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+use crate::{cmd::command_pool::FrameScene, core::logical_device::GraphicsInterface, math::geometry, pipeline::traits::VulkanPipeline};
+
+/// State of the frame being recorded, handed to `Scene::record`.
+pub struct FrameContext<'a> {
+    pub device: &'a GraphicsInterface,
+    /// Swapchain image the command buffer renders to.
+    pub image_index: usize,
+    /// Extent of the render area, the fixed resolution when rendering offscreen.
+    pub extent: vk::Extent2D,
+    /// Pipeline, geometry and descriptor set owned by the renderer for this image.
+    pub resources: &'a FrameScene<'a>,
+    /// Render pass and framebuffer the scene is recorded in, which secondary command buffers inherit.
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    /// How the scene subpass was begun, from `Scene::contents`.
+    pub contents: vk::SubpassContents
+}
+
+impl FrameContext<'_> {
+    /// Inheritance for secondary command buffers recorded with `RENDER_PASS_CONTINUE` for this frame.
+    pub fn inheritance_info(&self) -> vk::CommandBufferInheritanceInfo<'static> {
+        vk::CommandBufferInheritanceInfo::default()
+            .render_pass(self.render_pass)
+            .subpass(0)
+            .framebuffer(self.framebuffer)
+    }
+
+    /// Executes `secondaries` in the scene subpass. A subpass holds either inline draws or secondary
+    /// command buffers, so this fails unless the scene returns `SECONDARY_COMMAND_BUFFERS` from `contents`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be the one of this frame, and `secondaries` must have been recorded with `inheritance_info`.
+    pub unsafe fn execute_commands(&self, command_buffer: vk::CommandBuffer, secondaries: &[vk::CommandBuffer]) -> Result<()> {
+        if self.contents != vk::SubpassContents::SECONDARY_COMMAND_BUFFERS {
+            return Err(anyhow!("Secondary command buffers cannot be executed in a subpass begun with {:?}.", self.contents));
+        }
+        self.device.instance.cmd_execute_commands(command_buffer, secondaries);
+        Ok(())
+    }
+}
+
+/// User content drawn every frame, which turns `Vulcor` into a host for custom rendering.
+pub trait Scene {
+    /// Advances the scene by `delta_time` seconds, once per frame before recording.
+    fn update(&mut self, delta_time: f32);
+
+    /// Records draws into `command_buffer`, inside the scene render pass which is already begun.
+    /// Pre-recorded command buffers only call this when they are recorded again.
+    fn record(&self, command_buffer: vk::CommandBuffer, frame: &FrameContext);
+
+    /// `SECONDARY_COMMAND_BUFFERS` when `record` only executes secondary command buffers, e.g. recorded
+    /// on several threads, through `FrameContext::execute_commands`. Draws must then not be recorded inline.
+    fn contents(&self) -> vk::SubpassContents {
+        vk::SubpassContents::INLINE
+    }
+}
+
+/// Scene drawing nothing, so frames only hold the clear color of the render pass.
+/// Exercises acquire, submission and present without any pipeline, e.g. as a smoke test on a software driver.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClearScene;
+
+impl Scene for ClearScene {
+    fn update(&mut self, _delta_time: f32) {}
+
+    fn record(&self, _command_buffer: vk::CommandBuffer, _frame: &FrameContext) {}
+}
+
+/// Default scene, drawing the renderer's mesh with its pipeline and outline unless it is outside the frustum, then its particles.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeshScene;
+
+impl Scene for MeshScene {
+    fn update(&mut self, _delta_time: f32) {}
+
+    fn record(&self, command_buffer: vk::CommandBuffer, frame: &FrameContext) {
+        let device = &frame.device.instance;
+        let resources = frame.resources;
+        // Meshes of unknown bounds are always drawn
+        let visible = resources.bounds
+            .is_none_or(|bounds| geometry::cull(std::slice::from_ref(&bounds), &resources.frustum).next().is_some());
+        unsafe {
+            if visible {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, resources.pipeline.instance());
+                device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, resources.pipeline.layout(), 0, &[resources.descriptor_set], &[]);
+                resources.mesh.record_draw(device, command_buffer, resources.vertex_buffers, resources.index_buffer);
+                if let Some((pipeline, outline)) = resources.outline {
+                    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.instance());
+                    outline.push(device, command_buffer, pipeline.layout());
+                    resources.mesh.record_draw(device, command_buffer, resources.vertex_buffers, resources.index_buffer);
+                }
+            }
+            if let Some(particles) = resources.particles {
+                particles.record_draw(device, command_buffer);
+            }
+        }
+    }
+}
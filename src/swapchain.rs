@@ -1,8 +1,25 @@
-use anyhow::{Result};
+use anyhow::{anyhow, Result};
+use std::fmt::{self, Display, Formatter};
 use ash::{khr::swapchain, vk::{self, Extent2D, SwapchainKHR}, Device};
-use winit::window::Window;
 
-use crate::{core::context::VulkanContext, Graphics, QueueFamilyIndices};
+use crate::{core::{context::VulkanContext, graphics::Graphics}, resources::image};
+
+#[derive(Debug)]
+pub enum SwapchainError {
+    UnsupportedFormatUsage(vk::Format, vk::ImageUsageFlags),
+    UnsupportedSurfaceUsage(vk::ImageUsageFlags)
+}
+
+impl Display for SwapchainError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormatUsage(format, usage) => write!(f, "Swapchain format {:?} cannot back the requested usage {:?}.", format, usage),
+            Self::UnsupportedSurfaceUsage(usage) => write!(f, "The surface does not support the swapchain image usage {:?}.", usage),
+        }
+    }
+}
+
+impl std::error::Error for SwapchainError {}
 
 #[derive(Clone, Debug)]
 pub struct SwapchainSupport {
@@ -17,14 +34,136 @@ pub struct SwapchainConfig {
     pub capabilities: vk::SurfaceCapabilitiesKHR,
     pub format: vk::SurfaceFormatKHR,
     pub present_mode: vk::PresentModeKHR,
-    pub extent: Extent2D
+    pub extent: Extent2D,
+    pub transform: vk::SurfaceTransformFlagsKHR,
+    /// Usage of the swapchain images, always including `COLOR_ATTACHMENT`.
+    pub usage: vk::ImageUsageFlags
+}
+
+impl SwapchainConfig {
+    /// Whether writes of linear color are encoded to sRGB by the hardware. Otherwise the last pass writing the images
+    /// has to encode, e.g. a `FullscreenPass` with `ENCODE_SRGB_FRAGMENT_SHADER`, or colors come out too dark.
+    pub fn encodes_srgb(&self) -> bool {
+        image::is_srgb(self.format.format)
+    }
+
+    /// Whether the presentation engine rotates images by a quarter turn, in which case the extent is stored in the native orientation.
+    pub fn is_rotated(&self) -> bool {
+        self.transform.intersects(vk::SurfaceTransformFlagsKHR::ROTATE_90 | vk::SurfaceTransformFlagsKHR::ROTATE_270)
+    }
+
+    /// Clockwise rotation in degrees that the projection must apply to compensate for the surface pre-transform.
+    pub fn pre_rotation_degrees(&self) -> f32 {
+        match self.transform {
+            vk::SurfaceTransformFlagsKHR::ROTATE_90 => 90.0,
+            vk::SurfaceTransformFlagsKHR::ROTATE_180 => 180.0,
+            vk::SurfaceTransformFlagsKHR::ROTATE_270 => 270.0,
+            _ => 0.0
+        }
+    }
+}
+
+/// Hint trading latency for power when picking the present mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PowerPreference {
+    /// Prefer `MAILBOX` for low latency without tearing.
+    #[default]
+    HighPerformance,
+    /// Prefer `FIFO`, which idles the GPU until the next vertical blank, e.g. when running on battery.
+    LowPower,
+    /// Prefer `IMMEDIATE`, presenting as fast as frames are rendered at the cost of tearing, e.g. for benchmarks.
+    Unthrottled,
+    /// Prefer `MAILBOX`, then `FIFO_RELAXED` for variable frame rates. A frame missing the vertical blank
+    /// is presented immediately instead of waiting a whole refresh, which avoids a stutter but may tear near the blank.
+    Adaptive
+}
+
+/// How an image rendered at a fixed resolution is fitted into the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    /// Keep the aspect ratio, filling the remaining area with black bars.
+    #[default]
+    Letterbox,
+    /// Cover the whole window, distorting the image when the aspect ratios differ.
+    Stretch
 }
 
+/// Internal resolution the scene is rendered at, independently of the window size.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedResolution {
+    pub extent: vk::Extent2D,
+    pub scaling: Scaling
+}
+
+impl FixedResolution {
+    /// Region of a `target` sized image covered by the scaled image, the viewport the image is drawn into.
+    pub fn destination(&self, target: vk::Extent2D) -> vk::Rect2D {
+        let (width, height) = match self.scaling {
+            Scaling::Stretch => (target.width, target.height),
+            Scaling::Letterbox => {
+                let scale = (target.width as f32 / self.extent.width as f32).min(target.height as f32 / self.extent.height as f32);
+                ((self.extent.width as f32 * scale) as u32, (self.extent.height as f32 * scale) as u32)
+            }
+        };
+        let x = ((target.width - width) / 2) as i32;
+        let y = ((target.height - height) / 2) as i32;
+        vk::Rect2D { offset: vk::Offset2D { x, y }, extent: vk::Extent2D { width, height } }
+    }
+}
+
+/// Requested swapchain behaviour, validated against the surface capabilities on creation.
+#[derive(Clone, Debug)]
+pub struct SwapchainOptions {
+    pub composite_alpha: vk::CompositeAlphaFlagsKHR,
+    /// Request exactly `min_image_count` images instead of one extra, e.g. for strict double-buffering.
+    pub minimal_image_count: bool,
+    /// Color spaces by decreasing priority. The first one offered by the surface wins.
+    pub color_spaces: Vec<vk::ColorSpaceKHR>,
+    pub power_preference: PowerPreference,
+    /// Render unrotated when the surface supports it, letting the presentation engine rotate the image.
+    /// When false, the current transform is used and the projection compensates for it.
+    pub prefer_identity_transform: bool,
+    /// Usage added to `COLOR_ATTACHMENT`, e.g. `TRANSFER_SRC` for screenshots or `STORAGE` for compute post-processing.
+    pub extra_usage: vk::ImageUsageFlags,
+    /// Render offscreen at this resolution and scale the result into the swapchain images with a `FullscreenPass`.
+    pub fixed_resolution: Option<FixedResolution>,
+    /// Other formats the images can be viewed as through `SwapchainData::image_view_as`, e.g. `B8G8R8A8_UNORM` over
+    /// a `B8G8R8A8_SRGB` swapchain for a compute pass writing it as storage. Each must be compatible with the swapchain
+    /// format, i.e. of the same size class, typically the UNORM and SRGB variants of one layout.
+    /// Needs `VK_KHR_swapchain_mutable_format`, without it the formats are ignored and only the regular views exist.
+    pub view_formats: Vec<vk::Format>,
+    /// Let the presentation engine skip pixels hidden by other windows or off screen, whose content is then undefined.
+    /// Disable it when reading back whole swapchain images, e.g. screenshots of partly covered windows, at the cost
+    /// of shading and storing every pixel even when it never becomes visible.
+    pub clipped: bool
+}
+
+impl Default for SwapchainOptions {
+    fn default() -> Self {
+        Self { 
+            composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE, 
+            minimal_image_count: false,
+            color_spaces: vec![vk::ColorSpaceKHR::SRGB_NONLINEAR],
+            power_preference: PowerPreference::default(),
+            prefer_identity_transform: true,
+            extra_usage: vk::ImageUsageFlags::empty(),
+            fixed_resolution: None,
+            view_formats: vec![],
+            clipped: true
+        }
+    }
+}
+
+/// Swapchain and its images. Recreation, e.g. on resize, destroys this value and builds a new one:
+/// image handles, views, format and extent read from it are invalid once `Vulcor::recreate_swapchain` ran,
+/// and must be fetched again through the accessors rather than cached across frames.
 pub struct SwapchainData {
     pub khr: SwapchainKHR,
     pub loader: swapchain::Device,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    /// Views of every image in each of the extra formats the swapchain was created mutable for.
+    pub alternate_views: Vec<(vk::Format, Vec<vk::ImageView>)>,
     pub config: SwapchainConfig
 }
 
@@ -41,45 +180,133 @@ impl SwapchainSupport {
         })
     }
 
+    /// Queries the capabilities again, keeping the formats and present modes which rarely change.
+    /// `current_extent` in particular can change between two resizes.
+    pub fn refresh_capabilities(&mut self, context: &VulkanContext, physical_device: &vk::PhysicalDevice) -> Result<()> {
+        self.capabilities = unsafe { context.surface_loader.get_physical_device_surface_capabilities(*physical_device, context.surface)? };
+        Ok(())
+    }
 
 }
 
 impl SwapchainData {
-    pub fn new(context: &VulkanContext, logical_device: &Device, physical_device: &vk::PhysicalDevice, window: &Window) -> Result<Self> {
-        let loader = swapchain::Device::new(&context.instance, &logical_device);
-        let (swapchain, config) = Self::create_swapchain(&context, &physical_device, &window, &loader)?;
+    /// Images are shared by the graphics and presentation families of `graphics`, and `options.view_formats` only apply
+    /// when it enabled `VK_KHR_swapchain_mutable_format`. `cached` support, typically from the swapchain being replaced,
+    /// saves querying the formats and present modes again.
+    pub fn new(context: &VulkanContext, graphics: &Graphics, desired_extent: Option<vk::Extent2D>, options: &SwapchainOptions, cached: Option<&SwapchainSupport>) -> Result<Self> {
+        let logical_device = &graphics.logical.instance;
+        let loader = swapchain::Device::new(&context.instance, logical_device);
+        let view_formats = Self::view_formats(options, graphics.logical.swapchain_mutable_format, cached);
+        let (swapchain, config) = Self::create_swapchain(context, graphics, desired_extent, &loader, options, &view_formats, cached)?;
         let images = unsafe { loader.get_swapchain_images(swapchain)? };
-        let image_views = Self::create_image_views(&logical_device, &images, &config.format)?;
+        let image_views = Self::create_image_views(logical_device, &images, config.format.format)?;
+        let alternate_views = view_formats.iter()
+            .filter(|format| **format != config.format.format)
+            .map(|format| Ok((*format, Self::create_image_views(logical_device, &images, *format)?)))
+            .collect::<Result<Vec<_>>>()?;
         Ok(Self {
             khr: swapchain,
             loader,
             images,
             image_views,
+            alternate_views,
             config
         })
     }
 
+    /// Extra view formats to create the swapchain mutable for, empty when none was asked for or it is not supported.
+    fn view_formats(options: &SwapchainOptions, mutable_format: bool, cached: Option<&SwapchainSupport>) -> Vec<vk::Format> {
+        if options.view_formats.is_empty() {
+            return vec![];
+        }
+        if !mutable_format {
+            // Only warn once rather than on every recreation
+            if cached.is_none() {
+                log::warn!("VK_KHR_swapchain_mutable_format is not available, ignoring the view formats {:?}", options.view_formats);
+            }
+            return vec![];
+        }
+        options.view_formats.clone()
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Presentable image at `index`, as returned by `acquire_next_image`.
+    pub fn image(&self, index: usize) -> Option<vk::Image> {
+        self.images.get(index).copied()
+    }
+
+    pub fn image_view(&self, index: usize) -> Option<vk::ImageView> {
+        self.image_views.get(index).copied()
+    }
+
+    /// View of the image at `index` reinterpreted as `format`, one of `SwapchainOptions::view_formats` or the swapchain format.
+    /// `None` when the swapchain could not be created mutable for it.
+    pub fn image_view_as(&self, index: usize, format: vk::Format) -> Option<vk::ImageView> {
+        if format == self.format() {
+            return self.image_view(index);
+        }
+        self.alternate_views.iter()
+            .find(|(view_format, _)| *view_format == format)
+            .and_then(|(_, views)| views.get(index).copied())
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.config.format.format
+    }
+
+    /// Extent of the images, which is pre-rotated when `config.is_rotated()`.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.config.extent
+    }
+
     pub fn cleanup(&self, graphics: &Graphics) {
         unsafe {
             self.image_views.iter()
+                .chain(self.alternate_views.iter().flat_map(|(_, views)| views))
                 .for_each(|v| graphics.logical.instance.destroy_image_view(*v, None));
             self.loader.destroy_swapchain(self.khr, None);
         }
     }
 
-    fn create_swapchain(context: &VulkanContext, physical_device: &vk::PhysicalDevice, window: &Window, swapchain_loader: &swapchain::Device) -> Result<(vk::SwapchainKHR, SwapchainConfig)> {
-        let queue_family = QueueFamilyIndices::new(context, physical_device)?;
-        let details = SwapchainSupport::new(context, physical_device)?;
-        let format = Self::select_swapchain_formats(&details);
-        let present_mode = Self::select_swapchain_present_mode(&details);
-        let extent = Self::select_swapchain_extent(&details, window);
+    fn create_swapchain(context: &VulkanContext, graphics: &Graphics, desired_extent: Option<vk::Extent2D>, swapchain_loader: &swapchain::Device, options: &SwapchainOptions, view_formats: &[vk::Format], cached: Option<&SwapchainSupport>) -> Result<(vk::SwapchainKHR, SwapchainConfig)> {
+        let physical_device = &graphics.physical.instance;
+        let queue_family = &graphics.queue_family;
+        // The capabilities are always fresh, a stale `current_extent` gets the new swapchain out of date right away
+        let details = match cached {
+            Some(cached) => {
+                let mut details = cached.clone();
+                details.refresh_capabilities(context, physical_device)?;
+                details
+            },
+            None => SwapchainSupport::new(context, physical_device)?
+        };
+        let format = Self::select_swapchain_formats(&details, &options.color_spaces);
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | options.extra_usage;
+        Self::validate_surface_usage(&details, image_usage)?;
+        // The list has to include the swapchain format itself
+        let format_list = match view_formats.is_empty() {
+            true => vec![],
+            false => std::iter::once(format.format).chain(view_formats.iter().copied().filter(|f| *f != format.format)).collect::<Vec<_>>()
+        };
+        Self::validate_format_usage(context, physical_device, format.format, &format_list, image_usage)?;
+        let present_mode = Self::select_swapchain_present_mode(&details, options.power_preference);
+        let transform = Self::select_transform(&details, options.prefer_identity_transform);
+        let extent = Self::pre_rotated_extent(Self::select_swapchain_extent(&details, desired_extent), transform);
+        let composite_alpha = Self::select_composite_alpha(&details, options.composite_alpha);
         let image_count = {
             let max = details.capabilities.max_image_count;
-            let preferred = details.capabilities.min_image_count + 1;
-            if max > 0 { preferred.max(max) } else { preferred }
+            let min = details.capabilities.min_image_count;
+            let preferred = if options.minimal_image_count { min } else { min + 1 };
+            if max > 0 { preferred.min(max) } else { preferred }
         };
+        log::debug!("Requesting {} swapchain images", image_count);
 
-        let use_concurrent_mode = queue_family.graphics != queue_family.presentation;
+        // Images written on the graphics queue and presented from another family are shared concurrently
+        // rather than transferred between families every frame
+        let use_concurrent_mode = queue_family.separate_present();
         let image_sharing_mode = if use_concurrent_mode { vk::SharingMode::CONCURRENT } else { vk::SharingMode::EXCLUSIVE };
         let queue_family_indices = if use_concurrent_mode { vec![queue_family.graphics, queue_family.presentation] } else { vec![] };
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
@@ -88,68 +315,148 @@ impl SwapchainData {
             .image_color_space(format.color_space)
             .image_format(format.format)
             .image_extent(extent)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(image_sharing_mode)
-            .pre_transform(details.capabilities.current_transform)
+            .pre_transform(transform)
             .present_mode(present_mode)
             .queue_family_indices(&queue_family_indices)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .clipped(true)
+            .composite_alpha(composite_alpha)
+            .clipped(options.clipped)
             .image_array_layers(1);
+        let mut format_list_info = vk::ImageFormatListCreateInfo::default()
+            .view_formats(&format_list);
+        let swapchain_create_info = match format_list.is_empty() {
+            true => swapchain_create_info,
+            false => swapchain_create_info
+                .flags(vk::SwapchainCreateFlagsKHR::MUTABLE_FORMAT)
+                .push_next(&mut format_list_info)
+        };
 
-        let config = SwapchainConfig { capabilities: details.capabilities, format, present_mode, extent, support: details };
+        let config = SwapchainConfig { capabilities: details.capabilities, format, present_mode, extent, transform, usage: image_usage, support: details };
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
         Ok((swapchain, config))
     }
 
-    fn select_swapchain_formats(support: &SwapchainSupport) -> vk::SurfaceFormatKHR  {
-        *support.formats.iter()
-            .find(|f| f.format == vk::Format::B8G8R8A8_SRGB && f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
-            .unwrap_or(&support.formats[0])
+    /// Walks the color spaces by priority, preferring `B8G8R8A8_SRGB` within a color space when it is offered, then any
+    /// other sRGB format, so that linear shader output is encoded on write.
+    fn select_swapchain_formats(support: &SwapchainSupport, color_spaces: &[vk::ColorSpaceKHR]) -> vk::SurfaceFormatKHR  {
+        let format = color_spaces.iter()
+            .find_map(|color_space| {
+                let candidates = support.formats.iter()
+                    .filter(|f| f.color_space == *color_space)
+                    .collect::<Vec<_>>();
+                candidates.iter()
+                    .find(|f| f.format == vk::Format::B8G8R8A8_SRGB)
+                    .or(candidates.iter().find(|f| image::is_srgb(f.format)))
+                    .or(candidates.first())
+                    .map(|f| **f)
+            })
+            .unwrap_or(support.formats[0]);
+        if !image::is_srgb(format.format) {
+            log::warn!("Presenting in {:?}, which is not encoded to sRGB on write, the last pass must encode linear color itself, e.g. with `ENCODE_SRGB_FRAGMENT_SHADER`", format.format);
+        }
+        format
+    }
+
+    fn validate_surface_usage(support: &SwapchainSupport, usage: vk::ImageUsageFlags) -> Result<()> {
+        let unsupported = usage & !support.capabilities.supported_usage_flags;
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(SwapchainError::UnsupportedSurfaceUsage(unsupported)))
+        }
+    }
+
+    /// Swapchain images are optimally tiled, so the format must expose the matching optimal tiling features.
+    /// A mutable swapchain only needs each feature from one of the formats in `format_list`, e.g. storage from a UNORM view.
+    fn validate_format_usage(context: &VulkanContext, physical_device: &vk::PhysicalDevice, format: vk::Format, format_list: &[vk::Format], usage: vk::ImageUsageFlags) -> Result<()> {
+        let features = std::iter::once(format).chain(format_list.iter().copied())
+            .map(|f| unsafe { context.instance.get_physical_device_format_properties(*physical_device, f) }.optimal_tiling_features)
+            .fold(vk::FormatFeatureFlags::empty(), |acc, features| acc | features);
+        let required = [
+            (vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::FormatFeatureFlags::COLOR_ATTACHMENT),
+            (vk::ImageUsageFlags::TRANSFER_SRC, vk::FormatFeatureFlags::TRANSFER_SRC),
+            (vk::ImageUsageFlags::TRANSFER_DST, vk::FormatFeatureFlags::TRANSFER_DST),
+            (vk::ImageUsageFlags::STORAGE, vk::FormatFeatureFlags::STORAGE_IMAGE),
+            (vk::ImageUsageFlags::SAMPLED, vk::FormatFeatureFlags::SAMPLED_IMAGE),
+        ].iter()
+            .filter(|(u, _)| usage.contains(*u))
+            .fold(vk::FormatFeatureFlags::empty(), |acc, (_, feature)| acc | *feature);
+
+        if features.contains(required) {
+            Ok(())
+        } else {
+            Err(anyhow!(SwapchainError::UnsupportedFormatUsage(format, usage)))
+        }
     }
 
-    fn select_swapchain_present_mode(support: &SwapchainSupport) -> vk::PresentModeKHR {
-        *support.present_modes.iter()
-            .find(|&p| *p == vk::PresentModeKHR::MAILBOX)
-            .unwrap_or(&vk::PresentModeKHR::FIFO)
+    fn select_swapchain_present_mode(support: &SwapchainSupport, power_preference: PowerPreference) -> vk::PresentModeKHR {
+        // FIFO is always supported
+        if power_preference == PowerPreference::LowPower {
+            return vk::PresentModeKHR::FIFO;
+        }
+        let preferred: &[vk::PresentModeKHR] = match power_preference {
+            PowerPreference::Unthrottled => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX],
+            PowerPreference::Adaptive => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO_RELAXED],
+            _ => &[vk::PresentModeKHR::MAILBOX]
+        };
+        preferred.iter()
+            .find(|p| support.present_modes.contains(p))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+
+    /// Quarter-turn transforms expect the swapchain images in the display's native orientation.
+    fn pre_rotated_extent(extent: vk::Extent2D, transform: vk::SurfaceTransformFlagsKHR) -> vk::Extent2D {
+        if transform.intersects(vk::SurfaceTransformFlagsKHR::ROTATE_90 | vk::SurfaceTransformFlagsKHR::ROTATE_270) {
+            vk::Extent2D { width: extent.height, height: extent.width }
+        } else {
+            extent
+        }
     }
 
-    fn select_swapchain_extent(support: &SwapchainSupport, window: &Window) -> vk::Extent2D {
+    /// Falls back to the first supported transform when the current one is not reported as supported.
+    fn select_transform(support: &SwapchainSupport, prefer_identity: bool) -> vk::SurfaceTransformFlagsKHR {
+        let supported = support.capabilities.supported_transforms;
+        let current = support.capabilities.current_transform;
+        if prefer_identity && supported.contains(vk::SurfaceTransformFlagsKHR::IDENTITY) {
+            return vk::SurfaceTransformFlagsKHR::IDENTITY;
+        }
+        if supported.contains(current) {
+            return current;
+        }
+        let fallback = vk::SurfaceTransformFlagsKHR::from_raw(supported.as_raw() & supported.as_raw().wrapping_neg());
+        log::warn!("Current transform {:?} is not supported by the surface, falling back to {:?}.", current, fallback);
+        fallback
+    }
+
+    fn select_composite_alpha(support: &SwapchainSupport, requested: vk::CompositeAlphaFlagsKHR) -> vk::CompositeAlphaFlagsKHR {
+        if support.capabilities.supported_composite_alpha.contains(requested) {
+            return requested;
+        }
+        log::warn!("Composite alpha {:?} is not supported by the surface, falling back to OPAQUE.", requested);
+        vk::CompositeAlphaFlagsKHR::OPAQUE
+    }
+
+    /// The surface's current extent, or `desired` within the supported range when the surface lets the swapchain decide,
+    /// e.g. on Wayland or for a headless surface. Without `desired` the smallest supported extent is used.
+    /// Both are in physical pixels, so a window on a 2x display gets twice as many pixels per side as its logical size.
+    fn select_swapchain_extent(support: &SwapchainSupport, desired: Option<vk::Extent2D>) -> vk::Extent2D {
         if support.capabilities.current_extent.width != std::u32::MAX {
             return support.capabilities.current_extent;
         }
         let min = support.capabilities.min_image_extent;
         let max = support.capabilities.max_image_extent;
-        let width = window.inner_size().width.clamp(max.width, min.width);
-        let height = window.inner_size().height.clamp(max.height, min.height);
+        let desired = desired.unwrap_or(min);
+        let width = desired.width.clamp(min.width, max.width);
+        let height = desired.height.clamp(min.height, max.height);
         vk::Extent2D { width: width, height: height}
     }
 
-    fn create_image_views(device: &Device, images: &Vec<vk::Image>, format: &vk::SurfaceFormatKHR) -> Result<Vec<vk::ImageView>> {
-        let image_views = images.iter()
-            .map(|img| {
-                let info = vk::ImageViewCreateInfo::default()
-                    .view_type(vk::ImageViewType::TYPE_2D)
-                    .format(format.format)
-                    .components(vk::ComponentMapping {
-                        r: vk::ComponentSwizzle::R,
-                        g: vk::ComponentSwizzle::G,
-                        b: vk::ComponentSwizzle::B,
-                        a: vk::ComponentSwizzle::A,
-                    })
-                    .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        base_mip_level: 0,
-                        level_count: 1,
-                        base_array_layer: 0,
-                        layer_count: 1,
-                    })
-                    .image(*img);
-                unsafe { device.create_image_view(&info, None).unwrap() }
-            })
-            .collect::<Vec<_>>();
-
-        Ok(image_views)
+    fn create_image_views(device: &Device, images: &Vec<vk::Image>, format: vk::Format) -> Result<Vec<vk::ImageView>> {
+        images.iter()
+            .map(|img| image::create_image_view(device, *img, format, vk::ImageAspectFlags::COLOR, 1))
+            .collect()
     }
 
 }
\ No newline at end of file
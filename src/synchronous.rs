@@ -2,10 +2,17 @@ use anyhow::{Result};
 use ash::vk;
 use crate::{core::graphics::Graphics, swapchain::SwapchainData};
 
-const MAX_FRAMES_IN_FLIGHT: usize = 2;
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
 
+/// Frame synchronization. Semaphores handed to the presentation engine are tied to swapchain images rather
+/// than to frames in flight: an acquire or present semaphore may only be reused once the image it was used
+/// with is acquired again, which is the only point where its previous wait is known to have completed.
 pub struct RenderSync {
-    image_available: Vec<vk::Semaphore>,
+    /// Acquire semaphores not owned by any image, there is always at least one.
+    free_image_available: Vec<vk::Semaphore>,
+    /// Acquire semaphore signaled by the latest acquisition of each swapchain image.
+    image_available: Vec<Option<vk::Semaphore>>,
+    /// Signaled by the submission rendering to each swapchain image and waited on by its present.
     render_completed: Vec<vk::Semaphore>,
     in_flight: Vec<vk::Fence>,
     pub images_in_flight: Vec<vk::Fence>,
@@ -13,21 +20,19 @@ pub struct RenderSync {
 }
 
 impl RenderSync {
-    pub fn new(graphics: &Graphics, swapchain: &SwapchainData) -> Result<Self> {
-        let mut image_available_semaphores: Vec<vk::Semaphore> = vec![];
-        let mut render_completed_semaphores: Vec<vk::Semaphore> = vec![];
+    /// `frames_in_flight` is clamped to the swapchain image count, see `frames_in_flight_for`.
+    pub fn new(graphics: &Graphics, swapchain: &SwapchainData, frames_in_flight: usize) -> Result<Self> {
+        let image_count = swapchain.images.len();
+        let frames_in_flight = Self::frames_in_flight_for(frames_in_flight, image_count);
+        // Each image owns at most one acquire semaphore, the extra one is for the acquisition in progress
+        let free_image_available = (0..image_count + 1)
+            .map(|_| Self::create_semaphore(graphics))
+            .collect::<Result<Vec<_>>>()?;
+        let render_completed = (0..image_count)
+            .map(|_| Self::create_semaphore(graphics))
+            .collect::<Result<Vec<_>>>()?;
         let mut in_flight_fences: Vec<vk::Fence> = vec![];
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            let image_available = {
-                let create_info = vk::SemaphoreCreateInfo::default();
-                unsafe { graphics.logical.instance.create_semaphore(&create_info, None)? }
-            };
-            image_available_semaphores.push(image_available);
-            let render_completed = {
-                let create_info = vk::SemaphoreCreateInfo::default();
-                unsafe { graphics.logical.instance.create_semaphore(&create_info, None)? }
-            };
-            render_completed_semaphores.push(render_completed);
+        for _ in 0..frames_in_flight {
             let fence = {
                 let create_info = vk::FenceCreateInfo::default()
                     .flags(vk::FenceCreateFlags::SIGNALED);
@@ -41,16 +46,36 @@ impl RenderSync {
             .collect();
 
         Ok(Self{
-            image_available: image_available_semaphores, 
-            render_completed: render_completed_semaphores,
+            free_image_available,
+            image_available: vec![None; image_count],
+            render_completed,
             in_flight: in_flight_fences,
             images_in_flight,
             frame: 0
         })
     }
 
+    /// More frames in flight than swapchain images would have a frame wait on the fence of an image
+    /// that cannot be acquired until that same frame presents, so the count is clamped to the images.
+    pub fn frames_in_flight_for(requested: usize, image_count: usize) -> usize {
+        let frames_in_flight = requested.clamp(1, image_count.max(1));
+        if frames_in_flight != requested {
+            log::warn!("{} frames in flight requested for {} swapchain images, using {}", requested, image_count, frames_in_flight);
+        }
+        frames_in_flight
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn create_semaphore(graphics: &Graphics) -> Result<vk::Semaphore> {
+        let create_info = vk::SemaphoreCreateInfo::default();
+        Ok(unsafe { graphics.logical.instance.create_semaphore(&create_info, None)? })
+    }
+
     pub fn cleanup(&self, graphics: &Graphics) {
-        self.image_available.iter().for_each(|s| {
+        self.free_image_available.iter().chain(self.image_available.iter().flatten()).for_each(|s| {
             unsafe { graphics.logical.instance.destroy_semaphore(*s, None) };
         });
         self.render_completed.iter().for_each(|s| {
@@ -59,17 +84,32 @@ impl RenderSync {
         self.in_flight.iter().for_each(|f| {
             unsafe { graphics.logical.instance.destroy_fence(*f, None) };
         });
-        self.images_in_flight.iter().for_each(|f| {
-            unsafe { graphics.logical.instance.destroy_fence(*f, None) };
-        });
     }
 
-    pub fn get_image_available(&self) -> vk::Semaphore {
-        self.image_available[self.frame]
+    /// Semaphore for the next acquisition, hand it back through `image_acquired` or `acquire_failed`.
+    pub fn next_image_available(&mut self) -> vk::Semaphore {
+        self.free_image_available.pop().expect("an acquire semaphore is always free")
     }
 
-    pub fn get_render_completed(&self) -> vk::Semaphore {
-        self.render_completed[self.frame]
+    /// Gives ownership of `semaphore` to the acquired image and recycles the one from its previous
+    /// acquisition, whose wait finished before the image could be presented and acquired again.
+    pub fn image_acquired(&mut self, index: usize, semaphore: vk::Semaphore) {
+        if let Some(previous) = self.image_available[index].replace(semaphore) {
+            self.free_image_available.push(previous);
+        }
+    }
+
+    /// Returns a semaphore that no acquisition signaled.
+    pub fn acquire_failed(&mut self, semaphore: vk::Semaphore) {
+        self.free_image_available.push(semaphore);
+    }
+
+    pub fn get_image_available(&self, index: usize) -> vk::Semaphore {
+        self.image_available[index].expect("image was acquired")
+    }
+
+    pub fn get_render_completed(&self, index: usize) -> vk::Semaphore {
+        self.render_completed[index]
     }
 
     pub fn get_in_flight_fence(&self) -> vk::Fence {
@@ -77,7 +117,7 @@ impl RenderSync {
     }
 
     pub fn increment_frame(&mut self) {
-        self.frame = (self.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.frame = (self.frame + 1) % self.in_flight.len();
     }
 
     pub fn update_image_in_flight(&mut self, index: usize) {
@@ -88,4 +128,22 @@ impl RenderSync {
         unsafe { graphics.logical.instance.reset_fences(&[self.get_in_flight_fence()])? };
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_in_flight_clamped_to_images() {
+        // Four frames over two images would have the third frame wait on an image it cannot acquire
+        assert_eq!(RenderSync::frames_in_flight_for(4, 2), 2);
+        assert_eq!(RenderSync::frames_in_flight_for(0, 2), 1);
+    }
+
+    #[test]
+    fn frames_in_flight_within_images_kept() {
+        assert_eq!(RenderSync::frames_in_flight_for(2, 3), 2);
+        assert_eq!(RenderSync::frames_in_flight_for(3, 3), 3);
+    }
+}
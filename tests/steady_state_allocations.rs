@@ -0,0 +1,90 @@
+//! Renders the default scene and checks that steady-state frames do not allocate on the heap.
+//! Needs a window and a Vulkan device, so it only runs with `--features validation-harness`.
+
+use ash::vk;
+use std::{alloc::{GlobalAlloc, Layout, System}, error::Error, sync::{atomic::{AtomicU64, Ordering}, Arc}, thread, time::Duration};
+use vulcor::{scene::MeshScene, App, FrameContext, Scene};
+use winit::event_loop::{ControlFlow, EventLoop};
+
+/// Frames rendered before counting, enough for every swapchain image and lazily created resource to have been used.
+const WARMUP_FRAMES: u32 = 16;
+const MEASURED_FRAMES: u32 = 100;
+/// Allocations of the measured frames until they are all rendered.
+const NOT_MEASURED: u64 = u64::MAX;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// System allocator counting every allocation and reallocation, on any thread.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Draws like `MeshScene`, counting allocations from the end of the warmup to the last measured frame.
+struct CountingScene {
+    frames: u32,
+    start: u64,
+    allocations: Arc<AtomicU64>
+}
+
+impl Scene for CountingScene {
+    fn update(&mut self, _delta_time: f32) {
+        self.frames += 1;
+        if self.frames == WARMUP_FRAMES {
+            self.start = ALLOCATIONS.load(Ordering::Relaxed);
+        } else if self.frames == WARMUP_FRAMES + MEASURED_FRAMES {
+            self.allocations.store(ALLOCATIONS.load(Ordering::Relaxed) - self.start, Ordering::Release);
+        }
+    }
+
+    fn record(&self, command_buffer: vk::CommandBuffer, frame: &FrameContext) {
+        MeshScene.record(command_buffer, frame);
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let allocations = Arc::new(AtomicU64::new(NOT_MEASURED));
+    let mut app = App::with_scene(Box::new(CountingScene { frames: 0, start: 0, allocations: Arc::clone(&allocations) }));
+    let shutdown = app.shutdown_handle();
+    // Sleeping does not allocate, so polling from here leaves the measured frames untouched
+    let measured = Arc::clone(&allocations);
+    thread::spawn(move || {
+        while measured.load(Ordering::Acquire) == NOT_MEASURED {
+            thread::sleep(Duration::from_millis(10));
+        }
+        shutdown.store(true, Ordering::Release);
+    });
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.run_app(&mut app)?;
+
+    match allocations.load(Ordering::Acquire) {
+        NOT_MEASURED => Err(format!("The renderer exited before {} frames were rendered", WARMUP_FRAMES + MEASURED_FRAMES).into()),
+        0 => {
+            println!("{} steady-state frames rendered without allocating", MEASURED_FRAMES);
+            Ok(())
+        },
+        count => Err(format!("{} steady-state frames allocated {} times", MEASURED_FRAMES, count).into())
+    }
+}